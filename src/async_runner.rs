@@ -0,0 +1,389 @@
+//! Async execution on tokio
+//!
+//! Enabled with the `async` feature. Sections are extracted synchronously, then the returned
+//! future owns everything it needs, so it is `Send + 'static` and can be spawned onto a
+//! runtime. Cancelling the token (or dropping the future) kills the running code block.
+//!
+//! Skipping, the step handler, progress frames and observers work as for the blocking API;
+//! only spawning and waiting for a block is async.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::events::RunnerObserver;
+use crate::lock::TaskLock;
+use crate::logs::LogDir;
+use crate::process::{self, ChildGroup, ProcessGroups};
+use crate::progress::ProgressReporter;
+use crate::report::{ExecutionReport, block_id};
+use crate::runner::{
+    BlockOutput, CodeBlock, Invocation, OutputHandler, OutputStream, Runner, Section, SectionRun,
+    StepHandler, log_command,
+};
+use crate::secrets::{MaskedStream, Secrets};
+
+impl Runner {
+    /// Run a task without blocking the calling thread
+    ///
+    /// The file is read and the section looked up before this returns; errors doing so are
+    /// reported by the future.
+    pub fn run_task_async<P: AsRef<Path>>(
+        &self,
+        markdown_path: P,
+        task_name: &str,
+        args: &[String],
+        cancel: CancellationToken,
+    ) -> impl Future<Output = Result<ExecutionReport>> + Send + use<P> {
+        let task = self.find_task_at(&[markdown_path], task_name, None);
+        let execution = task.map(|(_, section)| self.execute_section_async(&section, args, cancel));
+
+        async move { execution?.await }
+    }
+
+    /// Execute a section's code blocks in order without blocking the calling thread
+    ///
+    /// Output is streamed to the output handler, log files and observers while blocks run.
+    pub fn execute_section_async(
+        &self,
+        section: &Section,
+        args: &[String],
+        cancel: CancellationToken,
+    ) -> impl Future<Output = Result<ExecutionReport>> + Send + use<> {
+        let execution = AsyncExecution {
+            config: self.config.clone(),
+            forward_stdin: self.forward_stdin,
+            output: self.output.clone(),
+            observers: self.observers.clone(),
+            step: self.step.clone(),
+            progress: self.progress.clone(),
+            log_dir: self.log_dir.clone(),
+            running: Arc::clone(&self.running),
+            root: self.root.clone(),
+            lock_wait: self.lock_wait,
+            remote: self.remote.clone(),
+            secrets: Arc::clone(&self.secrets),
+            env: self.env.clone(),
+            dry_run: self.dry_run,
+            section: section.clone(),
+            args: args.to_vec(),
+        };
+
+        execution.run(cancel)
+    }
+}
+
+/// Everything needed to execute a section, owned by the future
+struct AsyncExecution {
+    config: Config,
+    forward_stdin: bool,
+    output: Option<OutputHandler>,
+    observers: Vec<Arc<dyn RunnerObserver>>,
+    step: Option<StepHandler>,
+    progress: Option<Arc<ProgressReporter>>,
+    log_dir: Option<LogDir>,
+    running: Arc<ProcessGroups>,
+    root: Option<PathBuf>,
+    lock_wait: bool,
+    remote: Option<String>,
+    secrets: Arc<Secrets>,
+    env: Vec<(String, String)>,
+    dry_run: bool,
+    section: Section,
+    args: Vec<String>,
+}
+
+impl AsyncExecution {
+    async fn run(self, cancel: CancellationToken) -> Result<ExecutionReport> {
+        let section = &self.section;
+        let mut run = SectionRun::new(
+            section,
+            &self.config,
+            &self.observers,
+            self.step.as_ref(),
+            self.progress.as_deref(),
+            self.dry_run,
+        );
+        if run.skipped() {
+            return Ok(run.finish());
+        }
+
+        let _lock = self.lock_task(&cancel).await.map_err(|e| run.fail(e))?;
+        run.begin();
+
+        for index in 0..section.codes.len() {
+            let Some(code_block) = run.next_block(index)? else {
+                continue;
+            };
+
+            let id = block_id(&section.title, index);
+            let log = match &self.log_dir {
+                Some(log_dir) => Some(log_dir.open_block(&section.title, index)?),
+                None => None,
+            };
+            let output = BlockOutput {
+                handler: self.output.as_ref(),
+                log: log.as_ref(),
+                observers: Some((&id, &self.observers)),
+                secrets: &self.secrets,
+            };
+
+            let started = Instant::now();
+            let exit = tokio::select! {
+                exit = self.execute_block(&code_block, output) => exit,
+                _ = cancel.cancelled() => Err(Error::Cancelled(section.title.clone())),
+            };
+            if let Err(e) = run.finish_block(index, &code_block, exit, started.elapsed()) {
+                if matches!(e, Error::Cancelled(_)) {
+                    self.run_cancel_hooks(&id).await;
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(run.finish())
+    }
+
+    /// Spawn a code block and stream its output until it exits
+    ///
+    /// The child is killed if the future is dropped, e.g. when the run is cancelled.
+    async fn execute_block(
+        &self,
+        block: &CodeBlock,
+        output: BlockOutput<'_>,
+    ) -> Result<(ExitStatus, String)> {
+        let lang = block.lang.as_str();
+        let mut invocation = Invocation::prepare(
+            &self.config,
+            self.forward_stdin,
+            block,
+            &self.args,
+            Some(&self.section),
+            self.remote.as_deref(),
+            &self.secrets,
+        )?;
+        invocation.root = self.root.clone();
+        invocation.env.splice(0..0, self.env.iter().cloned());
+        invocation.resolve_tools()?;
+
+        // Concurrent runs cannot share the terminal, so blocks never take it over here
+        if invocation.pty || invocation.interactive {
+            debug!(
+                lang,
+                "blocks are never given the terminal by the async runner"
+            );
+        }
+        let mut command = invocation.command();
+        log_command(&command, &self.secrets);
+        process::configure(&mut command, false);
+        let mut command = tokio::process::Command::from(command);
+        if invocation.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        let mut child = command
+            .stdout(output.stdio())
+            .stderr(output.stdio())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Execution(format!("Failed to execute {}: {}", lang, e)))?;
+        // Dropped with the future on cancellation, killing the block's whole process tree
+        let group = child
+            .id()
+            .map(|pid| ChildGroup::new(pid, false, &self.running));
+
+        let stdin = async {
+            if let (Some(code), Some(mut input)) = (&invocation.stdin, child.stdin.take()) {
+                input
+                    .write_all(code.as_bytes())
+                    .await
+                    .map_err(|e| Error::Execution(format!("Failed to write to stdin: {}", e)))?;
+            }
+            Ok::<_, Error>(())
+        };
+        let stdout = forward_output(child.stdout.take(), OutputStream::Stdout, output);
+        let stderr = forward_output(child.stderr.take(), OutputStream::Stderr, output);
+
+        let (stdin, _, _) = tokio::join!(stdin, stdout, stderr);
+        stdin?;
+
+        let status = child
+            .wait()
+            .await
+            .map_err(|e| Error::Execution(format!("Failed to wait for process: {}", e)))?;
+        if let Some(group) = group
+            && status.success()
+            && !process::interrupted()
+        {
+            group.finish();
+        }
+        Ok((status, invocation.program))
+    }
+
+    /// Take the lock of a task marked `{lock}`; waiting happens on a blocking thread
+    async fn lock_task(&self, cancel: &CancellationToken) -> Result<Option<TaskLock>> {
+        if !self.section.requires_lock() || self.dry_run {
+            return Ok(None);
+        }
+
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => std::env::current_dir()?,
+        };
+        match TaskLock::try_acquire(&root, &self.section.title) {
+            Err(Error::Locked { .. }) if self.lock_wait => {
+                let task = self.section.title.clone();
+                let waiting = tokio::task::spawn_blocking(move || TaskLock::acquire(&root, &task));
+                tokio::select! {
+                    result = waiting => result
+                        .map_err(|e| Error::Execution(format!("Failed to wait for task lock: {}", e)))?
+                        .map(Some),
+                    _ = cancel.cancelled() => Err(Error::Cancelled(self.section.title.clone())),
+                }
+            }
+            result => result.map(Some),
+        }
+    }
+
+    /// Run the configured `on_cancel` hooks after the task was cancelled
+    async fn run_cancel_hooks(&self, block: &str) {
+        for hook in &self.config.on_cancel {
+            info!(task = %self.section.title, block, hook, "running cancel hook");
+            let mut command = tokio::process::Command::from(process::cancel_hook(
+                hook,
+                &self.section.title,
+                block,
+            ));
+            match command.status().await {
+                Ok(status) if !status.success() => warn!(hook, %status, "cancel hook failed"),
+                Ok(_) => {}
+                Err(e) => warn!(hook, "failed to run cancel hook: {}", e),
+            }
+        }
+    }
+}
+
+/// Read a process output stream to the end, passing each chunk on
+async fn forward_output(
+    reader: Option<impl AsyncRead + Unpin>,
+    stream: OutputStream,
+    output: BlockOutput<'_>,
+) {
+    let Some(mut reader) = reader else {
+        return;
+    };
+    let mut buffer = [0; 8192];
+    let mut masked = MaskedStream::new(output.secrets);
+
+    loop {
+        match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => output.write(stream, &masked.push(&buffer[..n])),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                warn!("failed to read process output: {}", e);
+                break;
+            }
+        }
+    }
+    output.write(stream, &masked.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CodeBlock, StepAction};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn section(codes: &[&str]) -> Section {
+        Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: codes
+                .iter()
+                .map(|code| CodeBlock {
+                    lang: "sh".to_string(),
+                    code: code.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_section_async() {
+        let captured = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&captured);
+        let runner =
+            Runner::with_default_config().with_output_handler(Arc::new(move |_, data: &[u8]| {
+                sink.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(data))
+            }));
+
+        let future = runner.execute_section_async(
+            &section(&["echo one", "echo two"]),
+            &[],
+            CancellationToken::new(),
+        );
+        let report = tokio::spawn(future).await.unwrap().unwrap();
+
+        assert_eq!(report.blocks.len(), 2);
+        assert_eq!(*captured.lock().unwrap(), "one\ntwo\n");
+    }
+
+    #[tokio::test]
+    async fn test_step_handler_decides_blocks() {
+        let captured = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&captured);
+        let runner = Runner::with_default_config()
+            .with_output_handler(Arc::new(move |_, data: &[u8]| {
+                sink.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(data))
+            }))
+            .with_step_handler(Arc::new(|_, index, _| match index {
+                0 => StepAction::RunEdited("echo edited".to_string()),
+                _ => StepAction::Skip,
+            }));
+
+        let future = runner.execute_section_async(
+            &section(&["echo one", "echo two"]),
+            &[],
+            CancellationToken::new(),
+        );
+        let report = tokio::spawn(future).await.unwrap().unwrap();
+
+        assert_eq!(*captured.lock().unwrap(), "edited\n");
+        assert_eq!(report.blocks[0].skipped, None);
+        assert_eq!(
+            report.blocks[1].skipped.as_deref(),
+            Some("skipped by the user")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_kills_running_block() {
+        let cancel = CancellationToken::new();
+        let runner = Runner::with_default_config();
+        let future = runner.execute_section_async(&section(&["sleep 30"]), &[], cancel.clone());
+
+        let started = Instant::now();
+        let handle = tokio::spawn(future);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancel.cancel();
+
+        let err = handle.await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::Cancelled(task) if task == "Build"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
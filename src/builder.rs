@@ -0,0 +1,200 @@
+//! Typed builder for [`Runner`]
+//!
+//! Settings that the runner reads when it is created, such as the heading level and the
+//! runtimes, can be changed one at a time instead of through a whole [`Config`]:
+//!
+//! ```no_run
+//! use mx::Runner;
+//!
+//! let runner = Runner::builder()
+//!     .heading_level(3)
+//!     .env("RUST_LOG", "debug")
+//!     .cwd("/path/to/project")
+//!     .dry_run(true)
+//!     .build();
+//! let sections = runner.sections("README.md")?;
+//! # Ok::<(), mx::Error>(())
+//! ```
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::{Config, HeadingLevel, RuntimeConfig};
+use crate::events::RunnerObserver;
+use crate::runner::{OutputHandler, Runner};
+
+/// Builder of a [`Runner`], created with [`Runner::builder`]
+#[derive(Clone, Default)]
+pub struct RunnerBuilder {
+    config: Config,
+    observers: Vec<Arc<dyn RunnerObserver>>,
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    forward_stdin: bool,
+    detached: bool,
+    output: Option<OutputHandler>,
+    dry_run: bool,
+}
+
+impl Runner {
+    /// Start building a runner with the default configuration
+    pub fn builder() -> RunnerBuilder {
+        RunnerBuilder::default()
+    }
+}
+
+impl RunnerBuilder {
+    /// Use a configuration, replacing the one built so far
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Heading level of task sections
+    pub fn heading_level(mut self, level: impl Into<HeadingLevel>) -> Self {
+        self.config.heading_level = level.into();
+        self
+    }
+
+    /// Runtime of a language, replacing the configured one
+    pub fn runtime(mut self, lang: impl Into<String>, runtime: RuntimeConfig) -> Self {
+        self.config.runtimes.insert(lang.into(), runtime);
+        self
+    }
+
+    /// Add an observer notified as sections and code blocks run
+    pub fn observer(mut self, observer: Arc<dyn RunnerObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Set an environment variable for every code block
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Run code blocks in a directory, which is also passed in `MX_ROOT`
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Forward this process' stdin to code blocks
+    pub fn forward_stdin(mut self, forward: bool) -> Self {
+        self.forward_stdin = forward;
+        self
+    }
+
+    /// Keep code blocks away from the terminal, reading an empty stdin
+    pub fn detached_terminal(mut self, detached: bool) -> Self {
+        self.detached = detached;
+        self
+    }
+
+    /// Send code block output to a handler instead of inheriting the terminal
+    pub fn output_handler(mut self, handler: OutputHandler) -> Self {
+        self.output = Some(handler);
+        self
+    }
+
+    /// Go through tasks without running their code blocks
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Create the runner
+    pub fn build(self) -> Runner {
+        let mut runner = Runner::new(self.config)
+            .with_stdin_forwarding(self.forward_stdin)
+            .with_detached_terminal(self.detached)
+            .with_dry_run(self.dry_run);
+        for observer in self.observers {
+            runner = runner.with_observer(observer);
+        }
+        for (key, value) in self.env {
+            runner = runner.with_env(key, value);
+        }
+        if let Some(cwd) = self.cwd {
+            runner = runner.with_root(cwd);
+        }
+        if let Some(output) = self.output {
+            runner = runner.with_output_handler(output);
+        }
+        runner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::error::Result;
+    use crate::extract::SectionExtractor;
+    use crate::runner::{CodeBlock, Section};
+
+    fn task(code: &str) -> Section {
+        Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "bash".to_string(),
+                code: code.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().canonicalize().unwrap();
+
+        let runner = Runner::builder()
+            .heading_level(3)
+            .runtime("bash", RuntimeConfig::Simple("bash".to_string()))
+            .env("MX_BUILDER_GREETING", "hello")
+            .cwd(&dir)
+            .build();
+        assert_eq!(runner.config().heading_level, 3);
+        assert_eq!(runner.root(), Some(dir.as_path()));
+
+        let check = format!(
+            r#"test "$MX_BUILDER_GREETING" = hello && test "$(pwd -P)" = {}"#,
+            dir.display()
+        );
+        runner.execute_section(&task(&check)).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let runner = Runner::builder().dry_run(true).build();
+
+        let report = runner.execute_section(&task("exit 1")).unwrap();
+
+        assert_eq!(report.blocks.len(), 1);
+        assert_eq!(report.blocks[0].skipped.as_deref(), Some("dry run"));
+    }
+
+    #[test]
+    fn test_sections_without_mut() {
+        struct Fixed;
+        impl SectionExtractor for Fixed {
+            fn extract(&mut self, _markdown: &str, _level: u8) -> Result<Vec<Section>> {
+                Ok(vec![task("make")])
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("tasks.md");
+        fs::write(&path, "## Build\n").unwrap();
+        let runner = Runner::builder().build().with_extractor(Fixed);
+
+        let sections = runner.sections(&path).unwrap();
+        assert_eq!(sections[0].title, "Build");
+        assert_eq!(runner.sections(&path).unwrap().len(), 1);
+    }
+}
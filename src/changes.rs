@@ -0,0 +1,182 @@
+//! Tasks affected by the files changed since a git revision
+//!
+//! A task lists the files it works on as glob patterns in its `sources` metadata, relative to
+//! the file declaring it:
+//!
+//! ```markdown
+//! ## Build
+//!
+//! | sources | src/**/*.rs, Cargo.toml |
+//! |---------|-------------------------|
+//! ```
+//!
+//! `mx run --since origin/main` then runs the tasks whose sources changed since the current
+//! branch left `origin/main`, committed or not, and the tasks that depend on them.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use glob::{MatchOptions, Pattern};
+
+use crate::error::{Error, Result};
+use crate::files::canonical;
+use crate::runner::{Runner, Section};
+
+/// Files changed in the repository of a directory since it branched off a revision: the
+/// differences of the working tree to their merge base, and untracked files
+///
+/// Paths are absolute, sorted and free of duplicates.
+pub fn changed_files(dir: &Path, since: &str) -> Result<Vec<PathBuf>> {
+    if since.starts_with('-') {
+        return Err(Error::Git(format!("'{}' is not a revision", since)));
+    }
+    let root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim_end());
+    let base = git(dir, &["merge-base", since, "HEAD"])?;
+    let diff = git(dir, &["diff", "--name-only", "-z", base.trim_end()])?;
+    let untracked = git(
+        dir,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+        ],
+    )?;
+
+    let mut changed: Vec<PathBuf> = [diff, untracked]
+        .iter()
+        .flat_map(|paths| paths.split('\0'))
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(path))
+        .collect();
+    changed.sort();
+    changed.dedup();
+    Ok(changed)
+}
+
+/// Output of a git command run in a directory
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| Error::Git(format!("failed to run git: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Git(stderr.trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether a changed file matches one of the `sources` patterns of a task declared in a file
+///
+/// `*` stays within a directory, while `**` matches any number of them.
+pub fn sources_changed(
+    section: &Section,
+    markdown_path: &Path,
+    changed: &[PathBuf],
+) -> Result<bool> {
+    let sources = section.sources();
+    if sources.is_empty() {
+        return Ok(false);
+    }
+    let dir = canonical(std::path::absolute(markdown_path)?);
+    let dir = dir.parent().unwrap_or(Path::new("/"));
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::default()
+    };
+
+    for source in sources {
+        let pattern = normalize(&dir.join(&source));
+        let pattern = Pattern::new(&pattern.to_string_lossy()).map_err(|e| {
+            Error::Config(format!(
+                "Invalid sources pattern '{}' of task '{}': {}",
+                source, section.title, e.msg
+            ))
+        })?;
+        if changed
+            .iter()
+            .any(|path| pattern.matches_path_with(path, options))
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The tasks whose sources changed, or that depend on a task whose sources changed, in the
+/// order given
+pub fn select_changed(
+    runner: &Runner,
+    tasks: Vec<(PathBuf, Section)>,
+    changed: &[PathBuf],
+) -> Result<Vec<(PathBuf, Section)>> {
+    let mut selected = Vec::new();
+    for (path, section) in tasks {
+        let mut affected = false;
+        for (file, task) in runner.resolve_dependencies(&path, &section)? {
+            if sources_changed(&task, &file, changed)? {
+                affected = true;
+                break;
+            }
+        }
+        if affected {
+            selected.push((path, section));
+        }
+    }
+    Ok(selected)
+}
+
+/// A path with `.` and `..` resolved without looking at the file system, so patterns such as
+/// `../shared/**` match the paths git reports
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_sources_changed() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = canonical(temp.path().to_path_buf());
+        fs::create_dir_all(dir.join("api")).unwrap();
+        let readme = dir.join("api").join("README.md");
+        fs::write(&readme, "").unwrap();
+        let task = |sources: &str| Section {
+            title: "Build".to_string(),
+            metadata: BTreeMap::from([("sources".to_string(), sources.to_string())]),
+            ..Default::default()
+        };
+        let changed = [dir.join("api/src/main.rs"), dir.join("shared/lib.rs")];
+
+        assert!(sources_changed(&task("src/**/*.rs"), &readme, &changed).unwrap());
+        assert!(sources_changed(&task("Cargo.toml, ../shared/*.rs"), &readme, &changed).unwrap());
+        assert!(sources_changed(&task("src/**"), &readme, &changed).unwrap());
+        assert!(!sources_changed(&task("src/*.md"), &readme, &changed).unwrap());
+        assert!(!sources_changed(&task("*.rs"), &readme, &changed).unwrap());
+        assert!(!sources_changed(&Section::default(), &readme, &changed).unwrap());
+
+        let err = sources_changed(&task("src/[main"), &readme, &changed).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Invalid sources pattern 'src/[main'")
+        );
+    }
+}
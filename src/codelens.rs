@@ -0,0 +1,283 @@
+//! Code lenses for editor integrations
+//!
+//! `mx list --format codelens` prints a JSON array of [LSP code lenses] for the tasks of the
+//! given files: one on each task heading running the task, and one on each runnable code
+//! block running just that block. Positions are 0-based lines and UTF-16 columns, as in the
+//! Language Server Protocol, so an editor extension can pass the lenses on as they are and
+//! run the `argv` of a lens when it is clicked:
+//!
+//! ```json
+//! {
+//!   "range": {"start": {"line": 4, "character": 0}, "end": {"line": 4, "character": 8}},
+//!   "command": {
+//!     "title": "▶ Run task",
+//!     "command": "mx.runTask",
+//!     "arguments": ["/repo/README.md", "Build"]
+//!   },
+//!   "data": {
+//!     "file": "/repo/README.md",
+//!     "task": "Build",
+//!     "argv": ["mx", "run", "Build", "--file", "/repo/README.md"]
+//!   }
+//! }
+//! ```
+//!
+//! [LSP code lenses]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_codeLens
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::condition;
+use crate::config::Config;
+use crate::runner::Section;
+
+/// Editor command of the lens on a task heading, with the file and task as arguments
+pub const RUN_TASK_COMMAND: &str = "mx.runTask";
+
+/// Editor command of the lens on a code block, with the file, task and block index as
+/// arguments
+pub const RUN_BLOCK_COMMAND: &str = "mx.runBlock";
+
+/// A position in a document: 0-based line and UTF-16 column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A range in a document, the end being exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// The editor command a lens runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Command {
+    /// Text shown in the editor
+    pub title: String,
+    /// [`RUN_TASK_COMMAND`] or [`RUN_BLOCK_COMMAND`]
+    pub command: String,
+    pub arguments: Vec<serde_json::Value>,
+}
+
+/// What a lens runs, for extensions that do not implement the commands themselves
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LensData {
+    /// Markdown file of the task
+    pub file: PathBuf,
+    /// Title of the task
+    pub task: String,
+    /// Index of the code block, for a lens on a block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<usize>,
+    /// `mx` command line running the task or block
+    pub argv: Vec<String>,
+}
+
+/// A code lens on a task heading or code block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CodeLens {
+    pub range: Range,
+    pub command: Command,
+    pub data: LensData,
+}
+
+/// Converts byte offsets of a document into [`Position`]s
+pub struct LineIndex<'a> {
+    source: &'a str,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Position of a byte offset, clamped to the document
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self
+            .source
+            .get(start..offset)
+            .map_or(0, |text| text.encode_utf16().count());
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    /// Range of a byte range, without the line break it may end with
+    pub fn range(&self, span: &std::ops::Range<usize>) -> Range {
+        let end = span.end.min(self.source.len());
+        let text = self.source.get(span.start..end).unwrap_or_default();
+        let end = span.start + text.trim_end_matches(['\n', '\r']).len();
+        Range {
+            start: self.position(span.start),
+            end: self.position(end),
+        }
+    }
+
+    /// Empty range at the start of a 1-based line, for items whose span is unknown
+    fn line_start(line: usize) -> Range {
+        let position = Position {
+            line: line.saturating_sub(1) as u32,
+            character: 0,
+        };
+        Range {
+            start: position,
+            end: position,
+        }
+    }
+}
+
+/// Code lenses of the tasks of a Markdown file
+///
+/// Blocks that never run, because of their language or a `skip` attribute, get no lens, nor
+/// do tasks and blocks whose position is unknown.
+pub fn code_lenses(
+    config: &Config,
+    file: &Path,
+    markdown: &str,
+    sections: &[Section],
+) -> Vec<CodeLens> {
+    let index = LineIndex::new(markdown);
+    let range = |line: Option<usize>, span: &Option<std::ops::Range<usize>>| match (span, line) {
+        (Some(span), _) => Some(index.range(span)),
+        (None, Some(line)) => Some(LineIndex::line_start(line)),
+        (None, None) => None,
+    };
+    let file_arg = file.display().to_string();
+    let run_argv = |task: &str| -> Vec<String> {
+        vec![
+            "mx".to_string(),
+            "run".to_string(),
+            task.to_string(),
+            "--file".to_string(),
+            file_arg.clone(),
+        ]
+    };
+
+    let mut lenses = Vec::new();
+    for section in sections {
+        if let Some(range) = range(section.line, &section.span) {
+            lenses.push(CodeLens {
+                range,
+                command: Command {
+                    title: "▶ Run task".to_string(),
+                    command: RUN_TASK_COMMAND.to_string(),
+                    arguments: vec![file_arg.clone().into(), section.title.clone().into()],
+                },
+                data: LensData {
+                    file: file.to_path_buf(),
+                    task: section.title.clone(),
+                    block: None,
+                    argv: run_argv(&section.title),
+                },
+            });
+        }
+
+        for (block, code) in section.codes.iter().enumerate() {
+            if config.ignores_language(&code.lang) || condition::marked_skip(code.meta.as_deref()) {
+                continue;
+            }
+            let Some(range) = range(code.line, &code.span) else {
+                continue;
+            };
+
+            let mut argv = run_argv(&section.title);
+            argv.extend(["--block".to_string(), block.to_string()]);
+            lenses.push(CodeLens {
+                range,
+                command: Command {
+                    title: "▶ Run block".to_string(),
+                    command: RUN_BLOCK_COMMAND.to_string(),
+                    arguments: vec![
+                        file_arg.clone().into(),
+                        section.title.clone().into(),
+                        block.into(),
+                    ],
+                },
+                data: LensData {
+                    file: file.to_path_buf(),
+                    task: section.title.clone(),
+                    block: Some(block),
+                    argv,
+                },
+            });
+        }
+    }
+    lenses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CodeBlock;
+
+    fn pos(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_line_index() {
+        let source = "# Tâsk 🚀\nline two\r\n";
+        let index = LineIndex::new(source);
+
+        assert_eq!(index.position(0), pos(0, 0));
+        // "# Tâsk " is 7 UTF-16 units, the rocket is 2 more
+        assert_eq!(index.position("# Tâsk 🚀".len()), pos(0, 9));
+        assert_eq!(
+            index.range(&(source.find("line").unwrap()..source.len())),
+            Range {
+                start: pos(1, 0),
+                end: pos(1, 8),
+            }
+        );
+    }
+
+    #[test]
+    fn test_code_lenses() {
+        let markdown = "## Build\n\n```bash\nmake\n```\n\n```text\nexample\n```\n";
+        let block = |lang: &str, start: usize, end: usize| CodeBlock {
+            lang: lang.to_string(),
+            line: Some(markdown[..start].lines().count() + 1),
+            span: Some(start..end),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Build".to_string(),
+            line: Some(1),
+            span: Some(0..9),
+            codes: vec![block("bash", 10, 27), block("text", 28, markdown.len())],
+            ..Default::default()
+        };
+
+        let lenses = code_lenses(
+            &Config::default(),
+            Path::new("README.md"),
+            markdown,
+            &[section],
+        );
+        assert_eq!(lenses.len(), 2);
+        assert_eq!(lenses[0].range.end, pos(0, 8));
+        assert_eq!(lenses[0].command.command, RUN_TASK_COMMAND);
+        assert_eq!(lenses[1].range.start, pos(2, 0));
+        assert_eq!(lenses[1].range.end, pos(4, 3));
+        assert_eq!(
+            lenses[1].data.argv,
+            ["mx", "run", "Build", "--file", "README.md", "--block", "0"]
+        );
+    }
+}
@@ -0,0 +1,387 @@
+//! Runtime command templates
+//!
+//! A runtime command may contain placeholders that are filled in per code block:
+//!
+//! - `{file}`: path of a temporary file containing the code
+//! - `{code}`: the code itself
+//! - `{args}`: the task arguments, as separate arguments when the placeholder stands alone
+//! - `{dir}`: private temporary directory of the block, which also holds `{file}`
+//! - `{out}`: path in `{dir}` for a compiled program, used by the steps of a pipeline
+//!
+//! A template using neither `{file}` nor `{code}` still gets the code the way its execution
+//! mode says: on stdin, or as a file path or the code appended as the last argument.
+//!
+//! The template is split into arguments *before* substitution, like a POSIX shell would split
+//! it but without any expansion, so substituted values are never re-split or interpreted by a
+//! shell: `python3 {file}` stays two arguments whatever the file name is. With `shell = true`
+//! the command runs through `sh -c` instead, and every substituted value is single-quoted;
+//! within quotes of the template, those are closed around the value, so that
+//! `notify "done: {args}"` cannot expand `$(...)` or backticks in the arguments.
+
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// Values substituted into a command template
+#[derive(Debug, Clone, Copy)]
+pub struct Substitutions<'a> {
+    /// Temporary file containing the code, if the template uses `{file}`
+    pub file: Option<&'a Path>,
+    /// Code of the block
+    pub code: &'a str,
+    /// Task arguments
+    pub args: &'a [String],
+    /// Temporary directory of the block, if the template uses `{dir}`
+    pub dir: Option<&'a Path>,
+    /// Output path of a compile step, for pipelines
+    pub out: Option<&'a Path>,
+}
+
+const PLACEHOLDERS: [&str; 5] = ["{file}", "{code}", "{args}", "{dir}", "{out}"];
+
+/// Check whether a command uses any placeholder
+pub fn has_placeholders(command: &str) -> bool {
+    PLACEHOLDERS
+        .iter()
+        .any(|placeholder| command.contains(placeholder))
+}
+
+/// Check whether a command uses the `{file}` placeholder
+pub fn uses_file(command: &str) -> bool {
+    command.contains("{file}")
+}
+
+/// Check whether a command uses the `{dir}` placeholder
+pub fn uses_dir(command: &str) -> bool {
+    command.contains("{dir}")
+}
+
+/// Check whether a command says where the code goes, with `{file}` or `{code}`
+pub fn places_code(command: &str) -> bool {
+    uses_file(command) || command.contains("{code}")
+}
+
+/// Split a command line into arguments like a POSIX shell, without any expansion
+///
+/// Quotes group words, so `docker run -v "$PWD:/work" image` has four arguments, the third
+/// being `$PWD:/work` as written.
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    shell_words::split(command)
+        .map_err(|e| Error::Config(format!("Invalid command '{}': {}", command, e)))
+}
+
+/// Build the argument vector of a command template, split with [`split_command`]
+///
+/// Placeholders are substituted within each argument. A standalone `{args}` expands to one
+/// argument per task argument.
+pub fn build_argv(template: &[String], subs: &Substitutions<'_>) -> Vec<String> {
+    let mut argv = Vec::new();
+
+    for token in template {
+        if token == "{args}" {
+            argv.extend(subs.args.iter().cloned());
+        } else {
+            argv.push(substitute(token, subs, false));
+        }
+    }
+
+    argv
+}
+
+/// Build a `sh -c` script from a command template, quoting every substituted value
+///
+/// Within single or double quotes of the template, `{args}` is a single value of the
+/// arguments joined by spaces.
+pub fn build_shell_script(command: &str, subs: &Substitutions<'_>) -> String {
+    substitute(command, subs, true)
+}
+
+/// Build a `sh -c` script running the steps of a pipeline in order, stopping at the first
+/// one that fails
+///
+/// Each step is split and substituted like a command without `shell`, then quoted, so no
+/// step is interpreted by the shell. The last step replaces the shell with `exec`.
+pub fn build_steps_script(steps: &[String], subs: &Substitutions<'_>) -> Result<String> {
+    let mut commands = Vec::with_capacity(steps.len());
+    for step in steps {
+        let argv = build_argv(&split_command(step)?, subs);
+        let argv: Vec<String> = argv.iter().map(|arg| shell_quote(arg)).collect();
+        commands.push(argv.join(" "));
+    }
+
+    if let Some(last) = commands.last_mut() {
+        last.insert_str(0, "exec ");
+    }
+    Ok(commands.join(" && "))
+}
+
+/// Insert arguments, shell-quoted, after the first word of a shell command line
+pub fn insert_shell_args(command: &str, args: &[String]) -> String {
+    if args.is_empty() {
+        return command.to_string();
+    }
+
+    let command = command.trim_start();
+    let (program, rest) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let args: Vec<String> = args.iter().map(|arg| shell_quote(arg)).collect();
+    format!("{} {} {}", program, args.join(" "), rest)
+        .trim_end()
+        .to_string()
+}
+
+/// Quote a value for a POSIX shell
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes of a shell script open at some point of it
+#[derive(Debug, Clone, Copy, Default)]
+struct ShellQuotes {
+    single: bool,
+    double: bool,
+    escaped: bool,
+}
+
+impl ShellQuotes {
+    /// The quotes open after some more of the script
+    fn advance(mut self, text: &str) -> Self {
+        for c in text.chars() {
+            match c {
+                _ if self.escaped => self.escaped = false,
+                '\'' if !self.double => self.single = !self.single,
+                '"' if !self.single => self.double = !self.double,
+                '\\' if !self.single => self.escaped = true,
+                _ => {}
+            }
+        }
+        self
+    }
+
+    /// Quote a value where these quotes are open, closing and reopening them around it
+    fn quote(self, value: &str) -> String {
+        if self.single {
+            format!("'{}'", shell_quote(value))
+        } else if self.double {
+            format!("\"{}\"", shell_quote(value))
+        } else {
+            shell_quote(value)
+        }
+    }
+}
+
+/// Replace placeholders in a single piece of text, in one pass so values are never rescanned
+///
+/// With `shell`, values are quoted for the quotes of the text around them.
+fn substitute(text: &str, subs: &Substitutions<'_>, shell: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut quotes = ShellQuotes::default();
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        quotes = quotes.advance(&rest[..start]);
+        let candidate = &rest[start..];
+
+        let quote = |value: &str| match shell {
+            true => quotes.quote(value),
+            false => value.to_string(),
+        };
+        let placeholder = PLACEHOLDERS
+            .into_iter()
+            .find(|placeholder| candidate.starts_with(placeholder));
+        let replacement = match placeholder {
+            Some("{file}") => subs.file.map(|file| quote(&file.to_string_lossy())),
+            Some("{code}") => Some(quote(subs.code)),
+            Some("{dir}") => subs.dir.map(|dir| quote(&dir.to_string_lossy())),
+            Some("{out}") => subs.out.map(|out| quote(&out.to_string_lossy())),
+            Some(_) if shell && (quotes.single || quotes.double) => {
+                Some(quote(&subs.args.join(" ")))
+            }
+            Some(_) => Some(
+                subs.args
+                    .iter()
+                    .map(|arg| quote(arg))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            None => None,
+        };
+
+        match (placeholder, replacement) {
+            (Some(placeholder), Some(replacement)) => {
+                result.push_str(&replacement);
+                quotes.escaped = false;
+                rest = &candidate[placeholder.len()..];
+            }
+            _ => {
+                result.push('{');
+                quotes = quotes.advance("{");
+                rest = &candidate[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    const HOSTILE: &str = "x'; rm -rf ~; echo '$(whoami) `id` \"q\" {code}";
+
+    fn subs<'a>(file: Option<&'a Path>, code: &'a str, args: &'a [String]) -> Substitutions<'a> {
+        Substitutions {
+            file,
+            code,
+            args,
+            dir: None,
+            out: None,
+        }
+    }
+
+    #[test]
+    fn test_build_argv_keeps_values_whole() {
+        let file = Path::new("/tmp/my file; rm -rf ~.py");
+        let args = vec!["a b".to_string(), "$HOME".to_string()];
+
+        let template = split_command("python3 -X utf8 {file} --code={code} {args}").unwrap();
+        let argv = build_argv(&template, &subs(Some(file), HOSTILE, &args));
+
+        assert_eq!(
+            argv,
+            vec![
+                "python3".to_string(),
+                "-X".to_string(),
+                "utf8".to_string(),
+                "/tmp/my file; rm -rf ~.py".to_string(),
+                format!("--code={}", HOSTILE),
+                "a b".to_string(),
+                "$HOME".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"docker run --rm -v "$PWD:/work" python:3.12 'python' -c"#).unwrap(),
+            [
+                "docker",
+                "run",
+                "--rm",
+                "-v",
+                "$PWD:/work",
+                "python:3.12",
+                "python",
+                "-c"
+            ]
+        );
+        assert_eq!(
+            split_command(r#"deno run "--allow-read={file}""#).unwrap(),
+            ["deno", "run", "--allow-read={file}"]
+        );
+        assert!(split_command("python3 'unterminated").is_err());
+    }
+
+    #[test]
+    fn test_insert_shell_args() {
+        let args = vec!["-u".to_string(), "it's".to_string()];
+        assert_eq!(
+            insert_shell_args("python3 '/tmp/main.py' | tee log", &args),
+            "python3 '-u' 'it'\\''s' '/tmp/main.py' | tee log"
+        );
+        assert_eq!(insert_shell_args("node", &args[..1]), "node '-u'");
+        assert_eq!(insert_shell_args("node", &[]), "node");
+    }
+
+    #[test]
+    fn test_substituted_values_are_not_rescanned() {
+        let template = split_command("echo {code}").unwrap();
+        let argv = build_argv(&template, &subs(None, "{args}", &["x".to_string()]));
+        assert_eq!(argv, vec!["echo".to_string(), "{args}".to_string()]);
+    }
+
+    #[test]
+    fn test_dir_placeholder() {
+        let dir = Path::new("/tmp/mx_1");
+        let subs = Substitutions {
+            dir: Some(dir),
+            ..subs(None, "", &[])
+        };
+        let template = split_command("cargo run --manifest-path {dir}/Cargo.toml").unwrap();
+
+        assert_eq!(
+            build_argv(&template, &subs),
+            ["cargo", "run", "--manifest-path", "/tmp/mx_1/Cargo.toml"]
+        );
+        assert!(uses_dir(&template.join(" ")));
+        assert!(!places_code("cd {dir} && make"));
+        assert!(places_code("psql -c {code}"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_steps_script() {
+        let dir = Path::new("/tmp/mx 1");
+        let (file, out) = (dir.join("main.c"), dir.join("main"));
+        let args = vec!["it's".to_string()];
+        let subs = Substitutions {
+            dir: Some(dir),
+            out: Some(&out),
+            ..subs(Some(&file), "", &args)
+        };
+        let steps = ["cc {file} -o {out}".to_string(), "{out} {args}".to_string()];
+
+        assert_eq!(
+            build_steps_script(&steps, &subs).unwrap(),
+            "'cc' '/tmp/mx 1/main.c' '-o' '/tmp/mx 1/main' && exec '/tmp/mx 1/main' 'it'\\''s'"
+        );
+
+        let steps = ["sh -c 'exit 3'".to_string(), "echo unreachable".to_string()];
+        let script = build_steps_script(&steps, &subs).unwrap();
+        let output = Command::new("sh").arg("-c").arg(&script).output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(output.stdout.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_script_quotes_hostile_values() {
+        let args = vec!["it's".to_string(), "; echo pwned".to_string()];
+        let script = build_shell_script("printf '%s|' {code} {args}", &subs(None, HOSTILE, &args));
+
+        let output = Command::new("sh").arg("-c").arg(&script).output().unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!("{}|it's|; echo pwned|", HOSTILE)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_script_quotes_values_inside_template_quotes() {
+        let args = vec!["$(echo pwned)".to_string(), "`id`".to_string()];
+        let template = r#"printf '%s|' "code: {code}" 'file: {file}' "args: {args}" \"{args}"#;
+        let file = Path::new("/tmp/$(echo pwned) 'x'.sh");
+        let script = build_shell_script(template, &subs(Some(file), HOSTILE, &args));
+
+        let output = Command::new("sh").arg("-c").arg(&script).output().unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!(
+                "code: {}|file: {}|args: $(echo pwned) `id`|\"$(echo pwned)|`id`|",
+                HOSTILE,
+                file.display()
+            )
+        );
+    }
+}
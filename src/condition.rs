@@ -0,0 +1,124 @@
+//! Conditional execution of tasks and code blocks
+//!
+//! Code fences and headings can carry guard attributes, e.g. ```` ```bash os=linux ```` or
+//! `## Install {os=macos if-env=CI}`. A task or block whose guards do not hold is skipped.
+//!
+//! | Attribute             | Runs when                                            |
+//! |-----------------------|------------------------------------------------------|
+//! | `os=linux,macos`      | the current OS (or OS family, e.g. `unix`) is listed |
+//! | `if-env=CI`           | the environment variable is set and not empty        |
+//! | `unless-env=CI`       | the environment variable is unset or empty           |
+//! | `if-exists=path`      | the path exists (relative to the working directory)  |
+//! | `unless-exists=path`  | the path does not exist                              |
+//!
+//! `skip` (or `no_run`, `no-run`, `ignore`) never runs, e.g. for an illustrative block
+//! inside a task.
+
+use std::path::Path;
+
+use crate::source::{parse_attributes, split_list};
+
+/// Attributes marking a block or task that never runs
+const SKIP_ATTRIBUTES: [&str; 4] = ["skip", "no_run", "no-run", "ignore"];
+
+/// Evaluate the guard attributes in a fence info string or heading attribute list
+///
+/// Returns the reason for skipping if a guard does not hold; other attributes are ignored.
+pub fn skip_reason(meta: Option<&str>) -> Option<String> {
+    parse_attributes(meta?)
+        .into_iter()
+        .find_map(|(key, value)| check(&key, &value))
+}
+
+/// Check whether a fence info string or heading attribute list marks something that never
+/// runs, whatever its guards
+pub fn marked_skip(meta: Option<&str>) -> bool {
+    meta.is_some_and(|meta| {
+        parse_attributes(meta)
+            .iter()
+            .any(|(key, _)| SKIP_ATTRIBUTES.contains(&key.as_str()))
+    })
+}
+
+/// Check a single attribute, returning a skip reason if it is a guard that does not hold
+fn check(key: &str, value: &str) -> Option<String> {
+    if SKIP_ATTRIBUTES.contains(&key) {
+        return Some(match value {
+            "" => format!("marked {}", key),
+            reason => format!("marked {}: {}", key, reason),
+        });
+    }
+
+    let holds = match key {
+        "os" => split_list(value).iter().any(|os| matches_os(os)),
+        "if-env" => env_is_set(value),
+        "unless-env" => !env_is_set(value),
+        "if-exists" => Path::new(value).exists(),
+        "unless-exists" => !Path::new(value).exists(),
+        _ => return None,
+    };
+
+    if holds {
+        return None;
+    }
+
+    let detail = match key {
+        "os" => format!("running on {}", std::env::consts::OS),
+        "if-env" => "not set".to_string(),
+        "unless-env" => "set".to_string(),
+        "if-exists" => "does not exist".to_string(),
+        _ => "exists".to_string(),
+    };
+    Some(format!("{}={} ({})", key, value, detail))
+}
+
+fn matches_os(os: &str) -> bool {
+    let os = match os {
+        "darwin" | "mac" => "macos",
+        os => os,
+    };
+    os == std::env::consts::OS || os == std::env::consts::FAMILY
+}
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_reason() {
+        let current = std::env::consts::OS;
+
+        assert_eq!(skip_reason(None), None);
+        assert_eq!(skip_reason(Some("tags=ci")), None);
+        assert_eq!(skip_reason(Some(&format!("os=plan9,{}", current))), None);
+        assert_eq!(
+            skip_reason(Some("os=plan9")),
+            Some(format!("os=plan9 (running on {})", current))
+        );
+
+        assert_eq!(skip_reason(Some("if-exists=Cargo.toml")), None);
+        assert_eq!(
+            skip_reason(Some("unless-exists=Cargo.toml")),
+            Some("unless-exists=Cargo.toml (exists)".to_string())
+        );
+        assert_eq!(
+            skip_reason(Some("if-env=MX_TEST_SURELY_UNSET_VARIABLE")),
+            Some("if-env=MX_TEST_SURELY_UNSET_VARIABLE (not set)".to_string())
+        );
+
+        assert_eq!(
+            skip_reason(Some("no_run")),
+            Some("marked no_run".to_string())
+        );
+        assert_eq!(
+            skip_reason(Some("tags=ci skip=\"needs a VPN\"")),
+            Some("marked skip: needs a VPN".to_string())
+        );
+        assert!(marked_skip(Some("ignore os=linux")));
+        assert!(!marked_skip(Some("os=plan9")));
+    }
+}
@@ -1,18 +1,36 @@
 //! Configuration for mx task runner
 
+use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
+use crate::command;
 use crate::error::{Error, Result};
+use crate::extract::Extractor;
+use crate::namespace::NamespaceConfig;
+use crate::remote::{KubernetesConfig, Remote};
+use crate::runner::{CodeBlock, Section};
+use crate::sandbox::{Sandbox, SandboxConfig};
+use crate::secrets::SecretSource;
+use crate::source::{normalize_title, slugify};
+use crate::sql::{self, SqlConfig};
+use crate::theme::Theme;
+use crate::toolchain::ToolEnv;
+use crate::workspace::WorkspaceConfig;
 
 /// Execution mode for a runtime
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ExecutionMode {
     /// Pass code via stdin
+    #[default]
     Stdin,
     /// Write code to a temporary file and pass it as argument
     File,
@@ -20,9 +38,13 @@ pub enum ExecutionMode {
     Arg,
 }
 
-impl Default for ExecutionMode {
-    fn default() -> Self {
-        Self::Stdin
+impl fmt::Display for ExecutionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionMode::Stdin => write!(f, "stdin"),
+            ExecutionMode::File => write!(f, "file"),
+            ExecutionMode::Arg => write!(f, "arg"),
+        }
     }
 }
 
@@ -42,72 +64,699 @@ impl TryFrom<&str> for ExecutionMode {
     }
 }
 
-/// Runtime configuration that can be either a simple string or a detailed config
+/// Heading levels whose sections are tasks: a single level, a range such as `2..4`, or `any`
+///
+/// With a range, sections at every level in it are extracted; a section's code blocks end at
+/// the first heading of a deeper level in the range, which starts a task of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadingLevel {
+    min: u8,
+    max: u8,
+}
+
+impl HeadingLevel {
+    /// Every heading level
+    pub const ANY: HeadingLevel = HeadingLevel { min: 1, max: 6 };
+
+    /// Levels from `min` to `max` inclusive, each from 1 to 6
+    pub fn range(min: u8, max: u8) -> Result<Self> {
+        if !(1..=6).contains(&min) || !(1..=6).contains(&max) || min > max {
+            return Err(Error::Config(format!(
+                "Invalid heading level range {}..{}, expected levels from 1 to 6 in increasing order",
+                min, max
+            )));
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Shallowest level, used for new sections
+    pub fn min(self) -> u8 {
+        self.min
+    }
+
+    /// Deepest level
+    pub fn max(self) -> u8 {
+        self.max
+    }
+
+    /// The level, if this is a single level rather than a range
+    pub fn single(self) -> Option<u8> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    /// Whether sections at `level` are tasks
+    pub fn contains(self, level: u8) -> bool {
+        self.levels().contains(&level)
+    }
+
+    /// The levels in increasing order
+    pub fn levels(self) -> RangeInclusive<u8> {
+        self.min..=self.max
+    }
+}
+
+impl Default for HeadingLevel {
+    fn default() -> Self {
+        Self::from(default_heading_level())
+    }
+}
+
+impl From<u8> for HeadingLevel {
+    fn from(level: u8) -> Self {
+        Self {
+            min: level,
+            max: level,
+        }
+    }
+}
+
+impl PartialEq<u8> for HeadingLevel {
+    fn eq(&self, level: &u8) -> bool {
+        self.single() == Some(*level)
+    }
+}
+
+impl fmt::Display for HeadingLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.single() {
+            Some(level) => write!(f, "{}", level),
+            None if *self == Self::ANY => f.write_str("any"),
+            None => write!(f, "{}..{}", self.min, self.max),
+        }
+    }
+}
+
+impl FromStr for HeadingLevel {
+    type Err = Error;
+
+    /// Parse `2`, `2..4`, `2-4` or `any`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("any") {
+            return Ok(Self::ANY);
+        }
+
+        let invalid = || {
+            Error::Config(format!(
+                "Invalid heading level '{}', expected a number from 1 to 6, a range like 2..4, or any",
+                s
+            ))
+        };
+        let (min, max) = s
+            .split_once("..")
+            .or_else(|| s.split_once('-'))
+            .unwrap_or((s, s));
+        let min = min.trim().parse().map_err(|_| invalid())?;
+        let max = max.trim().parse().map_err(|_| invalid())?;
+        Self::range(min, max).map_err(|_| invalid())
+    }
+}
+
+impl Serialize for HeadingLevel {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self.single() {
+            Some(level) => serializer.serialize_u8(level),
+            None => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HeadingLevel {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Level(i64),
+            Text(String),
+        }
+
+        let level = match Repr::deserialize(deserializer)? {
+            Repr::Level(level) => level.to_string(),
+            Repr::Text(text) => text,
+        };
+        level.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A small task defined in the configuration, a `[tasks.NAME]` table
+///
+/// Such tasks are listed and run along with the tasks of the Markdown files; a Markdown task
+/// with the same title takes precedence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTask {
+    /// Code to run, e.g. `rm -rf target`
+    pub run: String,
+    /// Language of the code, run by its runtime (default: `sh`)
+    #[serde(default = "default_task_lang")]
+    pub lang: String,
+    /// Description shown in task lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Tags to select the task by, as with `## Title [tag]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+fn default_task_lang() -> String {
+    "sh".to_string()
+}
+
+/// How a task name is matched against section titles, after an exact title or a `#anchor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleMatch {
+    /// Only the whole title (default)
+    #[default]
+    Exact,
+    /// Part of the title, ignoring case, formatting and emoji
+    Contains,
+    /// The anchor of the title, so `build and test` finds `## Build and test!`
+    Slug,
+    /// A regular expression found anywhere in the title, or in the title without formatting
+    /// and emoji, ignoring case
+    Regex,
+}
+
+impl TitleMatch {
+    /// The section whose title matches a task name
+    ///
+    /// Fails if sections with different titles match, or if the name is not a valid regular
+    /// expression in `regex` mode.
+    pub fn find<'a>(&self, sections: &'a [Section], name: &str) -> Result<Option<&'a Section>> {
+        let matches: Vec<&Section> = match self {
+            Self::Exact => Vec::new(),
+            Self::Contains => {
+                let name = normalize_title(name);
+                sections
+                    .iter()
+                    .filter(|section| normalize_title(&section.title).contains(&name))
+                    .collect()
+            }
+            Self::Slug => {
+                let anchor = slugify(name);
+                sections
+                    .iter()
+                    .filter(|section| section.anchor() == anchor)
+                    .collect()
+            }
+            Self::Regex => {
+                let regex = RegexBuilder::new(name)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| {
+                        Error::Config(format!("Invalid task pattern '{}': {}", name, e))
+                    })?;
+                sections
+                    .iter()
+                    .filter(|section| {
+                        regex.is_match(&section.title)
+                            || regex.is_match(&normalize_title(&section.title))
+                    })
+                    .collect()
+            }
+        };
+
+        let mut titles: Vec<String> = Vec::new();
+        for section in &matches {
+            if !titles.contains(&section.title) {
+                titles.push(section.title.clone());
+            }
+        }
+        if titles.len() > 1 {
+            return Err(Error::AmbiguousTask {
+                task: name.to_string(),
+                files: Vec::new(),
+                titles,
+            });
+        }
+        Ok(matches.first().copied())
+    }
+}
+
+/// Runtime configuration: a command string, an argument vector, a detailed config, or the
+/// steps of a compile-then-run pipeline
+///
+/// Command strings are split into arguments like a POSIX shell would, so quoted segments such
+/// as `-v "$PWD:/work"` stay one argument; no expansion happens.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(untagged)]
 pub enum RuntimeConfig {
     /// Simple command string (execution_mode defaults to stdin)
     Simple(String),
+    /// Program and arguments, taken as they are (execution_mode defaults to stdin)
+    Argv(Vec<String>),
     /// Detailed configuration with command and execution mode
     Detailed {
         command: String,
         #[serde(default)]
         execution_mode: ExecutionMode,
+        /// Arguments passed to the command before the code or code file, e.g. `["-u"]`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        /// Environment variables set for the command
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        /// Run the command through `sh -c`, with placeholder values shell-quoted
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        shell: bool,
+        /// Confine code blocks, e.g. `sandbox = "bwrap"`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sandbox: Option<Sandbox>,
+        /// Run code blocks on an SSH host or a `k8s:` target, e.g. `remote = "admin@db1"`
+        #[serde(default, alias = "target", skip_serializing_if = "Option::is_none")]
+        remote: Option<String>,
+        /// Run code blocks in a pseudo-terminal, for programs that need one
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pty: bool,
+    },
+    /// Commands run one after another in the block's temporary directory, e.g.
+    /// `["gcc {file} -o {out}", "{out} {args}"]`; the code is always passed in `{file}`
+    Steps {
+        steps: Vec<String>,
+        /// Environment variables set for every step
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        /// Confine code blocks, e.g. `sandbox = "bwrap"`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sandbox: Option<Sandbox>,
+        /// Run code blocks on an SSH host or a `k8s:` target
+        #[serde(default, alias = "target", skip_serializing_if = "Option::is_none")]
+        remote: Option<String>,
     },
 }
 
 impl RuntimeConfig {
-    /// Get the command string from the runtime config
-    pub fn command(&self) -> &str {
+    /// Get the command string from the runtime config, quoting an argument vector
+    pub fn command(&self) -> Cow<'_, str> {
+        match self {
+            RuntimeConfig::Simple(cmd) => Cow::Borrowed(cmd),
+            RuntimeConfig::Argv(argv) => Cow::Owned(shell_words::join(argv)),
+            RuntimeConfig::Detailed { command, .. } => Cow::Borrowed(command),
+            RuntimeConfig::Steps { steps, .. } => Cow::Owned(steps.join(" && ")),
+        }
+    }
+
+    /// Get the program and arguments of the command, or of the first step
+    pub fn command_argv(&self) -> Result<Vec<String>> {
         match self {
-            RuntimeConfig::Simple(cmd) => cmd,
-            RuntimeConfig::Detailed { command, .. } => command,
+            RuntimeConfig::Argv(argv) => Ok(argv.clone()),
+            RuntimeConfig::Simple(command) | RuntimeConfig::Detailed { command, .. } => {
+                command::split_command(command)
+            }
+            RuntimeConfig::Steps { steps, .. } => match steps.first() {
+                Some(step) => command::split_command(step),
+                None => Ok(Vec::new()),
+            },
+        }
+    }
+
+    /// Steps of a compile-then-run pipeline, empty for a single command
+    pub fn steps(&self) -> &[String] {
+        match self {
+            RuntimeConfig::Steps { steps, .. } => steps,
+            _ => &[],
         }
     }
 
     /// Get the execution mode from the runtime config
     pub fn execution_mode(&self) -> ExecutionMode {
         match self {
-            RuntimeConfig::Simple(_) => ExecutionMode::default(),
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) => ExecutionMode::default(),
             RuntimeConfig::Detailed { execution_mode, .. } => execution_mode.clone(),
+            RuntimeConfig::Steps { .. } => ExecutionMode::File,
+        }
+    }
+
+    /// Arguments passed to the command before the code or code file
+    pub fn args(&self) -> &[String] {
+        match self {
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) | RuntimeConfig::Steps { .. } => &[],
+            RuntimeConfig::Detailed { args, .. } => args,
+        }
+    }
+
+    /// Environment variables set for the command
+    pub fn env(&self) -> impl Iterator<Item = (&String, &String)> {
+        let env = match self {
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) => None,
+            RuntimeConfig::Detailed { env, .. } | RuntimeConfig::Steps { env, .. } => Some(env),
+        };
+        env.into_iter().flatten()
+    }
+
+    /// Whether the command runs through `sh -c`
+    pub fn shell(&self) -> bool {
+        match self {
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) | RuntimeConfig::Steps { .. } => {
+                false
+            }
+            RuntimeConfig::Detailed { shell, .. } => *shell,
+        }
+    }
+
+    /// Sandbox the code blocks run in, if any
+    pub fn sandbox(&self) -> Option<Sandbox> {
+        match self {
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) => None,
+            RuntimeConfig::Detailed { sandbox, .. } | RuntimeConfig::Steps { sandbox, .. } => {
+                *sandbox
+            }
+        }
+    }
+
+    /// Whether code blocks run in a pseudo-terminal
+    pub fn pty(&self) -> bool {
+        match self {
+            RuntimeConfig::Detailed { pty, .. } => *pty,
+            _ => false,
+        }
+    }
+
+    /// Remote target the code blocks run on, if any
+    pub fn remote(&self) -> Option<&str> {
+        match self {
+            RuntimeConfig::Simple(_) | RuntimeConfig::Argv(_) => None,
+            RuntimeConfig::Detailed { remote, .. } | RuntimeConfig::Steps { remote, .. } => {
+                remote.as_deref()
+            }
+        }
+    }
+}
+
+/// Names of the project configuration file, in order of precedence
+pub const PROJECT_CONFIG_FILES: &[&str] = &["mx.toml", ".mx.toml"];
+
+/// Environment variable overriding `heading_level`
+pub const HEADING_LEVEL_ENV: &str = "MX_HEADING_LEVEL";
+
+/// Prefix of environment variables overriding a runtime's command, e.g. `MX_RUNTIME_PYTHON`
+pub const RUNTIME_ENV_PREFIX: &str = "MX_RUNTIME_";
+
+/// Sources of configuration, merged in order: built-in defaults, the user configuration,
+/// the project configuration, the frontmatter of task files, then environment variables
+///
+/// Each layer only needs to set what it changes; tables such as `runtimes` are merged key by
+/// key, so a user configuration can set `python = "python3.12"` without losing the other
+/// default runtimes. Command line flags are applied on top by the caller.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLayers {
+    /// Personal configuration, e.g. `~/.config/mx/config.toml`; skipped if missing
+    pub user: Option<PathBuf>,
+    /// Project configuration, e.g. the nearest `mx.toml`; must exist if set
+    pub project: Option<PathBuf>,
+    /// Markdown files whose `[mx]` TOML frontmatter is applied, in order
+    pub frontmatter: Vec<PathBuf>,
+    /// Apply [`HEADING_LEVEL_ENV`] and [`RUNTIME_ENV_PREFIX`] environment variables
+    pub env: bool,
+}
+
+impl ConfigLayers {
+    /// The user configuration and the nearest project configuration in `dir` or its ancestors
+    pub fn discover(dir: &Path) -> Self {
+        Self {
+            user: user_config_path(),
+            project: find_project_config(dir),
+            frontmatter: Vec::new(),
+            env: true,
+        }
+    }
+}
+
+/// Where a configuration value came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "layer", rename_all = "lowercase")]
+pub enum ConfigSource {
+    /// Built-in default
+    Default,
+    /// The user configuration file
+    User { path: PathBuf },
+    /// The project configuration file
+    Project { path: PathBuf },
+    /// The `[mx]` frontmatter of a task file
+    Frontmatter { path: PathBuf },
+    /// An environment variable
+    Env { var: String },
+    /// A command line flag
+    Cli { flag: String },
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::User { path } => write!(f, "user config {}", path.display()),
+            ConfigSource::Project { path } => write!(f, "project config {}", path.display()),
+            ConfigSource::Frontmatter { path } => write!(f, "frontmatter of {}", path.display()),
+            ConfigSource::Env { var } => write!(f, "environment {}", var),
+            ConfigSource::Cli { flag } => write!(f, "command line {}", flag),
         }
     }
 }
 
+/// Source of every configuration value, keyed by its dotted path (e.g. `runtimes.python`)
+///
+/// Only leaf values are recorded: a detailed runtime has entries such as
+/// `runtimes.go.command`, an array such as `on_cancel` a single entry.
+pub type Provenance = BTreeMap<String, ConfigSource>;
+
 /// Configuration for mx task runner
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    /// Runtime mappings: language -> command or detailed config
+    /// Runtime mappings: language -> command or detailed config, sorted by language
     #[serde(default = "default_runtimes")]
-    pub runtimes: HashMap<String, RuntimeConfig>,
+    pub runtimes: BTreeMap<String, RuntimeConfig>,
+
+    /// Heading level of task sections: a level, a range such as `2..4`, or `any` (default: 2)
+    #[serde(default)]
+    pub heading_level: HeadingLevel,
+
+    /// Skip sections that fail to extract instead of failing the whole document
+    #[serde(default)]
+    pub resilient: bool,
+
+    /// Backend extracting sections: `mq` (default) or `cmark`
+    #[serde(default)]
+    pub extractor: Extractor,
+
+    /// mq module extracting sections instead of the bundled `sections.mq`: the path of a `.mq`
+    /// file, or the module itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sections_query: Option<String>,
+
+    /// How task names match section titles: `exact` (default), `contains`, `slug` or `regex`
+    #[serde(default, rename = "match")]
+    pub title_match: TitleMatch,
+
+    /// Maximum nesting depth of `extends` chains (default: 8)
+    #[serde(default = "default_max_extends_depth")]
+    pub max_extends_depth: usize,
+
+    /// Directory to write per-block stdout/stderr log files to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Keep the temporary directories holding generated code files, for debugging
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_temp: bool,
+
+    /// How shell blocks that read their own stdin get their code: `file` (default), `arg`, or
+    /// `stdin` to leave it on stdin, see [`crate::shell`]
+    #[serde(default = "default_shell_stdin")]
+    pub shell_stdin: ExecutionMode,
+
+    /// Languages of illustrative blocks that are never executed, e.g. `text` or `console`
+    #[serde(default = "default_ignore_languages")]
+    pub ignore_languages: Vec<String>,
+
+    /// Shell commands run when a task is interrupted by a signal, with `MX_TASK` and
+    /// `MX_BLOCK` set to the interrupted task and block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_cancel: Vec<String>,
 
-    /// Heading level for sections (default: 2)
-    #[serde(default = "default_heading_level")]
-    pub heading_level: u8,
+    /// Nix dev shell code blocks run inside, e.g. `nix develop` or `nix shell nixpkgs#python3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nix_shell: Option<String>,
+
+    /// Version manager resolving the programs of code blocks: `mise`, `asdf` or `direnv`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_env: Option<ToolEnv>,
+
+    /// Namespace and context of `k8s:` remote targets
+    #[serde(default, skip_serializing_if = "KubernetesConfig::is_empty")]
+    pub kubernetes: KubernetesConfig,
+
+    /// Connection profiles of SQL code blocks
+    #[serde(default, skip_serializing_if = "SqlConfig::is_empty")]
+    pub sql: SqlConfig,
+
+    /// Secrets that runtime `env` values refer to as `secret:NAME`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secrets: BTreeMap<String, SecretSource>,
+
+    /// Environment variables whose values are masked in output, e.g. `AWS_SECRET_ACCESS_KEY`
+    /// or `*_TOKEN`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sensitive: Vec<String>,
+
+    /// Tasks allowed to loosen the sandbox with `{allow-net}` or `{sandbox=off}`
+    #[serde(default, skip_serializing_if = "SandboxConfig::is_empty")]
+    pub sandbox: SandboxConfig,
+
+    /// Tasks defined in the configuration, by title
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tasks: BTreeMap<String, ConfigTask>,
+
+    /// Colors of the command line output
+    #[serde(default)]
+    pub theme: Theme,
+
+    /// Packages of a mono-repo whose tasks run as `member:task`
+    #[serde(default, skip_serializing_if = "WorkspaceConfig::is_empty")]
+    pub workspace: WorkspaceConfig,
+
+    /// How qualified task names such as `db:migrate` are written
+    #[serde(default, skip_serializing_if = "NamespaceConfig::is_default")]
+    pub namespaces: NamespaceConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             runtimes: default_runtimes(),
-            heading_level: default_heading_level(),
+            heading_level: HeadingLevel::default(),
+            resilient: false,
+            extractor: Extractor::default(),
+            sections_query: None,
+            title_match: TitleMatch::default(),
+            max_extends_depth: default_max_extends_depth(),
+            log_dir: None,
+            keep_temp: false,
+            shell_stdin: default_shell_stdin(),
+            ignore_languages: default_ignore_languages(),
+            on_cancel: Vec::new(),
+            nix_shell: None,
+            tool_env: None,
+            kubernetes: KubernetesConfig::default(),
+            sql: SqlConfig::default(),
+            secrets: BTreeMap::new(),
+            sensitive: Vec::new(),
+            sandbox: SandboxConfig::default(),
+            tasks: BTreeMap::new(),
+            theme: Theme::default(),
+            workspace: WorkspaceConfig::default(),
+            namespaces: NamespaceConfig::default(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from a TOML file
+    ///
+    /// Files listed in `extends` (a path or an array of paths, relative to the including
+    /// file) are loaded first and deep-merged, with the including file taking precedence.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let content = fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+        let table = load_table(path.as_ref(), &mut Vec::new(), None, &mut Vec::new())?;
+        let config: Config = toml::Value::Table(table).try_into()?;
         Ok(config)
     }
 
+    /// Load configuration by merging layers over the built-in defaults
+    pub fn from_layers(layers: &ConfigLayers) -> Result<Self> {
+        Self::from_layers_with_provenance(layers).map(|(config, _)| config)
+    }
+
+    /// Load configuration like [`Config::from_layers`], recording where each value came from
+    pub fn from_layers_with_provenance(layers: &ConfigLayers) -> Result<(Self, Provenance)> {
+        let mut merged = toml::Table::new();
+        let mut provenance = Provenance::new();
+        if let Ok(toml::Value::Table(defaults)) = toml::Value::try_from(Config::default()) {
+            merge_traced(
+                &mut merged,
+                defaults,
+                "",
+                &ConfigSource::Default,
+                &mut provenance,
+            );
+        }
+
+        if let Some(user) = layers.user.as_deref().filter(|path| path.is_file()) {
+            let source = ConfigSource::User {
+                path: user.to_path_buf(),
+            };
+            let table = load_table(user, &mut Vec::new(), None, &mut Vec::new())?;
+            merge_traced(&mut merged, table, "", &source, &mut provenance);
+        }
+        if let Some(project) = &layers.project {
+            let source = ConfigSource::Project {
+                path: project.clone(),
+            };
+            let table = load_table(project, &mut Vec::new(), None, &mut Vec::new())?;
+            merge_traced(&mut merged, table, "", &source, &mut provenance);
+        }
+        for markdown in &layers.frontmatter {
+            let content = fs::read_to_string(markdown)?;
+            if let Some(table) = frontmatter_config(&content).map_err(|e| {
+                Error::Config(format!(
+                    "Invalid frontmatter in {}: {}",
+                    markdown.display(),
+                    e
+                ))
+            })? {
+                // Otherwise a runbook could allow itself out of the sandbox
+                if table.contains_key("sandbox") {
+                    return Err(Error::Config(format!(
+                        "The frontmatter of {} cannot set `sandbox`; allow tasks in mx.toml",
+                        markdown.display()
+                    )));
+                }
+                let source = ConfigSource::Frontmatter {
+                    path: markdown.clone(),
+                };
+                merge_traced(&mut merged, table, "", &source, &mut provenance);
+            }
+        }
+        if layers.env {
+            let vars = std::env::vars_os().filter_map(|(key, value)| {
+                Some((key.into_string().ok()?, value.into_string().ok()?))
+            });
+            for (var, table) in env_overrides(vars, &merged)? {
+                merge_traced(
+                    &mut merged,
+                    table,
+                    "",
+                    &ConfigSource::Env { var },
+                    &mut provenance,
+                );
+            }
+        }
+
+        Ok((toml::Value::Table(merged).try_into()?, provenance))
+    }
+
     /// Get runtime command for a language
-    pub fn get_runtime(&self, lang: &str) -> Option<&str> {
+    pub fn get_runtime(&self, lang: &str) -> Option<Cow<'_, str>> {
         self.runtimes.get(lang).map(|config| config.command())
     }
 
+    /// Get the runtime of a code block: the client of its SQL profile, or the runtime of its
+    /// language if any
+    pub fn runtime_for(
+        &self,
+        block: &CodeBlock,
+        section: Option<&Section>,
+    ) -> Result<Option<Cow<'_, RuntimeConfig>>> {
+        Ok(match sql::resolve(self, block, section)? {
+            Some(runtime) => Some(Cow::Owned(runtime)),
+            None => self.runtimes.get(&block.lang).map(Cow::Borrowed),
+        })
+    }
+
     /// Get execution mode for a language
     pub fn get_execution_mode(&self, lang: &str) -> ExecutionMode {
         self.runtimes
@@ -116,6 +765,54 @@ impl Config {
             .unwrap_or_default()
     }
 
+    /// Check whether the runtime of a language runs through `sh -c`
+    pub fn get_shell(&self, lang: &str) -> bool {
+        self.runtimes.get(lang).is_some_and(|config| config.shell())
+    }
+
+    /// Get the sandbox of a language's runtime, if any
+    pub fn get_sandbox(&self, lang: &str) -> Option<Sandbox> {
+        self.runtimes.get(lang).and_then(RuntimeConfig::sandbox)
+    }
+
+    /// Get the remote target of a language's runtime, if any
+    pub fn get_remote(&self, lang: &str) -> Option<&str> {
+        self.runtimes.get(lang).and_then(RuntimeConfig::remote)
+    }
+
+    /// Tasks of the `[tasks]` table as sections with a single code block, sorted by title
+    pub fn task_sections(&self) -> Vec<Section> {
+        self.tasks
+            .iter()
+            .map(|(title, task)| Section {
+                title: title.clone(),
+                level: self.heading_level.min(),
+                codes: vec![CodeBlock {
+                    lang: task.lang.clone(),
+                    code: task.run.clone(),
+                    ..Default::default()
+                }],
+                description: task.description.clone(),
+                tags: task.tags.clone(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Check whether blocks of a language are never executed: blocks without a language and
+    /// those listed in `ignore_languages`
+    pub fn ignores_language(&self, lang: &str) -> bool {
+        lang.is_empty() || self.ignore_languages.iter().any(|ignored| ignored == lang)
+    }
+
+    /// Check whether the value of an environment variable is masked in output: whether it
+    /// matches a glob pattern of `sensitive`
+    pub fn is_sensitive(&self, var: &str) -> bool {
+        self.sensitive
+            .iter()
+            .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(var)))
+    }
+
     /// Check if runtime exists for a language
     pub fn has_runtime(&self, lang: &str) -> bool {
         self.runtimes.contains_key(lang)
@@ -145,6 +842,12 @@ impl Config {
                 RuntimeConfig::Detailed {
                     command,
                     execution_mode: mode.clone(),
+                    args: Vec::new(),
+                    env: BTreeMap::new(),
+                    shell: false,
+                    sandbox: None,
+                    remote: None,
+                    pty: false,
                 }
             } else {
                 RuntimeConfig::Simple(command)
@@ -158,60 +861,447 @@ impl Config {
 
     /// Validate that all configured runtimes are available in PATH
     pub fn validate_runtimes(&self) -> Result<()> {
-        for (lang, config) in &self.runtimes {
-            let cmd = config.command();
-            let binary = cmd.split_whitespace().next().unwrap_or(cmd);
-            if which::which(binary).is_err() {
-                return Err(Error::Config(format!(
-                    "Runtime '{}' for language '{}' not found in PATH",
-                    binary, lang
-                )));
-            }
+        for lang in self.runtimes.keys() {
+            self.validate_runtime(lang)?;
         }
         Ok(())
     }
+
+    /// Validate that the runtime configured for a language is available in PATH
+    pub fn validate_runtime(&self, lang: &str) -> Result<()> {
+        let runtime = self
+            .runtimes
+            .get(lang)
+            .ok_or_else(|| Error::RuntimeNotFound(lang.to_string()))?;
+        // A remote runtime only needs ssh or kubectl here
+        let argv = runtime.command_argv()?;
+        let binary = match self.get_remote(lang) {
+            Some(target) => Remote::parse(target, &self.kubernetes).program(),
+            None => argv.first().map(String::as_str).unwrap_or_default(),
+        };
+        if !binary_in_path(binary) {
+            return Err(Error::Config(format!(
+                "Runtime '{}' for language '{}' not found in PATH",
+                binary, lang
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn binary_in_path(binary: &str) -> bool {
+    which::which(binary).is_ok()
+}
+
+/// There is no PATH to search in the browser, so runtimes are assumed to exist
+#[cfg(target_arch = "wasm32")]
+fn binary_in_path(_binary: &str) -> bool {
+    true
+}
+
+/// The user's configuration directory for mx: `$XDG_CONFIG_HOME/mx` or `~/.config/mx` on
+/// unix, `%APPDATA%\mx` on Windows
+pub fn user_config_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+
+    let config_dir = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("mx"))
+}
+
+/// Path of the user configuration file, `config.toml` in [`user_config_dir`]
+pub fn user_config_path() -> Option<PathBuf> {
+    user_config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// Path of the global task file, `tasks.md` in [`user_config_dir`], whose tasks can be run
+/// from any directory with `--global`
+pub fn global_tasks_path() -> Option<PathBuf> {
+    user_config_dir().map(|dir| dir.join("tasks.md"))
+}
+
+/// Find the project configuration in `dir` or the nearest ancestor that has one
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find_map(|dir| {
+        PROJECT_CONFIG_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Configuration from a Markdown file's TOML frontmatter, i.e. its `[mx]` table
+///
+/// ```markdown
+/// +++
+/// [mx]
+/// heading_level = 3
+/// +++
+/// ```
+pub fn frontmatter_config(markdown: &str) -> Result<Option<toml::Table>> {
+    let Some(rest) = markdown
+        .strip_prefix("+++\n")
+        .or_else(|| markdown.strip_prefix("+++\r\n"))
+    else {
+        return Ok(None);
+    };
+    let Some(end) = rest
+        .match_indices("+++")
+        .map(|(index, _)| index)
+        .find(|index| *index == 0 || rest[..*index].ends_with('\n'))
+    else {
+        return Ok(None);
+    };
+
+    let mut frontmatter: toml::Table = toml::from_str(&rest[..end])?;
+    match frontmatter.remove("mx") {
+        Some(toml::Value::Table(table)) => Ok(Some(table)),
+        Some(other) => Err(Error::Config(format!(
+            "expected an [mx] table, found {}",
+            other
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Overrides from `MX_HEADING_LEVEL` and `MX_RUNTIME_<LANG>` variables, sorted by variable
+///
+/// The language is the lowercased rest of a runtime variable's name. If `base` has a
+/// detailed runtime for it, only its command is replaced, keeping e.g. its execution mode.
+/// Empty variables are ignored.
+fn env_overrides(
+    vars: impl IntoIterator<Item = (String, String)>,
+    base: &toml::Table,
+) -> Result<Vec<(String, toml::Table)>> {
+    let mut overrides = Vec::new();
+
+    for (var, value) in vars {
+        if value.is_empty() {
+            continue;
+        }
+
+        let table = if var == HEADING_LEVEL_ENV {
+            let level: HeadingLevel = value.parse().map_err(|_| {
+                Error::Config(format!(
+                    "{} must be a number from 1 to 6, a range like 2..4, or any, got '{}'",
+                    var, value
+                ))
+            })?;
+            let level = match level.single() {
+                Some(level) => toml::Value::Integer(level.into()),
+                None => toml::Value::String(level.to_string()),
+            };
+            toml::Table::from_iter([("heading_level".to_string(), level)])
+        } else if let Some(lang) = var.strip_prefix(RUNTIME_ENV_PREFIX) {
+            let lang = lang.to_lowercase();
+            let detailed = base
+                .get("runtimes")
+                .and_then(|runtimes| runtimes.get(&lang))
+                .is_some_and(toml::Value::is_table);
+            let runtime = if detailed {
+                toml::Value::Table(toml::Table::from_iter([(
+                    "command".to_string(),
+                    toml::Value::String(value),
+                )]))
+            } else {
+                toml::Value::String(value)
+            };
+            toml::Table::from_iter([(
+                "runtimes".to_string(),
+                toml::Value::Table(toml::Table::from_iter([(lang, runtime)])),
+            )])
+        } else {
+            continue;
+        };
+
+        overrides.push((var, table));
+    }
+
+    overrides.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(overrides)
+}
+
+/// Every file a configuration file is loaded from: the file itself, then the files of its
+/// `extends` chain, canonicalized
+pub fn config_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    load_table(path, &mut Vec::new(), None, &mut files)?;
+    Ok(files)
+}
+
+/// Load a config file as a TOML table, resolving its `extends` chain
+///
+/// Every file read is added to `files`.
+fn load_table(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+    max_depth: Option<usize>,
+    files: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let canonical = fs::canonicalize(path).map_err(|e| {
+        Error::Config(format!(
+            "Failed to load config {}{}: {}",
+            path.display(),
+            format_chain(chain, None),
+            e
+        ))
+    })?;
+
+    if chain.contains(&canonical) {
+        return Err(Error::Config(format!(
+            "Cycle detected in config extends{}",
+            format_chain(chain, Some(&canonical))
+        )));
+    }
+
+    let content = fs::read_to_string(&canonical)?;
+    let mut table: toml::Table = toml::from_str(&content)?;
+
+    let max_depth = max_depth.unwrap_or_else(|| {
+        table
+            .get("max_extends_depth")
+            .and_then(|v| v.as_integer())
+            .map_or_else(default_max_extends_depth, |depth| depth.max(0) as usize)
+    });
+
+    if chain.len() > max_depth {
+        return Err(Error::Config(format!(
+            "Config extends chain exceeds the maximum depth of {}{}",
+            max_depth,
+            format_chain(chain, Some(&canonical))
+        )));
+    }
+
+    let extends = match table.remove("extends") {
+        None => Vec::new(),
+        Some(toml::Value::String(path)) => vec![path],
+        Some(toml::Value::Array(paths)) => paths
+            .into_iter()
+            .map(|v| match v {
+                toml::Value::String(path) => Ok(path),
+                other => Err(Error::Config(format!(
+                    "Invalid extends entry in {}: expected a path, found {}",
+                    canonical.display(),
+                    other
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Some(other) => {
+            return Err(Error::Config(format!(
+                "Invalid extends value in {}: expected a path or an array of paths, found {}",
+                canonical.display(),
+                other
+            )));
+        }
+    };
+
+    let base_dir = canonical
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    if !files.contains(&canonical) {
+        files.push(canonical.clone());
+    }
+    chain.push(canonical);
+
+    let mut merged = toml::Table::new();
+    for base in extends {
+        let base_table = load_table(&base_dir.join(base), chain, Some(max_depth), files)?;
+        merge_tables(&mut merged, base_table);
+    }
+    merge_tables(&mut merged, table);
+
+    chain.pop();
+
+    Ok(merged)
+}
+
+/// Deep-merge `overlay` into `base`, with values from `overlay` taking precedence
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Deep-merge `overlay` into `base` like [`merge_tables`], recording the source of every
+/// value it sets
+fn merge_traced(
+    base: &mut toml::Table,
+    overlay: toml::Table,
+    prefix: &str,
+    source: &ConfigSource,
+    provenance: &mut Provenance,
+) {
+    for (key, value) in overlay {
+        let path = format!("{}{}", prefix, key);
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_traced(
+                    base_table,
+                    overlay_table,
+                    &format!("{}.", path),
+                    source,
+                    provenance,
+                );
+            }
+            (_, value) => {
+                // Whatever the value replaces, e.g. a whole table, no longer exists
+                let nested = format!("{}.", path);
+                provenance.retain(|key, _| *key != path && !key.starts_with(&nested));
+                record_leaves(&path, &value, source, provenance);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn record_leaves(
+    path: &str,
+    value: &toml::Value,
+    source: &ConfigSource,
+    provenance: &mut Provenance,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                record_leaves(&format!("{}.{}", path, key), value, source, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path.to_string(), source.clone());
+        }
+    }
+}
+
+/// Format an extends chain for error messages (e.g. " (a.toml -> b.toml -> a.toml)")
+fn format_chain(chain: &[PathBuf], last: Option<&Path>) -> String {
+    if chain.is_empty() {
+        return String::new();
+    }
+
+    let paths: Vec<String> = chain
+        .iter()
+        .map(|p| p.as_path())
+        .chain(last)
+        .map(|p| p.display().to_string())
+        .collect();
+
+    format!(" ({})", paths.join(" -> "))
 }
 
 /// Default runtime mappings
-fn default_runtimes() -> HashMap<String, RuntimeConfig> {
-    let mut runtimes = HashMap::new();
+///
+/// Every entry can be overridden, and more languages added, by a configuration layer.
+fn default_runtimes() -> BTreeMap<String, RuntimeConfig> {
+    let mut runtimes = BTreeMap::new();
+    let detailed = |command: &str, execution_mode| RuntimeConfig::Detailed {
+        command: command.to_string(),
+        execution_mode,
+        args: Vec::new(),
+        env: BTreeMap::new(),
+        shell: false,
+        sandbox: None,
+        remote: None,
+        pty: false,
+    };
 
     // Languages with stdin execution mode (default)
-    runtimes.insert("bash".to_string(), RuntimeConfig::Simple("bash".to_string()));
-    runtimes.insert("sh".to_string(), RuntimeConfig::Simple("sh".to_string()));
-    runtimes.insert("python".to_string(), RuntimeConfig::Simple("python3".to_string()));
-    runtimes.insert("ruby".to_string(), RuntimeConfig::Simple("ruby".to_string()));
-    runtimes.insert("node".to_string(), RuntimeConfig::Simple("node".to_string()));
-    runtimes.insert("javascript".to_string(), RuntimeConfig::Simple("node".to_string()));
-    runtimes.insert("js".to_string(), RuntimeConfig::Simple("node".to_string()));
-    runtimes.insert("php".to_string(), RuntimeConfig::Simple("php".to_string()));
-    runtimes.insert("perl".to_string(), RuntimeConfig::Simple("perl".to_string()));
-    runtimes.insert("jq".to_string(), RuntimeConfig::Simple("jq".to_string()));
-
-    // Go requires file-based execution
-    runtimes.insert("go".to_string(), RuntimeConfig::Detailed {
-        command: "go run".to_string(),
-        execution_mode: ExecutionMode::File,
-    });
-    runtimes.insert("golang".to_string(), RuntimeConfig::Detailed {
-        command: "go run".to_string(),
-        execution_mode: ExecutionMode::File,
-    });
+    for (lang, command) in [
+        ("bash", "bash"),
+        ("sh", "sh"),
+        ("zsh", "zsh"),
+        ("fish", "fish"),
+        ("python", "python3"),
+        ("ruby", "ruby"),
+        ("node", "node"),
+        ("javascript", "node"),
+        ("js", "node"),
+        ("php", "php"),
+        ("perl", "perl"),
+        ("lua", "lua -"),
+        ("jq", "jq"),
+    ] {
+        runtimes.insert(lang.to_string(), RuntimeConfig::Simple(command.to_string()));
+    }
 
-    // mq requires argument-based execution
-    runtimes.insert("mq".to_string(), RuntimeConfig::Detailed {
-        command: "mq".to_string(),
-        execution_mode: ExecutionMode::Arg,
-    });
+    // Languages whose interpreters need a file, e.g. to infer its type from the extension
+    for (lang, command) in [
+        ("go", "go run"),
+        ("golang", "go run"),
+        ("typescript", "deno run"),
+        ("ts", "deno run"),
+        ("deno", "deno run"),
+        ("r", "Rscript"),
+        ("julia", "julia"),
+        ("powershell", "pwsh -NoProfile -NonInteractive -File"),
+        ("pwsh", "pwsh -NoProfile -NonInteractive -File"),
+    ] {
+        runtimes.insert(lang.to_string(), detailed(command, ExecutionMode::File));
+    }
+
+    // Query languages taking the code as an argument
+    for (lang, command) in [
+        ("mq", "mq"),
+        ("sqlite3", "sqlite3 :memory:"),
+        ("psql", "psql -c"),
+        ("mysql", "mysql -e"),
+    ] {
+        runtimes.insert(lang.to_string(), detailed(command, ExecutionMode::Arg));
+    }
+
+    // Rust is compiled, then run
+    for lang in ["rust", "rs"] {
+        runtimes.insert(
+            lang.to_string(),
+            RuntimeConfig::Steps {
+                steps: vec![
+                    "rustc --edition 2021 {file} -o {out}".to_string(),
+                    "{out} {args}".to_string(),
+                ],
+                env: BTreeMap::new(),
+                sandbox: None,
+                remote: None,
+            },
+        );
+    }
 
     runtimes
 }
 
+/// Default languages of blocks that are never executed
+fn default_ignore_languages() -> Vec<String> {
+    ["text", "txt", "plaintext", "console", "output"]
+        .map(String::from)
+        .to_vec()
+}
+
 /// Default heading level
 fn default_heading_level() -> u8 {
     2
 }
 
+/// Default maximum nesting depth of `extends` chains
+fn default_shell_stdin() -> ExecutionMode {
+    ExecutionMode::File
+}
+
+fn default_max_extends_depth() -> usize {
+    8
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,14 +1312,17 @@ mod tests {
         assert_eq!(config.heading_level, 2);
         assert!(config.has_runtime("bash"));
         assert!(config.has_runtime("python"));
+        assert!(config.ignores_language("console"));
+        assert!(config.ignores_language(""));
+        assert!(!config.ignores_language("bash"));
     }
 
     #[test]
     fn test_get_runtime() {
         let config = Config::default();
-        assert_eq!(config.get_runtime("bash"), Some("bash"));
-        assert_eq!(config.get_runtime("python"), Some("python3"));
-        assert_eq!(config.get_runtime("unknown"), None);
+        assert_eq!(config.get_runtime("bash").as_deref(), Some("bash"));
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3"));
+        assert_eq!(config.get_runtime("unknown").as_deref(), None);
     }
 
     #[test]
@@ -245,6 +1338,23 @@ mod tests {
 
         // Test arg-based execution mode
         assert_eq!(config.get_execution_mode("mq"), ExecutionMode::Arg);
+        assert_eq!(config.get_execution_mode("psql"), ExecutionMode::Arg);
+    }
+
+    #[test]
+    fn test_builtin_runtimes() {
+        let config = Config::default();
+        for lang in [
+            "zsh", "fish", "lua", "ts", "r", "julia", "pwsh", "sqlite3", "rust",
+        ] {
+            assert!(config.has_runtime(lang), "{}", lang);
+            assert!(!config.runtimes[lang].command_argv().unwrap().is_empty());
+        }
+        assert_eq!(
+            config.get_runtime("typescript").as_deref(),
+            Some("deno run")
+        );
+        assert_eq!(config.runtimes["rust"].command_argv().unwrap()[0], "rustc");
     }
 
     #[test]
@@ -259,6 +1369,12 @@ mod tests {
         let config = RuntimeConfig::Detailed {
             command: "go run".to_string(),
             execution_mode: ExecutionMode::File,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            shell: false,
+            sandbox: None,
+            remote: None,
+            pty: false,
         };
         assert_eq!(config.command(), "go run");
         assert_eq!(config.execution_mode(), ExecutionMode::File);
@@ -273,7 +1389,7 @@ heading_level = 2
 python = "python3"
 "#;
         let config: Config = toml::from_str(toml).unwrap();
-        assert_eq!(config.get_runtime("python"), Some("python3"));
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3"));
         assert_eq!(config.get_execution_mode("python"), ExecutionMode::Stdin);
     }
 
@@ -287,10 +1403,48 @@ command = "go run"
 execution_mode = "file"
 "#;
         let config: Config = toml::from_str(toml).unwrap();
-        assert_eq!(config.get_runtime("go"), Some("go run"));
+        assert_eq!(config.get_runtime("go").as_deref(), Some("go run"));
         assert_eq!(config.get_execution_mode("go"), ExecutionMode::File);
     }
 
+    #[test]
+    fn test_toml_deserialization_argv() {
+        let toml = r#"
+[runtimes]
+python = ["docker", "run", "-i", "--rm", "-v", "$PWD:/work", "python:3.12", "python"]
+ruby = 'docker run -i -v "$PWD:/work" ruby:3 ruby'
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let argv = |lang| config.runtimes[lang].command_argv().unwrap();
+
+        assert_eq!(argv("python")[5], "$PWD:/work");
+        assert_eq!(argv("ruby")[4], "$PWD:/work");
+        assert_eq!(
+            config.get_runtime("python").as_deref(),
+            Some("docker run -i --rm -v '$PWD:/work' python:3.12 python")
+        );
+        assert_eq!(config.get_execution_mode("python"), ExecutionMode::Stdin);
+    }
+
+    #[test]
+    fn test_toml_deserialization_steps() {
+        let toml = r#"
+[runtimes.c]
+steps = ["cc -O2 {file} -o {out}", "{out} {args}"]
+env = { CFLAGS = "-Wall" }
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let runtime = &config.runtimes["c"];
+
+        assert_eq!(runtime.steps().len(), 2);
+        assert_eq!(runtime.command_argv().unwrap()[0], "cc");
+        assert_eq!(runtime.env().count(), 1);
+        assert_eq!(
+            config.get_runtime("c").as_deref(),
+            Some("cc -O2 {file} -o {out} && {out} {args}")
+        );
+    }
+
     #[test]
     fn test_toml_deserialization_mixed() {
         let toml = r#"
@@ -310,15 +1464,343 @@ execution_mode = "arg"
         let config: Config = toml::from_str(toml).unwrap();
 
         // Simple config
-        assert_eq!(config.get_runtime("python"), Some("python3"));
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3"));
         assert_eq!(config.get_execution_mode("python"), ExecutionMode::Stdin);
 
         // Detailed config with file mode
-        assert_eq!(config.get_runtime("go"), Some("go run"));
+        assert_eq!(config.get_runtime("go").as_deref(), Some("go run"));
         assert_eq!(config.get_execution_mode("go"), ExecutionMode::File);
 
         // Detailed config with arg mode
-        assert_eq!(config.get_runtime("mq"), Some("mq"));
+        assert_eq!(config.get_runtime("mq").as_deref(), Some("mq"));
         assert_eq!(config.get_execution_mode("mq"), ExecutionMode::Arg);
     }
+
+    fn write_config(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_extends_merges_base_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        write_config(
+            dir,
+            "base.toml",
+            r#"
+heading_level = 3
+
+[runtimes]
+python = "python3.11"
+ruby = "ruby"
+"#,
+        );
+        let path = write_config(
+            dir,
+            "mx.toml",
+            r#"
+extends = "base.toml"
+
+[runtimes]
+python = "python3.12"
+"#,
+        );
+
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.heading_level, 3);
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3.12"));
+        assert_eq!(config.get_runtime("ruby").as_deref(), Some("ruby"));
+
+        let dir = dir.canonicalize().unwrap();
+        assert_eq!(
+            config_files(&path).unwrap(),
+            [dir.join("mx.toml"), dir.join("base.toml")]
+        );
+    }
+
+    #[test]
+    fn test_layers_are_merged_in_order() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let project = dir.join("project");
+        let nested = project.join("docs").join("ops");
+        fs::create_dir_all(&nested).unwrap();
+
+        let user = write_config(
+            dir,
+            "config.toml",
+            "heading_level = 3\nresilient = true\n\n[runtimes]\npython = \"python3.12\"",
+        );
+        write_config(&project, "mx.toml", "heading_level = 4");
+        let readme = write_config(
+            &nested,
+            "README.md",
+            "+++\ntitle = \"Ops\"\n\n[mx]\nkeep_temp = true\n+++\n\n## Deploy\n",
+        );
+
+        let layers = ConfigLayers {
+            frontmatter: vec![readme],
+            user: Some(user),
+            ..ConfigLayers::discover(&nested)
+        };
+        assert_eq!(layers.project, Some(project.join("mx.toml")));
+
+        let config = Config::from_layers(&layers).unwrap();
+        assert_eq!(config.heading_level, 4);
+        assert!(config.resilient);
+        assert!(config.keep_temp);
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3.12"));
+        assert_eq!(config.get_runtime("bash").as_deref(), Some("bash"));
+    }
+
+    #[test]
+    fn test_frontmatter_cannot_allow_sandbox_escapes() {
+        let temp = tempfile::tempdir().unwrap();
+        let readme = write_config(
+            temp.path(),
+            "README.md",
+            "+++\n[mx.sandbox]\nallow_off = [\"*\"]\n+++\n\n## Deploy {sandbox=off}\n",
+        );
+        let layers = ConfigLayers {
+            frontmatter: vec![readme],
+            ..Default::default()
+        };
+
+        let err = Config::from_layers(&layers).unwrap_err();
+        assert!(err.to_string().contains("cannot set `sandbox`"));
+    }
+
+    #[test]
+    fn test_layers_record_provenance() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let project = write_config(
+            dir,
+            "mx.toml",
+            "heading_level = 3\n\n[runtimes.bash]\ncommand = \"bash\"\nexecution_mode = \"file\"",
+        );
+        let layers = ConfigLayers {
+            project: Some(project.clone()),
+            ..Default::default()
+        };
+
+        let (config, provenance) = Config::from_layers_with_provenance(&layers).unwrap();
+        let from_project = ConfigSource::Project { path: project };
+
+        assert_eq!(config.heading_level, 3);
+        assert_eq!(provenance["heading_level"], from_project);
+        assert_eq!(provenance["runtimes.bash.execution_mode"], from_project);
+        assert!(!provenance.contains_key("runtimes.bash"));
+        assert_eq!(provenance["runtimes.python"], ConfigSource::Default);
+        assert_eq!(provenance["resilient"], ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_heading_level() {
+        assert_eq!("3".parse::<HeadingLevel>().unwrap(), 3);
+        let range: HeadingLevel = "2-4".parse().unwrap();
+        assert_eq!((range.min(), range.max(), range.single()), (2, 4, None));
+        assert_eq!("2..4".parse::<HeadingLevel>().unwrap(), range);
+        assert_eq!("ANY".parse::<HeadingLevel>().unwrap(), HeadingLevel::ANY);
+        for invalid in ["0", "7", "4..2", "2..", "two"] {
+            assert!(invalid.parse::<HeadingLevel>().is_err(), "{}", invalid);
+        }
+
+        let config: Config = toml::from_str("heading_level = \"2..4\"").unwrap();
+        assert_eq!(config.heading_level, range);
+        assert!(config.heading_level.contains(3) && !config.heading_level.contains(5));
+        assert!(toml::from_str::<Config>("heading_level = 9").is_err());
+
+        let serialized = toml::to_string(&Config {
+            heading_level: HeadingLevel::ANY,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(serialized.contains("heading_level = \"any\""));
+        assert!(
+            toml::to_string(&Config::default())
+                .unwrap()
+                .contains("heading_level = 2\n")
+        );
+    }
+
+    #[test]
+    fn test_config_tasks() {
+        let config: Config = toml::from_str(
+            r#"
+heading_level = 3
+
+[tasks.clean]
+run = "rm -rf target"
+description = "Remove build output"
+tags = ["dev"]
+
+[tasks.serve]
+run = "print('serving')"
+lang = "python"
+"#,
+        )
+        .unwrap();
+
+        let sections = config.task_sections();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].title, "clean");
+        assert_eq!(sections[0].level, 3);
+        assert_eq!(sections[0].codes[0].lang, "sh");
+        assert_eq!(sections[0].codes[0].code, "rm -rf target");
+        assert_eq!(
+            sections[0].description.as_deref(),
+            Some("Remove build output")
+        );
+        assert!(sections[0].has_tag("dev"));
+        assert_eq!(sections[1].codes[0].lang, "python");
+
+        assert!(toml::from_str::<Config>("[tasks.clean]\ncommand = \"rm\"\n").is_err());
+    }
+
+    #[test]
+    fn test_title_match() {
+        let sections: Vec<Section> = ["Deploy to production", "Deploy to staging", "Build & test"]
+            .into_iter()
+            .map(|title| Section {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let find = |mode: TitleMatch, name: &str| {
+            mode.find(&sections, name)
+                .unwrap()
+                .map(|section| section.title.as_str())
+        };
+
+        assert_eq!(find(TitleMatch::Exact, "Deploy to staging"), None);
+        assert_eq!(
+            find(TitleMatch::Contains, "STAGING"),
+            Some("Deploy to staging")
+        );
+        assert_eq!(find(TitleMatch::Slug, "build  test"), Some("Build & test"));
+        assert_eq!(
+            find(TitleMatch::Regex, "deploy .*staging"),
+            Some("Deploy to staging")
+        );
+        assert_eq!(find(TitleMatch::Regex, "^staging"), None);
+        assert!(matches!(
+            TitleMatch::Regex.find(&sections, "deploy ("),
+            Err(Error::Config(_))
+        ));
+
+        let config: Config = toml::from_str("match = \"regex\"").unwrap();
+        assert_eq!(config.title_match, TitleMatch::Regex);
+        assert_eq!(Config::default().title_match, TitleMatch::Exact);
+        assert!(toml::from_str::<Config>("match = \"fuzzy\"").is_err());
+    }
+
+    #[test]
+    fn test_title_match_fails_on_several_titles() {
+        let sections: Vec<Section> = ["Deploy web", "Deploy db", "Deploy web"]
+            .into_iter()
+            .map(|title| Section {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        for (mode, name) in [(TitleMatch::Contains, "deploy"), (TitleMatch::Regex, "dep")] {
+            match mode.find(&sections, name) {
+                Err(Error::AmbiguousTask { titles, .. }) => {
+                    assert_eq!(titles, ["Deploy web", "Deploy db"]);
+                }
+                other => panic!("expected an ambiguous match, got {:?}", other),
+            }
+        }
+        // Several sections with one title are left to the duplicate title checks
+        let found = TitleMatch::Contains.find(&sections, "web").unwrap();
+        assert_eq!(
+            found.map(|section| section.title.as_str()),
+            Some("Deploy web")
+        );
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let base = match toml::Value::try_from(Config::default()).unwrap() {
+            toml::Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        let vars = [
+            ("MX_RUNTIME_PYTHON", "python3.12"),
+            ("MX_RUNTIME_GO", "go1.22 run"),
+            ("MX_HEADING_LEVEL", "3"),
+            ("MX_RUNTIME_RUBY", ""),
+            ("HOME", "/root"),
+        ]
+        .map(|(var, value)| (var.to_string(), value.to_string()));
+
+        let mut merged = base.clone();
+        let mut provenance = Provenance::new();
+        for (var, table) in env_overrides(vars, &base).unwrap() {
+            merge_traced(
+                &mut merged,
+                table,
+                "",
+                &ConfigSource::Env { var },
+                &mut provenance,
+            );
+        }
+        let config: Config = toml::Value::Table(merged).try_into().unwrap();
+
+        assert_eq!(config.heading_level, 3);
+        assert_eq!(config.get_runtime("python").as_deref(), Some("python3.12"));
+        assert_eq!(config.get_runtime("go").as_deref(), Some("go1.22 run"));
+        assert_eq!(config.get_execution_mode("go"), ExecutionMode::File);
+        assert_eq!(config.get_runtime("ruby").as_deref(), Some("ruby"));
+        assert_eq!(
+            provenance["runtimes.go.command"],
+            ConfigSource::Env {
+                var: "MX_RUNTIME_GO".to_string()
+            }
+        );
+
+        let invalid = [("MX_HEADING_LEVEL".to_string(), "7".to_string())];
+        assert!(env_overrides(invalid, &base).is_err());
+        let range = [("MX_HEADING_LEVEL".to_string(), "any".to_string())];
+        assert_eq!(
+            env_overrides(range, &base).unwrap()[0].1["heading_level"].as_str(),
+            Some("any")
+        );
+    }
+
+    #[test]
+    fn test_extends_cycle_is_reported() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        write_config(dir, "a.toml", r#"extends = "b.toml""#);
+        write_config(dir, "b.toml", r#"extends = ["a.toml"]"#);
+
+        let err = Config::from_file(dir.join("a.toml"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Cycle detected"), "{}", err);
+        assert!(err.contains("a.toml -> "), "{}", err);
+    }
+
+    #[test]
+    fn test_extends_depth_limit() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        write_config(
+            dir,
+            "mx.toml",
+            "max_extends_depth = 1\nextends = \"one.toml\"",
+        );
+        write_config(dir, "one.toml", r#"extends = "two.toml""#);
+        write_config(dir, "two.toml", "heading_level = 4");
+
+        let err = Config::from_file(dir.join("mx.toml"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("maximum depth of 1"), "{}", err);
+    }
 }
@@ -0,0 +1,190 @@
+//! Local crash reports
+//!
+//! When enabled with `MX_CRASH_REPORT=1`, a panic writes a plain-text report (version,
+//! platform, sanitized arguments, panic message and backtrace) to the temp directory.
+//! Nothing is ever sent over the network; users attach the file to an issue themselves.
+
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable enabling crash reports
+pub const CRASH_REPORT_ENV: &str = "MX_CRASH_REPORT";
+
+const ISSUES_URL: &str = "https://github.com/harehare/mx/issues";
+const REDACTED: &str = "<redacted>";
+
+/// Install a panic hook that writes crash reports, if enabled via `MX_CRASH_REPORT`
+pub fn install_panic_hook_from_env() {
+    let enabled = std::env::var(CRASH_REPORT_ENV)
+        .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+
+    if enabled {
+        install_panic_hook(std::env::args().collect());
+    }
+}
+
+/// Install a panic hook that writes a crash report for the given command line
+///
+/// The previously installed hook still runs first, so the usual panic message is kept.
+pub fn install_panic_hook(args: Vec<String>) {
+    let args = sanitize_args(&args);
+    let previous = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        previous(info);
+
+        let report = format_report(&args, &panic_message(info), &Backtrace::force_capture());
+        match write_report(&report) {
+            Ok(path) => eprintln!(
+                "\nmx crashed unexpectedly. A crash report was written to {}\n\
+                 Please attach it to an issue at {} (nothing has been sent anywhere).",
+                path.display(),
+                ISSUES_URL
+            ),
+            Err(e) => eprintln!(
+                "\nmx crashed unexpectedly and the crash report could not be written: {}",
+                e
+            ),
+        }
+    }));
+}
+
+/// Format a crash report
+pub fn format_report(args: &[String], message: &str, backtrace: &Backtrace) -> String {
+    format!(
+        "mx crash report\n\
+         version: {}\n\
+         platform: {}-{}\n\
+         timestamp: {}\n\
+         args: {}\n\
+         message: {}\n\
+         \n\
+         backtrace:\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        unix_timestamp(),
+        args.join(" "),
+        message,
+        backtrace
+    )
+}
+
+/// Remove potentially sensitive values from command line arguments
+///
+/// Task arguments after `--` are redacted entirely, as are values of `KEY=VALUE` pairs and
+/// the home directory prefix of paths.
+pub fn sanitize_args(args: &[String]) -> Vec<String> {
+    let home = std::env::var("HOME").ok().filter(|home| !home.is_empty());
+    let mut after_separator = false;
+
+    args.iter()
+        .map(|arg| {
+            if after_separator {
+                return REDACTED.to_string();
+            }
+            if arg == "--" {
+                after_separator = true;
+                return arg.clone();
+            }
+
+            let arg = match &home {
+                Some(home) => arg.replace(home.as_str(), "~"),
+                None => arg.clone(),
+            };
+
+            match arg.split_once('=') {
+                Some((key, _)) if !key.starts_with('-') => format!("{}={}", key, REDACTED),
+                _ => arg,
+            }
+        })
+        .collect()
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<non-string panic payload>".to_string());
+
+    match info.location() {
+        Some(location) => format!("{} at {}:{}", payload, location.file(), location.line()),
+        None => payload,
+    }
+}
+
+fn write_report(report: &str) -> std::io::Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!(
+        "mx-crash-{}-{}.txt",
+        unix_timestamp(),
+        std::process::id()
+    ));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sanitize_args() {
+        let sanitized = sanitize_args(&args(&[
+            "mx",
+            "run",
+            "Deploy",
+            "--runtime",
+            "python:python3",
+            "TOKEN=abc",
+            "--",
+            "secret",
+            "--flag",
+        ]));
+
+        assert_eq!(
+            sanitized,
+            args(&[
+                "mx",
+                "run",
+                "Deploy",
+                "--runtime",
+                "python:python3",
+                "TOKEN=<redacted>",
+                "--",
+                "<redacted>",
+                "<redacted>",
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report(
+            &args(&["mx", "list"]),
+            "boom at src/main.rs:1",
+            &Backtrace::disabled(),
+        );
+
+        assert!(report.starts_with("mx crash report\n"));
+        assert!(report.contains(&format!("version: {}", env!("CARGO_PKG_VERSION"))));
+        assert!(report.contains("args: mx list\n"));
+        assert!(report.contains("message: boom at src/main.rs:1\n"));
+    }
+}
@@ -0,0 +1,129 @@
+//! Running code blocks inside a Nix dev shell
+//!
+//! With `nix_shell` set, every code block runs through the shell's `--command`, so the
+//! documented commands use the toolchains pinned by the project's flake:
+//!
+//! ```toml
+//! nix_shell = "nix develop"   # or e.g. "nix shell nixpkgs#python3"
+//! ```
+//!
+//! Tasks pick a shell with a `{devshell}` heading attribute or a `devshell` metadata entry:
+//! a flake output such as `{devshell=.#ci}` is entered with `nix develop`, a quoted command
+//! such as `{devshell="nix shell nixpkgs#jq"}` is used as it is, a bare `{devshell}` enters
+//! `nix_shell` or `nix develop`, and `{devshell=off}` runs the task outside any shell.
+
+use crate::command;
+use crate::config::Config;
+use crate::error::Result;
+use crate::runner::Section;
+
+/// Dev shell entered when a task asks for one and `nix_shell` is not set
+pub const DEFAULT_SHELL: &str = "nix develop";
+
+/// The dev shell a code block runs in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevShell {
+    /// Program and arguments entering the shell, e.g. `["nix", "develop"]`
+    pub argv: Vec<String>,
+}
+
+impl DevShell {
+    /// The dev shell for a code block of a task, or `None` if it runs outside any shell
+    pub fn resolve(config: &Config, section: Option<&Section>) -> Result<Option<Self>> {
+        let task_shell = section.and_then(|section| {
+            section
+                .attribute("devshell")
+                .or_else(|| section.metadata.get("devshell").cloned())
+        });
+
+        let shell = match task_shell.as_deref() {
+            Some("off") => return Ok(None),
+            Some("") => config
+                .nix_shell
+                .as_deref()
+                .unwrap_or(DEFAULT_SHELL)
+                .to_string(),
+            Some(value) if value.contains(char::is_whitespace) => value.to_string(),
+            Some(flake) => format!("{} {}", DEFAULT_SHELL, command::shell_quote(flake)),
+            None => match &config.nix_shell {
+                Some(shell) => shell.clone(),
+                None => return Ok(None),
+            },
+        };
+        let argv = command::split_command(&shell)?;
+        Ok((!argv.is_empty()).then_some(Self { argv }))
+    }
+
+    /// Program and arguments running `program` with `args` inside the shell
+    pub fn wrap(&self, program: &str, args: &[String]) -> (String, Vec<String>) {
+        let mut wrapped: Vec<String> = self.argv[1..].to_vec();
+        wrapped.push("--command".to_string());
+        wrapped.push(program.to_string());
+        wrapped.extend(args.iter().cloned());
+        (self.argv[0].clone(), wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(meta: &str) -> Section {
+        Section {
+            title: "Build".to_string(),
+            level: 2,
+            meta: Some(meta.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn argv(config: &Config, section: Option<&Section>) -> Option<Vec<String>> {
+        DevShell::resolve(config, section)
+            .unwrap()
+            .map(|shell| shell.argv)
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut config = Config::default();
+        assert_eq!(argv(&config, None), None);
+        assert_eq!(
+            argv(&config, Some(&task("devshell"))).unwrap(),
+            ["nix", "develop"]
+        );
+        assert_eq!(
+            argv(&config, Some(&task("devshell=.#ci"))).unwrap(),
+            ["nix", "develop", ".#ci"]
+        );
+        assert_eq!(
+            argv(&config, Some(&task(r#"devshell="nix shell nixpkgs#jq""#))).unwrap(),
+            ["nix", "shell", "nixpkgs#jq"]
+        );
+
+        config.nix_shell = Some("nix shell nixpkgs#python3".to_string());
+        assert_eq!(
+            argv(&config, None).unwrap(),
+            ["nix", "shell", "nixpkgs#python3"]
+        );
+        assert_eq!(argv(&config, Some(&task("devshell=off"))), None);
+
+        let mut section = task("");
+        section
+            .metadata
+            .insert("devshell".to_string(), ".#docs".to_string());
+        assert_eq!(
+            argv(&config, Some(&section)).unwrap(),
+            ["nix", "develop", ".#docs"]
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let shell = DevShell {
+            argv: vec!["nix".to_string(), "develop".to_string()],
+        };
+        let (program, args) = shell.wrap("python3", &["-u".to_string()]);
+        assert_eq!(program, "nix");
+        assert_eq!(args, ["develop", "--command", "python3", "-u"]);
+    }
+}
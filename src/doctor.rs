@@ -0,0 +1,539 @@
+//! Health checks of a project, for `mx doctor`
+//!
+//! The checks cover the tasks files, the configuration files, the runtimes of the languages
+//! the tasks use, and the integrations they rely on: Docker for runtimes running containers,
+//! and the SSH hosts and Kubernetes clusters of remote targets. Each check that fails comes
+//! with a hint on how to fix it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command;
+use crate::config::Config;
+use crate::remote::Remote;
+use crate::runner::{CodeBlock, Runner, Section};
+use crate::validate::{self, Severity};
+
+/// How long a version or reachability probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a check is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Area {
+    TasksFiles,
+    Configuration,
+    Runtimes,
+    Integrations,
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Area::TasksFiles => "Tasks files",
+            Area::Configuration => "Configuration",
+            Area::Runtimes => "Runtimes",
+            Area::Integrations => "Integrations",
+        })
+    }
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Something may not work, e.g. an unreachable SSH host
+    Warning,
+    /// Something will not work, e.g. a language without a runtime
+    Error,
+}
+
+/// Result of one check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub area: Area,
+    pub status: Status,
+    /// What was checked, e.g. `README.md` or `python`
+    pub subject: String,
+    /// What was found, e.g. `12 tasks` or `Python 3.12.1`
+    pub message: String,
+    /// How to fix a failed check
+    pub help: Option<String>,
+}
+
+impl Check {
+    fn new(
+        area: Area,
+        status: Status,
+        subject: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            area,
+            status,
+            subject: subject.into(),
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+/// Run every check for the tasks files and configuration files of a project
+pub fn diagnose(runner: &Runner, files: &[PathBuf], config_files: &[PathBuf]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut sections = Vec::new();
+
+    for path in files {
+        let (check, found) = check_tasks_file(runner, path);
+        checks.push(check);
+        sections.extend(found);
+    }
+    checks.extend(config_files.iter().map(|path| check_config_file(path)));
+
+    let config = runner.config();
+    let mut programs: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut remotes: BTreeMap<String, Remote> = BTreeMap::new();
+    for section in &sections {
+        for block in &section.codes {
+            if config.ignores_language(&block.lang) {
+                continue;
+            }
+            if let Some(remote) = Remote::resolve(config, &block.lang, Some(section), None) {
+                remotes.insert(remote.to_string(), remote);
+                continue;
+            }
+            match runtime_program(config, block, section) {
+                Ok(Some(program)) => {
+                    programs
+                        .entry(program)
+                        .or_default()
+                        .insert(block.lang.clone());
+                }
+                Ok(None) => {}
+                Err(check) => {
+                    if !checks.contains(&check) {
+                        checks.push(check);
+                    }
+                }
+            }
+        }
+    }
+
+    for (program, langs) in &programs {
+        checks.push(check_program(config, program, langs));
+    }
+    if programs.keys().any(|program| program == "docker") {
+        checks.push(check_docker());
+    }
+    checks.extend(remotes.values().map(check_remote));
+    checks.extend(check_wrappers(config));
+
+    checks.sort_by_key(|check| check.area);
+    checks
+}
+
+/// Whether any check failed with an error
+pub fn has_errors(checks: &[Check]) -> bool {
+    checks.iter().any(|check| check.status == Status::Error)
+}
+
+fn check_tasks_file(runner: &Runner, path: &Path) -> (Check, Vec<Section>) {
+    let subject = path.display().to_string();
+    if !path.is_file() {
+        let check = Check::new(Area::TasksFiles, Status::Error, subject, "not found")
+            .with_help("create it with `mx init`, or pass another file with --file");
+        return (check, Vec::new());
+    }
+
+    let markdown = match runner.load_task_markdown(path) {
+        Ok(markdown) => markdown,
+        Err(e) => {
+            let check = Check::new(Area::TasksFiles, Status::Error, subject, e.to_string());
+            return (check, Vec::new());
+        }
+    };
+    let extraction = runner.extract_sections_resilient(&markdown);
+
+    let tasks = format!("{} task(s)", extraction.sections.len());
+    let check = match extraction.diagnostics.as_slice() {
+        [] => Check::new(Area::TasksFiles, Status::Ok, subject, tasks),
+        [first, ..] => Check::new(
+            Area::TasksFiles,
+            Status::Error,
+            subject,
+            format!(
+                "{}; {} section(s) could not be parsed, first '{}' at line {}",
+                tasks,
+                extraction.diagnostics.len(),
+                first.heading,
+                first.line
+            ),
+        )
+        .with_help("run `mx validate` for details"),
+    };
+    (check, extraction.sections)
+}
+
+fn check_config_file(path: &Path) -> Check {
+    let subject = path.display().to_string();
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return Check::new(Area::Configuration, Status::Error, subject, e.to_string()),
+    };
+
+    let issues = validate::validate_config(&source);
+    match issues
+        .iter()
+        .find(|issue| issue.severity == Severity::Error)
+    {
+        None if issues.is_empty() => Check::new(Area::Configuration, Status::Ok, subject, "valid"),
+        None => Check::new(
+            Area::Configuration,
+            Status::Warning,
+            subject,
+            format!("{} warning(s)", issues.len()),
+        )
+        .with_help("run `mx config check` for details"),
+        Some(issue) => Check::new(Area::Configuration, Status::Error, subject, &issue.message)
+            .with_help("run `mx config check` for details"),
+    }
+}
+
+/// The local program running a code block, or a failed check if it has no runtime
+fn runtime_program(
+    config: &Config,
+    block: &CodeBlock,
+    section: &Section,
+) -> std::result::Result<Option<String>, Check> {
+    let runtime = match config.runtime_for(block, Some(section)) {
+        Ok(Some(runtime)) => runtime,
+        Ok(None) => {
+            return Err(Check::new(
+                Area::Runtimes,
+                Status::Error,
+                &block.lang,
+                "no runtime configured",
+            )
+            .with_help(format!(
+                "add one under [runtimes] in mx.toml, e.g. {} = \"{}\", or list it in \
+                 ignore_languages",
+                block.lang, block.lang
+            )));
+        }
+        Err(e) => {
+            return Err(Check::new(
+                Area::Runtimes,
+                Status::Error,
+                &block.lang,
+                e.to_string(),
+            ));
+        }
+    };
+
+    let argv = if runtime.shell() {
+        command::split_command(&runtime.command())
+    } else {
+        runtime.command_argv()
+    };
+    Ok(argv
+        .ok()
+        .and_then(|argv| argv.into_iter().next())
+        .filter(|program| !program.contains('{')))
+}
+
+fn check_program(config: &Config, program: &str, langs: &BTreeSet<String>) -> Check {
+    let subject = format!(
+        "{} ({})",
+        program,
+        langs.iter().cloned().collect::<Vec<_>>().join(", ")
+    );
+    match probe(program, &["--version"]) {
+        Probe::Missing => {
+            // A dev shell or version manager may provide programs that are not on PATH
+            let status = if config.nix_shell.is_some() || config.tool_env.is_some() {
+                Status::Warning
+            } else {
+                Status::Error
+            };
+            let lang = langs.iter().next().map(String::as_str).unwrap_or_default();
+            Check::new(Area::Runtimes, status, subject, "not found on PATH").with_help(format!(
+                "install {}, or point runtimes.{} at another command",
+                program, lang
+            ))
+        }
+        Probe::Finished { success, output } => {
+            let version = output.filter(|_| success);
+            let message = version.unwrap_or_else(|| "found (version unknown)".to_string());
+            Check::new(Area::Runtimes, Status::Ok, subject, message)
+        }
+        Probe::TimedOut => Check::new(
+            Area::Runtimes,
+            Status::Ok,
+            subject,
+            "found (version unknown)",
+        ),
+    }
+}
+
+fn check_docker() -> Check {
+    match probe("docker", &["info", "--format", "{{.ServerVersion}}"]) {
+        Probe::Finished {
+            success: true,
+            output,
+        } => Check::new(
+            Area::Integrations,
+            Status::Ok,
+            "docker",
+            format!("daemon {}", output.unwrap_or_default()),
+        ),
+        Probe::Finished { output, .. } => Check::new(
+            Area::Integrations,
+            Status::Error,
+            "docker",
+            output.unwrap_or_else(|| "daemon is not reachable".to_string()),
+        )
+        .with_help("start the Docker daemon, or check that you may use its socket"),
+        Probe::Missing | Probe::TimedOut => Check::new(
+            Area::Integrations,
+            Status::Error,
+            "docker",
+            "daemon is not reachable",
+        )
+        .with_help("start the Docker daemon, or check that you may use its socket"),
+    }
+}
+
+fn check_remote(remote: &Remote) -> Check {
+    let mut args: Vec<String> = match remote {
+        Remote::Ssh { .. } => ["-o", "BatchMode=yes", "-o", "ConnectTimeout=5"]
+            .map(String::from)
+            .to_vec(),
+        Remote::Kubernetes { .. } => vec!["--request-timeout=5s".to_string()],
+    };
+    args.extend(remote.args("true"));
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let subject = format!("{} {}", remote.program(), remote);
+    let help = match remote {
+        Remote::Ssh { host } => format!(
+            "check that `ssh {}` logs in without asking for a password",
+            host
+        ),
+        Remote::Kubernetes { .. } => {
+            "check the kubeconfig context and that the workload is running".to_string()
+        }
+    };
+    match probe(remote.program(), &args) {
+        Probe::Finished { success: true, .. } => {
+            Check::new(Area::Integrations, Status::Ok, subject, "reachable")
+        }
+        Probe::Missing => Check::new(
+            Area::Integrations,
+            Status::Warning,
+            subject,
+            format!("{} is not installed", remote.program()),
+        )
+        .with_help(format!("install {}", remote.program())),
+        Probe::Finished { output, .. } => Check::new(
+            Area::Integrations,
+            Status::Warning,
+            subject,
+            output.unwrap_or_else(|| "unreachable".to_string()),
+        )
+        .with_help(help),
+        Probe::TimedOut => {
+            Check::new(Area::Integrations, Status::Warning, subject, "timed out").with_help(help)
+        }
+    }
+}
+
+/// Checks of the programs of `nix_shell` and `tool_env`
+fn check_wrappers(config: &Config) -> Vec<Check> {
+    let nix = config
+        .nix_shell
+        .as_deref()
+        .and_then(|shell| command::split_command(shell).ok())
+        .and_then(|argv| argv.into_iter().next());
+    let tools = config.tool_env.map(|tools| tools.program().to_string());
+
+    [(nix, "nix_shell"), (tools, "tool_env")]
+        .into_iter()
+        .filter_map(|(program, key)| Some((program?, key)))
+        .map(|(program, key)| match probe(&program, &["--version"]) {
+            Probe::Missing => Check::new(
+                Area::Integrations,
+                Status::Error,
+                &program,
+                format!("not found on PATH, but {} uses it", key),
+            )
+            .with_help(format!(
+                "install {}, or remove {} from mx.toml",
+                program, key
+            )),
+            Probe::Finished {
+                success: true,
+                output,
+            } => Check::new(
+                Area::Integrations,
+                Status::Ok,
+                &program,
+                output.unwrap_or_default(),
+            ),
+            Probe::Finished { .. } | Probe::TimedOut => {
+                Check::new(Area::Integrations, Status::Ok, &program, "found")
+            }
+        })
+        .collect()
+}
+
+/// What running a program told
+enum Probe {
+    /// The program could not be started
+    Missing,
+    /// The program exited, printing `output` (its first non-empty line)
+    Finished {
+        success: bool,
+        output: Option<String>,
+    },
+    /// The program did not exit within [`PROBE_TIMEOUT`] and was killed
+    TimedOut,
+}
+
+fn probe(program: &str, args: &[&str]) -> Probe {
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let Ok(mut child) = child else {
+        return Probe::Missing;
+    };
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < PROBE_TIMEOUT => {
+                thread::sleep(Duration::from_millis(20))
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Probe::TimedOut;
+            }
+        }
+    };
+
+    let mut output = String::new();
+    for stream in [
+        child.stdout.take().map(|s| Box::new(s) as Box<dyn Read>),
+        child.stderr.take().map(|s| Box::new(s) as Box<dyn Read>),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let mut text = String::new();
+        let _ = { stream }.read_to_string(&mut text);
+        output.push_str(&text);
+        output.push('\n');
+    }
+    Probe::Finished {
+        success: status.success(),
+        output: output
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::RuntimeConfig;
+    use crate::error::Result;
+    use crate::extract::SectionExtractor;
+
+    #[test]
+    fn test_diagnose() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let tasks = dir.join("README.md");
+        fs::write(&tasks, "## Build\n").unwrap();
+        let config_file = dir.join("mx.toml");
+        fs::write(&config_file, "heading_levle = 2\n").unwrap();
+
+        struct Fixed;
+        impl SectionExtractor for Fixed {
+            fn extract(&mut self, _markdown: &str, _level: u8) -> Result<Vec<Section>> {
+                let codes = ["sh", "cobol", "text"].map(|lang| CodeBlock {
+                    lang: lang.to_string(),
+                    code: "echo hi".to_string(),
+                    ..Default::default()
+                });
+                Ok(vec![Section {
+                    title: "Build".to_string(),
+                    level: 2,
+                    codes: codes.to_vec(),
+                    ..Default::default()
+                }])
+            }
+        }
+        let mut config = Config::default();
+        config
+            .runtimes
+            .insert("sh".to_string(), RuntimeConfig::Simple("sh".to_string()));
+        config.runtimes.insert(
+            "cobol".to_string(),
+            RuntimeConfig::Simple("mx-doctor-no-such-program".to_string()),
+        );
+        let runner = Runner::new(config).with_extractor(Fixed);
+
+        let checks = diagnose(
+            &runner,
+            &[tasks.clone(), dir.join("TASKS.md")],
+            &[config_file],
+        );
+        let find = |subject: &str| {
+            checks
+                .iter()
+                .find(|check| check.subject.contains(subject))
+                .unwrap_or_else(|| panic!("no check of {}: {:?}", subject, checks))
+        };
+
+        assert_eq!(find("README.md").status, Status::Ok);
+        assert_eq!(find("README.md").message, "1 task(s)");
+        assert_eq!(find("TASKS.md").status, Status::Error);
+        assert_eq!(find("mx.toml").status, Status::Error);
+        assert_eq!(find("sh (sh)").status, Status::Ok);
+        assert_eq!(find("mx-doctor-no-such-program").status, Status::Error);
+        assert!(!checks.iter().any(|check| check.subject.contains("text")));
+        assert!(has_errors(&checks));
+        assert!(checks.is_sorted_by_key(|check| check.area));
+    }
+
+    #[test]
+    fn test_language_without_runtime() {
+        let runner = Runner::with_default_config();
+        let block = CodeBlock {
+            lang: "cobol".to_string(),
+            ..Default::default()
+        };
+
+        let check = runtime_program(runner.config(), &block, &Section::default()).unwrap_err();
+        assert_eq!(check.status, Status::Error);
+        assert!(check.help.unwrap().contains("[runtimes]"));
+    }
+}
@@ -1,7 +1,13 @@
 //! Error types for mx
 
+use std::ops::Range;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
 use thiserror::Error;
 
+use crate::report::block_id;
+
 /// Result type for mx operations
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -32,11 +38,216 @@ pub enum Error {
     #[error("TOML parse error: {0}")]
     TomlParse(#[from] toml::de::Error),
 
-    /// Section not found
-    #[error("Section not found: {0}")]
-    SectionNotFound(String),
+    /// No task has the name a task was looked up by
+    #[error("Section not found: {task}{}", suggestion_hint(suggestions))]
+    SectionNotFound {
+        /// Name the task was looked up by
+        task: String,
+        /// Titles of the tasks with the closest names, closest first
+        suggestions: Vec<String>,
+    },
+
+    /// A task was picked by an index past the tasks with its title
+    #[error("Task '{task}' has no index {index}; {count} task(s) have that title")]
+    TaskIndexOutOfRange {
+        /// Title of the tasks
+        task: String,
+        /// 0-based index that was asked for
+        index: usize,
+        /// Number of tasks with the title
+        count: usize,
+    },
+
+    /// Task found in more than one file, or a task name matching several titles in a `match`
+    /// mode other than `exact`
+    #[error("Task '{task}' {}", ambiguity(files, titles))]
+    AmbiguousTask {
+        /// Title of the task, or the name it was looked up by
+        task: String,
+        /// Files that define the task
+        files: Vec<PathBuf>,
+        /// Titles the name matches, in document order
+        titles: Vec<String>,
+    },
+
+    /// Several tasks have the title a task was named by
+    #[error(
+        "Task '{task}' is defined {} times: {}; pick one with --index",
+        locations.len(),
+        locations.join(", ")
+    )]
+    DuplicateTitle {
+        /// Title of the tasks
+        task: String,
+        /// Where each task is defined, e.g. `README.md:12 (under Setup)`
+        locations: Vec<String>,
+    },
+
+    /// A dependency of a task could not be found
+    #[error("Task '{task}' depends on '{dependency}', which was not found")]
+    MissingDependency {
+        /// Title of the task declaring the dependency
+        task: String,
+        /// The dependency as written, e.g. `docs/db.md#migrate`
+        dependency: String,
+    },
+
+    /// Tasks depend on each other in a cycle
+    #[error("Dependency cycle: {}", .0.join(" -> "))]
+    DependencyCycle(Vec<String>),
+
+    /// A task with the same title already exists
+    #[error("Task '{0}' already exists")]
+    DuplicateTask(String),
+
+    /// A version manager could not provide the program of a code block
+    #[error("{tool} cannot provide {program} for this project: {message}")]
+    Toolchain {
+        /// Version manager, e.g. `mise`
+        tool: String,
+        /// Program of the code block
+        program: String,
+        /// What the version manager reported
+        message: String,
+    },
 
     /// Runtime not found
-    #[error("Runtime not found for language: {0}")]
+    #[error("Runtime not found for language: {0}; configure one under [runtimes] in mx.toml")]
     RuntimeNotFound(String),
+
+    /// The command line or environment of a code block exceeds the platform limit
+    #[error(
+        "The {lang} command is too large to start ({size} bytes, the limit is {limit}); use execution_mode = \"file\" for large code blocks"
+    )]
+    CommandTooLarge {
+        /// Language of the code block
+        lang: String,
+        /// Size of the oversized argument or of the whole command line and environment
+        size: usize,
+        /// Platform limit that was exceeded
+        limit: usize,
+    },
+
+    /// A Markdown or configuration file was not trusted to be executed
+    #[error(
+        "{path} is not trusted; review it, then run `mx trust {path}` to record it or pass --trust to run it once",
+        path = .0.display()
+    )]
+    Untrusted(PathBuf),
+
+    /// A task marked with `{lock}` is already running in another process
+    #[error(
+        "Task '{task}' is already running{}; pass --wait to wait for it to finish",
+        pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default()
+    )]
+    Locked {
+        /// Title of the task
+        task: String,
+        /// Lock file of the task
+        path: PathBuf,
+        /// Process holding the lock, if known
+        pid: Option<u32>,
+    },
+
+    /// A secret referenced as `secret:NAME` could not be fetched
+    #[error("Secret '{name}' is unavailable: {message}")]
+    Secret {
+        /// Name of the secret under `[secrets]`
+        name: String,
+        /// Why it could not be fetched
+        message: String,
+    },
+
+    /// A task runner file could not be imported
+    #[error("Import error: {0}")]
+    Import(String),
+
+    /// Parameters a task requires were neither given nor entered
+    #[error(
+        "Task '{task}' requires the parameter(s) {}; pass them with --param NAME=VALUE",
+        params.join(", ")
+    )]
+    MissingParameters {
+        /// Title of the task
+        task: String,
+        /// Names of the missing parameters
+        params: Vec<String>,
+    },
+
+    /// A `--param` is malformed or names a parameter no task declares
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    /// The files changed since a revision could not be listed with git
+    #[error("Git error: {0}")]
+    Git(String),
+
+    /// A task was cancelled while running
+    #[error("Task '{0}' was cancelled")]
+    Cancelled(String),
+
+    /// The process running a code block exited unsuccessfully
+    #[error("{lang} execution failed ({status})")]
+    CommandFailed {
+        /// Language of the code block
+        lang: String,
+        /// Program that ran the code block, e.g. `python3`
+        command: String,
+        /// Exit status of the process
+        status: ExitStatus,
+    },
+
+    /// A code block of a task failed
+    #[error("Task '{task}' failed at block {}: {source}", block_id(task, *block))]
+    BlockFailed {
+        /// Title of the task
+        task: String,
+        /// Task file the code block was read from, if known
+        file: Option<PathBuf>,
+        /// Index of the failed code block within the section
+        block: usize,
+        /// 1-based line number of the code fence, if known
+        line: Option<usize>,
+        /// Byte range of the fenced block in the Markdown source, if known
+        span: Option<Range<usize>>,
+        /// Exit code of the block's process, if it exited normally
+        exit_code: Option<i32>,
+        /// Underlying error
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Error for a task name that no task has, without suggestions
+    pub fn section_not_found(task: impl Into<String>) -> Self {
+        Error::SectionNotFound {
+            task: task.into(),
+            suggestions: Vec::new(),
+        }
+    }
+}
+
+fn suggestion_hint(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        [title] => format!("; did you mean '{}'?", title),
+        titles => format!("; did you mean one of '{}'?", titles.join("', '")),
+    }
+}
+
+fn ambiguity(files: &[PathBuf], titles: &[String]) -> String {
+    if titles.is_empty() {
+        format!("is defined in multiple files: {}", display_paths(files))
+    } else {
+        format!("matches several tasks: '{}'", titles.join("', '"))
+    }
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,281 @@
+//! Run events and observers
+//!
+//! Library users instrument execution by adding a [`RunnerObserver`] to the runner. For
+//! the common case of just recording what happened, a runner also reports a stream of
+//! [`RunEvent`]s to an [`EventHandler`]; [`JsonlWriter`] turns them into one JSON object per
+//! line, as printed by `mx run --output jsonl`:
+//!
+//! ```text
+//! {"event":"task_started","task":"Build","total_blocks":2}
+//! {"event":"block_started","task":"Build","id":"Build#0","index":0,"lang":"bash"}
+//! {"event":"block_finished","task":"Build","id":"Build#0","index":0,"lang":"bash","exit_code":0,"duration_ms":12,"success":true}
+//! {"event":"task_finished","task":"Build","duration_ms":30,"success":true}
+//! ```
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::report::{BlockReport, ExecutionReport, block_id};
+use crate::runner::{CodeBlock, OutputStream, Section};
+
+/// Hooks called while a runner executes sections
+///
+/// Every method has an empty default, so observers only implement what they need.
+pub trait RunnerObserver: Send + Sync {
+    /// A section is about to run (it may still turn out to be skipped)
+    fn on_section_start(&self, _section: &Section) {}
+
+    /// A code block is about to run
+    fn on_block_start(&self, _section: &Section, _index: usize, _block: &CodeBlock) {}
+
+    /// A chunk of output of the running block, identified by its block ID
+    fn on_block_output(&self, _id: &str, _stream: OutputStream, _data: &[u8]) {}
+
+    /// A code block finished, failed or was skipped
+    fn on_block_complete(&self, _section: &Section, _outcome: &BlockOutcome) {}
+
+    /// A section finished; on failure the report holds the blocks completed so far
+    fn on_section_complete(&self, _report: &ExecutionReport, _success: bool) {}
+}
+
+/// How a code block ended
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockOutcome {
+    /// Timing of the block, with the reason if it was skipped
+    pub report: BlockReport,
+    /// Exit code of the process, or None if it never ran, could not start or was killed
+    pub exit_code: Option<i32>,
+    /// Whether the block succeeded; skipped blocks count as successful
+    pub success: bool,
+}
+
+/// Something that happened while running a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RunEvent {
+    /// A task is about to execute its code blocks
+    TaskStarted { task: String, total_blocks: usize },
+    /// A code block is about to execute
+    BlockStarted {
+        task: String,
+        /// Stable block identifier, e.g. `Build#1`
+        id: String,
+        index: usize,
+        lang: String,
+    },
+    /// A code block was skipped because of its conditions
+    BlockSkipped {
+        task: String,
+        id: String,
+        index: usize,
+        lang: String,
+        reason: String,
+    },
+    /// A code block finished, successfully or not
+    BlockFinished {
+        task: String,
+        id: String,
+        index: usize,
+        lang: String,
+        /// Exit code of the process, or None if it could not be started or was killed by a signal
+        exit_code: Option<i32>,
+        duration_ms: u64,
+        success: bool,
+    },
+    /// A task finished, successfully or not
+    TaskFinished {
+        task: String,
+        duration_ms: u64,
+        success: bool,
+        /// Why the whole task was skipped, if it was
+        #[serde(skip_serializing_if = "Option::is_none")]
+        skipped: Option<String>,
+    },
+}
+
+/// Callback receiving the events of a run
+pub type EventHandler = Arc<dyn Fn(&RunEvent) + Send + Sync>;
+
+/// Writes events as JSON lines
+pub struct JsonlWriter {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonlWriter {
+    /// Write events to any writer
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Write events to stdout
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    /// Write events to an open file descriptor inherited from the parent process
+    ///
+    /// The descriptor is owned by the parent, so it is never closed.
+    #[cfg(unix)]
+    pub fn from_fd(fd: i32) -> Self {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: the caller hands us a descriptor it keeps open for the lifetime of the
+        // process, and ManuallyDrop ensures we never close it.
+        let file = unsafe { File::from_raw_fd(fd) };
+        Self::new(InheritedFd(ManuallyDrop::new(file)))
+    }
+
+    /// Write an event; write errors are logged and otherwise ignored
+    pub fn write(&self, event: &RunEvent) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+
+        let result = match self.writer.lock() {
+            Ok(mut writer) => writer
+                .write_all(line.as_bytes())
+                .and_then(|_| writer.flush()),
+            Err(_) => return,
+        };
+        if let Err(e) = result {
+            warn!("failed to write run event: {}", e);
+        }
+    }
+
+    /// Turn the writer into an event handler for [`Runner::with_event_handler`]
+    ///
+    /// [`Runner::with_event_handler`]: crate::Runner::with_event_handler
+    pub fn into_handler(self) -> EventHandler {
+        Arc::new(move |event| self.write(event))
+    }
+}
+
+/// Observer translating runner callbacks into [`RunEvent`]s
+pub(crate) struct EventObserver(pub(crate) EventHandler);
+
+impl RunnerObserver for EventObserver {
+    fn on_section_start(&self, section: &Section) {
+        (self.0)(&RunEvent::TaskStarted {
+            task: section.title.clone(),
+            total_blocks: section.codes.len(),
+        });
+    }
+
+    fn on_block_start(&self, section: &Section, index: usize, block: &CodeBlock) {
+        (self.0)(&RunEvent::BlockStarted {
+            task: section.title.clone(),
+            id: block_id(&section.title, index),
+            index,
+            lang: block.lang.clone(),
+        });
+    }
+
+    fn on_block_complete(&self, section: &Section, outcome: &BlockOutcome) {
+        let block = &outcome.report;
+        let event = match &block.skipped {
+            Some(reason) => RunEvent::BlockSkipped {
+                task: section.title.clone(),
+                id: block.id.clone(),
+                index: block.index,
+                lang: block.lang.clone(),
+                reason: reason.clone(),
+            },
+            None => RunEvent::BlockFinished {
+                task: section.title.clone(),
+                id: block.id.clone(),
+                index: block.index,
+                lang: block.lang.clone(),
+                exit_code: outcome.exit_code,
+                duration_ms: millis(block.duration),
+                success: outcome.success,
+            },
+        };
+        (self.0)(&event);
+    }
+
+    fn on_section_complete(&self, report: &ExecutionReport, success: bool) {
+        (self.0)(&RunEvent::TaskFinished {
+            task: report.task.clone(),
+            duration_ms: millis(report.duration),
+            success,
+            skipped: report.skipped.clone(),
+        });
+    }
+}
+
+/// A file that is not closed on drop
+struct InheritedFd(ManuallyDrop<File>);
+
+impl Write for InheritedFd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+/// Milliseconds of a duration, as reported in events
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_jsonl_writer() {
+        let buffer = Buffer::default();
+        let handler = JsonlWriter::new(buffer.clone()).into_handler();
+
+        handler(&RunEvent::BlockFinished {
+            task: "Build".to_string(),
+            id: "Build#0".to_string(),
+            index: 0,
+            lang: "bash".to_string(),
+            exit_code: Some(2),
+            duration_ms: 5,
+            success: false,
+        });
+        handler(&RunEvent::TaskFinished {
+            task: "Build".to_string(),
+            duration_ms: 7,
+            success: false,
+            skipped: None,
+        });
+
+        assert_eq!(
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap(),
+            concat!(
+                r#"{"event":"block_finished","task":"Build","id":"Build#0","index":0,"lang":"bash","exit_code":2,"duration_ms":5,"success":false}"#,
+                "\n",
+                r#"{"event":"task_finished","task":"Build","duration_ms":7,"success":false}"#,
+                "\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Expected output of code blocks
+//!
+//! A code block followed by an ` ```output ` block is expected to print exactly that on
+//! stdout, which turns documentation into tests: `mx test` runs tasks with an
+//! [`OutputCapture`] observer and reports the blocks whose output differs. Trailing
+//! whitespace on each line and trailing blank lines are ignored.
+//!
+//! `mx run --record` goes the other way, writing what each block printed into its output
+//! block with [`record_outputs`], so examples in documentation stay current.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::events::RunnerObserver;
+use crate::report::{ExecutionReport, block_id};
+use crate::runner::{OutputStream, Section};
+use crate::source::{code_fence, find_fences};
+
+/// Language of the block holding the expected output of the block before it
+pub const OUTPUT_LANG: &str = "output";
+
+/// Observer collecting the stdout of each code block, by block ID
+#[derive(Debug, Default)]
+pub struct OutputCapture {
+    stdout: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl OutputCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything a block printed on stdout so far, lossily decoded
+    pub fn stdout(&self, id: &str) -> String {
+        self.stdout
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|data| String::from_utf8_lossy(data).into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Forget the output captured so far
+    pub fn clear(&self) {
+        self.stdout.lock().unwrap().clear();
+    }
+}
+
+impl RunnerObserver for OutputCapture {
+    fn on_block_output(&self, id: &str, stream: OutputStream, data: &[u8]) {
+        if stream == OutputStream::Stdout {
+            self.stdout
+                .lock()
+                .unwrap()
+                .entry(id.to_string())
+                .or_default()
+                .extend_from_slice(data);
+        }
+    }
+}
+
+/// A code block that printed something other than its expected output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputMismatch {
+    /// Block ID, e.g. `Greet#0`
+    pub id: String,
+    /// 1-based line number of the code fence, if known
+    pub line: Option<usize>,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl OutputMismatch {
+    /// Line diff from the expected to the actual output
+    pub fn diff(&self) -> Vec<DiffLine<'_>> {
+        diff_lines(&self.expected, &self.actual)
+    }
+}
+
+/// A line of a diff between expected and actual output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    /// In both outputs
+    Same(&'a str),
+    /// Expected but not printed
+    Missing(&'a str),
+    /// Printed but not expected
+    Unexpected(&'a str),
+}
+
+/// Compare the captured stdout of the blocks that ran with their expected output
+pub fn check_outputs(
+    section: &Section,
+    report: &ExecutionReport,
+    capture: &OutputCapture,
+) -> Vec<OutputMismatch> {
+    report
+        .blocks
+        .iter()
+        .filter(|block| block.skipped.is_none())
+        .filter_map(|block| {
+            let code = section.codes.get(block.index)?;
+            let expected = code.expected_output.as_deref()?;
+            let actual = capture.stdout(&block_id(&section.title, block.index));
+            (normalize(expected) != normalize(&actual)).then(|| OutputMismatch {
+                id: block.id.clone(),
+                line: code.line,
+                expected: normalize(expected),
+                actual: normalize(&actual),
+            })
+        })
+        .collect()
+}
+
+/// Markdown with the captured stdout of each block that ran in an output block after it
+///
+/// An output block right after a code block is replaced, otherwise one is inserted. Blocks
+/// whose position in the Markdown is unknown are left alone. Returns the Markdown and the
+/// number of output blocks written.
+pub fn record_outputs(
+    markdown: &str,
+    section: &Section,
+    report: &ExecutionReport,
+    capture: &OutputCapture,
+) -> (String, usize) {
+    let mut edits: Vec<(std::ops::Range<usize>, String)> = report
+        .blocks
+        .iter()
+        .filter(|block| block.skipped.is_none())
+        .filter_map(|block| {
+            let end = section.codes.get(block.index)?.span.as_ref()?.end;
+            let output = normalize(&capture.stdout(&block_id(&section.title, block.index)));
+            let output = if output.is_empty() {
+                output
+            } else {
+                format!("{}\n", output)
+            };
+            let fence = code_fence(&output);
+            let output_block = format!("{}{}\n{}{}", fence, OUTPUT_LANG, output, fence);
+
+            let rest = markdown.get(end..)?;
+            match find_fences(rest).first() {
+                Some(next)
+                    if next.lang == OUTPUT_LANG && rest[..next.span.start].trim().is_empty() =>
+                {
+                    Some((end + next.span.start..end + next.span.end, output_block))
+                }
+                _ => Some((end..end, format!("\n\n{}", output_block))),
+            }
+        })
+        .collect();
+
+    // From the end, so the positions of earlier edits stay valid
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut recorded = markdown.to_string();
+    for (range, text) in &edits {
+        recorded.replace_range(range.clone(), text);
+    }
+    (recorded, edits.len())
+}
+
+/// Drop trailing whitespace from every line and trailing blank lines
+fn normalize(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_end_matches('\n').to_string()
+}
+
+/// Diff two texts line by line, keeping their longest common subsequence
+pub fn diff_lines<'a>(expected: &'a str, actual: &'a str) -> Vec<DiffLine<'a>> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // common[i][j]: length of the common subsequence of expected[i..] and actual[j..]
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut diff = Vec::new();
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push(DiffLine::Same(expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j])
+        {
+            diff.push(DiffLine::Unexpected(actual[j]));
+            j += 1;
+        } else {
+            diff.push(DiffLine::Missing(expected[i]));
+            i += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::report::BlockReport;
+    use crate::runner::CodeBlock;
+
+    #[test]
+    fn test_check_outputs() {
+        let block = |expected: &str| CodeBlock {
+            lang: "sh".to_string(),
+            expected_output: Some(expected.to_string()),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Greet".to_string(),
+            codes: vec![block("hello  \n\n"), block("a\nb\nc")],
+            ..Default::default()
+        };
+        let mut report = ExecutionReport::new("Greet");
+        for index in 0..2 {
+            report.blocks.push(BlockReport {
+                id: block_id("Greet", index),
+                index,
+                lang: "sh".to_string(),
+                duration: Duration::ZERO,
+                skipped: None,
+            });
+        }
+
+        let capture = OutputCapture::new();
+        capture.on_block_output("Greet#0", OutputStream::Stdout, b"hello\n");
+        capture.on_block_output("Greet#0", OutputStream::Stderr, b"noise\n");
+        capture.on_block_output("Greet#1", OutputStream::Stdout, b"a\nc\nd\n");
+
+        let mismatches = check_outputs(&section, &report, &capture);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].id, "Greet#1");
+        assert_eq!(
+            mismatches[0].diff(),
+            [
+                DiffLine::Same("a"),
+                DiffLine::Missing("b"),
+                DiffLine::Same("c"),
+                DiffLine::Unexpected("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_outputs() {
+        let markdown = "## Greet\n\n```sh\necho hi\n```\n\n```output\nold\n```\n\n\
+                        ```sh\necho '```'\n```\nDone.\n";
+        // The section as extracted from a document, with the positions of its blocks
+        let section = |markdown: &str| {
+            let block = |code: &str| {
+                let start = markdown.find(code).unwrap() - "```sh\n".len();
+                let end = start + markdown[start..].find("\n```\n").unwrap() + 4;
+                CodeBlock {
+                    lang: "sh".to_string(),
+                    span: Some(start..end),
+                    ..Default::default()
+                }
+            };
+            Section {
+                title: "Greet".to_string(),
+                codes: vec![block("echo hi"), block("echo '")],
+                ..Default::default()
+            }
+        };
+        let mut report = ExecutionReport::new("Greet");
+        for index in 0..2 {
+            report.blocks.push(BlockReport {
+                id: block_id("Greet", index),
+                index,
+                lang: "sh".to_string(),
+                duration: Duration::ZERO,
+                skipped: None,
+            });
+        }
+
+        let capture = OutputCapture::new();
+        capture.on_block_output("Greet#0", OutputStream::Stdout, b"hi  \n\n");
+        capture.on_block_output("Greet#1", OutputStream::Stdout, b"```\n");
+
+        let (recorded, count) = record_outputs(markdown, &section(markdown), &report, &capture);
+        assert_eq!(count, 2);
+        assert_eq!(
+            recorded,
+            "## Greet\n\n```sh\necho hi\n```\n\n```output\nhi\n```\n\n\
+             ```sh\necho '```'\n```\n\n````output\n```\n````\nDone.\n"
+        );
+        // Recording again finds the output blocks it wrote
+        let (again, _) = record_outputs(&recorded, &section(&recorded), &report, &capture);
+        assert_eq!(again, recorded);
+    }
+}
@@ -0,0 +1,467 @@
+//! Export tasks to other task runners and CI
+//!
+//! `mx export --format makefile|justfile|package-json-scripts|github-actions` turns the tasks
+//! of Markdown files into Make targets, just recipes, npm scripts or the jobs of a GitHub
+//! Actions workflow, for teams moving between tools or calling tasks from existing tooling.
+//! Each generated target runs its task with
+//! `mx run --no-deps`, so runtimes, configuration and trust apply as usual, while the task's
+//! dependencies become dependencies in the target format:
+//!
+//! ```just
+//! # Build the project
+//! build: install
+//!     mx run Build --file README.md --no-deps
+//! ```
+//!
+//! Descriptions become comments, or a `scripts-info` object next to the npm scripts.
+//! Workflow jobs install mx, run their task with `--trust` as CI has no trust store, and
+//! list the jobs of its dependencies under `needs`.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::files::canonical;
+use crate::runner::{Runner, Section};
+
+/// A task runner format tasks can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A GNU Make `Makefile`
+    Makefile,
+    /// A `justfile` for just
+    Justfile,
+    /// The `scripts` of a `package.json`
+    PackageJsonScripts,
+    /// A GitHub Actions workflow with a job per task
+    GithubActions,
+}
+
+impl ExportFormat {
+    /// Every format, in the order they are listed
+    pub const ALL: &[ExportFormat] = &[
+        ExportFormat::Makefile,
+        ExportFormat::Justfile,
+        ExportFormat::PackageJsonScripts,
+        ExportFormat::GithubActions,
+    ];
+
+    /// Name used on the command line, e.g. `package-json-scripts`
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Makefile => "makefile",
+            ExportFormat::Justfile => "justfile",
+            ExportFormat::PackageJsonScripts => "package-json-scripts",
+            ExportFormat::GithubActions => "github-actions",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ExportFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = ExportFormat::ALL.iter().map(|f| f.name()).collect();
+                Error::Config(format!(
+                    "Unknown export format '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// A task to export
+#[derive(Debug, Clone)]
+pub struct ExportTask {
+    /// Target, recipe or script name, unique among the exported tasks
+    pub name: String,
+    /// Markdown file of the task
+    pub file: PathBuf,
+    pub section: Section,
+    /// Indexes of the tasks this task depends on
+    pub depends: Vec<usize>,
+}
+
+/// The tasks of Markdown files, or only those with a tag, and every task they depend on,
+/// dependencies first
+///
+/// Dependencies in other files are exported too. Fails like `mx run` on a missing
+/// dependency or a dependency cycle.
+pub fn collect_tasks<P: AsRef<Path>>(
+    runner: &mut Runner,
+    markdown_paths: &[P],
+    tag: Option<&str>,
+) -> Result<Vec<ExportTask>> {
+    let mut tasks: Vec<ExportTask> = Vec::new();
+    let mut index: HashMap<(PathBuf, String), usize> = HashMap::new();
+
+    for markdown_path in markdown_paths {
+        let markdown_path = markdown_path.as_ref();
+        let mut sections = runner.list_task_sections(markdown_path)?;
+        if let Some(tag) = tag {
+            sections.retain(|section| section.has_tag(tag));
+        }
+        for section in sections {
+            for (file, section) in runner.resolve_dependencies(markdown_path, &section)? {
+                let key = (canonical(file.clone()), section.title.clone());
+                if index.contains_key(&key) {
+                    continue;
+                }
+                index.insert(key, tasks.len());
+                tasks.push(ExportTask {
+                    name: unique_name(&tasks, &section.title),
+                    file,
+                    section,
+                    depends: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // Dependencies were resolved above, so each one is among the tasks
+    let mut files = HashMap::new();
+    for task in &mut tasks {
+        for dependency in task.section.dependencies() {
+            let missing = || Error::MissingDependency {
+                task: task.section.title.clone(),
+                dependency: dependency.clone(),
+            };
+            if let Some((path, found)) =
+                runner.find_dependency(&task.file, &dependency, &mut files, missing)?
+                && let Some(&depends) = index.get(&(canonical(path), found.title))
+                && !task.depends.contains(&depends)
+            {
+                task.depends.push(depends);
+            }
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// A name for a task usable by every format: ASCII letters, digits, `-` and `_`, starting
+/// with a letter, and not taken by another task
+fn unique_name(tasks: &[ExportTask], title: &str) -> String {
+    let mut name: String = title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '_' => Some(c),
+            ' ' => Some('-'),
+            _ => None,
+        })
+        .collect();
+    if name.is_empty() {
+        name = "task".to_string();
+    } else if !name.starts_with(|c: char| c.is_ascii_lowercase()) {
+        name.insert_str(0, "task-");
+    }
+
+    let taken = |name: &str| tasks.iter().any(|task| task.name == name);
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|name| !taken(name))
+        .unwrap()
+}
+
+/// The `mx` command line running a task without its dependencies
+fn run_command(task: &ExportTask) -> String {
+    shell_words::join([
+        "mx",
+        "run",
+        &task.section.title,
+        "--file",
+        &task.file.to_string_lossy(),
+        "--no-deps",
+    ])
+}
+
+/// Lines of a task's description, if it has one
+fn description(task: &ExportTask) -> Vec<&str> {
+    task.section
+        .description
+        .as_deref()
+        .map(|description| description.trim().lines().map(str::trim_end).collect())
+        .unwrap_or_default()
+}
+
+/// Render tasks in a task runner format
+pub fn export(format: ExportFormat, tasks: &[ExportTask]) -> String {
+    match format {
+        ExportFormat::Makefile => makefile(tasks),
+        ExportFormat::Justfile => justfile(tasks),
+        ExportFormat::PackageJsonScripts => package_json_scripts(tasks),
+        ExportFormat::GithubActions => github_actions(tasks),
+    }
+}
+
+const GENERATED: &str = "# Generated by `mx export`; each target runs a task with mx";
+
+/// Installer of the latest mx release
+const INSTALL_SCRIPT: &str =
+    "https://raw.githubusercontent.com/harehare/mx/refs/heads/main/bin/install.sh";
+
+fn makefile(tasks: &[ExportTask]) -> String {
+    let mut output = format!("{}\n", GENERATED);
+    let names: Vec<&str> = tasks.iter().map(|task| task.name.as_str()).collect();
+    let _ = writeln!(output, "\n.PHONY: {}", names.join(" "));
+
+    for task in tasks {
+        output.push('\n');
+        for line in description(task) {
+            // A trailing backslash would continue the comment onto the target
+            let _ = writeln!(output, "# {}", line.trim_end_matches('\\'));
+        }
+        let _ = write!(output, "{}:", task.name);
+        for &depends in &task.depends {
+            let _ = write!(output, " {}", tasks[depends].name);
+        }
+        let _ = writeln!(output, "\n\t{}", run_command(task).replace('$', "$$"));
+    }
+    output
+}
+
+fn justfile(tasks: &[ExportTask]) -> String {
+    let mut output = format!("{}\n", GENERATED);
+
+    for task in tasks {
+        output.push('\n');
+        for line in description(task) {
+            let _ = writeln!(output, "# {}", line);
+        }
+        let _ = write!(output, "{}:", task.name);
+        for &depends in &task.depends {
+            let _ = write!(output, " {}", tasks[depends].name);
+        }
+        let _ = writeln!(
+            output,
+            "\n    {}",
+            run_command(task).replace("{{", "{{ \"{{\" }}")
+        );
+    }
+    output
+}
+
+fn package_json_scripts(tasks: &[ExportTask]) -> String {
+    let mut scripts = serde_json::Map::new();
+    let mut info = serde_json::Map::new();
+
+    for task in tasks {
+        // npm has no dependencies between scripts, so a script runs those it depends on first
+        let mut commands: Vec<String> = task
+            .depends
+            .iter()
+            .map(|&depends| format!("npm run {}", tasks[depends].name))
+            .collect();
+        commands.push(run_command(task));
+        scripts.insert(task.name.clone(), commands.join(" && ").into());
+
+        let description = description(task);
+        if !description.is_empty() {
+            info.insert(task.name.clone(), description.join("\n").into());
+        }
+    }
+
+    let mut package = serde_json::Map::new();
+    package.insert("scripts".to_string(), scripts.into());
+    if !info.is_empty() {
+        package.insert("scripts-info".to_string(), info.into());
+    }
+    let mut output = serde_json::to_string_pretty(&package).unwrap_or_default();
+    output.push('\n');
+    output
+}
+
+/// A YAML string, written as JSON, which YAML reads the same
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn github_actions(tasks: &[ExportTask]) -> String {
+    let mut output = format!(
+        "{}\nname: Tasks\n\non:\n  push:\n  pull_request:\n\njobs:\n",
+        GENERATED
+    );
+
+    for task in tasks {
+        let _ = writeln!(output, "  {}:", task.name);
+        let _ = writeln!(output, "    name: {}", yaml_string(&task.section.title));
+        if !task.depends.is_empty() {
+            let needs: Vec<&str> = task
+                .depends
+                .iter()
+                .map(|&depends| tasks[depends].name.as_str())
+                .collect();
+            let _ = writeln!(output, "    needs: [{}]", needs.join(", "));
+        }
+        let _ = writeln!(output, "    runs-on: ubuntu-latest");
+        let _ = writeln!(output, "    steps:");
+        let _ = writeln!(output, "      - uses: actions/checkout@v4");
+        let _ = writeln!(output, "      - name: Install mx");
+        let _ = writeln!(output, "        run: |");
+        let _ = writeln!(output, "          curl -sSL {} | bash", INSTALL_SCRIPT);
+        let _ = writeln!(
+            output,
+            "          echo \"$HOME/.mx/bin\" >> \"$GITHUB_PATH\""
+        );
+        let step = format!("Run {}", task.section.title);
+        let _ = writeln!(output, "      - name: {}", yaml_string(&step));
+        let _ = writeln!(
+            output,
+            "        run: {}",
+            yaml_string(&format!("{} --trust", run_command(task)))
+        );
+        output.push('\n');
+    }
+    output.pop();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use super::*;
+    use crate::extract::SectionExtractor;
+
+    fn tasks() -> Vec<ExportTask> {
+        let task = |name: &str, title: &str, description: Option<&str>, depends| ExportTask {
+            name: name.to_string(),
+            file: PathBuf::from("docs/tasks.md"),
+            section: Section {
+                title: title.to_string(),
+                description: description.map(str::to_string),
+                ..Default::default()
+            },
+            depends,
+        };
+        vec![
+            task("install", "Install", None, vec![]),
+            task(
+                "build-app",
+                "Build app",
+                Some("Build the app\nfor $TARGET"),
+                vec![0],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_export_formats() {
+        assert_eq!(
+            export(ExportFormat::Makefile, &tasks()),
+            format!(
+                "{}\n\n.PHONY: install build-app\n\ninstall:\n\tmx run Install --file \
+                 docs/tasks.md --no-deps\n\n# Build the app\n# for $TARGET\nbuild-app: install\n\
+                 \tmx run 'Build app' --file docs/tasks.md --no-deps\n",
+                GENERATED
+            )
+        );
+
+        let justfile = export(ExportFormat::Justfile, &tasks());
+        assert!(justfile.contains(
+            "# for $TARGET\nbuild-app: install\n    \
+             mx run 'Build app' --file docs/tasks.md --no-deps\n"
+        ));
+
+        let package: serde_json::Value =
+            serde_json::from_str(&export(ExportFormat::PackageJsonScripts, &tasks())).unwrap();
+        assert_eq!(
+            package["scripts"]["build-app"],
+            "npm run install && mx run 'Build app' --file docs/tasks.md --no-deps"
+        );
+        assert_eq!(
+            package["scripts-info"]["build-app"],
+            "Build the app\nfor $TARGET"
+        );
+        assert!(package["scripts-info"].get("install").is_none());
+    }
+
+    #[test]
+    fn test_export_github_actions() {
+        let workflow = export(ExportFormat::GithubActions, &tasks());
+        assert!(workflow.contains("\njobs:\n  install:\n    name: \"Install\"\n    runs-on:"));
+        assert!(workflow.contains(
+            "  build-app:\n    name: \"Build app\"\n    needs: [install]\n    \
+             runs-on: ubuntu-latest\n"
+        ));
+        assert!(workflow.contains(
+            "      - name: \"Run Build app\"\n        \
+             run: \"mx run 'Build app' --file docs/tasks.md --no-deps --trust\"\n"
+        ));
+        assert!(workflow.ends_with("--trust\"\n"));
+    }
+
+    /// Reads a task per line, as `Title` or `Title: dependency`
+    struct LineTasks;
+
+    impl SectionExtractor for LineTasks {
+        fn extract(&mut self, markdown: &str, _level: u8) -> Result<Vec<Section>> {
+            let task = |line: &str| {
+                let (title, depends) = line.split_once(": ").unwrap_or((line, ""));
+                Section {
+                    title: title.to_string(),
+                    level: 2,
+                    metadata: BTreeMap::from([("depends".to_string(), depends.to_string())]),
+                    ..Default::default()
+                }
+            };
+            Ok(markdown.lines().map(task).collect())
+        }
+    }
+
+    #[test]
+    fn test_namespaced_dependencies_are_exported() {
+        let temp = tempfile::tempdir().unwrap();
+        let readme = temp.path().join("README.md");
+        fs::write(&readme, "Deploy: db:migrate").unwrap();
+        fs::write(temp.path().join("db.md"), "Migrate").unwrap();
+        let mut runner = Runner::with_default_config().with_extractor(LineTasks);
+
+        let tasks = collect_tasks(&mut runner, &[&readme], None).unwrap();
+        let titles: Vec<_> = tasks
+            .iter()
+            .map(|task| task.section.title.as_str())
+            .collect();
+        assert_eq!(titles, ["Migrate", "Deploy"]);
+        assert_eq!(tasks[1].depends, [0]);
+        assert!(export(ExportFormat::Makefile, &tasks).contains("\ndeploy: migrate\n"));
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let mut tasks = tasks();
+        assert_eq!(unique_name(&tasks, "Run E2E tests!"), "run-e2e-tests");
+        assert_eq!(unique_name(&tasks, "Install"), "install-2");
+        assert_eq!(unique_name(&tasks, "2nd step"), "task-2nd-step");
+        tasks[0].name = "task".to_string();
+        assert_eq!(unique_name(&tasks, "デプロイ"), "task-2");
+    }
+
+    #[test]
+    fn test_format_names() {
+        for &format in ExportFormat::ALL {
+            assert_eq!(format.name().parse::<ExportFormat>().unwrap(), format);
+        }
+        assert!("rakefile".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,436 @@
+//! Section extraction backends
+//!
+//! The runner finds the sections of a document with a [`SectionExtractor`], then locates them
+//! in the source for their positions, metadata and descriptions. Two backends come with mx,
+//! each compiled in with the feature of the same name and chosen with `extractor` in
+//! `mx.toml`:
+//!
+//! - `mq` (default) evaluates the bundled `sections.mq`, or the configured `sections_query`,
+//!   with the mq engine, and runs the queries of `mx query`
+//! - `cmark` walks the events of pulldown-cmark, which starts faster and makes a smaller
+//!   binary for users who don't customize extraction
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::expect::OUTPUT_LANG;
+use crate::runner::{CodeBlock, Section};
+use crate::source::parse_heading;
+
+#[cfg(not(any(feature = "mq", feature = "cmark")))]
+compile_error!("mx needs the `mq` or `cmark` feature to extract sections");
+
+/// Finds the sections of a Markdown document
+pub trait SectionExtractor: Send {
+    /// Sections of one heading level in document order, each running to the next heading of
+    /// that level, with their titles, tags, attributes, code blocks and descriptions
+    fn extract(&mut self, markdown: &str, level: u8) -> Result<Vec<Section>>;
+
+    /// Evaluate an mq query over a document, returning the values it yields as text
+    fn query(&mut self, _markdown: &str, _query: &str) -> Result<Vec<String>> {
+        Err(Error::Config(
+            "mq queries need the mq extractor".to_string(),
+        ))
+    }
+}
+
+/// Extraction backend, set with `extractor` in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Extractor {
+    /// The mq engine, with a customizable query
+    Mq,
+    /// pulldown-cmark
+    Cmark,
+}
+
+impl Default for Extractor {
+    /// `mq` when it is compiled in
+    fn default() -> Self {
+        if cfg!(feature = "mq") {
+            Self::Mq
+        } else {
+            Self::Cmark
+        }
+    }
+}
+
+impl Extractor {
+    /// Name used in the configuration and as the feature compiling it in
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mq => "mq",
+            Self::Cmark => "cmark",
+        }
+    }
+}
+
+/// The extractor configured, or one failing every extraction with the reason it can't be used
+pub fn from_config(config: &Config) -> Box<dyn SectionExtractor> {
+    match config.extractor {
+        #[cfg(feature = "mq")]
+        Extractor::Mq => Box::new(MqExtractor::new(config)),
+        #[cfg(feature = "cmark")]
+        Extractor::Cmark => Box::new(CmarkExtractor),
+        #[allow(unreachable_patterns)]
+        extractor => Box::new(Unavailable(format!(
+            "the {} extractor is not compiled in; build mx with the `{}` feature",
+            extractor.name(),
+            extractor.name()
+        ))),
+    }
+}
+
+/// Stands in for an extractor that can't be used, failing with the reason
+struct Unavailable(String);
+
+impl SectionExtractor for Unavailable {
+    fn extract(&mut self, _markdown: &str, _level: u8) -> Result<Vec<Section>> {
+        Err(Error::Config(self.0.clone()))
+    }
+
+    fn query(&mut self, _markdown: &str, _query: &str) -> Result<Vec<String>> {
+        Err(Error::Config(self.0.clone()))
+    }
+}
+
+/// A section from its heading text, with tags and attributes split off the title
+fn section(
+    heading: &str,
+    level: u8,
+    codes: Vec<CodeBlock>,
+    description: Option<String>,
+) -> Section {
+    let heading = parse_heading(heading);
+    Section {
+        title: heading.title.to_string(),
+        level,
+        codes,
+        description,
+        tags: heading.tags,
+        meta: heading.attributes.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+/// Add a code block to a section's blocks; an output block holds the expected output of the
+/// block before it instead
+fn push_block(blocks: &mut Vec<CodeBlock>, lang: String, code: String) {
+    if lang == OUTPUT_LANG
+        && let Some(previous) = blocks.last_mut()
+        && previous.expected_output.is_none()
+    {
+        previous.expected_output = Some(code);
+        return;
+    }
+
+    blocks.push(CodeBlock {
+        lang,
+        code,
+        ..Default::default()
+    });
+}
+
+#[cfg(feature = "mq")]
+pub use self::mq::MqExtractor;
+
+#[cfg(feature = "mq")]
+mod mq {
+    use std::collections::BTreeMap;
+
+    use mq_lang::{Engine, Ident, RuntimeValue, parse_markdown_input};
+
+    use super::{SectionExtractor, push_block, section};
+    use crate::config::Config;
+    use crate::error::{Error, Result};
+    use crate::runner::{CodeBlock, Section};
+
+    pub(super) const SECTIONS_QUERY: &str = include_str!("../sections.mq");
+
+    /// The mq module defining `sections_with_code`: the configured `sections_query`, read
+    /// from the file it names if it is a path to a `.mq` file, or the bundled `sections.mq`
+    pub(super) fn sections_module(config: &Config) -> Result<String> {
+        let Some(query) = &config.sections_query else {
+            return Ok(SECTIONS_QUERY.to_string());
+        };
+        let path = query.trim();
+        if path.ends_with(".mq") && !path.contains('\n') {
+            std::fs::read_to_string(path).map_err(|e| {
+                Error::Config(format!("Failed to read sections query {}: {}", path, e))
+            })
+        } else {
+            Ok(query.clone())
+        }
+    }
+
+    /// Extracts sections with the mq engine
+    pub struct MqExtractor {
+        /// Initialized on first use, as loading its builtin module takes a while
+        engine: Option<Engine>,
+        /// The mq module extracting sections, or why it could not be loaded
+        module: std::result::Result<String, String>,
+    }
+
+    impl MqExtractor {
+        pub fn new(config: &Config) -> Self {
+            Self {
+                engine: None,
+                module: sections_module(config).map_err(|e| e.to_string()),
+            }
+        }
+
+        /// Evaluate a query over a document, after the definitions of the sections module
+        fn eval(&mut self, markdown: &str, query: &str) -> Result<mq_lang::RuntimeValues> {
+            let module = self
+                .module
+                .as_deref()
+                .map_err(|e| Error::Config(e.to_string()))?;
+            let query = format!("{}\n | {}", module, query);
+            let input = parse_markdown_input(markdown)
+                .map_err(|e| Error::Markdown(format!("Failed to parse markdown: {}", e)))?;
+
+            let engine = self.engine.get_or_insert_with(|| {
+                let mut engine = Engine::default();
+                engine.load_builtin_module();
+                engine
+            });
+            engine
+                .eval(&query, input.into_iter())
+                .map_err(|e| Error::Query(format!("Failed to execute query: {}", e)))
+        }
+    }
+
+    impl SectionExtractor for MqExtractor {
+        fn extract(&mut self, markdown: &str, level: u8) -> Result<Vec<Section>> {
+            let query = format!("nodes | sections_with_code({})", level);
+            let result = self.eval(markdown, &query)?;
+
+            Ok(result
+                .into_iter()
+                .filter_map(|value| match value {
+                    RuntimeValue::Dict(dict) => Some(parse_section(&dict, level)),
+                    _ => None,
+                })
+                .collect())
+        }
+
+        /// `None` values are left out
+        fn query(&mut self, markdown: &str, query: &str) -> Result<Vec<String>> {
+            Ok(self
+                .eval(markdown, query)?
+                .into_iter()
+                .filter(|value| !matches!(value, RuntimeValue::None))
+                .map(|value| value.to_string())
+                .collect())
+        }
+    }
+
+    fn string(dict: &BTreeMap<Ident, RuntimeValue>, key: &str) -> Option<String> {
+        match dict.get(&Ident::from(key)) {
+            Some(RuntimeValue::String(s)) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+
+    fn parse_section(dict: &BTreeMap<Ident, RuntimeValue>, level: u8) -> Section {
+        let level = match dict.get(&Ident::from("level")) {
+            Some(RuntimeValue::Number(n)) => n.value() as u8,
+            _ => level,
+        };
+
+        let mut codes: Vec<CodeBlock> = Vec::new();
+        if let Some(RuntimeValue::Array(blocks)) = dict.get(&Ident::from("codes")) {
+            for block in blocks {
+                if let RuntimeValue::Dict(block) = block {
+                    push_block(
+                        &mut codes,
+                        string(block, "lang").unwrap_or_default(),
+                        string(block, "code").unwrap_or_default(),
+                    );
+                }
+            }
+        }
+
+        section(
+            &string(dict, "title").unwrap_or_default(),
+            level,
+            codes,
+            string(dict, "description"),
+        )
+    }
+}
+
+#[cfg(feature = "cmark")]
+pub use self::cmark::CmarkExtractor;
+
+#[cfg(feature = "cmark")]
+mod cmark {
+    use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+    use super::{SectionExtractor, push_block, section};
+    use crate::error::Result;
+    use crate::runner::{CodeBlock, Section};
+
+    /// Extracts sections with pulldown-cmark
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct CmarkExtractor;
+
+    /// A section being read: its heading, code blocks and first paragraph
+    struct Partial {
+        heading: String,
+        codes: Vec<CodeBlock>,
+        description: Option<String>,
+    }
+
+    /// Text being collected from the events inside a heading, code block or paragraph
+    enum Inline {
+        Heading(u8, String),
+        Code(String, String),
+        Paragraph(String),
+    }
+
+    impl SectionExtractor for CmarkExtractor {
+        fn extract(&mut self, markdown: &str, level: u8) -> Result<Vec<Section>> {
+            let mut sections = Vec::new();
+            let mut current: Option<Partial> = None;
+            let mut inline: Option<Inline> = None;
+            let finish = |partial: Partial| {
+                section(&partial.heading, level, partial.codes, partial.description)
+            };
+
+            for event in Parser::new_ext(markdown, Options::ENABLE_TABLES) {
+                match event {
+                    Event::Start(Tag::Heading { level: depth, .. }) => {
+                        inline = Some(Inline::Heading(depth as u8, String::new()));
+                    }
+                    Event::Start(Tag::CodeBlock(kind)) => {
+                        let lang = match kind {
+                            CodeBlockKind::Fenced(info) => info
+                                .split_whitespace()
+                                .next()
+                                .unwrap_or_default()
+                                .to_string(),
+                            CodeBlockKind::Indented => String::new(),
+                        };
+                        inline = Some(Inline::Code(lang, String::new()));
+                    }
+                    Event::Start(Tag::Paragraph)
+                        if current
+                            .as_ref()
+                            .is_some_and(|partial| partial.description.is_none()) =>
+                    {
+                        inline = Some(Inline::Paragraph(String::new()));
+                    }
+                    Event::End(TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Paragraph) => {
+                        match inline.take() {
+                            Some(Inline::Heading(depth, heading)) if depth == level => {
+                                sections.extend(current.take().map(finish));
+                                current = Some(Partial {
+                                    heading,
+                                    codes: Vec::new(),
+                                    description: None,
+                                });
+                            }
+                            Some(Inline::Code(lang, mut code)) => {
+                                if let Some(partial) = &mut current {
+                                    if code.ends_with('\n') {
+                                        code.pop();
+                                    }
+                                    push_block(&mut partial.codes, lang, code);
+                                }
+                            }
+                            Some(Inline::Paragraph(paragraph)) => {
+                                if let Some(partial) = &mut current {
+                                    partial.description = Some(paragraph);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    Event::Text(text) | Event::Code(text) => match &mut inline {
+                        Some(
+                            Inline::Heading(_, buffer)
+                            | Inline::Code(_, buffer)
+                            | Inline::Paragraph(buffer),
+                        ) => buffer.push_str(&text),
+                        None => {}
+                    },
+                    Event::SoftBreak | Event::HardBreak => {
+                        if let Some(Inline::Heading(_, buffer) | Inline::Paragraph(buffer)) =
+                            &mut inline
+                        {
+                            buffer.push(' ');
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            sections.extend(current.take().map(finish));
+            Ok(sections)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mq")]
+    #[test]
+    fn test_sections_module() {
+        use super::mq::{SECTIONS_QUERY, sections_module};
+
+        let mut config = Config::default();
+        assert_eq!(sections_module(&config).unwrap(), SECTIONS_QUERY);
+
+        let inline = "def sections_with_code(md_nodes, level): [] end";
+        config.sections_query = Some(inline.to_string());
+        assert_eq!(sections_module(&config).unwrap(), inline);
+
+        config.sections_query = Some("no/such/sections.mq".to_string());
+        assert!(matches!(sections_module(&config), Err(Error::Config(_))));
+        let err = MqExtractor::new(&config)
+            .extract("## Task\n", 2)
+            .unwrap_err();
+        assert!(err.to_string().contains("no/such/sections.mq"));
+    }
+
+    #[cfg(feature = "cmark")]
+    #[test]
+    fn test_cmark_extractor() {
+        let markdown = "# Project\n\nIntro\n\n## Build [ci] {os=linux}\n\nCompiles `mx`\nfast.\n\n\
+                        ```bash name=make\nmake\n```\n\n```output\ndone\n```\n\n\
+                        ### Notes\n\n```text\nexample\n```\n\n## Test\n\n    indented\n";
+        let sections = CmarkExtractor.extract(markdown, 2).unwrap();
+
+        assert_eq!(sections.len(), 2);
+        let build = &sections[0];
+        assert_eq!(build.title, "Build");
+        assert_eq!(build.tags, ["ci"]);
+        assert_eq!(build.meta.as_deref(), Some("os=linux"));
+        assert_eq!(build.description.as_deref(), Some("Compiles mx fast."));
+        let blocks: Vec<_> = build
+            .codes
+            .iter()
+            .map(|code| (code.lang.as_str(), code.code.as_str()))
+            .collect();
+        assert_eq!(blocks, [("bash", "make"), ("text", "example")]);
+        assert_eq!(build.codes[0].expected_output.as_deref(), Some("done"));
+
+        assert_eq!(sections[1].title, "Test");
+        assert_eq!(sections[1].codes[0].lang, "");
+        assert_eq!(sections[1].codes[0].code, "indented");
+    }
+
+    #[test]
+    fn test_unavailable_extractor() {
+        let mut extractor = Unavailable("the cmark extractor is not compiled in".to_string());
+        assert!(matches!(
+            extractor.extract("## Build\n", 2),
+            Err(Error::Config(_))
+        ));
+        assert!(extractor.query("## Build\n", "nodes").is_err());
+    }
+}
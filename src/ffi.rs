@@ -0,0 +1,254 @@
+//! C ABI for embedding mx in other languages
+//!
+//! Enabled with the `mx-ffi` feature. All strings are NUL-terminated UTF-8. Strings returned
+//! by mx must be released with [`mx_string_free`]. Functions returning an `int` use `0` for
+//! success and `-1` for failure, in which case [`mx_last_error`] describes the problem.
+//!
+//! ```c
+//! void on_output(void *user_data, int stream, const uint8_t *data, size_t len);
+//!
+//! char *tasks = mx_list_tasks("README.md", NULL);   // JSON array of sections
+//! mx_string_free(tasks);
+//!
+//! const char *args[] = {"--release"};
+//! if (mx_run_task("README.md", "Build", NULL, args, 1, on_output, ctx) != 0) {
+//!     char *error = mx_last_error();
+//!     mx_string_free(error);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::runner::{OutputStream, Runner};
+
+/// `stream` value passed to the output callback for standard output
+pub const MX_STREAM_STDOUT: c_int = 1;
+/// `stream` value passed to the output callback for standard error
+pub const MX_STREAM_STDERR: c_int = 2;
+
+/// Callback receiving code block output: `(user_data, stream, data, len)`
+///
+/// It is called from background threads, possibly concurrently for stdout and stderr.
+pub type MxOutputCallback =
+    extern "C" fn(user_data: *mut c_void, stream: c_int, data: *const u8, len: usize);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// List the tasks of a Markdown file as a JSON array of sections
+///
+/// `config_path` may be NULL to use the default configuration. Returns NULL on failure.
+///
+/// # Safety
+///
+/// `markdown_path` must be a valid C string; `config_path` must be NULL or a valid C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mx_list_tasks(
+    markdown_path: *const c_char,
+    config_path: *const c_char,
+) -> *mut c_char {
+    let result = guard(|| {
+        let markdown_path = unsafe { required_str(markdown_path, "markdown_path") }?;
+        let config = unsafe { load_config(config_path) }?;
+
+        let sections = Runner::new(config).list_task_sections(markdown_path)?;
+        serde_json::to_string(&sections)
+            .map_err(|e| Error::Execution(format!("Failed to serialize tasks: {}", e)))
+    });
+
+    match result {
+        Some(json) => into_c_string(json),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Run a task, passing its output to `callback`
+///
+/// If `callback` is NULL the output goes to the host process' stdout and stderr.
+/// `config_path` may be NULL to use the default configuration.
+///
+/// # Safety
+///
+/// `markdown_path` and `task` must be valid C strings, `config_path` NULL or a valid C
+/// string, and `args` must point to `args_len` valid C strings (or be NULL if `args_len`
+/// is 0). `user_data` is passed to `callback` as-is and must be safe to use from other threads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mx_run_task(
+    markdown_path: *const c_char,
+    task: *const c_char,
+    config_path: *const c_char,
+    args: *const *const c_char,
+    args_len: usize,
+    callback: Option<MxOutputCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    let result = guard(|| {
+        let markdown_path = unsafe { required_str(markdown_path, "markdown_path") }?;
+        let task = unsafe { required_str(task, "task") }?;
+        let config = unsafe { load_config(config_path) }?;
+        let args = unsafe { string_array(args, args_len) }?;
+
+        let mut runner = Runner::new(config);
+        if let Some(callback) = callback {
+            let user_data = UserData(user_data);
+            runner = runner.with_output_handler(Arc::new(move |stream, data: &[u8]| {
+                let stream = match stream {
+                    OutputStream::Stdout => MX_STREAM_STDOUT,
+                    OutputStream::Stderr => MX_STREAM_STDERR,
+                };
+                callback(user_data.get(), stream, data.as_ptr(), data.len());
+            }));
+        }
+
+        runner.run_task_with_args(markdown_path, task, &args)
+    });
+
+    match result {
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// Get the error message of the last failed call on this thread, or NULL if there was none
+#[unsafe(no_mangle)]
+pub extern "C" fn mx_last_error() -> *mut c_char {
+    match LAST_ERROR.with(|last| last.borrow_mut().take()) {
+        Some(message) => into_c_string(message),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free a string returned by mx
+///
+/// # Safety
+///
+/// `s` must be NULL or a pointer returned by mx that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mx_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Opaque host pointer handed back to the output callback
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+// SAFETY: the embedder guarantees `user_data` can be used from other threads (see mx_run_task).
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// Run `f`, recording its error or panic as the last error instead of unwinding into C
+fn guard<T>(f: impl FnOnce() -> Result<T>) -> Option<T> {
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => {
+            LAST_ERROR.with(|last| last.borrow_mut().take());
+            return Some(value);
+        }
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => "mx panicked".to_string(),
+    };
+
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    None
+}
+
+unsafe fn optional_str<'a>(s: *const c_char, name: &str) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(Some)
+        .map_err(|_| Error::Config(format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn required_str<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    unsafe { optional_str(s, name) }?
+        .ok_or_else(|| Error::Config(format!("{} must not be NULL", name)))
+}
+
+unsafe fn string_array(args: *const *const c_char, len: usize) -> Result<Vec<String>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if args.is_null() {
+        return Err(Error::Config("args must not be NULL".to_string()));
+    }
+
+    (0..len)
+        .map(|i| unsafe { required_str(*args.add(i), "args") }.map(str::to_string))
+        .collect()
+}
+
+unsafe fn load_config(config_path: *const c_char) -> Result<Config> {
+    match unsafe { optional_str(config_path, "config_path") }? {
+        Some(path) => Config::from_file(path),
+        None => Ok(Config::default()),
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Interior NULs cannot be represented; replace them rather than failing
+    CString::new(s.replace('\0', "\u{FFFD}"))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take_last_error() -> Option<String> {
+        let error = mx_last_error();
+        if error.is_null() {
+            return None;
+        }
+        let message = unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { mx_string_free(error) };
+        Some(message)
+    }
+
+    #[test]
+    fn test_errors_are_reported() {
+        let tasks = unsafe { mx_list_tasks(ptr::null(), ptr::null()) };
+        assert!(tasks.is_null());
+        assert_eq!(
+            take_last_error().as_deref(),
+            Some("Config error: markdown_path must not be NULL")
+        );
+        assert_eq!(take_last_error(), None);
+
+        let path = CString::new("does-not-exist.md").unwrap();
+        let task = CString::new("Build").unwrap();
+        let status = unsafe {
+            mx_run_task(
+                path.as_ptr(),
+                task.as_ptr(),
+                ptr::null(),
+                ptr::null(),
+                0,
+                None,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(status, -1);
+        assert!(take_last_error().unwrap().starts_with("IO error"));
+    }
+}
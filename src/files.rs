@@ -0,0 +1,313 @@
+//! Markdown task file discovery
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::find_project_config;
+use crate::error::{Error, Result};
+
+/// Extensions of Markdown files, in lowercase
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "mdx", "markdown"];
+
+/// Dependency and build directories that are not searched for task files
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "vendor"];
+
+/// The project a command runs in
+///
+/// Tasks run with the project root as their working directory and in `MX_ROOT`, so they
+/// behave the same when mx is started from a subdirectory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// Directory of the project configuration, or else of the tasks file
+    pub root: PathBuf,
+    /// Project configuration file (`mx.toml` or `.mx.toml`), if any
+    pub config: Option<PathBuf>,
+}
+
+impl Project {
+    /// Find the project `dir` belongs to
+    ///
+    /// The root is the nearest directory, starting at `dir`, that has a project
+    /// configuration. Without one, it is the directory of `tasks_file` if that exists
+    /// relative to `dir`, or else the nearest ancestor containing it.
+    pub fn discover(dir: &Path, tasks_file: &Path) -> Option<Self> {
+        if let Some(config) = find_project_config(dir) {
+            let root = config.parent().map(Path::to_path_buf).unwrap_or_default();
+            return Some(Self {
+                root: canonical(root),
+                config: Some(config),
+            });
+        }
+
+        let root = if dir.join(tasks_file).is_file() {
+            dir.join(tasks_file).parent()?.to_path_buf()
+        } else if tasks_file.is_relative() {
+            dir.ancestors()
+                .find(|ancestor| ancestor.join(tasks_file).is_file())?
+                .to_path_buf()
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            root: canonical(root),
+            config: None,
+        })
+    }
+
+    /// Resolve a file argument: as given if it exists, else relative to the project root
+    ///
+    /// Paths found in neither place are returned unchanged, so that reading them produces
+    /// the usual error.
+    pub fn resolve(&self, file: &Path) -> PathBuf {
+        if file.exists() || file.is_absolute() || is_glob(&file.to_string_lossy()) {
+            return file.to_path_buf();
+        }
+
+        let in_root = self.root.join(file);
+        if in_root.exists() {
+            in_root
+        } else {
+            file.to_path_buf()
+        }
+    }
+}
+
+pub(crate) fn canonical(path: PathBuf) -> PathBuf {
+    path.canonicalize().unwrap_or(path)
+}
+
+/// A reference to a task: its title, its `#anchor`, or either prefixed with a Markdown file
+/// as in `docs/db.md#migrate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskRef<'a> {
+    /// Markdown file the task is in, if the reference names one
+    pub file: Option<&'a Path>,
+    /// Title of the task, or its anchor starting with `#`
+    pub task: &'a str,
+}
+
+impl<'a> TaskRef<'a> {
+    /// Split `file.md#section` into the file and `#section`
+    ///
+    /// Only a part before `#` ending in `.md`, `.mdx` or `.markdown` is taken as a file, so titles
+    /// such as `C# build` are left alone.
+    pub fn parse(reference: &'a str) -> Self {
+        let file_end = reference
+            .match_indices('#')
+            .map(|(index, _)| index)
+            .find(|&index| is_markdown(Path::new(&reference[..index])));
+
+        match file_end {
+            Some(index) => Self {
+                file: Some(Path::new(&reference[..index])),
+                task: &reference[index..],
+            },
+            None => Self {
+                file: None,
+                task: reference,
+            },
+        }
+    }
+}
+
+/// Expand file arguments that contain glob patterns (e.g. `ops/*.md`)
+///
+/// Plain paths are returned unchanged, even if they don't exist, so that reading them later
+/// produces the usual IO error. Patterns that match nothing are an error. Duplicates are
+/// removed while preserving the order of first appearance.
+pub fn expand_file_patterns(patterns: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for pattern in patterns {
+        let pattern_str = pattern.to_string_lossy();
+        if !is_glob(&pattern_str) {
+            if !files.contains(pattern) {
+                files.push(pattern.clone());
+            }
+            continue;
+        }
+
+        let paths = glob::glob(&pattern_str)
+            .map_err(|e| Error::Config(format!("Invalid file pattern '{}': {}", pattern_str, e)))?;
+
+        let mut matched = paths
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .peekable();
+        if matched.peek().is_none() {
+            return Err(Error::Config(format!(
+                "No files match pattern '{}'",
+                pattern_str
+            )));
+        }
+
+        for path in matched {
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Find the Markdown files in a directory and its subdirectories, sorted by path
+///
+/// Hidden directories, symlinked ones and dependency or build directories such as
+/// `node_modules` and `target` are skipped, as are subdirectories that cannot be read.
+pub fn find_markdown_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Err(Error::Config(format!(
+            "'{}' is not a directory",
+            dir.display()
+        )));
+    }
+
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Paths under the current directory are given without a `./` prefix
+            let path = match dir.as_path() {
+                dir if dir == Path::new(".") => PathBuf::from(&*name),
+                dir => dir.join(&*name),
+            };
+            if file_type.is_dir() {
+                if !name.starts_with('.') && !SKIPPED_DIRS.contains(&&*name) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() && is_markdown(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        MARKDOWN_EXTENSIONS
+            .iter()
+            .any(|markdown| ext.eq_ignore_ascii_case(markdown))
+    })
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_expand_file_patterns() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("a.md"), "## A").unwrap();
+        fs::write(dir.join("b.md"), "## B").unwrap();
+        fs::write(dir.join("c.txt"), "").unwrap();
+
+        let files = expand_file_patterns(&[
+            dir.join("b.md"),
+            dir.join("*.md"),
+            PathBuf::from("missing.md"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join("b.md"),
+                dir.join("a.md"),
+                PathBuf::from("missing.md")
+            ]
+        );
+
+        let err = expand_file_patterns(&[dir.join("*.rst")]).unwrap_err();
+        assert!(err.to_string().contains("No files match pattern"));
+    }
+
+    #[test]
+    fn test_find_markdown_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for sub in ["api/db", "web", ".git", "web/node_modules/pkg"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "README.md",
+            "api/db/RUNBOOK.markdown",
+            "api/notes.txt",
+            "web/docs.mdx",
+            ".git/HEAD.md",
+            "web/node_modules/pkg/README.md",
+        ] {
+            fs::write(dir.join(file), "## Task").unwrap();
+        }
+
+        assert_eq!(
+            find_markdown_files(dir).unwrap(),
+            vec![
+                dir.join("README.md"),
+                dir.join("api/db/RUNBOOK.markdown"),
+                dir.join("web/docs.mdx"),
+            ]
+        );
+        assert!(find_markdown_files(&dir.join("README.md")).is_err());
+    }
+
+    #[test]
+    fn test_discover_project() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let nested = dir.join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.join("TASKS.md"), "## Build").unwrap();
+
+        let project = Project::discover(&nested, Path::new("TASKS.md")).unwrap();
+        assert_eq!(project.root, dir.canonicalize().unwrap());
+        assert_eq!(project.config, None);
+        assert_eq!(
+            project.resolve(Path::new("TASKS.md")),
+            project.root.join("TASKS.md")
+        );
+
+        fs::write(dir.join("src").join(".mx.toml"), "").unwrap();
+        let project = Project::discover(&nested, Path::new("TASKS.md")).unwrap();
+        assert_eq!(project.root, dir.join("src").canonicalize().unwrap());
+        assert_eq!(project.config, Some(dir.join("src").join(".mx.toml")));
+    }
+
+    #[test]
+    fn test_parse_task_ref() {
+        assert_eq!(
+            TaskRef::parse("docs/db.md#migrate"),
+            TaskRef {
+                file: Some(Path::new("docs/db.md")),
+                task: "#migrate",
+            }
+        );
+        assert_eq!(TaskRef::parse("#build-and-test").file, None);
+        assert_eq!(TaskRef::parse("C# build").task, "C# build");
+        assert_eq!(
+            TaskRef::parse("RUNBOOK.MARKDOWN#Restart web").file,
+            Some(Path::new("RUNBOOK.MARKDOWN"))
+        );
+        assert_eq!(
+            TaskRef::parse("docs/setup.mdx#install").file,
+            Some(Path::new("docs/setup.mdx"))
+        );
+    }
+}
@@ -0,0 +1,544 @@
+//! Task files in formats other than Markdown
+//!
+//! A [`TaskSource`] reads the tasks of a document format, chosen by file extension. mx reads
+//! AsciiDoc (`.adoc`, `.asciidoc`) and Org-mode (`.org`) files, where headings and source
+//! blocks map to tasks and code blocks:
+//!
+//! ```text
+//! == Build [ci]                   * Build :ci:
+//! :depends: setup                 :PROPERTIES:
+//!                                 :DEPENDS: setup
+//! [source,bash]                   :END:
+//! ----
+//! cargo build                     #+begin_src bash
+//! ----                            cargo build
+//!                                 #+end_src
+//! ```
+//!
+//! AsciiDoc attribute entries and Org properties right under a heading are the task's
+//! metadata. Org documents keep their title in `#+TITLE`, so a `*` heading is a level-2
+//! task like `## Build`, and `**` is level 3.
+
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::expect::OUTPUT_LANG;
+use crate::runner::{CodeBlock, Section};
+use crate::source::parse_heading;
+
+/// Reads the tasks of a document format
+pub trait TaskSource: Send + Sync {
+    /// File extensions of the format, without the dot and in lowercase
+    fn extensions(&self) -> &[&str];
+
+    /// Sections with a heading level in the range, in document order, each running to the
+    /// next heading in the range, with their positions in the text
+    fn sections(&self, text: &str, levels: RangeInclusive<u8>) -> Result<Vec<Section>>;
+
+    /// Whether the source reads a file, by its extension
+    fn reads(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.extensions()
+                    .contains(&extension.to_ascii_lowercase().as_str())
+            })
+    }
+}
+
+/// Task sources for the formats mx reads besides Markdown
+pub fn builtin() -> Vec<std::sync::Arc<dyn TaskSource>> {
+    vec![std::sync::Arc::new(AsciiDoc), std::sync::Arc::new(OrgMode)]
+}
+
+/// AsciiDoc: `==` headings and `[source,lang]` listing blocks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AsciiDoc;
+
+impl TaskSource for AsciiDoc {
+    fn extensions(&self) -> &[&str] {
+        &["adoc", "asciidoc", "asc"]
+    }
+
+    fn sections(&self, text: &str, levels: RangeInclusive<u8>) -> Result<Vec<Section>> {
+        let mut builder = Builder::new(levels);
+        // The `[source,lang,...]` line before a block and where it starts
+        let mut attributes: Option<(&str, usize, usize)> = None;
+        let mut lines = Lines::new(text);
+
+        while let Some((number, offset, line)) = lines.next() {
+            let trimmed = line.trim_end();
+
+            if is_delimiter(trimmed, '-') || is_delimiter(trimmed, '.') {
+                let pending = attributes.take();
+                let (lang, meta) = match pending {
+                    Some((list, _, _)) if trimmed.starts_with('-') => source_attributes(list),
+                    _ => (String::new(), String::new()),
+                };
+                let (line, start) =
+                    pending.map_or((number, offset), |(_, line, start)| (line, start));
+                let (code, end) = lines.until(|closing| closing.trim_end() == trimmed);
+                if trimmed.starts_with('-') {
+                    builder.block(lang, meta, code, line, start..end);
+                } else {
+                    builder.break_paragraph();
+                }
+                continue;
+            }
+            if is_delimiter(trimmed, '/') {
+                lines.until(|closing| closing.trim_end() == trimmed);
+                continue;
+            }
+            attributes = None;
+
+            // Example, sidebar, quote and open blocks hold ordinary content
+            if ['=', '*', '_']
+                .iter()
+                .any(|&marker| is_delimiter(trimmed, marker))
+                || trimmed == "--"
+            {
+                builder.break_paragraph();
+            } else if let Some(level) = heading_depth(trimmed, '=') {
+                let span = offset..offset + trimmed.len();
+                builder.heading(
+                    level,
+                    trimmed[level as usize..].trim(),
+                    Vec::new(),
+                    number,
+                    span,
+                );
+            } else if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                attributes = Some((&trimmed[1..trimmed.len() - 1], number, offset));
+                builder.break_paragraph();
+            } else if let Some((name, value)) = attribute_entry(trimmed) {
+                builder.metadata(name, value);
+            } else if trimmed.starts_with("//") || is_block_title(trimmed) {
+                continue;
+            } else {
+                builder.text(trimmed);
+            }
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+/// Org-mode: `*` headings with `:tags:` and `#+begin_src lang` blocks
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OrgMode;
+
+impl TaskSource for OrgMode {
+    fn extensions(&self) -> &[&str] {
+        &["org"]
+    }
+
+    fn sections(&self, text: &str, levels: RangeInclusive<u8>) -> Result<Vec<Section>> {
+        let mut builder = Builder::new(levels);
+        let mut lines = Lines::new(text);
+
+        while let Some((number, offset, line)) = lines.next() {
+            let trimmed = line.trim();
+            let keyword = trimmed.to_ascii_lowercase();
+
+            if let Some(depth) = heading_depth(line.trim_end(), '*') {
+                let heading = line.trim_end()[depth as usize..].trim();
+                let (heading, tags) = org_tags(heading);
+                let span = offset..offset + line.trim_end().len();
+                builder.heading(depth + 1, heading, tags, number, span);
+            } else if let Some(info) = keyword.strip_prefix("#+begin_src") {
+                let info = &trimmed[trimmed.len() - info.len()..];
+                let (lang, meta) = info
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((info.trim(), ""));
+                let (code, end) = lines
+                    .until(|closing| closing.trim().to_ascii_lowercase().starts_with("#+end_src"));
+                let code = code
+                    .lines()
+                    .map(|line| match line.trim_start().strip_prefix(',') {
+                        Some(rest) if rest.starts_with('*') || rest.starts_with("#+") => {
+                            let indent = line.len() - line.trim_start().len();
+                            format!("{}{}", &line[..indent], rest)
+                        }
+                        _ => line.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                builder.block(
+                    lang.to_string(),
+                    meta.trim().to_string(),
+                    code,
+                    number,
+                    offset..end,
+                );
+            } else if let Some(block) = keyword.strip_prefix("#+begin_") {
+                let name = block
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                lines.until(|closing| {
+                    closing.trim().to_ascii_lowercase() == format!("#+end_{}", name)
+                });
+                builder.break_paragraph();
+            } else if keyword == ":properties:" {
+                while let Some((_, _, property)) = lines.next() {
+                    let property = property.trim();
+                    if property.eq_ignore_ascii_case(":end:") {
+                        break;
+                    }
+                    if let Some((name, value)) = attribute_entry(property) {
+                        builder.metadata(name, value);
+                    }
+                }
+            } else if trimmed.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+                continue;
+            } else {
+                builder.text(trimmed);
+            }
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+/// Lines of a text with their 1-based number and byte offset
+struct Lines<'a> {
+    text: &'a str,
+    offset: usize,
+    number: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            offset: 0,
+            number: 0,
+        }
+    }
+
+    fn next(&mut self) -> Option<(usize, usize, &'a str)> {
+        let rest = &self.text[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let line = rest.split_inclusive('\n').next().unwrap_or(rest);
+        let offset = self.offset;
+        self.offset += line.len();
+        self.number += 1;
+        Some((self.number, offset, line.trim_end_matches(['\n', '\r'])))
+    }
+
+    /// The lines up to the closing line, and the offset of the end of the closing line
+    /// without its line break, or of the text if it is never closed
+    fn until(&mut self, closes: impl Fn(&str) -> bool) -> (String, usize) {
+        let mut content: Vec<&str> = Vec::new();
+        while let Some((_, offset, line)) = self.next() {
+            if closes(line) {
+                return (content.join("\n"), offset + line.trim_end().len());
+            }
+            content.push(line);
+        }
+        (content.join("\n"), self.text.len())
+    }
+}
+
+/// Collects sections while a document is read
+struct Builder {
+    levels: RangeInclusive<u8>,
+    sections: Vec<Section>,
+    current: Option<Section>,
+    /// Level and title of the enclosing sections in the range
+    ancestors: Vec<(u8, String)>,
+    paragraph: Vec<String>,
+    /// Whether the lines read since the heading may still be metadata
+    in_header: bool,
+}
+
+impl Builder {
+    fn new(levels: RangeInclusive<u8>) -> Self {
+        Self {
+            levels,
+            sections: Vec::new(),
+            current: None,
+            ancestors: Vec::new(),
+            paragraph: Vec::new(),
+            in_header: false,
+        }
+    }
+
+    fn heading(
+        &mut self,
+        level: u8,
+        text: &str,
+        tags: Vec<String>,
+        line: usize,
+        span: Range<usize>,
+    ) {
+        if !self.levels.contains(&level) {
+            self.break_paragraph();
+            self.in_header = false;
+            return;
+        }
+        self.finish_section();
+
+        while self
+            .ancestors
+            .last()
+            .is_some_and(|(depth, _)| *depth >= level)
+        {
+            self.ancestors.pop();
+        }
+        let heading = parse_heading(text);
+        let mut section = Section {
+            title: heading.title.to_string(),
+            level,
+            parent: self.ancestors.last().map(|(_, title)| title.clone()),
+            line: Some(line),
+            span: Some(span),
+            tags: heading.tags,
+            meta: heading.attributes.map(str::to_string),
+            ..Default::default()
+        };
+        for tag in tags {
+            if !section.tags.contains(&tag) {
+                section.tags.push(tag);
+            }
+        }
+        self.ancestors.push((level, section.title.clone()));
+        self.current = Some(section);
+        self.in_header = true;
+    }
+
+    /// A metadata entry, if it comes right under the heading
+    fn metadata(&mut self, name: &str, value: &str) {
+        let Some(section) = self.current.as_mut().filter(|_| self.in_header) else {
+            return;
+        };
+        section
+            .metadata
+            .entry(name.to_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    /// A line of text; an empty line ends a paragraph
+    fn text(&mut self, line: &str) {
+        if line.is_empty() {
+            self.break_paragraph();
+            return;
+        }
+        self.in_header = false;
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|section| section.description.is_none())
+        {
+            self.paragraph.push(line.to_string());
+        }
+    }
+
+    fn block(&mut self, lang: String, meta: String, code: String, line: usize, span: Range<usize>) {
+        self.break_paragraph();
+        self.in_header = false;
+        let Some(section) = self.current.as_mut() else {
+            return;
+        };
+
+        // An output block holds the expected output of the block before it
+        if lang == OUTPUT_LANG
+            && let Some(previous) = section.codes.last_mut()
+            && previous.expected_output.is_none()
+        {
+            previous.expected_output = Some(code);
+            return;
+        }
+        section.codes.push(CodeBlock {
+            lang,
+            code,
+            meta: (!meta.is_empty()).then_some(meta),
+            line: Some(line),
+            span: Some(span),
+            ..Default::default()
+        });
+    }
+
+    fn break_paragraph(&mut self) {
+        if self.paragraph.is_empty() {
+            return;
+        }
+        let paragraph = std::mem::take(&mut self.paragraph).join(" ");
+        if let Some(section) = &mut self.current {
+            section.description.get_or_insert(paragraph);
+        }
+    }
+
+    fn finish_section(&mut self) {
+        self.break_paragraph();
+        if let Some(mut section) = self.current.take() {
+            // Tags from `tags=` block attributes apply to the whole section
+            let block_tags: Vec<String> =
+                section.codes.iter().flat_map(|code| code.tags()).collect();
+            for tag in block_tags {
+                if !section.tags.contains(&tag) {
+                    section.tags.push(tag);
+                }
+            }
+            self.sections.push(section);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Section> {
+        self.finish_section();
+        self.sections
+    }
+}
+
+/// Level of a heading made of a run of `marker`s and a space, e.g. 2 for `== Build`
+fn heading_depth(line: &str, marker: char) -> Option<u8> {
+    let depth = line.chars().take_while(|&c| c == marker).count();
+    let heading = (1..=6).contains(&depth) && line[depth..].starts_with(' ');
+    heading.then_some(depth as u8)
+}
+
+/// Whether a line delimits an AsciiDoc block, e.g. `----`
+fn is_delimiter(line: &str, marker: char) -> bool {
+    line.len() >= 4 && line.chars().all(|c| c == marker)
+}
+
+/// AsciiDoc block titles such as `.Output`
+fn is_block_title(line: &str) -> bool {
+    line.strip_prefix('.')
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| !c.is_whitespace() && c != '.')
+}
+
+/// An AsciiDoc attribute entry or Org property, `:name: value`
+fn attribute_entry(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.strip_prefix(':')?.split_once(':')?;
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| (name, value.trim()))
+}
+
+/// Language and remaining attributes of an AsciiDoc `source,lang,a=b,skip` attribute list
+fn source_attributes(list: &str) -> (String, String) {
+    let mut attributes = list.split(',').map(str::trim);
+    if attributes.next() != Some("source") {
+        return (String::new(), String::new());
+    }
+    let lang = attributes.next().unwrap_or_default().to_string();
+    let meta: Vec<&str> = attributes
+        .filter(|attribute| !attribute.is_empty())
+        .collect();
+    (lang, meta.join(" "))
+}
+
+/// Split the `:tag1:tag2:` suffix off an Org heading
+fn org_tags(heading: &str) -> (&str, Vec<String>) {
+    let Some((title, suffix)) = heading.rsplit_once(char::is_whitespace) else {
+        return (heading, Vec::new());
+    };
+    if suffix.len() < 3 || !suffix.starts_with(':') || !suffix.ends_with(':') {
+        return (heading, Vec::new());
+    }
+    let tags = suffix
+        .trim_matches(':')
+        .split(':')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    (title.trim_end(), tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asciidoc_sections() {
+        let text = "= Project\n:toc:\n\n== Build [ci]\n:depends: setup\n\n\
+                    Compiles the\nproject.\n\n[source,bash,name=make]\n----\ncargo build\n----\n\n\
+                    [source,output]\n----\nok\n----\n\n=== Notes\n\n....\nliteral\n....\n\n\
+                    == Test\n\n----\nplain\n----\n";
+        let sections = AsciiDoc.sections(text, 2..=2).unwrap();
+
+        assert_eq!(sections.len(), 2);
+        let build = &sections[0];
+        assert_eq!(build.title, "Build");
+        assert_eq!(build.tags, ["ci"]);
+        assert_eq!(build.line, Some(4));
+        assert_eq!(&text[build.span.clone().unwrap()], "== Build [ci]");
+        assert_eq!(build.metadata["depends"], "setup");
+        assert_eq!(build.description.as_deref(), Some("Compiles the project."));
+        assert_eq!(build.codes.len(), 1);
+        let code = &build.codes[0];
+        assert_eq!(
+            (code.lang.as_str(), code.code.as_str()),
+            ("bash", "cargo build")
+        );
+        assert_eq!(code.name().as_deref(), Some("make"));
+        assert_eq!(code.expected_output.as_deref(), Some("ok"));
+        assert_eq!(
+            &text[code.span.clone().unwrap()],
+            "[source,bash,name=make]\n----\ncargo build\n----"
+        );
+
+        assert_eq!(sections[1].title, "Test");
+        assert_eq!(sections[1].codes[0].lang, "");
+        assert_eq!(sections[1].codes[0].code, "plain");
+    }
+
+    #[test]
+    fn test_org_sections() {
+        let text = "#+TITLE: Project\n\n* Build :ci:danger:\n:PROPERTIES:\n:DEPENDS: setup\n\
+                    :END:\nCompiles the project.\n\n#+BEGIN_SRC bash name=make\ncargo build\n\
+                    ,* not a heading\n#+END_SRC\n** Details\n#+begin_example\n\
+                    * not a heading either\n#+end_example\n\
+                    * Test\n#+begin_src python\nprint(1)\n#+end_src\n";
+        let sections = OrgMode.sections(text, 2..=3).unwrap();
+
+        let titles: Vec<_> = sections
+            .iter()
+            .map(|section| {
+                (
+                    section.title.as_str(),
+                    section.level,
+                    section.parent.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                ("Build", 2, None),
+                ("Details", 3, Some("Build")),
+                ("Test", 2, None)
+            ]
+        );
+
+        let build = &sections[0];
+        assert_eq!(build.tags, ["ci", "danger"]);
+        assert_eq!(build.metadata["depends"], "setup");
+        assert_eq!(build.description.as_deref(), Some("Compiles the project."));
+        assert_eq!(build.codes[0].code, "cargo build\n* not a heading");
+        assert_eq!(build.codes[0].meta.as_deref(), Some("name=make"));
+        assert_eq!(build.codes[0].line, Some(9));
+        assert!(sections[1].codes.is_empty());
+        assert_eq!(sections[2].codes[0].lang, "python");
+    }
+
+    #[test]
+    fn test_reads_by_extension() {
+        assert!(AsciiDoc.reads(Path::new("docs/ops.ADOC")));
+        assert!(OrgMode.reads(Path::new("tasks.org")));
+        assert!(!OrgMode.reads(Path::new("README.md")));
+    }
+}
@@ -0,0 +1,210 @@
+//! Run history
+//!
+//! The CLI appends every task run to `.mx/history.jsonl` in the project root, one JSON
+//! object per line, so runs can be listed with `mx history` and repeated with `mx rerun`.
+//! A failed run records the block it failed at, for `mx run --resume` to continue from.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+use crate::logs::rfc3339_timestamp;
+
+/// Location of the history file, relative to the project root
+pub const HISTORY_FILE: &str = ".mx/history.jsonl";
+
+/// A recorded task run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Title of the task
+    pub task: String,
+    /// Markdown file the task was run from
+    pub file: PathBuf,
+    /// Arguments passed to the task
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// 0 if the task succeeded, else the exit code of the failed block; `None` if it failed
+    /// without one, e.g. because it was cancelled or its runtime could not be started
+    pub exit_code: Option<i32>,
+    /// Index of the block the task failed at, if it failed running one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_block: Option<usize>,
+    /// Wall-clock time of the run in milliseconds
+    pub duration_ms: u64,
+    /// Start of the run, e.g. `2025-01-02T03:04:05Z`
+    pub timestamp: String,
+}
+
+impl HistoryEntry {
+    /// Entry for a run that started at `started` and just finished
+    pub fn new(
+        task: impl Into<String>,
+        file: impl Into<PathBuf>,
+        args: &[String],
+        exit_code: Option<i32>,
+        started: SystemTime,
+    ) -> Self {
+        let duration = started.elapsed().unwrap_or_default();
+        Self {
+            task: task.into(),
+            file: file.into(),
+            args: args.to_vec(),
+            exit_code,
+            failed_block: None,
+            duration_ms: duration.as_millis() as u64,
+            timestamp: rfc3339_timestamp(started),
+        }
+    }
+
+    /// Whether the task succeeded
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Wall-clock time of the run
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// The history file of a project
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// History stored at [`HISTORY_FILE`] in a project root
+    pub fn in_root(root: &Path) -> Self {
+        Self::at(root.join(HISTORY_FILE))
+    }
+
+    /// History stored in the given file
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Location of the history file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a run, creating the file and its directory if needed
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let line = serde_json::to_string(entry)
+            .map_err(|e| Error::Execution(format!("Failed to serialize history entry: {}", e)))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// Recorded runs, oldest first; a missing file is an empty history
+    ///
+    /// Lines that cannot be parsed, e.g. after an interrupted write, are skipped.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    warn!(
+                        "skipping line {} of {}: {}",
+                        index + 1,
+                        self.path.display(),
+                        e
+                    );
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Most recent run of a task of a file
+    pub fn last_run(&self, task: &str, file: &Path) -> Result<Option<HistoryEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .rev()
+            .find(|entry| entry.task == task && entry.file == file))
+    }
+
+    /// Most recent run, or most recent failed run with `failed_only`
+    pub fn last(&self, failed_only: bool) -> Result<Option<HistoryEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .rev()
+            .find(|entry| !failed_only || !entry.success()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let history = History::in_root(dir);
+        assert!(history.entries().unwrap().is_empty());
+
+        let started = SystemTime::now();
+        let args = ["--prod".to_string()];
+        let mut failed = HistoryEntry::new("Deploy", "README.md", &args, Some(3), started);
+        failed.failed_block = Some(2);
+        history.append(&failed).unwrap();
+        history
+            .append(&HistoryEntry::new(
+                "Build",
+                "README.md",
+                &[],
+                Some(0),
+                started,
+            ))
+            .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(history.path())
+            .unwrap()
+            .write_all(b"{\"task\": \"trunc")
+            .unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].args, args);
+        assert_eq!(history.last(false).unwrap().unwrap().task, "Build");
+        assert_eq!(history.last(true).unwrap().unwrap().task, "Deploy");
+        assert_eq!(
+            history
+                .last_run("Deploy", Path::new("README.md"))
+                .unwrap()
+                .and_then(|entry| entry.failed_block),
+            Some(2)
+        );
+        assert_eq!(
+            history.last_run("Deploy", Path::new("OTHER.md")).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,626 @@
+//! Import tasks from other task runners
+//!
+//! `mx import Makefile|justfile|package.json` writes a tasks file with a section per Make
+//! target, just recipe or npm script: its comment or `scripts-info` entry as the
+//! description, the targets it depends on as `depends` metadata, and its commands in a
+//! fenced block. Commands are copied as they are, so Make variables and just
+//! interpolations are left for the user to rewrite.
+
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::export::ExportFormat;
+use crate::source::code_fence;
+
+/// A task read from another task runner's file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportedTask {
+    /// Target, recipe or script name, used as the section title
+    pub title: String,
+    pub description: Option<String>,
+    /// Titles of the imported tasks it depends on
+    pub depends: Vec<String>,
+    /// Language of the code block
+    pub lang: String,
+    /// Commands, empty for a task that only runs its dependencies
+    pub code: String,
+}
+
+/// The format of a task runner file, from its name
+pub fn detect_format(path: &Path) -> Option<ExportFormat> {
+    let name = path.file_name()?.to_str()?;
+    match name.to_lowercase().as_str() {
+        "makefile" | "gnumakefile" => Some(ExportFormat::Makefile),
+        "justfile" | ".justfile" => Some(ExportFormat::Justfile),
+        "package.json" => Some(ExportFormat::PackageJsonScripts),
+        _ if name.ends_with(".mk") => Some(ExportFormat::Makefile),
+        _ if name.ends_with(".just") => Some(ExportFormat::Justfile),
+        _ => None,
+    }
+}
+
+/// Read the tasks of a task runner file
+///
+/// Dependencies on names that are not tasks, such as files a Make target is built from,
+/// are left out.
+pub fn import(format: ExportFormat, source: &str) -> Result<Vec<ImportedTask>> {
+    let mut tasks = match format {
+        ExportFormat::Makefile => parse_makefile(source),
+        ExportFormat::Justfile => parse_justfile(source),
+        ExportFormat::PackageJsonScripts => parse_package_json(source)?,
+        ExportFormat::GithubActions => {
+            return Err(Error::Import(
+                "GitHub Actions workflows can't be imported".to_string(),
+            ));
+        }
+    };
+
+    let titles: Vec<String> = tasks.iter().map(|task| task.title.clone()).collect();
+    for task in &mut tasks {
+        task.depends.retain(|depends| titles.contains(depends));
+    }
+    Ok(tasks)
+}
+
+/// Comment lines before a target, joined into a description
+#[derive(Default)]
+struct Comments(Vec<String>);
+
+impl Comments {
+    fn push(&mut self, line: &str) {
+        let text = line.trim_start_matches('#').trim();
+        if !text.is_empty() {
+            self.0.push(text.to_string());
+        }
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let lines = std::mem::take(&mut self.0);
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+/// A recipe line without the Make or just prefixes that hide it (`@`) or ignore its failure
+/// (`-`), the latter turned into `|| true`
+fn recipe_line(line: &str) -> String {
+    let command = line.trim_start_matches(['@', '+']);
+    match command.strip_prefix('-') {
+        Some(command) => {
+            format!(
+                "{} || true",
+                command.trim_start_matches(['@', '+']).trim_start()
+            )
+        }
+        None => command.to_string(),
+    }
+}
+
+/// Shell code running recipe lines in order, stopping at the first failure as Make and just do
+fn shell_code(lines: &[String]) -> String {
+    let mut code = String::new();
+    if lines.len() > 1 {
+        code.push_str("set -e\n");
+    }
+    for line in lines {
+        code.push_str(line);
+        code.push('\n');
+    }
+    code
+}
+
+/// Add a task, or merge it into an earlier one with the same title as Make does with rules
+fn add_task(tasks: &mut Vec<ImportedTask>, task: ImportedTask) {
+    match tasks.iter_mut().find(|t| t.title == task.title) {
+        Some(existing) => {
+            existing.description = existing.description.take().or(task.description);
+            for depends in task.depends {
+                if !existing.depends.contains(&depends) {
+                    existing.depends.push(depends);
+                }
+            }
+            existing.code.push_str(&task.code);
+        }
+        None => tasks.push(task),
+    }
+}
+
+/// Targets of a Makefile
+///
+/// Special targets like `.PHONY`, pattern rules and targets named by variables are left
+/// out, as are order-only prerequisites. A `## text` comment after the prerequisites is
+/// taken as the description, as in self-documenting Makefiles.
+pub fn parse_makefile(source: &str) -> Vec<ImportedTask> {
+    let mut tasks = Vec::new();
+    let mut comments = Comments::default();
+    // Targets of the rule whose recipe is being read, with its lines
+    let mut current: Option<(Vec<ImportedTask>, Vec<String>)> = None;
+    let mut in_define = false;
+
+    let finish = |tasks: &mut Vec<ImportedTask>,
+                  current: Option<(Vec<ImportedTask>, Vec<String>)>| {
+        if let Some((targets, lines)) = current {
+            let code = if lines.is_empty() {
+                String::new()
+            } else {
+                shell_code(&lines)
+            };
+            for mut task in targets {
+                task.code = code.clone();
+                add_task(tasks, task);
+            }
+        }
+    };
+
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if in_define {
+            in_define = line.trim() != "endef";
+            continue;
+        }
+        if let Some(command) = line.strip_prefix('\t') {
+            if let Some((_, recipe)) = &mut current {
+                // `$$` is a literal `$` in Make
+                recipe.push(recipe_line(command).replace("$$", "$"));
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comments.take();
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            comments.push(trimmed);
+            continue;
+        }
+
+        finish(&mut tasks, current.take());
+        let description = comments.take();
+        if trimmed.starts_with("define ") {
+            in_define = true;
+            continue;
+        }
+
+        // A rule, possibly continued over several lines
+        let mut rule = line.to_string();
+        while rule.ends_with('\\') {
+            rule.pop();
+            match lines.next() {
+                Some(next) => rule.push_str(next),
+                None => break,
+            }
+        }
+        let Some((targets, rest)) = split_rule(&rule) else {
+            continue;
+        };
+
+        let (rest, inline_description) = match rest.split_once("##") {
+            Some((rest, text)) => (rest, Some(text.trim().to_string())),
+            None => (rest, None),
+        };
+        let (prerequisites, inline_command) = match rest.split_once(';') {
+            Some((prerequisites, command)) => (prerequisites, Some(command.trim())),
+            None => (rest, None),
+        };
+        let prerequisites = prerequisites.split('|').next().unwrap_or_default();
+        let prerequisites = prerequisites.split('#').next().unwrap_or_default();
+        let depends: Vec<String> = prerequisites
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        let targets: Vec<ImportedTask> = targets
+            .split_whitespace()
+            .filter(|target| !target.starts_with('.') && !target.contains(['%', '$']))
+            .map(|target| ImportedTask {
+                title: target.to_string(),
+                description: inline_description.clone().or_else(|| description.clone()),
+                depends: depends.clone(),
+                lang: "sh".to_string(),
+                code: String::new(),
+            })
+            .collect();
+        let recipe = inline_command
+            .filter(|command| !command.is_empty())
+            .map(|command| vec![recipe_line(command).replace("$$", "$")])
+            .unwrap_or_default();
+        current = Some((targets, recipe));
+    }
+    finish(&mut tasks, current);
+
+    tasks.retain(|task| !task.code.is_empty() || !task.depends.is_empty());
+    tasks
+}
+
+/// Split a Makefile rule into its targets and what follows the colon, or `None` for any
+/// other line such as a variable assignment
+fn split_rule(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let (targets, rest) = (&line[..colon], &line[colon..]);
+    if targets.contains('=') || targets.trim().is_empty() {
+        return None;
+    }
+    let rest = rest.trim_start_matches(':');
+    if rest.starts_with('=') {
+        return None;
+    }
+    let first = targets.split_whitespace().next()?;
+    if matches!(
+        first,
+        "export" | "override" | "include" | "-include" | "sinclude" | "vpath"
+    ) {
+        return None;
+    }
+    Some((targets, rest))
+}
+
+/// Recipes of a justfile
+///
+/// Settings, variables, aliases and recipe parameters are left out, as are dependencies
+/// after `&&`, which just runs after the recipe. Shebang recipes keep their code, in the
+/// language of their interpreter.
+pub fn parse_justfile(source: &str) -> Vec<ImportedTask> {
+    let mut tasks = Vec::new();
+    let mut comments = Comments::default();
+    let mut doc: Option<String> = None;
+    let mut current: Option<(ImportedTask, Vec<String>)> = None;
+
+    let finish = |tasks: &mut Vec<ImportedTask>, current: Option<(ImportedTask, Vec<String>)>| {
+        if let Some((mut task, body)) = current {
+            let indent = body
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            let body: Vec<&str> = body
+                .iter()
+                .map(|line| line.get(indent..).unwrap_or_default())
+                .collect();
+            let end = body
+                .iter()
+                .rposition(|line| !line.is_empty())
+                .map_or(0, |i| i + 1);
+            let body = &body[..end];
+
+            match body.first().and_then(|line| line.strip_prefix("#!")) {
+                Some(shebang) => {
+                    task.lang = interpreter_language(shebang);
+                    task.code = body[1..].iter().map(|line| format!("{}\n", line)).collect();
+                }
+                None => {
+                    let lines: Vec<String> = body
+                        .iter()
+                        .filter(|line| !line.is_empty())
+                        .map(|line| recipe_line(line))
+                        .collect();
+                    task.code = if lines.is_empty() {
+                        String::new()
+                    } else {
+                        shell_code(&lines)
+                    };
+                }
+            }
+            tasks.push(task);
+        }
+    };
+
+    for line in source.lines() {
+        if line.starts_with([' ', '\t']) || (line.trim().is_empty() && current.is_some()) {
+            if let Some((_, body)) = &mut current {
+                body.push(line.to_string());
+            }
+            continue;
+        }
+
+        finish(&mut tasks, current.take());
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            comments.take();
+            doc = None;
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            comments.push(trimmed);
+            continue;
+        }
+        if let Some(attributes) = trimmed.strip_prefix('[') {
+            if let Some(text) = attributes.strip_prefix("doc(") {
+                doc = Some(
+                    text.trim_end_matches([']', ')'])
+                        .trim_matches(['"', '\''])
+                        .to_string(),
+                );
+            }
+            continue;
+        }
+
+        let description = doc.take().or_else(|| comments.take());
+        let Some((header, depends)) = split_recipe(trimmed) else {
+            continue;
+        };
+        let Some(name) = header.split_whitespace().next() else {
+            continue;
+        };
+        current = Some((
+            ImportedTask {
+                title: name.trim_start_matches('@').to_string(),
+                description,
+                depends,
+                lang: "sh".to_string(),
+                code: String::new(),
+            },
+            Vec::new(),
+        ));
+    }
+    finish(&mut tasks, current);
+
+    tasks
+}
+
+/// Split a justfile recipe header into the name with its parameters and the recipes it
+/// depends on, or `None` for any other line such as a setting or variable
+fn split_recipe(line: &str) -> Option<(&str, Vec<String>)> {
+    let first = line.split_whitespace().next()?;
+    if matches!(
+        first,
+        "set" | "alias" | "export" | "import" | "mod" | "import?" | "mod?"
+    ) || !first.starts_with(|c: char| c == '@' || c == '_' || c.is_ascii_alphabetic())
+    {
+        return None;
+    }
+
+    let colon = line.find(':')?;
+    let rest = &line[colon + 1..];
+    if rest.starts_with('=') {
+        return None;
+    }
+
+    // `build: (compile "release") test && notify` depends on compile and test
+    let mut depends = Vec::new();
+    let mut in_call = false;
+    for token in rest
+        .split("&&")
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        if let Some(name) = token.strip_prefix('(') {
+            depends.push(name.trim_end_matches(')').to_string());
+            in_call = !token.ends_with(')');
+        } else if in_call {
+            in_call = !token.ends_with(')');
+        } else {
+            depends.push(token.to_string());
+        }
+    }
+    Some((&line[..colon], depends))
+}
+
+/// Code block language of a shebang's interpreter, e.g. `python` for
+/// `/usr/bin/env python3`
+fn interpreter_language(shebang: &str) -> String {
+    let mut words = shebang.split_whitespace();
+    let mut program = words.next().unwrap_or_default();
+    if program.ends_with("/env") || program == "env" {
+        program = words
+            .find(|word| !word.starts_with('-'))
+            .unwrap_or_default();
+    }
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+    let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match name {
+        "" => "sh".to_string(),
+        "nodejs" => "node".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Scripts of a package.json
+///
+/// A `pre<name>` script, which npm runs before `<name>`, becomes a dependency of it.
+/// Descriptions come from a `scripts-info` object, as written by `mx export`.
+pub fn parse_package_json(source: &str) -> Result<Vec<ImportedTask>> {
+    let package: serde_json::Value = serde_json::from_str(source)
+        .map_err(|e| Error::Import(format!("invalid package.json: {}", e)))?;
+    let Some(scripts) = package
+        .get("scripts")
+        .and_then(|scripts| scripts.as_object())
+    else {
+        return Ok(Vec::new());
+    };
+    let info = package
+        .get("scripts-info")
+        .and_then(|info| info.as_object());
+
+    Ok(scripts
+        .iter()
+        .filter_map(|(name, script)| {
+            let script = script.as_str()?;
+            let pre = format!("pre{}", name);
+            Some(ImportedTask {
+                title: name.clone(),
+                description: info
+                    .and_then(|info| info.get(name))
+                    .and_then(|description| description.as_str())
+                    .map(str::to_string),
+                depends: scripts
+                    .contains_key(&pre)
+                    .then_some(pre)
+                    .into_iter()
+                    .collect(),
+                lang: "sh".to_string(),
+                code: format!("{}\n", script),
+            })
+        })
+        .collect())
+}
+
+/// A tasks file with a section per imported task, read from `origin`
+pub fn tasks_markdown(tasks: &[ImportedTask], origin: &str) -> String {
+    let mut markdown = format!("# Tasks\n\nImported from `{}` with `mx import`.\n", origin);
+
+    for task in tasks {
+        markdown.push_str(&format!("\n## {}\n", task.title));
+
+        if !task.depends.is_empty() {
+            let depends = task.depends.join(", ");
+            markdown.push_str(&format!(
+                "\n| depends | {} |\n|---------|{}|\n",
+                depends,
+                "-".repeat(depends.len() + 2)
+            ));
+        }
+        if let Some(description) = &task.description {
+            markdown.push_str(&format!("\n{}\n", description));
+        }
+        if !task.code.is_empty() {
+            let fence = code_fence(&task.code);
+            markdown.push_str(&format!(
+                "\n{}{}\n{}{}\n",
+                fence, task.lang, task.code, fence
+            ));
+        }
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_makefile() {
+        let makefile = "\
+CARGO ?= cargo
+.PHONY: build test
+
+# Build the project
+build:
+\t@$(CARGO) build
+\t-rm -f stale.lock
+
+test: build src/main.rs ## Run the tests
+\tcargo test | tee $$LOG
+
+%.o: %.c
+\tcc -c $<
+
+all: build test
+";
+        let tasks = import(ExportFormat::Makefile, makefile).unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(
+            tasks[0],
+            ImportedTask {
+                title: "build".to_string(),
+                description: Some("Build the project".to_string()),
+                depends: vec![],
+                lang: "sh".to_string(),
+                code: "set -e\n$(CARGO) build\nrm -f stale.lock || true\n".to_string(),
+            }
+        );
+        assert_eq!(tasks[1].description.as_deref(), Some("Run the tests"));
+        assert_eq!(tasks[1].depends, ["build"]);
+        assert_eq!(tasks[1].code, "cargo test | tee $LOG\n");
+        assert_eq!(tasks[2].depends, ["build", "test"]);
+        assert!(tasks[2].code.is_empty());
+    }
+
+    #[test]
+    fn test_parse_justfile() {
+        let justfile = "\
+set shell := [\"bash\", \"-c\"]
+version := \"1.0\"
+
+# Build the project
+build mode=\"debug\": (fetch \"all\") lint && notify
+    cargo build --{{mode}}
+
+    @echo done
+
+[doc('Print a report')]
+report:
+    #!/usr/bin/env python3
+    print(\"report\")
+
+fetch target:
+    git fetch {{target}}
+
+lint:
+notify:
+";
+        let tasks = import(ExportFormat::Justfile, justfile).unwrap();
+        let titles: Vec<&str> = tasks.iter().map(|task| task.title.as_str()).collect();
+        assert_eq!(titles, ["build", "report", "fetch", "lint", "notify"]);
+
+        assert_eq!(tasks[0].description.as_deref(), Some("Build the project"));
+        assert_eq!(tasks[0].depends, ["fetch", "lint"]);
+        assert_eq!(tasks[0].code, "set -e\ncargo build --{{mode}}\necho done\n");
+        assert_eq!(tasks[1].description.as_deref(), Some("Print a report"));
+        assert_eq!(tasks[1].lang, "python");
+        assert_eq!(tasks[1].code, "print(\"report\")\n");
+        assert!(tasks[3].code.is_empty());
+    }
+
+    #[test]
+    fn test_parse_package_json() {
+        let package = r#"{
+  "scripts": {"prebuild": "npm run clean", "build": "tsc", "clean": "rm -rf dist"},
+  "scripts-info": {"build": "Compile the sources"}
+}"#;
+        let tasks = import(ExportFormat::PackageJsonScripts, package).unwrap();
+        let build = tasks.iter().find(|task| task.title == "build").unwrap();
+        assert_eq!(build.depends, ["prebuild"]);
+        assert_eq!(build.description.as_deref(), Some("Compile the sources"));
+        assert_eq!(build.code, "tsc\n");
+
+        assert!(matches!(
+            import(ExportFormat::PackageJsonScripts, "{"),
+            Err(Error::Import(_))
+        ));
+    }
+
+    #[test]
+    fn test_tasks_markdown() {
+        let tasks = [
+            ImportedTask {
+                title: "build".to_string(),
+                description: Some("Build it".to_string()),
+                depends: vec!["fetch".to_string()],
+                lang: "sh".to_string(),
+                code: "echo '```'\n".to_string(),
+            },
+            ImportedTask {
+                title: "all".to_string(),
+                depends: vec!["build".to_string(), "test".to_string()],
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            tasks_markdown(&tasks, "Makefile"),
+            "# Tasks\n\nImported from `Makefile` with `mx import`.\n\
+             \n## build\n\n| depends | fetch |\n|---------|-------|\n\nBuild it\n\
+             \n````sh\necho '```'\n````\n\
+             \n## all\n\n| depends | build, test |\n|---------|-------------|\n"
+        );
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let detect = |name: &str| detect_format(Path::new(name));
+        assert_eq!(detect("project/Makefile"), Some(ExportFormat::Makefile));
+        assert_eq!(detect("rules.mk"), Some(ExportFormat::Makefile));
+        assert_eq!(detect("Justfile"), Some(ExportFormat::Justfile));
+        assert_eq!(
+            detect("package.json"),
+            Some(ExportFormat::PackageJsonScripts)
+        );
+        assert_eq!(detect("Rakefile"), None);
+    }
+}
@@ -1,12 +1,73 @@
 //! mx - Markdown-based task runner
 //!
 //! mx is a task runner that executes code blocks in Markdown files based on section titles.
-//! It uses mq query language to parse and extract sections from Markdown documents.
+//! It uses mq query language (or pulldown-cmark) to extract sections from Markdown documents.
 
+#[cfg(feature = "async")]
+mod async_runner;
+pub mod builder;
+pub mod changes;
+pub mod codelens;
+pub mod command;
+pub mod condition;
 pub mod config;
+pub mod crash;
+pub mod devshell;
+pub mod doctor;
 pub mod error;
+pub mod events;
+pub mod export;
+pub mod expect;
+pub mod extract;
+#[cfg(feature = "mx-ffi")]
+pub mod ffi;
+pub mod files;
+pub mod formats;
+pub mod history;
+pub mod import;
+pub mod limits;
+pub mod lock;
+pub mod logs;
+pub mod namespace;
+pub mod params;
+pub mod plan;
+pub mod prefix;
+pub mod process;
+pub mod progress;
+mod pty;
+#[cfg(feature = "mx-py")]
+pub mod python;
+pub mod remote;
+pub mod report;
+pub mod run_report;
 pub mod runner;
+pub mod sandbox;
+pub mod schedule;
+pub mod search;
+pub mod secrets;
+mod shell;
+mod source;
+pub mod sql;
+pub mod template;
+pub mod theme;
+pub mod toolchain;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod trust;
+pub mod validate;
+pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workspace;
 
-pub use config::{Config, ExecutionMode};
+pub use builder::RunnerBuilder;
+pub use config::{Config, ExecutionMode, HeadingLevel, TitleMatch};
 pub use error::{Error, Result};
-pub use runner::Runner;
+pub use events::{RunEvent, RunnerObserver};
+pub use plan::{Plan, PlanStep};
+pub use report::{BlockReport, ExecutionReport};
+pub use runner::{Extraction, ExtractionDiagnostic, Runner, StepAction};
+pub use warning::Warning;
+
+#[cfg(feature = "async")]
+pub use tokio_util::sync::CancellationToken;
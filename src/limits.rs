@@ -0,0 +1,72 @@
+//! Platform limits on the size of a child process' command line and environment
+//!
+//! Exceeding them makes spawning fail with a cryptic `E2BIG` ("Argument list too long"), so
+//! invocations are measured before spawning: task arguments that do not fit in the
+//! environment are moved to a file, and anything else that is too large is reported with a
+//! clear error.
+
+use std::ffi::OsStr;
+use std::mem::size_of;
+
+/// Longest single argument or `KEY=value` environment entry, in bytes
+#[cfg(target_os = "linux")]
+pub const MAX_STRING_LEN: usize = 128 * 1024 - 1;
+/// Longest single argument or `KEY=value` environment entry, in characters
+#[cfg(windows)]
+pub const MAX_STRING_LEN: usize = 32_767;
+/// Longest single argument or `KEY=value` environment entry, in bytes
+#[cfg(not(any(target_os = "linux", windows)))]
+pub const MAX_STRING_LEN: usize = MAX_TOTAL_LEN;
+
+/// Total size of the command line and environment, including per-string overhead
+#[cfg(target_os = "linux")]
+pub const MAX_TOTAL_LEN: usize = 2 * 1024 * 1024;
+/// Total size of the command line and environment, including per-string overhead
+#[cfg(not(target_os = "linux"))]
+pub const MAX_TOTAL_LEN: usize = 1024 * 1024;
+
+/// Room left for the loader and anything the platform adds to the environment
+const HEADROOM: usize = 16 * 1024;
+
+/// Size a string takes up when passed to a child: its bytes, a terminator and a pointer
+pub fn string_size(s: impl AsRef<OsStr>) -> usize {
+    s.as_ref().len() + 1 + size_of::<usize>()
+}
+
+/// Size of an environment entry, counted as `KEY=value`
+pub fn env_entry_size(key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> usize {
+    string_size(key) + 1 + value.as_ref().len()
+}
+
+/// Size of the environment inherited from this process
+pub fn inherited_env_size() -> usize {
+    std::env::vars_os()
+        .map(|(key, value)| env_entry_size(key, value))
+        .sum()
+}
+
+/// Budget for the command line and environment of a child
+pub fn budget() -> usize {
+    MAX_TOTAL_LEN - HEADROOM
+}
+
+/// Check that no single string is too long
+pub fn fits_string(size: usize) -> bool {
+    size <= MAX_STRING_LEN
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        let pointer = size_of::<usize>();
+        assert_eq!(string_size("abc"), 4 + pointer);
+        assert_eq!(
+            env_entry_size("MX_ARG_0", "x"),
+            "MX_ARG_0=x".len() + 1 + pointer
+        );
+        assert!(budget() < MAX_TOTAL_LEN);
+    }
+}
@@ -0,0 +1,124 @@
+//! Per-task locks
+//!
+//! A task marked with a `{lock}` heading attribute holds an exclusive file lock in
+//! `.mx/locks/` while it runs, so that two terminals can't run the same migration or deploy
+//! at once. The lock is released when the holder exits, even if it crashes.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::logs::file_slug;
+
+/// Directory holding the lock files, relative to the project root
+pub const LOCK_DIR: &str = ".mx/locks";
+
+/// An exclusive lock on a task, released when dropped
+#[derive(Debug)]
+pub struct TaskLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl TaskLock {
+    /// Lock file of a task in a project root
+    pub fn path_for(root: &Path, task: &str) -> PathBuf {
+        root.join(LOCK_DIR)
+            .join(format!("{}.lock", file_slug(task)))
+    }
+
+    /// Take the lock of a task, or fail with [`Error::Locked`] if another process holds it
+    pub fn try_acquire(root: &Path, task: &str) -> Result<Self> {
+        let path = Self::path_for(root, task);
+        let mut file = open(&path)?;
+        match file.try_lock() {
+            Ok(()) => Self::locked(file, path),
+            Err(TryLockError::WouldBlock) => Err(Error::Locked {
+                task: task.to_string(),
+                pid: holder_pid(&mut file),
+                path,
+            }),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Take the lock of a task, waiting for another process to release it
+    pub fn acquire(root: &Path, task: &str) -> Result<Self> {
+        match Self::try_acquire(root, task) {
+            Err(Error::Locked { pid, path, .. }) => {
+                warn!(task, holder = ?pid, "waiting for the task lock held by another process");
+                let file = open(&path)?;
+                file.lock()?;
+                Self::locked(file, path)
+            }
+            result => result,
+        }
+    }
+
+    /// Location of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record the holder's pid in a freshly locked file, for the error shown to others
+    fn locked(mut file: File, path: PathBuf) -> Result<Self> {
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        debug!(path = %path.display(), "acquired task lock");
+        Ok(Self { _file: file, path })
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+/// Pid written by the holder; unreadable where locks are mandatory, e.g. on Windows
+fn holder_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let lock = TaskLock::try_acquire(root, "Migrate database").unwrap();
+        assert_eq!(
+            lock.path(),
+            root.join(".mx/locks/Migrate-database.lock").as_path()
+        );
+
+        match TaskLock::try_acquire(root, "Migrate database") {
+            Err(Error::Locked { task, pid, .. }) => {
+                assert_eq!(task, "Migrate database");
+                if cfg!(unix) {
+                    assert_eq!(pid, Some(std::process::id()));
+                }
+            }
+            other => panic!("expected a lock error, got {:?}", other),
+        }
+        assert!(TaskLock::try_acquire(root, "Deploy").is_ok());
+
+        drop(lock);
+        assert!(TaskLock::acquire(root, "Migrate database").is_ok());
+    }
+}
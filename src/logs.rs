@@ -0,0 +1,174 @@
+//! Per-block log files
+//!
+//! With a log directory configured, each executed code block gets a pair of files named
+//! `<run timestamp>-<task>-<block>.stdout.log` and `.stderr.log`, written alongside the
+//! usual terminal output.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+
+use crate::error::Result;
+use crate::runner::OutputStream;
+
+/// Directory receiving the block logs of one run
+#[derive(Debug, Clone)]
+pub struct LogDir {
+    dir: PathBuf,
+    run_stamp: String,
+}
+
+impl LogDir {
+    /// Logs of one run; all its blocks share the run's start timestamp
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            run_stamp: utc_timestamp(SystemTime::now()),
+        }
+    }
+
+    /// Directory the logs are written to
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Create the log files of a code block, creating the directory if needed
+    pub fn open_block(&self, task: &str, index: usize) -> Result<BlockLog> {
+        fs::create_dir_all(&self.dir)?;
+        let stem = format!("{}-{}-{}", self.run_stamp, file_slug(task), index);
+        let open = |stream: &str| File::create(self.dir.join(format!("{}.{}.log", stem, stream)));
+
+        Ok(BlockLog {
+            stdout: Mutex::new(open("stdout")?),
+            stderr: Mutex::new(open("stderr")?),
+        })
+    }
+}
+
+/// Log files of a single code block
+#[derive(Debug)]
+pub struct BlockLog {
+    stdout: Mutex<File>,
+    stderr: Mutex<File>,
+}
+
+impl BlockLog {
+    /// Append output to the file of its stream; write errors are logged and otherwise ignored
+    pub fn write(&self, stream: OutputStream, data: &[u8]) {
+        let file = match stream {
+            OutputStream::Stdout => &self.stdout,
+            OutputStream::Stderr => &self.stderr,
+        };
+
+        let result = match file.lock() {
+            Ok(mut file) => file.write_all(data),
+            Err(_) => return,
+        };
+        if let Err(e) = result {
+            warn!("failed to write block log: {}", e);
+        }
+    }
+}
+
+/// Make a task title safe to use in a file name
+pub(crate) fn file_slug(task: &str) -> String {
+    let slug: String = task
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let slug = slug
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+
+    if slug.is_empty() {
+        "task".to_string()
+    } else {
+        slug.join("-")
+    }
+}
+
+/// Format a time as a compact UTC timestamp, e.g. `20250102T030405Z`
+fn utc_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hours, minutes, seconds) = utc_fields(time);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year, month, day, hours, minutes, seconds
+    )
+}
+
+/// Format a time as an RFC 3339 UTC timestamp, e.g. `2025-01-02T03:04:05Z`
+pub(crate) fn rfc3339_timestamp(time: SystemTime) -> String {
+    let (year, month, day, hours, minutes, seconds) = utc_fields(time);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hours, minutes, seconds
+    )
+}
+
+/// Calendar date and time of day in UTC
+fn utc_fields(time: SystemTime) -> (i64, i64, i64, u64, u64, u64) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+            "20240229T235959Z"
+        );
+        assert_eq!(
+            rfc3339_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+            "2024-02-29T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_block_log_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let logs = LogDir::new(dir);
+        let log = logs.open_block("Deploy to prod!", 1).unwrap();
+        log.write(OutputStream::Stdout, b"hello\n");
+        log.write(OutputStream::Stderr, b"oops\n");
+        drop(log);
+
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert!(names[0].ends_with("-Deploy-to-prod-1.stderr.log"));
+        assert!(names[1].ends_with("-Deploy-to-prod-1.stdout.log"));
+        assert_eq!(fs::read_to_string(dir.join(&names[1])).unwrap(), "hello\n");
+    }
+}
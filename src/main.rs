@@ -1,11 +1,46 @@
 //! mx - Markdown-based task runner CLI
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use colored::*;
-use miette::{IntoDiagnostic, Result};
-use std::path::PathBuf;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use miette::{IntoDiagnostic, NamedSource, Result, Severity, SourceSpan};
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
 
-use mx::{Config, ExecutionMode, Runner};
+use mx::changes;
+use mx::codelens::code_lenses;
+use mx::config::{ConfigLayers, ConfigSource, Provenance, global_tasks_path};
+use mx::doctor::{self, Check};
+use mx::events::{BlockOutcome, JsonlWriter, RunnerObserver};
+use mx::expect::{DiffLine, OutputCapture, OutputMismatch, record_outputs};
+use mx::export::{ExportFormat, collect_tasks, export};
+use mx::files::{Project, TaskRef};
+use mx::history::{History, HistoryEntry};
+use mx::import::{detect_format, import, tasks_markdown};
+use mx::params;
+use mx::prefix::LinePrefixer;
+use mx::progress::ProgressReporter;
+use mx::remote::Remote;
+use mx::report::block_id;
+use mx::run_report::{ReportFormat, RunRecorder};
+use mx::runner::{CodeBlock, OutputStream, Section, StepHandler};
+use mx::template::Template;
+use mx::workspace::{self, Member};
+use mx::{
+    Config, ExecutionMode, ExecutionReport, HeadingLevel, Runner, StepAction, TitleMatch, Warning,
+    validate, warning,
+};
+
+#[cfg(feature = "tui")]
+mod ui;
 
 const DEFAULT_TASKS_FILE: &str = "README.md";
 
@@ -18,18 +53,128 @@ struct Cli {
     #[arg(value_name = "TASK")]
     task: Option<String>,
 
-    /// Path to the markdown file
-    #[arg(short, long, default_value = DEFAULT_TASKS_FILE)]
-    file: PathBuf,
+    #[command(flatten)]
+    source: SourceArgs,
+
+    #[command(flatten)]
+    exec: ExecArgs,
+
+    /// Increase logging verbosity (-v for info, -vv for debug, -vvv for trace)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log errors, and hide banners of task runs such as "Running task:"
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// When to color the output; auto colors a terminal unless NO_COLOR is set
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    #[arg(global = true)]
+    color: ColorChoice,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// Options selecting the markdown file and how sections are extracted
+#[derive(Args)]
+struct SourceArgs {
+    /// Path to the markdown file (repeatable; quoted glob patterns like 'ops/*.md' are expanded)
+    #[arg(short, long, env = "MX_FILE", default_value = DEFAULT_TASKS_FILE)]
+    file: Vec<PathBuf>,
 
     /// Path to configuration file
-    #[arg(short, long)]
+    #[arg(short, long, env = "MX_CONFIG")]
     config: Option<PathBuf>,
 
-    /// Heading level for sections (1-6)
+    /// Heading level for sections: 1-6, a range such as 2-4, or any
+    #[arg(short, long, value_name = "LEVEL")]
+    level: Option<HeadingLevel>,
+
+    /// Match task names as case-insensitive regular expressions, e.g. 'deploy .*staging'
+    #[arg(long)]
+    regex: bool,
+
+    /// Use the personal task file ~/.config/mx/tasks.md instead of the project's files
     #[arg(short, long)]
-    level: Option<u8>,
+    global: bool,
+}
+
+impl SourceArgs {
+    /// Load the configuration, applying the heading level and title match overrides
+    fn load_config(&self) -> Result<Config> {
+        self.load_config_with_provenance().map(|(config, _)| config)
+    }
+
+    /// Load the configuration, recording where each value came from
+    fn load_config_with_provenance(&self) -> Result<(Config, Provenance)> {
+        let (mut config, mut provenance) =
+            load_config(self.config.clone(), self.files()?, self.global)?;
+
+        // Override heading level if specified
+        if let Some(level) = self.level {
+            config.heading_level = level;
+            provenance.insert(
+                "heading_level".to_string(),
+                ConfigSource::Cli {
+                    flag: "--level".to_string(),
+                },
+            );
+        }
+
+        if self.regex {
+            config.title_match = TitleMatch::Regex;
+            provenance.insert(
+                "match".to_string(),
+                ConfigSource::Cli {
+                    flag: "--regex".to_string(),
+                },
+            );
+        }
+
+        Ok((config, provenance))
+    }
+
+    /// Resolve the markdown files, expanding glob patterns
+    ///
+    /// Files not found in the current directory are looked up in the project root. With
+    /// `--global`, the only file is the global task file.
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        if self.global {
+            return global_tasks_path()
+                .map(|path| vec![path])
+                .ok_or_else(|| miette::miette!("Cannot locate the user configuration directory"));
+        }
+        let files: Vec<PathBuf> = match self.project() {
+            Some(project) => self.file.iter().map(|file| project.resolve(file)).collect(),
+            None => self.file.clone(),
+        };
+        mx::files::expand_file_patterns(&files).into_diagnostic()
+    }
+
+    /// Files to look a task up in: the file named by a `file.md#section` reference, or else
+    /// the `--file` arguments
+    fn task_files(&self, task: &TaskRef) -> Result<Vec<PathBuf>> {
+        if self.global {
+            return self.files();
+        }
+        match (task.file, self.project()) {
+            (Some(file), Some(project)) => Ok(vec![project.resolve(file)]),
+            (Some(file), None) => Ok(vec![file.to_path_buf()]),
+            (None, _) => self.files(),
+        }
+    }
+
+    /// The project the current directory belongs to, found by searching upward
+    fn project(&self) -> Option<Project> {
+        let cwd = std::env::current_dir().ok()?;
+        Project::discover(&cwd, self.file.first()?)
+    }
+}
 
+/// Options controlling how a task is executed
+#[derive(Args)]
+struct ExecArgs {
     /// Override runtime for a language (format: lang:command, e.g., python:python3.11)
     #[arg(short, long, value_name = "LANG:COMMAND")]
     runtime: Vec<String>,
@@ -38,12 +183,338 @@ struct Cli {
     #[arg(short, long, value_name = "MODE")]
     execution_mode: Option<String>,
 
+    /// Also write each block's stdout and stderr to timestamped files in this directory
+    #[arg(long, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    /// Run the task even if its file is not trusted, without recording it (see `mx trust`)
+    #[arg(long)]
+    trust: bool,
+
+    /// Keep the temporary directories holding generated code files (paths are logged with -v)
+    #[arg(long)]
+    keep_temp: bool,
+
+    /// Honour `{allow-net}` on every task heading, not only of tasks in `[sandbox] allow_net`
+    #[arg(long)]
+    allow_net: bool,
+
+    /// Honour `{sandbox=off}` on every task heading, not only of tasks in `[sandbox] allow_off`
+    #[arg(long)]
+    no_sandbox: bool,
+
+    /// Don't forward piped stdin to the task (by default `cat data | mx run task` passes the data on)
+    #[arg(long)]
+    no_stdin: bool,
+
+    /// Output format: human-readable text, or one JSON event per line (jsonl)
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// With --output jsonl, write events to this inherited file descriptor instead of stdout
+    #[arg(long, value_name = "FD")]
+    output_fd: Option<i32>,
+
+    /// Fail instead of running a task that has warnings (e.g. it is deprecated)
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Wait for a task marked `{lock}` that is running elsewhere (the default in a terminal)
+    #[arg(long, conflicts_with = "no_wait")]
+    wait: bool,
+
+    /// Fail if a task marked `{lock}` is running elsewhere (the default when not in a terminal)
+    #[arg(long)]
+    no_wait: bool,
+
+    /// Run only the task, without the tasks it depends on
+    #[arg(long)]
+    no_deps: bool,
+
+    /// Set a parameter the tasks declare in their `params` metadata (repeatable)
+    #[arg(long, value_name = "NAME=VALUE")]
+    param: Vec<String>,
+
+    /// Run only one code block of the task, by index (0 is the first) or `name=` attribute;
+    /// `mx run Task#name` does the same
+    #[arg(long, value_name = "BLOCK")]
+    block: Option<String>,
+
+    /// Run the Nth of several tasks with the same title (0 is the first), in file and document
+    /// order
+    #[arg(long, value_name = "N")]
+    index: Option<usize>,
+
+    /// Continue from the block the last run of the task failed at, skipping the blocks that
+    /// succeeded and the task's dependencies
+    #[arg(long, conflicts_with = "block")]
+    resume: bool,
+
+    /// Show each code block before it runs and ask whether to run, skip, edit or abort it
+    #[arg(long)]
+    step: bool,
+
+    /// Pass block output straight to the terminal instead of showing a spinner and the
+    /// status of each block (the default when stderr is a terminal)
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Hide the output of code blocks and show only whether each one passed and how long it
+    /// took
+    #[arg(long)]
+    summary: bool,
+
+    /// Start every line of block output with the ID of its block, e.g. `[Build#0]`
+    #[arg(long, conflicts_with = "summary")]
+    prefix: bool,
+
+    /// Set from the global --quiet: hide mx's own banners such as "Running task:"
+    #[arg(skip)]
+    quiet: bool,
+
+    /// Run every code block on an SSH host (admin@db1) or in a pod (k8s:deployment/web),
+    /// overriding `remote` settings
+    #[arg(long, value_name = "TARGET")]
+    on: Option<String>,
+
+    /// Print how long the task and each of its blocks took to stderr, as text or as the
+    /// JSON execution report
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "text"
+    )]
+    profile: Option<ProfileFormat>,
+
+    /// Write the execution report of each task to a file as a line of JSON instead
+    #[arg(long, value_name = "FILE")]
+    profile_output: Option<PathBuf>,
+
+    /// The --profile-output file, created when the first report is written
+    #[arg(skip)]
+    profile_file: OnceLock<std::fs::File>,
+
+    /// Write what each code block printed on stdout into an ```output block after it in the
+    /// task file, replacing the one there, once the task succeeds
+    #[arg(long)]
+    record: bool,
+
+    /// Write a report of the run, with each block's code, output, exit status and times, to
+    /// this Markdown file (or HTML, if it ends in .html)
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
     /// Arguments to pass to the task (use -- to separate: mx task -- arg1 arg2)
     #[arg(last = true)]
     args: Vec<String>,
+}
 
-    #[command(subcommand)]
-    command: Option<Commands>,
+impl ExecArgs {
+    /// Create a runner with the runtime overrides applied, running tasks in the project root
+    fn runner(&self, source: &SourceArgs) -> Result<Runner> {
+        let mut config = source.load_config()?;
+        // Parse execution mode if specified
+        let exec_mode = if let Some(mode_str) = &self.execution_mode {
+            Some(ExecutionMode::try_from(mode_str.as_str()).into_diagnostic()?)
+        } else {
+            None
+        };
+
+        if !self.runtime.is_empty() {
+            config
+                .apply_runtime_overrides(&self.runtime, exec_mode)
+                .into_diagnostic()?;
+        }
+
+        if let Some(log_dir) = &self.log_dir {
+            config.log_dir = Some(log_dir.clone());
+        }
+        if self.keep_temp {
+            config.keep_temp = true;
+        }
+        if self.allow_net {
+            config.sandbox.allow_net = vec!["*".to_string()];
+        }
+        if self.no_sandbox {
+            config.sandbox.allow_off = vec!["*".to_string()];
+        }
+
+        // Data piped into mx goes to the task rather than being read as code
+        let forward_stdin = !self.no_stdin && !std::io::stdin().is_terminal();
+
+        // Waiting is only useful when someone is watching; scripts and CI fail fast
+        let lock_wait = self.wait || (!self.no_wait && std::io::stdin().is_terminal());
+
+        let mut runner = Runner::new(config)
+            .with_stdin_forwarding(forward_stdin)
+            .with_lock_wait(lock_wait);
+        if let Some(reporter) = ProgressReporter::from_env() {
+            runner = runner.with_progress_reporter(reporter);
+        }
+        if let Some(target) = &self.on {
+            runner = runner.with_remote(target);
+        }
+        if self.step {
+            if !std::io::stdin().is_terminal() {
+                return Err(miette::miette!("--step needs a terminal to ask on"));
+            }
+            runner = runner.with_step_handler(step_handler());
+        }
+        if let Some(project) = source.project() {
+            runner = runner.with_root(project.root);
+        }
+        if self.output == OutputFormat::Text {
+            let ui = self.shows_progress().then(|| Arc::new(ProgressUi::new()));
+            let output = ui.clone();
+            let write = move |stream, data: &[u8]| match &output {
+                Some(ui) => ui.write(stream, data),
+                None => write_output(stream, data),
+            };
+            // Lines are flushed before the progress UI finishes a block's status line
+            if self.prefix {
+                runner = runner.with_observer(Arc::new(LinePrefixer::new(
+                    move |id, stream, line: &[u8]| write(stream, &prefixed_line(id, line)),
+                )));
+            } else if !self.summary && ui.is_some() {
+                runner = runner.with_output_handler(Arc::new(write));
+            }
+            if self.prefix || self.summary {
+                runner = runner.with_output_handler(Arc::new(|_, _: &[u8]| {}));
+            }
+            match ui {
+                Some(ui) => runner = runner.with_observer(ui),
+                None if self.summary => runner = runner.with_observer(Arc::new(BlockSummary)),
+                None => {}
+            }
+            return Ok(runner);
+        }
+
+        let writer = match self.output_fd {
+            Some(fd) => events_fd_writer(fd)?,
+            None => JsonlWriter::stdout(),
+        };
+        let runner = runner.with_event_handler(writer.into_handler());
+
+        if self.events_on_stdout() {
+            // Keep stdout for events only; block output goes to stderr
+            Ok(runner.with_output_handler(Arc::new(|_, data: &[u8]| {
+                let _ = std::io::stderr().write_all(data);
+            })))
+        } else {
+            Ok(runner)
+        }
+    }
+
+    /// Whether jsonl events are written to stdout, leaving no room for other output there
+    fn events_on_stdout(&self) -> bool {
+        self.output == OutputFormat::Jsonl && self.output_fd.is_none()
+    }
+
+    /// Whether to draw the progress UI, which needs a terminal and does not mix with the
+    /// prompts of --step
+    fn shows_progress(&self) -> bool {
+        !self.no_progress && !self.step && !self.quiet && std::io::stderr().is_terminal()
+    }
+
+    /// Whether to print banners such as "Running task:"
+    fn shows_banners(&self) -> bool {
+        !self.quiet && !self.events_on_stdout()
+    }
+}
+
+#[cfg(unix)]
+fn events_fd_writer(fd: i32) -> Result<JsonlWriter> {
+    if fd <= 2 {
+        return Err(miette::miette!(
+            "--output-fd must be an inherited descriptor above 2, got {}",
+            fd
+        ));
+    }
+    Ok(JsonlWriter::from_fd(fd))
+}
+
+#[cfg(not(unix))]
+fn events_fd_writer(_fd: i32) -> Result<JsonlWriter> {
+    Err(miette::miette!(
+        "--output-fd is only supported on unix platforms"
+    ))
+}
+
+/// Output format of a run
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Block output and a timing summary for humans
+    Text,
+    /// Structured run events, one JSON object per line
+    Jsonl,
+}
+
+/// Output format of the timing summary
+#[derive(Clone, Copy, ValueEnum)]
+enum ProfileFormat {
+    /// Human-readable per-block timings
+    Text,
+    /// Machine-readable execution report
+    Json,
+}
+
+/// When to color the output
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// Color output written to a terminal, unless NO_COLOR is set
+    Auto,
+    /// Always color the output, even when it is piped
+    Always,
+    /// Never color the output
+    Never,
+}
+
+impl ColorChoice {
+    /// Apply the choice to all output: text styling, diagnostics and syntax highlighting
+    fn apply(self) {
+        let color = match self {
+            ColorChoice::Auto => return,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        colored::control::set_override(color);
+        let _ = miette::set_hook(Box::new(move |_| {
+            Box::new(miette::MietteHandlerOpts::new().color(color).build())
+        }));
+    }
+
+    /// Whether to color output written to stderr
+    fn stderr(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Output format of `mx list`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ListFormat {
+    /// Task titles and descriptions for humans
+    Text,
+    /// LSP code lenses on task headings and code blocks as JSON, for editor extensions
+    Codelens,
+    /// The qualified name of each task, one per line, for shell completion
+    Names,
+}
+
+/// Output format of `mx config show`
+#[derive(Clone, Copy, ValueEnum)]
+enum ConfigFormat {
+    /// TOML with the source of each value as a comment
+    Toml,
+    /// The configuration and the source of each value as JSON
+    Json,
 }
 
 #[derive(Subcommand)]
@@ -51,82 +522,387 @@ enum Commands {
     /// Run a task from a markdown file
     Run {
         /// Task name (section title) to execute
-        task: String,
+        #[arg(required_unless_present_any = ["tag", "since"])]
+        task: Option<String>,
 
-        /// Path to the markdown file
-        #[arg(short, long, default_value = DEFAULT_TASKS_FILE)]
-        file: PathBuf,
+        /// Run all tasks with this tag, in document order
+        #[arg(short, long, conflicts_with = "task")]
+        tag: Option<String>,
 
-        /// Path to configuration file
-        #[arg(short, long)]
-        config: Option<PathBuf>,
+        /// Run the task in every workspace member that has it, one member after another
+        #[arg(long, requires = "task")]
+        all: bool,
+
+        /// With --all, run the members at the same time, tagging output lines with the member
+        #[arg(long, requires = "all", conflicts_with_all = ["prefix", "summary", "step"])]
+        parallel: bool,
+
+        /// Only run the tasks whose `sources` changed since this git revision, and the tasks
+        /// depending on them
+        #[arg(long, value_name = "REV", conflicts_with = "all")]
+        since: Option<String>,
+
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
 
-        /// Heading level for sections (1-6)
+    /// Run every task of the markdown files in document order, as a notebook
+    RunAll {
+        #[command(flatten)]
+        source: SourceArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+
+    /// Run tasks in a temporary directory, checking they succeed and print their ```output blocks
+    Test {
+        /// Only test tasks with this tag
         #[arg(short, long)]
-        level: Option<u8>,
+        tag: Option<String>,
 
-        /// Override runtime for a language (format: lang:command, e.g., python:python3.11)
-        #[arg(short, long, value_name = "LANG:COMMAND")]
-        runtime: Vec<String>,
+        /// Run the tasks even if their files are not trusted, without recording them
+        #[arg(long)]
+        trust: bool,
 
-        /// Set execution mode for runtime overrides (stdin, file, arg)
-        #[arg(short, long, value_name = "MODE")]
-        execution_mode: Option<String>,
+        /// List the tasks that would be tested without running them
+        #[arg(long)]
+        dry_run: bool,
 
-        /// Arguments to pass to the task (use -- to separate: mx run task -- arg1 arg2)
-        #[arg(last = true)]
-        args: Vec<String>,
+        #[command(flatten)]
+        source: SourceArgs,
     },
 
-    /// List all available tasks in a markdown file
-    List {
-        /// Path to the markdown file
-        #[arg(short, long, default_value = DEFAULT_TASKS_FILE)]
-        file: PathBuf,
+    /// Re-run the most recent task run recorded in the history
+    Rerun {
+        /// Re-run the most recent failed run instead
+        #[arg(long)]
+        last_failed: bool,
 
         /// Path to configuration file
-        #[arg(short, long)]
+        #[arg(short, long, env = "MX_CONFIG")]
         config: Option<PathBuf>,
 
-        /// Heading level for sections (1-6)
+        #[command(flatten)]
+        exec: ExecArgs,
+    },
+
+    /// Show recent task runs, oldest first
+    History {
+        /// Number of runs to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the runs as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List all available tasks in a markdown file
+    List {
+        /// Only list tasks with this tag
+        #[arg(short, long)]
+        tag: Option<String>,
+
+        /// Show each task's languages, number of code blocks, tags and dependencies as a table
+        #[arg(long)]
+        long: bool,
+
+        /// Output format
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ListFormat::Text)]
+        format: ListFormat,
+
+        /// List the tasks of every Markdown file under a directory (default: the current one)
+        #[arg(short, long, value_name = "DIR", num_args = 0..=1, default_missing_value = ".")]
+        recursive: Option<PathBuf>,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Search task titles, descriptions and code for every word of a query
+    Search {
+        /// Words to search for, case-insensitively
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Run an mq query over the markdown files and print the values it yields
+    Query {
+        /// mq expression, e.g. 'nodes | sections_with_code(2)'; the functions mx extracts
+        /// sections with are available
+        query: String,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Show a task's description, code blocks, and resolved runtimes without running it
+    Show {
+        /// Task name (section title) to show
+        task: String,
+
+        /// Show the Nth of several tasks with the same title (0 is the first)
+        #[arg(long, value_name = "N")]
+        index: Option<usize>,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Convert tasks to Make targets, just recipes, npm scripts or GitHub Actions jobs
+    /// running them with mx
+    Export {
+        /// Target format: makefile, justfile, package-json-scripts or github-actions
+        #[arg(long, value_name = "FORMAT")]
+        format: ExportFormat,
+
+        /// Only export tasks with this tag, and the tasks they depend on
         #[arg(short, long)]
-        level: Option<u8>,
+        tag: Option<String>,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Write a tasks file with a section per Make target, just recipe or npm script
+    Import {
+        /// Makefile, justfile or package.json to import
+        file: PathBuf,
+
+        /// Format of the file, if its name doesn't tell: makefile, justfile or
+        /// package-json-scripts
+        #[arg(long, value_name = "FORMAT")]
+        format: Option<ExportFormat>,
+
+        /// Output path for the tasks file
+        #[arg(short, long, default_value = "TASKS.md")]
+        output: PathBuf,
+    },
+
+    /// Append a new task section to a markdown file
+    Add {
+        /// Task name (section title); tag and attribute suffixes like `[ci]` are kept
+        name: String,
+
+        /// Language of the task's code block
+        #[arg(long, default_value = "bash")]
+        lang: String,
+
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Check a markdown file for problems without running anything
+    Validate {
+        #[command(flatten)]
+        source: SourceArgs,
+    },
+
+    /// Check that the tasks files, configuration, runtimes and integrations are usable
+    Doctor {
+        #[command(flatten)]
+        source: SourceArgs,
     },
 
-    /// Generate a sample configuration file
+    /// Trust markdown files to run their tasks, as long as their content is unchanged
+    Trust {
+        /// Files to trust
+        #[arg(default_value = DEFAULT_TASKS_FILE)]
+        files: Vec<PathBuf>,
+
+        /// Remove the files from the trust store instead
+        #[arg(long)]
+        revoke: bool,
+    },
+
+    /// Browse tasks, run them and watch their output in a terminal dashboard
+    #[cfg(feature = "tui")]
+    Ui {
+        #[command(flatten)]
+        source: SourceArgs,
+
+        /// Run tasks even if their files are not trusted, without recording them
+        #[arg(long)]
+        trust: bool,
+    },
+
+    /// Inspect configuration files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Generate a configuration file and a starter tasks file
     Init {
         /// Output path for configuration file
         #[arg(short, long, default_value = "mx.toml")]
         output: PathBuf,
+
+        /// Output path for the tasks file
+        #[arg(long, default_value = "TASKS.md")]
+        tasks: PathBuf,
+
+        /// Starter project: minimal, full, rust-project, node-project or docs-runbook
+        /// (asked for on a terminal, else full)
+        #[arg(short, long, value_name = "NAME")]
+        template: Option<Template>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check the user and project configuration files for unknown keys and invalid values
+    Check {
+        /// Configuration file to check instead of the discovered project file
+        #[arg(short, long, env = "MX_CONFIG")]
+        config: Option<PathBuf>,
+    },
+
+    /// Print the effective configuration and where each value came from
+    Show {
+        #[command(flatten)]
+        source: SourceArgs,
+
+        /// Output format
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ConfigFormat::Toml)]
+        format: ConfigFormat,
     },
 }
 
 fn main() -> Result<()> {
+    mx::crash::install_panic_hook_from_env();
+
     let cli = Cli::parse();
 
+    cli.color.apply();
+    init_logging(cli.verbose, cli.quiet, cli.color.stderr());
+    mx::process::forward_signals().into_diagnostic()?;
+
+    let result = run(cli);
+    if let (Err(e), Some(signal)) = (&result, mx::process::cancel_signal()) {
+        // Conventional status of a process killed by the signal, e.g. 130 for Ctrl-C
+        eprintln!("{:?}", e);
+        std::process::exit(128 + signal);
+    }
+
+    result
+}
+
+fn run(mut cli: Cli) -> Result<()> {
+    // --quiet is global, but only task runs have banners to hide
+    cli.exec.quiet = cli.quiet;
+    if let Some(
+        Commands::Run { exec, .. } | Commands::RunAll { exec, .. } | Commands::Rerun { exec, .. },
+    ) = &mut cli.command
+    {
+        exec.quiet = cli.quiet;
+    }
+
     match cli.command {
         Some(Commands::Run {
-            file,
             task,
+            tag,
+            all,
+            parallel,
+            since,
+            source,
+            exec,
+        }) => match (task, tag, since) {
+            (task, tag, Some(since)) => run_changed_tasks(source, &since, task, tag, exec)?,
+            (_, Some(tag), None) => run_tagged_tasks(source, tag, exec)?,
+            (Some(task), None, None) if all => run_workspace_task(source, task, exec, parallel)?,
+            (Some(task), None, None) => run_task(source, task, exec)?,
+            (None, None, None) => unreachable!("clap requires a task, a tag or a revision"),
+        },
+        Some(Commands::RunAll { source, exec }) => run_all_tasks(source, exec)?,
+        Some(Commands::Test {
+            tag,
+            trust,
+            dry_run,
+            source,
+        }) => test_tasks(source, tag, trust, dry_run)?,
+        Some(Commands::Rerun {
+            last_failed,
             config,
-            level,
-            runtime,
-            execution_mode,
-            args,
-        }) => run_task(file, task, config, level, runtime, execution_mode, args)?,
+            exec,
+        }) => rerun_task(last_failed, config, exec)?,
+        Some(Commands::History { limit, json }) => show_history(limit, json)?,
+        Some(Commands::List {
+            tag,
+            long,
+            format: ListFormat::Text,
+            recursive: Some(dir),
+            source,
+        }) => list_tree_tasks(source, &dir, tag, long)?,
         Some(Commands::List {
+            tag,
+            long,
+            format: ListFormat::Text,
+            recursive: None,
+            source,
+        }) => list_tasks(source, tag, long)?,
+        Some(Commands::List {
+            tag,
+            format: ListFormat::Codelens,
+            recursive,
+            source,
+            ..
+        }) => list_code_lenses(source, tag, recursive)?,
+        Some(Commands::List {
+            tag,
+            format: ListFormat::Names,
+            recursive,
+            source,
+            ..
+        }) => list_task_names(source, tag, recursive)?,
+        Some(Commands::Search { query, source }) => search_tasks(source, query.join(" "))?,
+        Some(Commands::Query { query, source }) => run_query(source, query)?,
+        Some(Commands::Show {
+            task,
+            index,
+            source,
+        }) => show_task(source, task, index)?,
+        Some(Commands::Export {
+            format,
+            tag,
+            source,
+        }) => export_tasks(source, format, tag)?,
+        Some(Commands::Import {
             file,
-            config,
-            level,
-        }) => list_tasks(file, config, level)?,
-        Some(Commands::Init { output }) => init_config(output)?,
+            format,
+            output,
+        }) => import_tasks(file, format, output)?,
+        Some(Commands::Add { name, lang, source }) => add_task(source, name, lang)?,
+        Some(Commands::Validate { source }) => validate_tasks(source)?,
+        Some(Commands::Doctor { source }) => run_doctor(source)?,
+        Some(Commands::Trust { files, revoke }) => trust_files(files, revoke)?,
+        #[cfg(feature = "tui")]
+        Some(Commands::Ui { source, trust }) => ui::run_ui(source, trust)?,
+        Some(Commands::Config {
+            command: ConfigCommand::Check { config },
+        }) => check_config(config)?,
+        Some(Commands::Config {
+            command: ConfigCommand::Show { source, format },
+        }) => show_config(source, format)?,
+        Some(Commands::Init {
+            output,
+            tasks,
+            template,
+        }) => init_project(output, tasks, template)?,
         None => {
             // If no subcommand, check if task is provided
             if let Some(task) = cli.task {
-                run_task(cli.file, task, cli.config, cli.level, cli.runtime, cli.execution_mode, cli.args)?;
+                run_task(cli.source, task, cli.exec)?;
             } else {
                 // No task provided, list available tasks
-                list_tasks(cli.file, cli.config, cli.level)?;
+                list_tasks(cli.source, None, false)?;
             }
         }
     }
@@ -134,128 +910,2402 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-/// Run a specific task
-fn run_task(
-    markdown_path: PathBuf,
-    task_name: String,
-    config_path: Option<PathBuf>,
-    level: Option<u8>,
-    runtime_overrides: Vec<String>,
-    execution_mode: Option<String>,
-    args: Vec<String>,
-) -> Result<()> {
-    let mut config = load_config(config_path)?;
-
-    // Override heading level if specified
-    if let Some(level) = level {
-        config.heading_level = level;
-    }
-
-    // Parse execution mode if specified
-    let exec_mode = if let Some(mode_str) = execution_mode {
-        Some(ExecutionMode::try_from(mode_str.as_str()).into_diagnostic()?)
+/// Initialize the tracing subscriber based on verbosity flags
+///
+/// `RUST_LOG` takes precedence over the flags when set.
+fn init_logging(verbose: u8, quiet: bool, ansi: bool) {
+    let level = if quiet {
+        "error"
     } else {
-        None
+        match verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }
     };
 
-    // Apply runtime overrides
-    if !runtime_overrides.is_empty() {
-        config
-            .apply_runtime_overrides(&runtime_overrides, exec_mode)
-            .into_diagnostic()?;
-    }
-
-    let mut runner = Runner::new(config);
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(format!("mx={}", level)));
 
-    println!("Running task: {}", task_name);
-    println!();
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(ansi)
+        .with_target(false)
+        .init();
+}
 
-    runner
-        .run_task_with_args(&markdown_path, &task_name, &args)
-        .into_diagnostic()?;
+/// Run a specific task
+fn run_task(source: SourceArgs, task_name: String, mut exec: ExecArgs) -> Result<()> {
+    let mut runner = exec.runner(&source)?;
+    let members = workspace_members(runner.config(), &source)?;
+    // `web:build` is the `build` task of the workspace member `web`, run in its directory
+    let separator = &runner.config().namespaces.separator;
+    let (task, files) = match workspace::split_task(&task_name, &members, separator) {
+        Some((member, task)) => {
+            runner = runner.with_root(&member.dir);
+            (TaskRef { file: None, task }, vec![member.file.clone()])
+        }
+        None => {
+            let task = TaskRef::parse(&task_name);
+            let files = source.task_files(&task)?;
+            (task, files)
+        }
+    };
+    let found = match runner.find_task_at(&files, task.task, exec.index) {
+        Ok(found) => found,
+        // `Task#block` selects a block, unless a task has that title
+        Err(mx::Error::SectionNotFound { .. })
+            if exec.block.is_none()
+                && let Some((title, block)) = task.task.rsplit_once('#')
+                && !title.is_empty() =>
+        {
+            exec.block = Some(block.to_string());
+            runner
+                .find_task_at(&files, title, exec.index)
+                .into_diagnostic()?
+        }
+        Err(e) => return Err(e).into_diagnostic(),
+    };
 
-    Ok(())
+    run_with_dependencies(runner, vec![found], &exec)
 }
 
-/// List all available tasks
-fn list_tasks(
-    markdown_path: PathBuf,
-    config_path: Option<PathBuf>,
-    level: Option<u8>,
+/// Run a task in every workspace member that has it, stopping at the first failure, or with
+/// `parallel` in all of them at once
+fn run_workspace_task(
+    source: SourceArgs,
+    task_name: String,
+    mut exec: ExecArgs,
+    parallel: bool,
 ) -> Result<()> {
-    let mut config = load_config(config_path)?;
-
-    // Override heading level if specified
-    if let Some(level) = level {
-        config.heading_level = level;
+    let runner = exec.runner(&source)?;
+    let members = workspace_members(runner.config(), &source)?;
+    if members.is_empty() {
+        return Err(miette::miette!(
+            "--all runs a task in every workspace member, but no [workspace] members are configured"
+        ));
     }
 
-    let mut runner = Runner::new(config);
-
-    let sections = runner.list_task_sections(&markdown_path).into_diagnostic()?;
-
-    if sections.is_empty() {
-        println!(
-            "{}",
-            format!("No tasks found in {}", markdown_path.display()).yellow()
-        );
-        return Ok(());
+    let mut tasks = Vec::new();
+    for member in &members {
+        let files = std::slice::from_ref(&member.file);
+        match runner.find_task_at(files, &task_name, exec.index) {
+            Ok(task) => tasks.push((member, task)),
+            Err(mx::Error::SectionNotFound { .. }) => {}
+            Err(e) => return Err(e).into_diagnostic(),
+        }
+    }
+    if tasks.is_empty() {
+        return Err(miette::miette!(
+            "No workspace member has a task '{}'",
+            task_name
+        ));
     }
 
-    let mut output = String::new();
-    output.push_str(&format!(
-        "{} {}\n\n",
-        "Available tasks in".bold(),
-        markdown_path.display().to_string().cyan()
-    ));
-
-    for section in sections {
-        if let Some(desc) = section.description {
-            let trimmed = desc.trim();
-            if !trimmed.is_empty() {
-                output.push_str(&format!(
-                    "  {} {}\n",
-                    section.title.green().bold(),
-                    format!("- {}", trimmed).bright_black()
-                ));
-            } else {
-                output.push_str(&format!("  {}\n", section.title.green().bold()));
+    if !parallel {
+        for (index, (member, task)) in tasks.into_iter().enumerate() {
+            if exec.shows_banners() {
+                if index > 0 {
+                    println!();
+                }
+                println!("{} {}", "Workspace member".bold(), member.name.task());
             }
-        } else {
-            output.push_str(&format!("  {}\n", section.title.green().bold()));
+            let runner = exec.runner(&source)?.with_root(&member.dir);
+            run_with_dependencies(runner, vec![task], &exec)?;
         }
+        return Ok(());
     }
 
-    print!("{}", output);
+    // Members share the terminal, so their blocks never take it over, and only their
+    // output and a line per member are printed
+    let banners = exec.shows_banners();
+    exec.no_progress = true;
+    exec.quiet = true;
+    let (exec, source) = (&exec, &source);
+    let failed: Vec<&str> = std::thread::scope(|scope| {
+        let runs: Vec<_> = tasks
+            .into_iter()
+            .map(|(member, task)| {
+                let run = scope.spawn(move || {
+                    let name = member.name.clone();
+                    let runner = exec
+                        .runner(source)?
+                        .with_root(&member.dir)
+                        .with_detached_terminal(true)
+                        .with_observer(Arc::new(LinePrefixer::new(
+                            move |_, stream, line: &[u8]| {
+                                write_output(stream, &prefixed_line(&name, line))
+                            },
+                        )))
+                        .with_output_handler(Arc::new(|_, _: &[u8]| {}));
+                    run_with_dependencies(runner, vec![task], exec)
+                });
+                (member, run)
+            })
+            .collect();
 
-    Ok(())
-}
+        runs.into_iter()
+            .filter_map(|(member, run)| {
+                let result = run
+                    .join()
+                    .unwrap_or_else(|_| Err(miette::miette!("the run panicked")));
+                match result {
+                    Ok(()) if banners => {
+                        eprintln!("{} {}", "✓".success(), member.name.task());
+                        None
+                    }
+                    Ok(()) => None,
+                    Err(e) => {
+                        eprintln!("{} {}: {}", "✗".error(), member.name.task(), e);
+                        Some(member.name.as_str())
+                    }
+                }
+            })
+            .collect()
+    });
 
-/// Initialize configuration file
-fn init_config(output_path: PathBuf) -> Result<()> {
-    if output_path.exists() {
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(miette::miette!(
+            "'{}' failed in {}",
+            task_name,
+            failed.join(", ")
+        ))
+    }
+}
+
+/// Members of the project's workspace that have a task file, see [`mx::workspace`]
+fn workspace_members(config: &Config, source: &SourceArgs) -> Result<Vec<Member>> {
+    if config.workspace.is_empty() {
+        return Ok(Vec::new());
+    }
+    let root = match source.project() {
+        Some(project) => project.root,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    config.workspace.members(&root).into_diagnostic()
+}
+
+/// Run the tasks whose `sources` changed since a git revision, and the tasks depending on
+/// them, out of a single task, the tasks with a tag, or every task
+fn run_changed_tasks(
+    source: SourceArgs,
+    since: &str,
+    task: Option<String>,
+    tag: Option<String>,
+    exec: ExecArgs,
+) -> Result<()> {
+    if task.is_none() && exec.block.is_some() {
+        return Err(miette::miette!(
+            "--block selects a block of a single task, not of --since"
+        ));
+    }
+    let runner = exec.runner(&source)?;
+    let tasks = match (task, tag) {
+        (Some(task), _) => {
+            let task = TaskRef::parse(&task);
+            let files = source.task_files(&task)?;
+            vec![
+                runner
+                    .find_task_at(&files, task.task, exec.index)
+                    .into_diagnostic()?,
+            ]
+        }
+        (None, Some(tag)) => runner
+            .find_tagged_in_files(&source.files()?, &tag)
+            .into_diagnostic()?,
+        (None, None) => {
+            let mut tasks = Vec::new();
+            for path in source.files()? {
+                let sections = runner.list_task_sections(&path).into_diagnostic()?;
+                tasks.extend(sections.into_iter().map(|section| (path.clone(), section)));
+            }
+            tasks
+        }
+    };
+
+    let dir = std::env::current_dir().into_diagnostic()?;
+    let changed = changes::changed_files(&dir, since).into_diagnostic()?;
+    let tasks = changes::select_changed(&runner, tasks, &changed).into_diagnostic()?;
+    if tasks.is_empty() {
+        if exec.shows_banners() {
+            let message = format!("No task has sources that changed since {}", since);
+            println!("{}", message.muted());
+        }
+        return Ok(());
+    }
+
+    run_with_dependencies(runner, tasks, &exec)
+}
+
+/// Run all tasks with a tag, stopping at the first failure
+fn run_tagged_tasks(source: SourceArgs, tag: String, exec: ExecArgs) -> Result<()> {
+    if exec.block.is_some() {
+        return Err(miette::miette!(
+            "--block selects a block of a single task, not of --tag"
+        ));
+    }
+    let runner = exec.runner(&source)?;
+    let tasks = runner
+        .find_tagged_in_files(&source.files()?, &tag)
+        .into_diagnostic()?;
+
+    if tasks.is_empty() {
+        return Err(miette::miette!("No tasks tagged '{}'", tag));
+    }
+
+    run_with_dependencies(runner, tasks, &exec)
+}
+
+/// Run every task of the files in document order, stopping at the first failure
+fn run_all_tasks(source: SourceArgs, exec: ExecArgs) -> Result<()> {
+    if exec.block.is_some() {
+        return Err(miette::miette!(
+            "--block selects a block of a single task, not of run-all"
+        ));
+    }
+    let runner = exec.runner(&source)?;
+    let mut tasks = Vec::new();
+    for path in source.files()? {
+        let sections = runner.list_task_sections(&path).into_diagnostic()?;
+        tasks.extend(sections.into_iter().map(|section| (path.clone(), section)));
+    }
+
+    if tasks.is_empty() {
+        return Err(miette::miette!("No tasks found"));
+    }
+
+    run_with_dependencies(runner, tasks, &exec)
+}
+
+/// Run tasks after the tasks they depend on, stopping at the first failure, and write the
+/// `--report` of the run whether it succeeds or not
+fn run_with_dependencies(
+    mut runner: Runner,
+    tasks: Vec<(PathBuf, Section)>,
+    exec: &ExecArgs,
+) -> Result<()> {
+    let recorder = exec.report.as_ref().map(|_| Arc::new(RunRecorder::new()));
+    if let Some(recorder) = &recorder {
+        runner = runner.with_observer(recorder.clone());
+    }
+    let capture = exec.record.then(|| Arc::new(OutputCapture::new()));
+    if let Some(capture) = &capture {
+        runner = runner.with_observer(capture.clone());
+    }
+    let queue = queue_tasks(&mut runner, tasks, exec)?;
+    // Before asking for parameters or running dependencies, not only as each task runs
+    if !exec.trust {
+        let mut checked: Vec<&Path> = Vec::new();
+        for (markdown_path, _, _) in &queue {
+            if !checked.contains(&markdown_path.as_path()) {
+                check_trust(&runner, markdown_path)?;
+                checked.push(markdown_path);
+            }
+        }
+    }
+    for (name, value) in task_params(&queue, exec)? {
+        runner = runner.with_env(name, value);
+    }
+    let result = run_queue(&mut runner, queue, exec, capture.as_deref());
+    let (Some(path), Some(recorder)) = (&exec.report, recorder) else {
+        return result;
+    };
+
+    let command = shell_words::join(std::env::args());
+    let report = recorder.render(ReportFormat::from_path(path), &command);
+    let written = std::fs::write(path, report)
+        .map_err(|e| miette::miette!("Failed to write the report to {}: {}", path.display(), e));
+    if written.is_ok() && exec.shows_banners() {
+        eprintln!("{} {}", "Report written to".bold(), path.display());
+    }
+    result.and(written)
+}
+
+/// Queue tasks after the tasks they depend on, each with whether it was requested
+///
+/// Every task is queued once, however many others depend on it.
+fn queue_tasks(
+    runner: &mut Runner,
+    tasks: Vec<(PathBuf, Section)>,
+    exec: &ExecArgs,
+) -> Result<Vec<(PathBuf, Section, bool)>> {
+    // Tasks in the order they run, whether they were requested, and their (file, title)
+    let mut queue: Vec<(PathBuf, Section, bool)> = Vec::new();
+    let mut keys: Vec<(PathBuf, String)> = Vec::new();
+    for (markdown_path, section) in tasks {
+        let resolved = if exec.no_deps || exec.resume {
+            vec![(markdown_path, section)]
+        } else {
+            runner
+                .resolve_dependencies(&markdown_path, &section)
+                .into_diagnostic()?
+        };
+        let last = resolved.len() - 1;
+
+        for (index, (path, section)) in resolved.into_iter().enumerate() {
+            let key = (
+                std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone()),
+                section.title.clone(),
+            );
+            match keys.iter().position(|queued| *queued == key) {
+                Some(position) => queue[position].2 |= index == last,
+                None => {
+                    keys.push(key);
+                    queue.push((path, section, index == last));
+                }
+            }
+        }
+    }
+    Ok(queue)
+}
+
+/// Values of the parameters the queued tasks declare, asked for in a terminal unless given
+/// with --param or set in the environment, see [`mx::params`]
+fn task_params(
+    queue: &[(PathBuf, Section, bool)],
+    exec: &ExecArgs,
+) -> Result<Vec<(String, String)>> {
+    let given = exec
+        .param
+        .iter()
+        .map(|param| params::parse_assignment(param))
+        .collect::<mx::Result<Vec<_>>>()
+        .into_diagnostic()?;
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    let ask = |section: &Section, name: &str| {
+        if !interactive {
+            return None;
+        }
+        eprint!("{} {}: ", section.title.task(), name.bold());
+        let _ = std::io::stderr().flush();
+        let mut answer = String::new();
+        match std::io::stdin().read_line(&mut answer) {
+            Ok(read) if read > 0 => Some(answer.trim_end_matches(['\r', '\n']).to_string()),
+            _ => None,
+        }
+    };
+    params::resolve(queue.iter().map(|(_, section, _)| section), &given, ask).into_diagnostic()
+}
+
+/// Run queued tasks in order, stopping at the first failure
+///
+/// Only the requested tasks get the arguments, and only their `--block` is run.
+fn run_queue(
+    runner: &mut Runner,
+    queue: Vec<(PathBuf, Section, bool)>,
+    exec: &ExecArgs,
+    capture: Option<&OutputCapture>,
+) -> Result<()> {
+    for (index, (markdown_path, section, requested)) in queue.iter().enumerate() {
+        if index > 0 && exec.shows_banners() {
+            println!();
+        }
+        let args: &[String] = if *requested { &exec.args } else { &[] };
+        let blocks = match &exec.block {
+            Some(block) if *requested => {
+                let index = section.find_block(block).ok_or_else(|| {
+                    miette::miette!("Task '{}' has no block '{}'", section.title, block)
+                })?;
+                index..index + 1
+            }
+            _ if *requested && exec.resume => {
+                resume_point(runner, markdown_path, section, exec)..section.codes.len()
+            }
+            _ => 0..section.codes.len(),
+        };
+        execute_task(runner, markdown_path, section, args, blocks, exec, capture)?;
+    }
+
+    Ok(())
+}
+
+/// Index of the block a task failed at in its last run, or 0 to run it from the start
+fn resume_point(
+    runner: &Runner,
+    markdown_path: &Path,
+    section: &Section,
+    exec: &ExecArgs,
+) -> usize {
+    let file = std::path::absolute(markdown_path).unwrap_or_else(|_| markdown_path.to_path_buf());
+    let last = run_history(runner).last_run(&section.title, &file);
+    let failed_block = match last {
+        Ok(last) => last.and_then(|entry| entry.failed_block),
+        Err(e) => {
+            tracing::warn!("failed to read the run history: {}", e);
+            None
+        }
+    };
+
+    match failed_block.filter(|&block| block < section.codes.len()) {
+        Some(block) => {
+            if exec.shows_banners() && block > 0 {
+                eprintln!(
+                    "{} {} from block {}, after the blocks that succeeded in its last run",
+                    "Resuming".bold(),
+                    section.title,
+                    block_id(&section.title, block)
+                );
+            }
+            block
+        }
+        None => {
+            if exec.shows_banners() {
+                eprintln!(
+                    "{} {} has no failed run to resume; running it from the start",
+                    "Note:".warning(),
+                    section.title
+                );
+            }
+            0
+        }
+    }
+}
+
+/// Execute a task's section and print its timing summary, recording the output of its blocks
+/// in its file if a capture is given
+fn execute_task(
+    runner: &mut Runner,
+    markdown_path: &Path,
+    section: &Section,
+    args: &[String],
+    blocks: Range<usize>,
+    exec: &ExecArgs,
+    capture: Option<&OutputCapture>,
+) -> Result<()> {
+    if !exec.trust {
+        check_trust(runner, markdown_path)?;
+    }
+    check_warnings(runner, markdown_path, section, exec.deny_warnings)?;
+
+    if exec.shows_banners() {
+        if blocks.len() == 1 && section.codes.len() > 1 {
+            println!(
+                "Running task: {} (block {})",
+                section.title,
+                block_id(&section.title, blocks.start)
+            );
+        } else {
+            println!("Running task: {}", section.title);
+        }
+        println!();
+    }
+
+    if let Some(capture) = capture {
+        capture.clear();
+    }
+    let started = SystemTime::now();
+    let result = runner.execute_blocks(section, args, blocks);
+    record_run(runner, markdown_path, section, args, &result, started);
+    let report = result.map_err(|e| execution_diagnostic(markdown_path, e))?;
+    if let Some(capture) = capture {
+        write_outputs(
+            runner,
+            markdown_path,
+            section,
+            &report,
+            capture,
+            !exec.trust,
+        )?;
+    }
+
+    write_profile(&report, exec)
+}
+
+/// Report how long a task took as --profile and --profile-output ask, or only the total
+/// with --summary, which prints the status line of each block as it finishes
+fn write_profile(report: &ExecutionReport, exec: &ExecArgs) -> Result<()> {
+    if let Some(path) = &exec.profile_output {
+        let file = match exec.profile_file.get() {
+            Some(file) => file,
+            None => {
+                let file = std::fs::File::create(path)
+                    .map_err(|e| miette::miette!("Failed to create {}: {}", path.display(), e))?;
+                exec.profile_file.get_or_init(|| file)
+            }
+        };
+        let json = serde_json::to_string(report).into_diagnostic()?;
+        writeln!(&*file, "{}", json).map_err(|e| {
+            miette::miette!("Failed to write the profile to {}: {}", path.display(), e)
+        })?;
+        return Ok(());
+    }
+
+    match exec.profile {
+        _ if exec.events_on_stdout() => {}
+        Some(ProfileFormat::Json) => eprintln!("{}", report.to_json().into_diagnostic()?),
+        Some(ProfileFormat::Text) => print_timing_summary(report, true),
+        None if exec.summary && !exec.quiet => print_timing_summary(report, false),
+        None => {}
+    }
+    Ok(())
+}
+
+/// Write the captured stdout of a task's blocks into the output blocks of its file
+///
+/// The task is looked up again, as recording the output of an earlier task may have moved
+/// it. A file trusted before the change stays trusted after it.
+fn write_outputs(
+    runner: &mut Runner,
+    markdown_path: &Path,
+    section: &Section,
+    report: &ExecutionReport,
+    capture: &OutputCapture,
+    keep_trust: bool,
+) -> Result<()> {
+    let markdown = runner.load_markdown(markdown_path).into_diagnostic()?;
+    let sections = runner.list_task_sections(markdown_path).into_diagnostic()?;
+    // Of several tasks with the title, the one that ran is the nearest to where it was
+    let current = sections
+        .iter()
+        .filter(|s| s.title == section.title && s.parent == section.parent)
+        .min_by_key(|s| s.line.zip(section.line).map_or(0, |(a, b)| a.abs_diff(b)))
+        .ok_or_else(|| mx::Error::section_not_found(&section.title))
+        .into_diagnostic()?;
+    let (recorded, count) = record_outputs(&markdown, current, report, capture);
+    if recorded == markdown {
+        return Ok(());
+    }
+    std::fs::write(markdown_path, &recorded).into_diagnostic()?;
+    eprintln!(
+        "{} the output of {} block{} in {}",
+        "Recorded".bold(),
+        count,
+        if count == 1 { "" } else { "s" },
+        markdown_path.display()
+    );
+
+    if keep_trust {
+        let mut store = mx::trust::TrustStore::load_default().into_diagnostic()?;
+        if store.is_trusted(markdown_path, &markdown) && !store.is_trusted(markdown_path, &recorded)
+        {
+            store.trust(markdown_path, &recorded).into_diagnostic()?;
+            store.save().into_diagnostic()?;
+        }
+    }
+    Ok(())
+}
+
+/// Fail unless a markdown file and the project configuration are trusted as they are now
+///
+/// The global task file is the user's own and needs no trust.
+fn check_trust(runner: &Runner, markdown_path: &Path) -> Result<()> {
+    let store = mx::trust::TrustStore::load_default().into_diagnostic()?;
+    if global_tasks_path().is_none_or(|path| path != markdown_path) {
+        let markdown = runner.load_markdown(markdown_path).into_diagnostic()?;
+        store.check(markdown_path, &markdown).into_diagnostic()?;
+    }
+    for file in PROJECT_CONFIG_FILES.get().into_iter().flatten() {
+        let content = std::fs::read_to_string(file).into_diagnostic()?;
+        store.check(file, &content).into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// Run every task, or the tagged ones, and check their output against the expected output
+///
+/// Tasks run in document order in a fresh temporary working directory, so they can't depend
+/// on or change the project's files. Block output is captured rather than printed; each task
+/// gets a pass or fail line, with a diff for every block whose output differs, and a summary
+/// table follows.
+fn test_tasks(source: SourceArgs, tag: Option<String>, trust: bool, dry_run: bool) -> Result<()> {
+    let capture = Arc::new(OutputCapture::new());
+    let mut runner = Runner::new(source.load_config()?)
+        .with_observer(capture.clone())
+        .with_output_handler(Arc::new(|_, _: &[u8]| {}));
+
+    let files = source.files()?;
+    let tasks = match &tag {
+        Some(tag) => runner.find_tagged_in_files(&files, tag),
+        None => files
+            .iter()
+            .map(|path| {
+                let sections = runner.list_task_sections(path)?;
+                Ok(sections.into_iter().map(|section| (path.clone(), section)))
+            })
+            .collect::<mx::Result<Vec<_>>>()
+            .map(|tasks| tasks.into_iter().flatten().collect()),
+    }
+    .into_diagnostic()?;
+    if tasks.is_empty() {
+        return Err(match tag {
+            Some(tag) => miette::miette!("No tasks tagged '{}'", tag),
+            None => miette::miette!("No tasks found"),
+        });
+    }
+
+    if dry_run {
+        let outcomes: Vec<TestOutcome> = tasks
+            .iter()
+            .map(|(path, section)| TestOutcome::new(path, section, TestResult::DryRun))
+            .collect();
+        print!("{}", format_test_table(&outcomes));
+        println!();
+        println!("{} tasks would run", tasks.len());
+        return Ok(());
+    }
+
+    if !trust {
+        let mut checked: Vec<&Path> = Vec::new();
+        for (markdown_path, _) in &tasks {
+            if !checked.contains(&markdown_path.as_path()) {
+                check_trust(&runner, markdown_path)?;
+                checked.push(markdown_path);
+            }
+        }
+    }
+
+    let workdir = tempfile::Builder::new()
+        .prefix("mx-test-")
+        .tempdir()
+        .into_diagnostic()?;
+    runner = runner.with_root(workdir.path());
+
+    let mut outcomes = Vec::new();
+    for (markdown_path, section) in &tasks {
+        capture.clear();
+        let started = Instant::now();
+        let result = runner.execute_section(section);
+        let mut outcome = TestOutcome::new(markdown_path, section, TestResult::Passed);
+        outcome.duration = Some(started.elapsed());
+
+        match result {
+            Ok(report) => {
+                let mismatches = mx::expect::check_outputs(section, &report, &capture);
+                if mismatches.is_empty() {
+                    println!("{} {}", "✓".success(), section.title);
+                } else {
+                    println!("{} {}", "✗".error(), section.title.bold());
+                    for mismatch in &mismatches {
+                        print_output_mismatch(markdown_path, mismatch);
+                    }
+                    outcome.result = TestResult::Failed;
+                }
+            }
+            Err(e) => {
+                println!("{} {}", "✗".error(), section.title.bold());
+                println!("    {}", e.to_string().error());
+                outcome.result = TestResult::Failed;
+            }
+        }
+        outcomes.push(outcome);
+    }
+
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| outcome.result == TestResult::Failed)
+        .count();
+    println!();
+    print!("{}", format_test_table(&outcomes));
+    println!();
+    println!("{} passed, {} failed", outcomes.len() - failed, failed);
+    if failed > 0 {
+        return Err(miette::miette!(
+            "{} of {} tasks failed",
+            failed,
+            outcomes.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Result of one task in `mx test`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TestResult {
+    Passed,
+    Failed,
+    /// Not run because of --dry-run
+    DryRun,
+}
+
+/// A task tested by `mx test`, as shown in the summary table
+struct TestOutcome<'a> {
+    path: &'a Path,
+    section: &'a Section,
+    result: TestResult,
+    duration: Option<std::time::Duration>,
+}
+
+impl<'a> TestOutcome<'a> {
+    fn new(path: &'a Path, section: &'a Section, result: TestResult) -> Self {
+        Self {
+            path,
+            section,
+            result,
+            duration: None,
+        }
+    }
+}
+
+/// Format the `mx test` summary as a table of tasks, files, results and durations
+fn format_test_table(outcomes: &[TestOutcome]) -> String {
+    let rows: Vec<[String; 4]> = outcomes
+        .iter()
+        .map(|outcome| {
+            let result = match outcome.result {
+                TestResult::Passed => "passed",
+                TestResult::Failed => "failed",
+                TestResult::DryRun => "would run",
+            };
+            [
+                outcome.section.title.clone(),
+                match outcome.section.line {
+                    Some(line) => format!("{}:{}", outcome.path.display(), line),
+                    None => outcome.path.display().to_string(),
+                },
+                result.to_string(),
+                outcome.duration.map(format_duration).unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let header = ["TASK", "FILE", "RESULT", "TIME"];
+    let mut widths = header.map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let [task, file, result, _] = widths;
+
+    let header = format!(
+        "{:<task$}  {:<file$}  {:<result$}  {}",
+        header[0], header[1], header[2], header[3]
+    );
+    let mut output = format!("  {}\n", header.bold());
+    for (outcome, [title, location, status, duration]) in outcomes.iter().zip(&rows) {
+        // Padded before coloring, as escape codes would count towards the width
+        let status = format!("{:<result$}", status);
+        let status = match outcome.result {
+            TestResult::Passed => status.success(),
+            TestResult::Failed => status.error(),
+            TestResult::DryRun => status.normal(),
+        };
+        let line = format!(
+            "  {:<task$}  {}  {}  {}",
+            title,
+            format!("{:<file$}", location).muted(),
+            status,
+            duration
+        );
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Print where a block's output differs from the expected output
+fn print_output_mismatch(markdown_path: &Path, mismatch: &OutputMismatch) {
+    let location = match mismatch.line {
+        Some(line) => format!("{}:{}", markdown_path.display(), line),
+        None => markdown_path.display().to_string(),
+    };
+    println!("    {} ({})", mismatch.id, location.muted());
+    for line in mismatch.diff() {
+        match line {
+            DiffLine::Same(text) => println!("      {}", text),
+            DiffLine::Missing(text) => println!("    {} {}", "-".error(), text.error()),
+            DiffLine::Unexpected(text) => println!("    {} {}", "+".success(), text.success()),
+        }
+    }
+}
+
+/// Append a task run to the project's history; failing to do so only logs a warning
+fn record_run(
+    runner: &Runner,
+    markdown_path: &Path,
+    section: &Section,
+    args: &[String],
+    result: &mx::Result<ExecutionReport>,
+    started: SystemTime,
+) {
+    let (exit_code, failed_block) = match result {
+        Ok(_) => (Some(0), None),
+        Err(mx::Error::BlockFailed {
+            exit_code, block, ..
+        }) => (*exit_code, Some(*block)),
+        Err(_) => (None, None),
+    };
+    let file = std::path::absolute(markdown_path).unwrap_or_else(|_| markdown_path.to_path_buf());
+    let mut entry = HistoryEntry::new(&section.title, file, args, exit_code, started);
+    entry.failed_block = failed_block;
+
+    let history = run_history(runner);
+    if let Err(e) = history.append(&entry) {
+        tracing::warn!(
+            "failed to record run in {}: {}",
+            history.path().display(),
+            e
+        );
+    }
+}
+
+/// History of the project the runner runs tasks in
+fn run_history(runner: &Runner) -> History {
+    match runner.root() {
+        Some(root) => History::in_root(root),
+        None => History::in_root(&history_root()),
+    }
+}
+
+/// Root of the project the current directory belongs to, or the current directory
+fn history_root() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    Project::discover(&cwd, Path::new(DEFAULT_TASKS_FILE)).map_or(cwd, |project| project.root)
+}
+
+/// Run a recorded task again, with its recorded arguments unless others are given
+fn rerun_task(last_failed: bool, config: Option<PathBuf>, mut exec: ExecArgs) -> Result<()> {
+    let history = History::in_root(&history_root());
+    let Some(entry) = history.last(last_failed).into_diagnostic()? else {
+        return Err(miette::miette!(
+            "No {}runs recorded in {}",
+            if last_failed { "failed " } else { "" },
+            history.path().display()
+        ));
+    };
+
+    if exec.args.is_empty() {
+        exec.args = entry.args;
+    }
+    if !exec.quiet {
+        eprintln!(
+            "{} {} from {}{}",
+            "Re-running".bold(),
+            entry.task,
+            entry.file.display(),
+            format_args_suffix(&exec.args)
+        );
+    }
+
+    let source = SourceArgs {
+        global: global_tasks_path().is_some_and(|path| path == entry.file),
+        file: vec![entry.file],
+        config,
+        level: None,
+        regex: false,
+    };
+    run_task(source, entry.task, exec)
+}
+
+/// Print the most recent runs of the project
+fn show_history(limit: usize, json: bool) -> Result<()> {
+    let entries = History::in_root(&history_root())
+        .entries()
+        .into_diagnostic()?;
+    let recent = &entries[entries.len().saturating_sub(limit)..];
+
+    for entry in recent {
+        if json {
+            println!("{}", serde_json::to_string(entry).into_diagnostic()?);
+            continue;
+        }
+
+        let status = match entry.exit_code {
+            Some(0) => format!("{:<7}", "ok").success(),
+            Some(code) => format!("{:<7}", format!("exit {}", code)).error(),
+            None => format!("{:<7}", "failed").error(),
+        };
+        println!(
+            "{}  {}  {:>8}  {} {}{}",
+            entry.timestamp.muted(),
+            status,
+            format_duration(entry.duration()),
+            entry.task.bold(),
+            entry.file.display().to_string().muted(),
+            format_args_suffix(&entry.args)
+        );
+    }
+
+    Ok(())
+}
+
+/// Format task arguments as they are passed on the command line, e.g. ` -- a b`
+fn format_args_suffix(args: &[String]) -> String {
+    if args.is_empty() {
+        return String::new();
+    }
+
+    let quoted: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                mx::command::shell_quote(arg)
+            }
+        })
+        .collect();
+    format!(" -- {}", quoted.join(" "))
+}
+
+/// Record files in the trust store, or remove them from it
+fn trust_files(files: Vec<PathBuf>, revoke: bool) -> Result<()> {
+    let mut store = mx::trust::TrustStore::load_default().into_diagnostic()?;
+
+    for file in &mx::files::expand_file_patterns(&files).into_diagnostic()? {
+        if revoke {
+            if store.revoke(file).into_diagnostic()? {
+                println!("No longer trusted: {}", file.display());
+            } else {
+                println!("Not trusted: {}", file.display());
+            }
+        } else {
+            let markdown = std::fs::read_to_string(file).into_diagnostic()?;
+            store.trust(file, &markdown).into_diagnostic()?;
+            println!("Trusted: {}", file.display());
+        }
+    }
+
+    store.save().into_diagnostic()
+}
+
+/// Print the warnings about a task to stderr, failing if warnings are denied
+fn check_warnings(
+    runner: &mut Runner,
+    markdown_path: &Path,
+    section: &Section,
+    deny: bool,
+) -> Result<()> {
+    let sections = runner.list_task_sections(markdown_path).into_diagnostic()?;
+    let mut warnings: Vec<Warning> = warning::duplicate_title_warnings(&sections)
+        .into_iter()
+        .filter(|warning| warning.task() == section.title)
+        .collect();
+    warnings.extend(
+        warning::shadowed_config_task_warnings(runner.config(), &sections)
+            .into_iter()
+            .filter(|warning| warning.task() == section.title),
+    );
+    warnings.extend(warning::task_warnings(section));
+
+    for warning in &warnings {
+        eprintln!("{} {}", "warning:".warning().bold(), warning);
+    }
+
+    if deny && !warnings.is_empty() {
+        return Err(miette::miette!(
+            "Task '{}' has {} warning(s) and --deny-warnings is set",
+            section.title,
+            warnings.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// A failed code block rendered as a miette diagnostic pointing into the markdown file
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{message}")]
+struct BlockFailureDiagnostic {
+    message: String,
+    #[source_code]
+    source_code: NamedSource<String>,
+    #[label("this code block failed")]
+    span: SourceSpan,
+}
+
+/// Convert an execution error into a diagnostic, pointing at the failed code fence if known
+fn execution_diagnostic(markdown_path: &Path, error: mx::Error) -> miette::Report {
+    if let mx::Error::BlockFailed {
+        line: Some(line),
+        span: Some(span),
+        ..
+    } = &error
+        && let Ok(markdown) = std::fs::read_to_string(markdown_path)
+    {
+        return miette::Report::new(BlockFailureDiagnostic {
+            message: format!("{} ({}:{})", error, markdown_path.display(), line),
+            source_code: NamedSource::new(markdown_path.display().to_string(), markdown),
+            span: SourceSpan::from(span.clone()),
+        });
+    }
+
+    miette::Report::from_err(error)
+}
+
+/// Print the timing summary of an execution report to stderr, with the time of each block if `blocks`
+fn print_timing_summary(report: &ExecutionReport, blocks: bool) {
+    eprintln!();
+    if let Some(reason) = &report.skipped {
+        eprintln!(
+            "{} {} {}",
+            "Skipped".warning().bold(),
+            report.task.bold(),
+            format!("({})", reason).muted()
+        );
+        return;
+    }
+
+    eprintln!(
+        "{} {} in {}",
+        "Finished".success().bold(),
+        report.task.bold(),
+        format_duration(report.duration)
+    );
+
+    if blocks && report.blocks.len() > 1 {
+        let slowest = report.slowest_block().map(|block| block.index);
+        for block in &report.blocks {
+            if let Some(reason) = &block.skipped {
+                let line = format!("  {:<16} {:<12} skipped: {}", block.id, block.lang, reason);
+                eprintln!("{}", line.muted());
+                continue;
+            }
+            let line = format!(
+                "  {:<16} {:<12} {:>10}",
+                block.id,
+                block.lang,
+                format_duration(block.duration)
+            );
+            if Some(block.index) == slowest {
+                eprintln!("{}", line.warning());
+            } else {
+                eprintln!("{}", line.muted());
+            }
+        }
+    }
+}
+
+/// Format a duration for display (e.g. "850ms", "1.25s")
+fn format_duration(duration: std::time::Duration) -> String {
+    if duration.as_secs() == 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+/// List all available tasks, optionally only those with a tag
+fn list_tasks(source: SourceArgs, tag: Option<String>, long: bool) -> Result<()> {
+    let mut runner = Runner::new(source.load_config()?);
+
+    let mut sections = Vec::new();
+    let files = source.files()?;
+    // Tasks of several files are told apart by the namespace of their file, as in `db:migrate`
+    let namespaced = files.len() > 1;
+    for (index, markdown_path) in files.into_iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        let file = namespaced.then_some(markdown_path.as_path());
+        list_file_tasks(&mut runner, &markdown_path, file, tag.as_deref(), long)?;
+        sections.extend(
+            runner
+                .list_task_sections(&markdown_path)
+                .into_diagnostic()?,
+        );
+    }
+
+    // Tasks of the configuration that a Markdown task of the same title hides are left out
+    for warning in warning::shadowed_config_task_warnings(runner.config(), &sections) {
+        eprintln!("{} {}", "warning:".warning().bold(), warning);
+    }
+    let mut config_tasks: Vec<Section> = runner
+        .config()
+        .task_sections()
+        .into_iter()
+        .filter(|task| !sections.iter().any(|section| section.title == task.title))
+        .collect();
+    if let Some(tag) = &tag {
+        config_tasks.retain(|task| task.has_tag(tag));
+    }
+    if !config_tasks.is_empty() {
+        println!();
+        print!(
+            "{} {}\n\n{}",
+            "Available tasks in".bold(),
+            "configuration".file(),
+            format_tasks(&config_tasks, &HashMap::new(), long, |_| None)
+        );
+    }
+
+    if !source.global {
+        list_global_tasks(&mut runner, tag.as_deref(), long)?;
+    }
+
+    Ok(())
+}
+
+/// List the tasks of every Markdown file under a directory, each with the reference that
+/// runs it
+fn list_tree_tasks(source: SourceArgs, dir: &Path, tag: Option<String>, long: bool) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+
+    let mut listed = false;
+    for path in mx::files::find_markdown_files(dir).into_diagnostic()? {
+        // One broken file does not hide the tasks of the others
+        let mut sections = match runner.list_task_sections(&path) {
+            Ok(sections) => sections,
+            Err(e) => {
+                eprintln!("{} {}: {}", "warning:".warning().bold(), path.display(), e);
+                continue;
+            }
+        };
+        if let Some(tag) = &tag {
+            sections.retain(|section| section.has_tag(tag));
+        }
+        if sections.is_empty() {
+            continue;
+        }
+
+        if listed {
+            println!();
+        }
+        listed = true;
+        print!(
+            "{} {}\n\n{}",
+            "Available tasks in".bold(),
+            path.display().to_string().file(),
+            format_tasks(&sections, &last_runs(&path), long, |section| {
+                Some(format!("{}#{}", path.display(), section.anchor()))
+            })
+        );
+    }
+
+    if !listed {
+        let message = format!("No tasks found under {}", dir.display());
+        println!("{}", message.warning());
+    }
+    Ok(())
+}
+
+/// List the tasks of the global task file, if there is one, apart from the project's
+fn list_global_tasks(runner: &mut Runner, tag: Option<&str>, long: bool) -> Result<()> {
+    let Some(path) = global_tasks_path().filter(|path| path.is_file()) else {
+        return Ok(());
+    };
+    let mut sections = runner.list_task_sections(&path).into_diagnostic()?;
+    if let Some(tag) = tag {
+        sections.retain(|section| section.has_tag(tag));
+    }
+    if sections.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    print!(
+        "{} {} {}\n\n{}",
+        "Global tasks in".bold(),
+        path.display().to_string().file(),
+        "(run with --global)".muted(),
+        format_tasks(&sections, &last_runs(&path), long, |_| None)
+    );
+    Ok(())
+}
+
+/// Print the code lenses of the tasks of every file, or of every file under a directory, as a
+/// JSON array
+fn list_code_lenses(
+    source: SourceArgs,
+    tag: Option<String>,
+    recursive: Option<PathBuf>,
+) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+    let files = match recursive {
+        Some(dir) => mx::files::find_markdown_files(&dir).into_diagnostic()?,
+        None => source.files()?,
+    };
+
+    let mut lenses = Vec::new();
+    for markdown_path in files {
+        let markdown = runner.load_markdown(&markdown_path).into_diagnostic()?;
+        let mut sections = runner
+            .list_task_sections(&markdown_path)
+            .into_diagnostic()?;
+        if let Some(tag) = &tag {
+            sections.retain(|section| section.has_tag(tag));
+        }
+        // Editors identify documents by absolute path
+        let file = std::path::absolute(&markdown_path).unwrap_or(markdown_path);
+        lenses.extend(code_lenses(runner.config(), &file, &markdown, &sections));
+    }
+
+    println!("{}", serde_json::to_string(&lenses).into_diagnostic()?);
+    Ok(())
+}
+
+/// Print the qualified name of every task `mx run` finds, one per line, including the tasks
+/// of workspace members and of the configuration
+fn list_task_names(
+    source: SourceArgs,
+    tag: Option<String>,
+    recursive: Option<PathBuf>,
+) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+    let files = match recursive {
+        Some(dir) => mx::files::find_markdown_files(&dir).into_diagnostic()?,
+        None => source.files()?,
+    };
+    let has_tag = |section: &Section| tag.as_ref().is_none_or(|tag| section.has_tag(tag));
+
+    let mut names = Vec::new();
+    for markdown_path in &files {
+        let file = (files.len() > 1).then_some(markdown_path.as_path());
+        let sections = runner.list_task_sections(markdown_path).into_diagnostic()?;
+        for section in sections.iter().filter(|section| has_tag(section)) {
+            names.push(runner.qualified_name(section, file));
+        }
+    }
+    let config_tasks = runner.config().task_sections();
+    for section in config_tasks.iter().filter(|section| has_tag(section)) {
+        names.push(runner.qualified_name(section, None));
+    }
+
+    let separator = &runner.config().namespaces.separator;
+    for member in workspace_members(runner.config(), &source)? {
+        let sections = runner.list_task_sections(&member.file).into_diagnostic()?;
+        for section in sections.iter().filter(|section| has_tag(section)) {
+            let name = runner.qualified_name(section, None);
+            names.push(format!("{}{}{}", member.name, separator, name));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    names.retain(|name| seen.insert(name.clone()));
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// List the tasks of a single markdown file
+fn list_file_tasks(
+    runner: &mut Runner,
+    markdown_path: &Path,
+    namespace: Option<&Path>,
+    tag: Option<&str>,
+    long: bool,
+) -> Result<()> {
+    let mut sections = runner.list_task_sections(markdown_path).into_diagnostic()?;
+    if let Some(tag) = tag {
+        sections.retain(|section| section.has_tag(tag));
+    }
+
+    if sections.is_empty() {
+        let message = match tag {
+            Some(tag) => format!("No tasks tagged '{}' in {}", tag, markdown_path.display()),
+            None => format!("No tasks found in {}", markdown_path.display()),
+        };
+        println!("{}", message.warning());
+        return Ok(());
+    }
+
+    print!(
+        "{} {}\n\n{}",
+        "Available tasks in".bold(),
+        markdown_path.display().to_string().file(),
+        format_tasks(&sections, &last_runs(markdown_path), long, |section| {
+            let name = runner.qualified_name(section, namespace);
+            (name != section.anchor()).then_some(name)
+        })
+    );
+
+    Ok(())
+}
+
+/// Format tasks as a table with `long`, or else as a list of titles and descriptions
+///
+/// The list shows the hint for a task after its title, such as the `file#anchor` reference
+/// or the qualified name running it.
+fn format_tasks(
+    sections: &[Section],
+    last_runs: &HashMap<String, HistoryEntry>,
+    long: bool,
+    hint: impl Fn(&Section) -> Option<String>,
+) -> String {
+    if long {
+        return format_task_table(sections, last_runs);
+    }
+
+    // With a range of heading levels, nested tasks are indented under their parents
+    let top_level = sections
+        .iter()
+        .map(|section| section.level)
+        .min()
+        .unwrap_or(0);
+    let mut output = String::new();
+    for section in sections {
+        let indent = "  ".repeat(1 + (section.level - top_level) as usize);
+        let mut title = format!("{}{}", section.title.task(), format_tags(section));
+        if let Some(hint) = hint(section) {
+            title = format!("{} {}", title, hint.muted());
+        }
+        match section.description.as_deref().map(str::trim) {
+            Some(desc) if !desc.is_empty() => output.push_str(&format!(
+                "{}{} {}\n",
+                indent,
+                title,
+                format!("- {}", desc).muted()
+            )),
+            _ => output.push_str(&format!("{}{}\n", indent, title)),
+        }
+    }
+    output
+}
+
+/// Format tasks as an aligned table of their languages, code blocks, tags, last run and
+/// description
+fn format_task_table(sections: &[Section], last_runs: &HashMap<String, HistoryEntry>) -> String {
+    let rows: Vec<[String; 7]> = sections
+        .iter()
+        .map(|section| {
+            [
+                section.title.clone(),
+                section.languages().join(", "),
+                section.codes.len().to_string(),
+                section.tags.join(", "),
+                section.dependencies().join(", "),
+                last_runs
+                    .get(&section.title)
+                    .map(|entry| {
+                        let duration = format_duration(entry.duration());
+                        if entry.success() {
+                            duration
+                        } else {
+                            format!("{} (failed)", duration)
+                        }
+                    })
+                    .unwrap_or_default(),
+                section
+                    .description
+                    .as_deref()
+                    .and_then(|desc| desc.trim().lines().next())
+                    .unwrap_or_default()
+                    .to_string(),
+            ]
+        })
+        .collect();
+
+    let header = [
+        "TASK",
+        "LANGUAGES",
+        "BLOCKS",
+        "TAGS",
+        "DEPENDS",
+        "LAST RUN",
+        "DESCRIPTION",
+    ];
+    let mut widths = header.map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let [task, languages, blocks, tags, depends, last_run, _] = widths;
+
+    let header = format!(
+        "{:<task$}  {:<languages$}  {:>blocks$}  {:<tags$}  {:<depends$}  {:<last_run$}  {}",
+        header[0], header[1], header[2], header[3], header[4], header[5], header[6]
+    );
+    let mut output = format!("  {}\n", header.bold());
+    for (
+        section,
+        [
+            title,
+            langs,
+            count,
+            tag_list,
+            dependencies,
+            duration,
+            description,
+        ],
+    ) in sections.iter().zip(&rows)
+    {
+        // Padded before coloring, as escape codes would count towards the width
+        let duration = format!("{:<last_run$}", duration);
+        let duration = match last_runs.get(&section.title) {
+            Some(entry) if !entry.success() => duration.error(),
+            _ => duration.normal(),
+        };
+        let mut line = format!(
+            "  {}  {:<languages$}  {:>blocks$}  {}  {:<depends$}  {}",
+            format!("{:<task$}", title).task(),
+            langs,
+            count,
+            format!("{:<tags$}", tag_list).tag(),
+            dependencies,
+            duration
+        );
+        if !description.is_empty() {
+            line.push_str(&format!("  {}", description.muted()));
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Print the values an mq query yields over each markdown file
+fn run_query(source: SourceArgs, query: String) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+    for markdown_path in source.files()? {
+        let markdown = runner.load_markdown(&markdown_path).into_diagnostic()?;
+        let values = runner
+            .query(&markdown, &query)
+            .map_err(|e| miette::miette!("{}: {}", markdown_path.display(), e))?;
+        for value in values {
+            println!("{}", value);
+        }
+    }
+    Ok(())
+}
+
+/// Print the tasks matching a query, with their matching lines highlighted
+fn search_tasks(source: SourceArgs, query: String) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+    let mut found = 0;
+
+    for markdown_path in source.files()? {
+        let sections = runner
+            .list_task_sections(&markdown_path)
+            .into_diagnostic()?;
+        for found_task in mx::search::search(&sections, &query) {
+            let section = found_task.section;
+            let location = match section.line {
+                Some(line) => format!("{}:{}", markdown_path.display(), line),
+                None => markdown_path.display().to_string(),
+            };
+            println!(
+                "{}{} {}",
+                section.title.task(),
+                format_tags(section),
+                location.muted()
+            );
+
+            for snippet in &found_task.snippets {
+                let field = match &snippet.field {
+                    mx::search::MatchField::Title => "title".to_string(),
+                    mx::search::MatchField::Description => "description".to_string(),
+                    mx::search::MatchField::Code { lang, .. } => match snippet.line {
+                        Some(line) => format!("{}:{}", lang, line),
+                        None => lang.clone(),
+                    },
+                };
+                println!(
+                    "  {} {}",
+                    format!("{:>12}", field).muted(),
+                    highlight_ranges(&snippet.text, &snippet.highlights)
+                );
+            }
+            println!();
+            found += 1;
+        }
+    }
+
+    if found == 0 {
+        return Err(miette::miette!("No tasks match '{}'", query));
+    }
+
+    Ok(())
+}
+
+/// Highlight byte ranges of a text; the ranges must be sorted
+fn highlight_ranges(text: &str, ranges: &[std::ops::Range<usize>]) -> String {
+    let mut highlighted = String::new();
+    let mut end = 0;
+
+    for range in ranges {
+        // Overlapping matches of different words are highlighted once
+        let start = range.start.max(end);
+        if start >= range.end {
+            continue;
+        }
+        highlighted.push_str(&text[end..start]);
+        highlighted.push_str(&text[start..range.end].yellow().bold().to_string());
+        end = range.end;
+    }
+
+    highlighted.push_str(&text[end..]);
+    highlighted
+}
+
+/// The most recent recorded run of each task of a markdown file, by title
+fn last_runs(markdown_path: &Path) -> HashMap<String, HistoryEntry> {
+    let file = std::path::absolute(markdown_path).unwrap_or_else(|_| markdown_path.to_path_buf());
+    let entries = History::in_root(&history_root())
+        .entries()
+        .unwrap_or_default();
+
+    // Later runs replace earlier ones
+    entries
+        .into_iter()
+        .filter(|entry| entry.file == file)
+        .map(|entry| (entry.task.clone(), entry))
+        .collect()
+}
+
+/// Format a section's tags for display (e.g. " [ci, danger]"), or an empty string
+fn format_tags(section: &Section) -> String {
+    if section.tags.is_empty() {
+        return String::new();
+    }
+
+    format!(" [{}]", section.tags.join(", ")).tag().to_string()
+}
+
+/// Pretty-print a task without running it
+fn show_task(source: SourceArgs, task_name: String, index: Option<usize>) -> Result<()> {
+    let runner = Runner::new(source.load_config()?);
+    let task = TaskRef::parse(&task_name);
+    let (markdown_path, section) = runner
+        .find_task_at(&source.task_files(&task)?, task.task, index)
+        .into_diagnostic()?;
+
+    let location = match section.line {
+        Some(line) => format!("{}:{}", markdown_path.display(), line),
+        None => markdown_path.display().to_string(),
+    };
+    println!(
+        "{}{} {}",
+        section.title.task(),
+        format_tags(&section),
+        format!("({})", location).muted()
+    );
+
+    if let Some(desc) = section.description.as_deref().map(str::trim)
+        && !desc.is_empty()
+    {
+        println!();
+        for line in desc.lines() {
+            println!("  {}", line);
+        }
+    }
+
+    if !section.metadata.is_empty() {
+        println!();
+        let width = section
+            .metadata
+            .keys()
+            .map(|key| key.len())
+            .max()
+            .unwrap_or(0);
+        for (key, value) in &section.metadata {
+            println!("  {} {}", format!("{:<width$}", key).cyan(), value);
+        }
+    }
+
+    let highlighter = Highlighter::new();
+
+    for (index, code) in section.codes.iter().enumerate() {
+        println!();
+
+        let runtime = if code.lang.is_empty() {
+            "no language, skipped".warning().to_string()
+        } else if runner.config().ignores_language(&code.lang) {
+            "ignored language, skipped".warning().to_string()
+        } else if let Ok(Some(runtime)) = runner.config().runtime_for(code, Some(&section)) {
+            let mut runtime = format!(
+                "{} ({})",
+                runtime.command().cyan(),
+                runtime.execution_mode()
+            );
+            let remote = Remote::resolve(runner.config(), &code.lang, Some(&section), None);
+            if let Some(remote) = remote {
+                runtime.push_str(&format!(" on {}", remote.to_string().yellow()));
+            }
+            runtime
+        } else {
+            "no runtime configured".error().to_string()
+        };
+
+        println!(
+            "{} {} {} {}",
+            format!("Block #{}", index).bold(),
+            code.lang.lang(),
+            "→".muted(),
+            runtime
+        );
+        println!("{}", "─".repeat(40).muted());
+        print!("{}", highlighter.highlight(&code.code, &code.lang));
+        if !code.code.ends_with('\n') {
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+/// Step handler of `--step`, showing each code block and asking what to do with it
+///
+/// Editing reopens the prompt with the edited code; an unreadable answer aborts.
+fn step_handler() -> StepHandler {
+    let highlighter = Highlighter::new();
+    Arc::new(move |section: &Section, index: usize, block: &CodeBlock| {
+        let mut code = block.code.clone();
+        loop {
+            eprintln!();
+            eprintln!(
+                "{} {}",
+                block_id(&section.title, index).bold(),
+                block.lang.lang()
+            );
+            eprintln!("{}", "─".repeat(40).muted());
+            eprint!("{}", highlighter.highlight(&code, &block.lang));
+            if !code.ends_with('\n') {
+                eprintln!();
+            }
+            eprint!("{} ", "[r]un, [s]kip, [e]dit, [a]bort?".bold());
+            let _ = std::io::stderr().flush();
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+                return StepAction::Abort;
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "" | "r" | "run" if code != block.code => return StepAction::RunEdited(code),
+                "" | "r" | "run" => return StepAction::Run,
+                "s" | "skip" => return StepAction::Skip,
+                "a" | "abort" => return StepAction::Abort,
+                "e" | "edit" => match edit_code(&code, &block.lang) {
+                    Ok(edited) => code = edited,
+                    Err(err) => eprintln!("{} {}", "Error:".error().bold(), err),
+                },
+                other => eprintln!("Unknown answer '{}'", other),
+            }
+        }
+    })
+}
+
+/// Edit code in `$VISUAL` or `$EDITOR` (`vi` by default), returning the saved code
+fn edit_code(code: &str, lang: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut argv = shell_words::split(&editor).into_diagnostic()?;
+    if argv.is_empty() {
+        return Err(miette::miette!("No editor set in $VISUAL or $EDITOR"));
+    }
+
+    // The extension lets the editor pick its syntax mode
+    let extension: String = lang.chars().filter(char::is_ascii_alphanumeric).collect();
+    let file = tempfile::Builder::new()
+        .prefix("mx-step-")
+        .suffix(&format!(".{}", extension))
+        .tempfile()
+        .into_diagnostic()?;
+    std::fs::write(file.path(), code).into_diagnostic()?;
+
+    argv.push(file.path().display().to_string());
+    let status = std::process::Command::new(&argv[0])
+        .args(&argv[1..])
+        .status()
+        .map_err(|e| miette::miette!("Failed to start editor '{}': {}", argv[0], e))?;
+    if !status.success() {
         return Err(miette::miette!(
-            "Configuration file already exists: {}",
-            output_path.display()
+            "Editor '{}' exited with {}",
+            argv[0],
+            status
         ));
     }
+    std::fs::read_to_string(file.path()).into_diagnostic()
+}
 
-    let config = Config::default();
-    let toml = toml::to_string_pretty(&config).into_diagnostic()?;
+/// Spinner for the running code block and a status line for each finished one, on stderr
+///
+/// Block output is printed above the status lines, which every task starts afresh.
+struct ProgressUi {
+    bars: Mutex<MultiProgress>,
+    running: Mutex<Option<ProgressBar>>,
+}
 
-    std::fs::write(&output_path, toml).into_diagnostic()?;
-    println!("Configuration file created: {}", output_path.display());
+impl ProgressUi {
+    fn new() -> Self {
+        Self {
+            bars: Mutex::new(MultiProgress::new()),
+            running: Mutex::new(None),
+        }
+    }
+
+    /// Print a chunk of block output above the status lines
+    fn write(&self, stream: OutputStream, data: &[u8]) {
+        let bars = self.bars.lock().unwrap().clone();
+        bars.suspend(|| write_output(stream, data));
+    }
+}
+
+impl RunnerObserver for ProgressUi {
+    fn on_section_start(&self, _section: &Section) {
+        *self.bars.lock().unwrap() = MultiProgress::new();
+    }
+
+    fn on_block_start(&self, section: &Section, index: usize, block: &CodeBlock) {
+        // A spinner would draw over the prompts of a block that has the terminal
+        if block.is_interactive(Some(section)) {
+            return;
+        }
+        let bar = self.bars.lock().unwrap().add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::with_template("{spinner:.cyan} {msg} {elapsed:.dim}")
+                .expect("valid progress template"),
+        );
+        bar.set_message(format!(
+            "{} {}",
+            block_id(&section.title, index).bold(),
+            block.lang.lang()
+        ));
+        bar.enable_steady_tick(Duration::from_millis(100));
+        *self.running.lock().unwrap() = Some(bar);
+    }
+
+    fn on_block_complete(&self, _section: &Section, outcome: &BlockOutcome) {
+        // Skipped blocks never started, and interactive blocks had no spinner
+        let running = self.running.lock().unwrap().take();
+        let bar =
+            running.unwrap_or_else(|| self.bars.lock().unwrap().add(ProgressBar::new_spinner()));
+
+        bar.set_style(ProgressStyle::with_template("{msg}").expect("valid progress template"));
+        bar.finish_with_message(block_status(outcome));
+    }
+}
+
+/// Write block output to the stream it came from
+fn write_output(stream: OutputStream, data: &[u8]) {
+    let _ = match stream {
+        OutputStream::Stdout => std::io::stdout().lock().write_all(data),
+        OutputStream::Stderr => std::io::stderr().lock().write_all(data),
+    };
+}
+
+/// A line of block output for `--prefix`, after the block ID in a color of its own
+fn prefixed_line(id: &str, line: &[u8]) -> Vec<u8> {
+    const COLORS: [Color; 6] = [
+        Color::Cyan,
+        Color::Magenta,
+        Color::Yellow,
+        Color::Blue,
+        Color::Green,
+        Color::BrightRed,
+    ];
+    // Blocks keep their color from run to run
+    let hash = id.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    let prefix = format!("[{}]", id).color(COLORS[hash % COLORS.len()]);
+
+    let mut prefixed = format!("{} ", prefix).into_bytes();
+    prefixed.extend_from_slice(line);
+    prefixed
+}
+
+/// Status lines of finished code blocks on stdout, for `--summary` without a terminal
+struct BlockSummary;
+
+impl RunnerObserver for BlockSummary {
+    fn on_block_complete(&self, _section: &Section, outcome: &BlockOutcome) {
+        println!("{}", block_status(outcome));
+    }
+}
+
+/// Status line of a finished code block: whether it passed, was skipped or failed, and how
+/// long it took
+fn block_status(outcome: &BlockOutcome) -> String {
+    let block = &outcome.report;
+    match (&block.skipped, outcome.success) {
+        (Some(reason), _) => format!(
+            "{} {} {} {}",
+            "-".warning(),
+            block.id,
+            block.lang.lang(),
+            format!("skipped: {}", reason).muted()
+        ),
+        (None, true) => format!(
+            "{} {} {} {}",
+            "✓".success(),
+            block.id.bold(),
+            block.lang.lang(),
+            format_duration(block.duration).muted()
+        ),
+        (None, false) => {
+            let exit = match outcome.exit_code {
+                Some(code) => format!("exit code {}, ", code),
+                None => String::new(),
+            };
+            format!(
+                "{} {} {} {}",
+                "✗".error(),
+                block.id.bold(),
+                block.lang.lang(),
+                format!("{}{}", exit, format_duration(block.duration)).muted()
+            )
+        }
+    }
+}
+
+/// Styles of the output by role, from the `[theme]` of the first configuration loaded
+static THEME: OnceLock<mx::theme::Theme> = OnceLock::new();
+
+/// Styling of output text by its role, following the `[theme]` configuration
+trait Themed {
+    /// Style the text as a role of the theme
+    fn themed(&self, role: fn(&mx::theme::Theme) -> &String) -> ColoredString;
+
+    fn task(&self) -> ColoredString {
+        self.themed(|theme| &theme.task)
+    }
+
+    fn file(&self) -> ColoredString {
+        self.themed(|theme| &theme.file)
+    }
+
+    fn tag(&self) -> ColoredString {
+        self.themed(|theme| &theme.tag)
+    }
+
+    fn lang(&self) -> ColoredString {
+        self.themed(|theme| &theme.lang)
+    }
+
+    fn muted(&self) -> ColoredString {
+        self.themed(|theme| &theme.muted)
+    }
+
+    fn success(&self) -> ColoredString {
+        self.themed(|theme| &theme.success)
+    }
+
+    fn warning(&self) -> ColoredString {
+        self.themed(|theme| &theme.warning)
+    }
+
+    fn error(&self) -> ColoredString {
+        self.themed(|theme| &theme.error)
+    }
+}
+
+impl<T: AsRef<str> + ?Sized> Themed for T {
+    fn themed(&self, role: fn(&mx::theme::Theme) -> &String) -> ColoredString {
+        let theme = THEME.get_or_init(Default::default);
+        // Styles are validated with the configuration; a broken one leaves the text plain
+        let style = mx::theme::Style::parse(role(theme)).unwrap_or_default();
+        let mut text = match style.color.and_then(|color| color.parse::<Color>().ok()) {
+            Some(color) => self.as_ref().color(color),
+            None => self.as_ref().normal(),
+        };
+        if style.bold {
+            text = text.bold();
+        }
+        if style.dimmed {
+            text = text.muted();
+        }
+        if style.italic {
+            text = text.italic();
+        }
+        if style.underline {
+            text = text.underline();
+        }
+        text
+    }
+}
+
+/// Terminal syntax highlighting for code blocks, disabled when the output is not colored
+struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Option<Theme>,
+}
+
+impl Highlighter {
+    fn new() -> Self {
+        let colored = colored::control::SHOULD_COLORIZE.should_colorize();
+        let theme = colored.then(|| {
+            let mut themes = ThemeSet::load_defaults();
+            themes
+                .themes
+                .remove("base16-ocean.dark")
+                .unwrap_or_default()
+        });
+
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        }
+    }
+
+    fn highlight(&self, code: &str, lang: &str) -> String {
+        let Some(theme) = &self.theme else {
+            return code.to_string();
+        };
+        let Some(syntax) = self.syntaxes.find_syntax_by_token(lang) else {
+            return code.to_string();
+        };
+
+        let mut lines = HighlightLines::new(syntax, theme);
+        let mut output = String::new();
+
+        for line in LinesWithEndings::from(code) {
+            match lines.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => output.push_str(&as_24_bit_terminal_escaped(&ranges, false)),
+                Err(_) => output.push_str(line),
+            }
+        }
+        output.push_str("\x1b[0m");
+
+        output
+    }
+}
+
+/// A validation issue rendered as a miette diagnostic
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{message}")]
+struct ValidationDiagnostic {
+    message: String,
+    #[source_code]
+    source_code: NamedSource<String>,
+    #[label("{label}")]
+    span: Option<SourceSpan>,
+    label: String,
+    #[help]
+    help: Option<String>,
+    #[diagnostic(severity)]
+    severity: Severity,
+}
+
+/// Print the tasks and their dependencies as Make targets, just recipes, npm scripts or a
+/// GitHub Actions workflow
+fn export_tasks(source: SourceArgs, format: ExportFormat, tag: Option<String>) -> Result<()> {
+    let mut runner = Runner::new(source.load_config()?);
+    let mut tasks =
+        collect_tasks(&mut runner, &source.files()?, tag.as_deref()).into_diagnostic()?;
+    if tasks.is_empty() {
+        return Err(match tag {
+            Some(tag) => miette::miette!("No tasks tagged '{}' to export", tag),
+            None => miette::miette!("No tasks to export"),
+        });
+    }
+
+    // Generated files usually live in the current directory, so refer to tasks from there
+    if let Ok(cwd) = std::env::current_dir() {
+        for task in &mut tasks {
+            if let Ok(relative) = task.file.strip_prefix(&cwd) {
+                task.file = relative.to_path_buf();
+            }
+        }
+    }
 
+    print!("{}", export(format, &tasks));
     Ok(())
 }
 
-/// Load configuration from file or use default
-fn load_config(config_path: Option<PathBuf>) -> Result<Config> {
-    let config = if let Some(path) = config_path {
-        Config::from_file(&path).into_diagnostic()?
-    } else {
+/// Write a tasks file from the targets, recipes or scripts of another task runner
+fn import_tasks(file: PathBuf, format: Option<ExportFormat>, output: PathBuf) -> Result<()> {
+    if output.exists() {
+        return Err(miette::miette!("File already exists: {}", output.display()));
+    }
+    let Some(format) = format.or_else(|| detect_format(&file)) else {
+        return Err(miette::miette!(
+            help = "pass --format makefile, justfile or package-json-scripts",
+            "Can't tell the format of {}",
+            file.display()
+        ));
+    };
+
+    let source = std::fs::read_to_string(&file).into_diagnostic()?;
+    let tasks = import(format, &source).into_diagnostic()?;
+    if tasks.is_empty() {
+        return Err(miette::miette!("No tasks found in {}", file.display()));
+    }
+
+    let origin = file.file_name().map_or_else(
+        || file.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    std::fs::write(&output, tasks_markdown(&tasks, &origin)).into_diagnostic()?;
+    println!(
+        "Imported {} tasks from {} into {}",
+        tasks.len(),
+        file.display(),
+        output.display()
+    );
+    println!("List them with: mx --file {} list", output.display());
+    Ok(())
+}
+
+/// Append a task section at the configured heading level, creating the file if needed
+fn add_task(source: SourceArgs, name: String, lang: String) -> Result<()> {
+    let [markdown_path] = source
+        .files()?
+        .try_into()
+        .map_err(|_| miette::miette!("mx add needs a single --file to add the task to"))?;
+    if name.trim().is_empty() || name.contains('\n') {
+        return Err(miette::miette!("Invalid task name: '{}'", name));
+    }
+
+    let config = source.load_config()?;
+    let markdown = match std::fs::read_to_string(&markdown_path) {
+        Ok(markdown) => markdown,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).into_diagnostic(),
+    };
+
+    let markdown = mx::template::append_task(&markdown, config.heading_level, name.trim(), &lang)
+        .into_diagnostic()?;
+    std::fs::write(&markdown_path, markdown).into_diagnostic()?;
+    println!(
+        "Added task '{}' to {}",
+        name.trim(),
+        markdown_path.display()
+    );
+
+    if !config.has_runtime(&lang) {
+        eprintln!(
+            "{} no runtime is configured for '{}'; add one to mx.toml to run the task",
+            "warning:".warning().bold(),
+            lang
+        );
+    }
+
+    Ok(())
+}
+
+/// Validate a markdown file and print a diagnostics report
+fn validate_tasks(source: SourceArgs) -> Result<()> {
+    let mut runner = Runner::new(source.load_config()?);
+    let files = source.files()?;
+    let mut failed = 0;
+
+    for markdown_path in &files {
+        if !validate_file(&mut runner, markdown_path)? {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(miette::miette!(
+            "{} of {} file(s) failed validation",
+            failed,
+            files.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validate a single markdown file, returning whether it is free of errors
+fn validate_file(runner: &mut Runner, markdown_path: &Path) -> Result<bool> {
+    let markdown = runner.load_markdown(markdown_path).into_diagnostic()?;
+    let issues = runner.validate(&runner.load_task_markdown(markdown_path).into_diagnostic()?);
+
+    Ok(report_issues(markdown_path, &markdown, issues))
+}
+
+/// Print validation issues of a file as diagnostics, returning whether it is valid
+fn report_issues(path: &Path, source: &str, issues: Vec<validate::Issue>) -> bool {
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == validate::Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+
+    for issue in issues {
+        eprintln!("{:?}", issue_diagnostic(path, source, issue));
+    }
+
+    if errors > 0 {
+        eprintln!(
+            "{} {} ({} error(s), {} warning(s))",
+            "Invalid:".error().bold(),
+            path.display(),
+            errors,
+            warnings
+        );
+        return false;
+    }
+
+    println!(
+        "{} {} ({} warning(s))",
+        "Valid:".success().bold(),
+        path.display(),
+        warnings
+    );
+
+    true
+}
+
+/// Render a validation issue as a diagnostic pointing into its file
+fn issue_diagnostic(path: &Path, source: &str, issue: validate::Issue) -> miette::Report {
+    miette::Report::new(ValidationDiagnostic {
+        message: issue.message,
+        source_code: NamedSource::new(path.display().to_string(), source.to_string()),
+        span: issue.span.map(SourceSpan::from),
+        label: issue.label,
+        help: issue.help,
+        severity: match issue.severity {
+            validate::Severity::Error => Severity::Error,
+            validate::Severity::Warning => Severity::Warning,
+        },
+    })
+}
+
+/// Validate the user and project configuration files
+fn check_config(config_path: Option<PathBuf>) -> Result<()> {
+    let files = config_files(config_path)?;
+    if files.is_empty() {
+        println!("No configuration files found; using defaults");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for path in &files {
+        let source = std::fs::read_to_string(path).into_diagnostic()?;
+        if !report_issues(path, &source, validate::validate_config(&source)) {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(miette::miette!(
+            "{} of {} configuration file(s) failed validation",
+            failed,
+            files.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The existing user configuration file and the project configuration file, if any
+fn config_files(config_path: Option<PathBuf>) -> Result<Vec<PathBuf>> {
+    let cwd = std::env::current_dir().into_diagnostic()?;
+    let mut layers = ConfigLayers::discover(&cwd);
+    if config_path.is_some() {
+        layers.project = config_path;
+    }
+
+    Ok(layers
+        .user
+        .into_iter()
+        .filter(|path| path.is_file())
+        .chain(layers.project)
+        .collect())
+}
+
+/// Check the project and print a report of what needs fixing
+fn run_doctor(source: SourceArgs) -> Result<()> {
+    // An invalid configuration is reported by its check rather than stopping the others
+    let config = source.load_config().unwrap_or_else(|e| {
+        tracing::debug!("using the default configuration: {:?}", e);
         Config::default()
+    });
+    let runner = Runner::new(config);
+    let checks = doctor::diagnose(&runner, &source.files()?, &config_files(source.config)?);
+
+    let mut area = None;
+    for check in &checks {
+        if area != Some(check.area) {
+            area = Some(check.area);
+            println!("{}", check.area.to_string().bold());
+        }
+        print_check(check);
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    let (errors, warnings) = (count(doctor::Status::Error), count(doctor::Status::Warning));
+    println!();
+    if errors > 0 {
+        return Err(miette::miette!(
+            "{} error(s) and {} warning(s) found",
+            errors,
+            warnings
+        ));
+    }
+    if warnings > 0 {
+        println!("{} {} warning(s)", "Usable:".warning().bold(), warnings);
+    } else {
+        println!("{} no problems found", "Healthy:".success().bold());
+    }
+    Ok(())
+}
+
+fn print_check(check: &Check) {
+    let mark = match check.status {
+        doctor::Status::Ok => "✓".success(),
+        doctor::Status::Warning => "!".warning(),
+        doctor::Status::Error => "✗".error(),
+    };
+    println!("  {} {}: {}", mark, check.subject, check.message);
+    if let Some(help) = &check.help {
+        println!("      {} {}", "help:".cyan(), help);
+    }
+}
+
+/// Print the merged configuration with the source of each value
+fn show_config(source: SourceArgs, format: ConfigFormat) -> Result<()> {
+    let (config, provenance) = source.load_config_with_provenance()?;
+
+    match format {
+        ConfigFormat::Json => {
+            let json = serde_json::json!({ "config": config, "sources": provenance });
+            println!("{}", serde_json::to_string_pretty(&json).into_diagnostic()?);
+        }
+        ConfigFormat::Toml => {
+            let toml = toml::to_string_pretty(&config).into_diagnostic()?;
+            let mut document: toml_edit::DocumentMut = toml.parse().into_diagnostic()?;
+            annotate_sources(document.as_table_mut(), "", &provenance);
+            print!("{}", document);
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the source of each value as a trailing comment
+fn annotate_sources(table: &mut toml_edit::Table, prefix: &str, provenance: &Provenance) {
+    for (key, item) in table.iter_mut() {
+        let path = format!("{}{}", prefix, key.get());
+        match item {
+            toml_edit::Item::Table(table) => {
+                annotate_sources(table, &format!("{}.", path), provenance);
+            }
+            toml_edit::Item::Value(value) => {
+                // Inline tables, e.g. a detailed runtime, list the sources of their values
+                let nested = format!("{}.", path);
+                let mut sources: Vec<String> = provenance
+                    .iter()
+                    .filter(|(key, _)| **key == path || key.starts_with(&nested))
+                    .map(|(_, source)| source.to_string())
+                    .collect();
+                sources.dedup();
+                if !sources.is_empty() {
+                    value
+                        .decor_mut()
+                        .set_suffix(format!(" # {}", sources.join(", ")));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Generate the configuration and tasks files of a template
+fn init_project(
+    output_path: PathBuf,
+    tasks_path: PathBuf,
+    template: Option<Template>,
+) -> Result<()> {
+    for path in [&output_path, &tasks_path] {
+        if path.exists() {
+            return Err(miette::miette!("File already exists: {}", path.display()));
+        }
+    }
+
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    let template = match template {
+        Some(template) => template,
+        None if interactive => prompt_template()?,
+        None => Template::Full,
     };
 
-    Ok(config)
+    std::fs::write(&output_path, template.config()).into_diagnostic()?;
+    println!("Configuration file created: {}", output_path.display());
+    std::fs::write(&tasks_path, template.tasks()).into_diagnostic()?;
+    println!("Tasks file created: {}", tasks_path.display());
+    println!(
+        "List its tasks with: mx --file {} list",
+        tasks_path.display()
+    );
+
+    Ok(())
+}
+
+/// Ask which template to generate
+fn prompt_template() -> Result<Template> {
+    eprintln!("{}", "Choose a template:".bold());
+    for (number, template) in Template::ALL.iter().enumerate() {
+        eprintln!(
+            "  {}) {:<14} {}",
+            number + 1,
+            template.name(),
+            template.description().muted()
+        );
+    }
+
+    loop {
+        eprint!("Template [1]: ");
+        std::io::stderr().flush().into_diagnostic()?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).into_diagnostic()? == 0 {
+            return Err(miette::miette!("No template chosen"));
+        }
+
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(Template::ALL[0]);
+        }
+        let chosen = match answer.parse::<usize>() {
+            Ok(number) => number
+                .checked_sub(1)
+                .and_then(|index| Template::ALL.get(index))
+                .copied(),
+            Err(_) => answer.parse().ok(),
+        };
+        match chosen {
+            Some(template) => return Ok(template),
+            None => eprintln!(
+                "Enter a number from 1 to {} or a template name",
+                Template::ALL.len()
+            ),
+        }
+    }
+}
+
+/// Load the configuration layers: user config, project config (`--config` or the nearest
+/// mx.toml), then the frontmatter of the task files
+///
+/// Global tasks don't belong to the project, so with `global` the project configuration is
+/// only read if it is named explicitly.
+fn load_config(
+    config_path: Option<PathBuf>,
+    files: Vec<PathBuf>,
+    global: bool,
+) -> Result<(Config, Provenance)> {
+    let cwd = std::env::current_dir().into_diagnostic()?;
+    let mut layers = ConfigLayers::discover(&cwd);
+    if config_path.is_some() || global {
+        layers.project = config_path;
+    }
+
+    for path in layers.user.iter().chain(&layers.project) {
+        let Ok(source) = std::fs::read_to_string(path) else {
+            continue;
+        };
+        let errors: Vec<_> = validate::validate_config(&source)
+            .into_iter()
+            .filter(|issue| issue.severity == validate::Severity::Error)
+            .collect();
+        if !errors.is_empty() {
+            for issue in errors {
+                eprintln!("{:?}", issue_diagnostic(path, &source, issue));
+            }
+            return Err(miette::miette!(
+                "Invalid configuration file: {}",
+                path.display()
+            ));
+        }
+    }
+
+    // Missing files are reported when their tasks are loaded
+    layers.frontmatter = files.into_iter().filter(|file| file.is_file()).collect();
+
+    let (config, provenance) = Config::from_layers_with_provenance(&layers).into_diagnostic()?;
+    let _ = THEME.set(config.theme.clone());
+    if let Some(project) = &layers.project {
+        let files = mx::config::config_files(project).into_diagnostic()?;
+        let _ = PROJECT_CONFIG_FILES.set(files);
+    }
+    Ok((config, provenance))
 }
+
+/// The project configuration file of the first configuration loaded and the files it
+/// extends, which must be trusted like task files: their tasks, runtimes, secret commands
+/// and hooks run too
+static PROJECT_CONFIG_FILES: OnceLock<Vec<PathBuf>> = OnceLock::new();
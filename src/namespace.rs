@@ -0,0 +1,171 @@
+//! Qualified task names of the form `namespace:task`
+//!
+//! A task's qualified name is its anchor, after the namespaces it belongs to: the file it is
+//! read from when tasks come from several files, and the task it is nested in. With
+//! `-f 'ops/*.md'`, `db:migrate` is `## Migrate` of `ops/db.md`, and `deploy:staging` is
+//! `### Staging` under `## Deploy`. Workspace members are namespaces too, see
+//! [`crate::workspace`]. Names are used by `mx run`, in `depends` and by `mx list`, and the
+//! separator can be changed:
+//!
+//! ```toml
+//! [namespaces]
+//! separator = "::"
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::runner::Section;
+use crate::source::slugify;
+
+/// File names whose tasks are namespaced by their directory instead
+const INDEX_FILES: &[&str] = &["readme", "index"];
+
+/// The `[namespaces]` configuration table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceConfig {
+    /// Separator between namespaces and the task name (default: `:`)
+    pub separator: String,
+}
+
+impl Default for NamespaceConfig {
+    fn default() -> Self {
+        Self {
+            separator: ":".to_string(),
+        }
+    }
+}
+
+impl NamespaceConfig {
+    /// Whether the defaults are used
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Namespace of the tasks of a file: its name without the extension, or the name of its
+/// directory for a `README.md` or an `index.md`
+pub fn file_namespace(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_string_lossy();
+    if !INDEX_FILES.contains(&stem.to_lowercase().as_str()) {
+        return Some(slugify(&stem));
+    }
+    let dir = std::path::absolute(path).ok()?;
+    let name = dir.parent()?.file_name()?.to_string_lossy().into_owned();
+    Some(slugify(&name))
+}
+
+/// Qualified name of a task whose file has the given namespace, e.g. `db:deploy:staging`
+pub fn qualified_name(section: &Section, namespace: Option<&str>, separator: &str) -> String {
+    let parent = section.parent.as_deref().map(slugify);
+    namespace
+        .map(str::to_string)
+        .into_iter()
+        .chain(parent)
+        .chain([section.anchor()])
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Whether a task name refers to a qualified name, comparing each part by its slug so that
+/// `DB:Migrate` finds `db:migrate`
+pub fn matches(name: &str, qualified: &str, separator: &str) -> bool {
+    let parts = name.split(separator).map(slugify);
+    parts.eq(qualified.split(separator).map(str::to_string))
+}
+
+/// The Markdown file in a directory whose tasks have a namespace, for references such as
+/// `db:migrate` in `depends`
+pub(crate) fn find_file(dir: &Path, namespace: &str) -> Option<PathBuf> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+
+    entries.into_iter().find_map(|path| {
+        if path.is_dir() {
+            let index = INDEX_FILES.iter().find_map(|name| {
+                fs::read_dir(&path).ok()?.find_map(|entry| {
+                    let file = entry.ok()?.path();
+                    let stem = file.file_stem()?.to_string_lossy().to_lowercase();
+                    (stem == *name && is_markdown(&file)).then_some(file)
+                })
+            })?;
+            (file_namespace(&index).as_deref() == Some(namespace)).then_some(index)
+        } else {
+            let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+            let own = is_markdown(&path) && !INDEX_FILES.contains(&stem.as_str());
+            (own && file_namespace(&path).as_deref() == Some(namespace)).then_some(path)
+        }
+    })
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ["md", "mdx", "markdown"]
+            .iter()
+            .any(|markdown| ext.eq_ignore_ascii_case(markdown))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualified_name() {
+        let section = |title: &str, parent: Option<&str>| Section {
+            title: title.to_string(),
+            parent: parent.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert_eq!(qualified_name(&section("Build", None), None, ":"), "build");
+        assert_eq!(
+            qualified_name(&section("Migrate DB", None), Some("db"), ":"),
+            "db:migrate-db"
+        );
+        assert_eq!(
+            qualified_name(&section("Staging", Some("Deploy")), Some("ops"), "::"),
+            "ops::deploy::staging"
+        );
+
+        assert!(matches("DB:Migrate DB", "db:migrate-db", ":"));
+        assert!(matches(
+            "ops::deploy::staging",
+            "ops::deploy::staging",
+            "::"
+        ));
+        assert!(!matches("db:migrate", "db:migrate-db", ":"));
+        assert!(!matches("migrate-db", "db:migrate-db", ":"));
+    }
+
+    #[test]
+    fn test_file_namespace_and_find_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("api")).unwrap();
+        for file in ["Database.md", "api/README.md", "README.md", "notes.txt"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        assert_eq!(
+            file_namespace(&dir.join("Database.md")).unwrap(),
+            "database"
+        );
+        assert_eq!(file_namespace(&dir.join("api/README.md")).unwrap(), "api");
+
+        assert_eq!(find_file(dir, "database"), Some(dir.join("Database.md")));
+        assert_eq!(find_file(dir, "api"), Some(dir.join("api/README.md")));
+        assert_eq!(find_file(dir, "notes"), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Parameters tasks require before they run
+//!
+//! A task declares the parameters it needs in its `params` metadata:
+//!
+//! ```markdown
+//! ## Deploy
+//!
+//! | params | VERSION, REGION |
+//! |--------|-----------------|
+//! ```
+//!
+//! Each parameter is given with `--param VERSION=1.2.3`, taken from the environment variable
+//! of the same name, or asked for in a terminal, and reaches the task's code blocks as that
+//! environment variable. Missing parameters stop the run before any block is executed.
+
+use crate::error::{Error, Result};
+use crate::runner::Section;
+
+/// Split a `--param NAME=VALUE` argument into the name and the value
+pub fn parse_assignment(param: &str) -> Result<(String, String)> {
+    let (name, value) = param.split_once('=').ok_or_else(|| {
+        Error::InvalidParameter(format!("'{}' is not of the form NAME=VALUE", param))
+    })?;
+    if !is_name(name) {
+        return Err(Error::InvalidParameter(format!(
+            "'{}' is not a valid parameter name",
+            name
+        )));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Whether a parameter name is usable as an environment variable name
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Values of the parameters of tasks, in the order the tasks declare them
+///
+/// A parameter is given, set in the environment, or else asked for with `ask`, which returns
+/// `None` when there is no one to ask. A given parameter that no task declares is an error,
+/// and so are the missing parameters of the first task that lacks some.
+pub fn resolve<'a>(
+    tasks: impl IntoIterator<Item = &'a Section>,
+    given: &[(String, String)],
+    mut ask: impl FnMut(&Section, &str) -> Option<String>,
+) -> Result<Vec<(String, String)>> {
+    let tasks: Vec<&Section> = tasks.into_iter().collect();
+    let mut declared: Vec<String> = Vec::new();
+    for name in tasks.iter().flat_map(|task| task.params()) {
+        if !declared.contains(&name) {
+            declared.push(name);
+        }
+    }
+    if let Some((name, _)) = given.iter().find(|(name, _)| !declared.contains(name)) {
+        let hint = if declared.is_empty() {
+            "the tasks declare no parameters".to_string()
+        } else {
+            format!("the tasks declare {}", declared.join(", "))
+        };
+        return Err(Error::InvalidParameter(format!(
+            "no task declares '{}'; {}",
+            name, hint
+        )));
+    }
+
+    let mut values: Vec<(String, String)> = Vec::new();
+    for task in tasks {
+        let mut missing = Vec::new();
+        for name in task.params() {
+            if !is_name(&name) {
+                return Err(Error::InvalidParameter(format!(
+                    "task '{}' declares '{}', which is not a valid parameter name",
+                    task.title, name
+                )));
+            }
+            if values.iter().any(|(known, _)| *known == name) {
+                continue;
+            }
+            // The last of several values given for a parameter wins
+            let value = given
+                .iter()
+                .rev()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.clone())
+                .or_else(|| std::env::var(&name).ok())
+                .or_else(|| ask(task, &name));
+            match value {
+                Some(value) => values.push((name, value)),
+                None => missing.push(name),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(Error::MissingParameters {
+                task: task.title.clone(),
+                params: missing,
+            });
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn task(title: &str, params: &str) -> Section {
+        Section {
+            title: title.to_string(),
+            metadata: BTreeMap::from([("params".to_string(), params.to_string())]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            parse_assignment("MX_TEST_VERSION=1.2=3").unwrap(),
+            ("MX_TEST_VERSION".to_string(), "1.2=3".to_string())
+        );
+        assert!(parse_assignment("MX_TEST_VERSION").is_err());
+        assert!(parse_assignment("1VERSION=1").is_err());
+        assert!(parse_assignment("MY VERSION=1").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let build = task("Build", "MX_TEST_VERSION");
+        let deploy = task("Deploy", "MX_TEST_VERSION, MX_TEST_REGION");
+        let given = [("MX_TEST_VERSION".to_string(), "1.2.3".to_string())];
+
+        let mut asked = Vec::new();
+        let values = resolve([&build, &deploy], &given, |task, name| {
+            asked.push(format!("{}.{}", task.title, name));
+            Some("eu".to_string())
+        })
+        .unwrap();
+        assert_eq!(
+            values,
+            [
+                ("MX_TEST_VERSION".to_string(), "1.2.3".to_string()),
+                ("MX_TEST_REGION".to_string(), "eu".to_string()),
+            ]
+        );
+        assert_eq!(asked, ["Deploy.MX_TEST_REGION"]);
+
+        let err = resolve([&build, &deploy], &given, |_, _| None).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MissingParameters { task, params }
+                if task == "Deploy" && params == ["MX_TEST_REGION"]
+        ));
+
+        let typo = [("MX_TEST_VERSON".to_string(), "1".to_string())];
+        let err = resolve([&build, &deploy], &typo, |_, _| None).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("the tasks declare MX_TEST_VERSION, MX_TEST_REGION")
+        );
+
+        let err = resolve([&task("Bad", "my version")], &[], |_, _| None).unwrap_err();
+        assert!(err.to_string().contains("not a valid parameter name"));
+    }
+}
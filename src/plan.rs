@@ -0,0 +1,98 @@
+//! Execution plans: what running a task would do, without running it
+
+use serde::Serialize;
+
+use crate::config::{Config, ExecutionMode};
+use crate::report::block_id;
+use crate::runner::Section;
+
+/// A single code block of a task with its resolved runtime
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanStep {
+    /// Stable block identifier, e.g. `Build#1`
+    pub id: String,
+    /// Index of the code block within the section
+    pub index: usize,
+    /// Language of the code block
+    pub lang: String,
+    /// Runtime command, or None if no runtime is configured for the language
+    pub command: Option<String>,
+    /// How the code is passed to the runtime
+    pub execution_mode: ExecutionMode,
+    /// 1-based line number of the code fence, if known
+    pub line: Option<usize>,
+    /// Code to execute
+    pub code: String,
+}
+
+/// The steps a task would execute, in order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Plan {
+    /// Title of the task
+    pub task: String,
+    /// Code blocks that run, in execution order
+    pub steps: Vec<PlanStep>,
+}
+
+impl Plan {
+    /// Build the plan for a section; blocks without a language or with an ignored one are left
+    /// out as they never run
+    pub fn build(config: &Config, section: &Section) -> Self {
+        let steps = section
+            .codes
+            .iter()
+            .enumerate()
+            .filter(|(_, code)| !config.ignores_language(&code.lang))
+            .map(|(index, code)| {
+                // A block whose SQL profile is missing has no runtime, as it would fail to run
+                let runtime = config.runtime_for(code, Some(section)).ok().flatten();
+                PlanStep {
+                    id: block_id(&section.title, index),
+                    index,
+                    lang: code.lang.clone(),
+                    command: runtime
+                        .as_ref()
+                        .map(|runtime| runtime.command().into_owned()),
+                    execution_mode: runtime
+                        .map(|runtime| runtime.execution_mode())
+                        .unwrap_or_default(),
+                    line: code.line,
+                    code: code.code.clone(),
+                }
+            })
+            .collect();
+
+        Self {
+            task: section.title.clone(),
+            steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CodeBlock;
+
+    #[test]
+    fn test_build_plan() {
+        let block = |lang: &str| CodeBlock {
+            lang: lang.to_string(),
+            code: "x".to_string(),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Build".to_string(),
+            codes: vec![block("bash"), block(""), block("cobol")],
+            ..Default::default()
+        };
+
+        let plan = Plan::build(&Config::default(), &section);
+
+        assert_eq!(plan.task, "Build");
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].command.as_deref(), Some("bash"));
+        assert_eq!(plan.steps[1].id, "Build#2");
+        assert_eq!(plan.steps[1].command, None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Block output split into lines, each tagged with the ID of its code block
+//!
+//! When several blocks print at once, their output interleaves chunk by chunk. [`LinePrefixer`]
+//! buffers each block's streams until a line is complete and hands the whole line on with the
+//! block ID, so it can be printed as `[Build#0] compiling...`. Add it as an observer and give
+//! the runner an output handler that drops the raw output.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::events::{BlockOutcome, RunnerObserver};
+use crate::runner::{OutputStream, Section};
+
+/// Writes one complete line of output, ending in `\n`, of the block with the given ID
+pub type LineWriter = Box<dyn Fn(&str, OutputStream, &[u8]) + Send + Sync>;
+
+/// Observer passing block output on line by line, see the [module docs](self)
+pub struct LinePrefixer {
+    write: LineWriter,
+    /// Incomplete last line of each block's stdout and stderr
+    pending: Mutex<HashMap<(String, OutputStream), Vec<u8>>>,
+}
+
+impl LinePrefixer {
+    /// Create a prefixer handing complete lines to `write`
+    pub fn new(write: impl Fn(&str, OutputStream, &[u8]) + Send + Sync + 'static) -> Self {
+        Self {
+            write: Box::new(write),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RunnerObserver for LinePrefixer {
+    fn on_block_output(&self, id: &str, stream: OutputStream, data: &[u8]) {
+        let lines = {
+            let mut pending = self.pending.lock().unwrap();
+            let buffer = pending.entry((id.to_string(), stream)).or_default();
+            buffer.extend_from_slice(data);
+            match buffer.iter().rposition(|&byte| byte == b'\n') {
+                Some(end) => buffer.drain(..=end).collect::<Vec<u8>>(),
+                None => return,
+            }
+        };
+        for line in lines.split_inclusive(|&byte| byte == b'\n') {
+            (self.write)(id, stream, line);
+        }
+    }
+
+    fn on_block_complete(&self, _section: &Section, outcome: &BlockOutcome) {
+        let id = &outcome.report.id;
+        for stream in [OutputStream::Stdout, OutputStream::Stderr] {
+            let rest = self.pending.lock().unwrap().remove(&(id.clone(), stream));
+            // Output that did not end in a newline still gets a line of its own
+            if let Some(mut rest) = rest.filter(|rest| !rest.is_empty()) {
+                rest.push(b'\n');
+                (self.write)(id, stream, &rest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::report::BlockReport;
+
+    #[test]
+    fn test_lines_of_interleaved_blocks() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let written = Arc::clone(&lines);
+        let prefixer = LinePrefixer::new(move |id, stream, line: &[u8]| {
+            let line = String::from_utf8_lossy(line).into_owned();
+            written
+                .lock()
+                .unwrap()
+                .push(format!("{:?} [{}] {}", stream, id, line));
+        });
+
+        prefixer.on_block_output("A#0", OutputStream::Stdout, b"one\ntw");
+        prefixer.on_block_output("B#0", OutputStream::Stdout, b"other\n");
+        prefixer.on_block_output("A#0", OutputStream::Stderr, b"oops");
+        prefixer.on_block_output("A#0", OutputStream::Stdout, b"o\nthree\nfour");
+        prefixer.on_block_complete(
+            &Section::default(),
+            &BlockOutcome {
+                report: BlockReport {
+                    id: "A#0".to_string(),
+                    index: 0,
+                    lang: "sh".to_string(),
+                    duration: Default::default(),
+                    skipped: None,
+                },
+                exit_code: Some(0),
+                success: true,
+            },
+        );
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "Stdout [A#0] one\n",
+                "Stdout [B#0] other\n",
+                "Stdout [A#0] two\n",
+                "Stdout [A#0] three\n",
+                "Stdout [A#0] four\n",
+                "Stderr [A#0] oops\n",
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Process groups and signal forwarding for code block processes
+//!
+//! Every code block runs in its own process group on unix and in its own job object on
+//! Windows, so the whole tree it spawns (e.g. `npm` starting `node`) can be killed at once.
+//! When mx owns the terminal, the group is also made the terminal's foreground group, so
+//! interactive blocks can read from it and Ctrl-C reaches them directly.
+//!
+//! [`forward_signals`] makes mx pass SIGINT, SIGTERM and SIGHUP on to the running groups and
+//! record the interruption, so the runner stops before the next block, runs the `on_cancel`
+//! hooks and cleans up its temporary files instead of dying on the spot.
+
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Process group id on unix, job object handle on Windows
+#[cfg(windows)]
+type GroupHandle = isize;
+/// Process group id on unix, job object handle on Windows
+#[cfg(not(windows))]
+type GroupHandle = i32;
+
+/// A set of running process groups
+#[derive(Debug, Default)]
+pub(crate) struct ProcessGroups(Mutex<Vec<GroupHandle>>);
+
+impl ProcessGroups {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn add(&self, group: GroupHandle) {
+        if let Ok(mut groups) = self.0.lock() {
+            groups.push(group);
+        }
+    }
+
+    fn remove(&self, group: GroupHandle) {
+        if let Ok(mut groups) = self.0.lock() {
+            groups.retain(|running| *running != group);
+        }
+    }
+
+    fn snapshot(&self) -> Vec<GroupHandle> {
+        self.0
+            .lock()
+            .map(|groups| groups.clone())
+            .unwrap_or_default()
+    }
+
+    /// Kill every process of every group in the set
+    pub(crate) fn kill_all(&self) {
+        // Held while killing, so a group cannot be released in the meantime
+        if let Ok(groups) = self.0.lock() {
+            for group in groups.iter() {
+                kill_group(*group);
+            }
+        }
+    }
+}
+
+/// Groups of every code block running in this process, for signal forwarding
+static RUNNING_GROUPS: ProcessGroups = ProcessGroups::new();
+
+/// Last signal received by the forwarding thread, or 0
+static CANCEL_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Kills the code blocks a runner is running, from any thread
+///
+/// Obtained from [`Runner::kill_handle`](crate::Runner::kill_handle).
+#[derive(Debug, Clone)]
+pub struct KillHandle(pub(crate) Arc<ProcessGroups>);
+
+impl KillHandle {
+    /// Kill the running code blocks and every process they started
+    ///
+    /// The killed blocks fail, which stops their tasks.
+    pub fn kill(&self) {
+        self.0.kill_all();
+    }
+}
+
+/// Configure a command to start in its own process group
+///
+/// With `interactive`, the group also takes over the terminal if mx owns it. Returns
+/// whether it does.
+pub(crate) fn configure(command: &mut Command, interactive: bool) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        command.process_group(0);
+
+        let foreground = interactive && owns_terminal();
+        if foreground {
+            // SAFETY: only async-signal-safe libc calls are made between fork and exec.
+            unsafe {
+                command.pre_exec(|| {
+                    take_terminal(libc::getpid());
+                    Ok(())
+                });
+            }
+        }
+        foreground
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (command, interactive);
+        false
+    }
+}
+
+/// A running code block's process group
+///
+/// While it exists, the group is registered with its runner for
+/// [`Runner::kill_running`](crate::Runner::kill_running) and with [`forward_signals`]. If it
+/// is dropped without [`ChildGroup::finish`], e.g. because the block failed, was cancelled
+/// or panicked, the whole group is killed.
+pub(crate) struct ChildGroup {
+    handle: GroupHandle,
+    owner: Arc<ProcessGroups>,
+    foreground: bool,
+    finished: bool,
+}
+
+impl ChildGroup {
+    /// Track the group led by a freshly spawned child
+    pub(crate) fn new(pid: u32, foreground: bool, owner: &Arc<ProcessGroups>) -> Self {
+        let handle = open_group(pid);
+        if foreground {
+            // Also done in the child; whichever runs first wins the race against a read
+            take_terminal(pid as i32);
+        }
+        RUNNING_GROUPS.add(handle);
+        owner.add(handle);
+
+        Self {
+            handle,
+            owner: Arc::clone(owner),
+            foreground,
+            finished: false,
+        }
+    }
+
+    /// Mark the block as succeeded; processes it left running are left alone
+    pub(crate) fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for ChildGroup {
+    fn drop(&mut self) {
+        RUNNING_GROUPS.remove(self.handle);
+        self.owner.remove(self.handle);
+        if !self.finished {
+            kill_group(self.handle);
+        }
+        close_group(self.handle);
+        if self.foreground {
+            restore_terminal();
+        }
+    }
+}
+
+/// Pass SIGINT, SIGTERM and SIGHUP on to running code blocks instead of terminating
+///
+/// The signal is recorded, so runners stop before their next block and the run returns
+/// [`Error::Cancelled`](crate::Error::Cancelled) as usual. A second signal arriving while no
+/// block runs, e.g. when mx waits for input, exits mx with the conventional status
+/// (128 + signal). Only supported on unix; elsewhere this does nothing.
+pub fn forward_signals() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                let previous = CANCEL_SIGNAL.swap(signal, Ordering::SeqCst);
+
+                let groups = RUNNING_GROUPS.snapshot();
+                if groups.is_empty() && previous != 0 {
+                    std::process::exit(128 + signal);
+                }
+                for pgid in groups {
+                    signal_group(pgid, signal);
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether a signal received by [`forward_signals`] interrupted the run
+///
+/// How a block exited does not matter: a block killing itself with SIGTERM merely fails.
+pub(crate) fn interrupted() -> bool {
+    cancel_signal().is_some()
+}
+
+/// Signal that interrupted the run, if any
+pub fn cancel_signal() -> Option<i32> {
+    Some(CANCEL_SIGNAL.load(Ordering::SeqCst)).filter(|signal| *signal != 0)
+}
+
+/// Command running an `on_cancel` hook through the platform shell
+pub(crate) fn cancel_hook(hook: &str, task: &str, block: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(hook);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(hook);
+        command
+    };
+    command.env("MX_TASK", task).env("MX_BLOCK", block);
+    command
+}
+
+/// Handle of the group led by `pid`; on Windows a new job object holding the process
+fn open_group(pid: u32) -> GroupHandle {
+    #[cfg(windows)]
+    // SAFETY: handles are checked for null and the process handle is closed again; the job
+    // handle is closed by `close_group`.
+    unsafe {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+        };
+
+        let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+        if job.is_null() {
+            return 0;
+        }
+        let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+        if !process.is_null() {
+            AssignProcessToJobObject(job, process);
+            CloseHandle(process);
+        }
+        job as isize
+    }
+
+    #[cfg(not(windows))]
+    {
+        pid as i32
+    }
+}
+
+fn close_group(group: GroupHandle) {
+    #[cfg(windows)]
+    if group != 0 {
+        // SAFETY: the job handle was created by `open_group` and is closed once.
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(group as _);
+        }
+    }
+
+    #[cfg(not(windows))]
+    let _ = group;
+}
+
+fn kill_group(group: GroupHandle) {
+    #[cfg(windows)]
+    if group != 0 {
+        // SAFETY: the job handle stays open until the group is dropped.
+        unsafe {
+            windows_sys::Win32::System::JobObjects::TerminateJobObject(group as _, 1);
+        }
+    }
+
+    #[cfg(unix)]
+    signal_group(group, libc::SIGKILL);
+
+    #[cfg(not(any(unix, windows)))]
+    let _ = group;
+}
+
+#[cfg(unix)]
+fn signal_group(pgid: i32, signal: i32) {
+    // SAFETY: kill has no memory safety requirements; a negative pid addresses the group.
+    unsafe {
+        libc::kill(-pgid, signal);
+    }
+}
+
+/// Whether stdin is a terminal and this process is in its foreground group
+#[cfg(unix)]
+fn owns_terminal() -> bool {
+    // SAFETY: plain libc queries on the stdin descriptor.
+    unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+            && libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp()
+    }
+}
+
+/// Make a process group the terminal's foreground group
+fn take_terminal(pgid: i32) {
+    #[cfg(unix)]
+    // SAFETY: async-signal-safe calls only, as this also runs between fork and exec.
+    // SIGTTOU is blocked so a background caller is not stopped by tcsetpgrp.
+    unsafe {
+        let mut block: libc::sigset_t = std::mem::zeroed();
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut block);
+        libc::sigaddset(&mut block, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &block, &mut previous);
+        libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+    }
+
+    #[cfg(not(unix))]
+    let _ = pgid;
+}
+
+/// Give the terminal back to mx's own process group
+fn restore_terminal() {
+    #[cfg(unix)]
+    // SAFETY: getpgrp cannot fail.
+    take_terminal(unsafe { libc::getpgrp() });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn spawn_tree() -> std::process::Child {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("sleep 30 & wait");
+        configure(&mut command, false);
+        command.spawn().unwrap()
+    }
+
+    #[test]
+    fn test_dropping_unfinished_group_kills_grandchildren() {
+        let mut child = spawn_tree();
+
+        let started = Instant::now();
+        drop(ChildGroup::new(child.id(), false, &Arc::default()));
+        child.wait().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_kill_handle() {
+        let mut child = spawn_tree();
+        let groups = Arc::default();
+        let group = ChildGroup::new(child.id(), false, &groups);
+
+        let started = Instant::now();
+        KillHandle(Arc::clone(&groups)).kill();
+        child.wait().unwrap();
+        group.finish();
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(groups.snapshot().is_empty());
+    }
+}
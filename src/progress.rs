@@ -0,0 +1,223 @@
+//! Machine-parsable progress protocol for wrapper UIs
+//!
+//! When the `MX_PROGRESS_FD` environment variable contains a file descriptor number,
+//! the mx command writes one JSON object per line to that descriptor while a task runs:
+//!
+//! ```text
+//! {"event":"task_start","task":"Build","total_blocks":3,"percent":0.0}
+//! {"event":"block_start","task":"Build","id":"Build#1","block":1,"lang":"bash","total_blocks":3,"percent":33.3,"eta_ms":1200}
+//! {"event":"task_finish","task":"Build","total_blocks":3,"percent":100.0,"success":true}
+//! ```
+
+use std::fs::File;
+use std::io::Write;
+use std::mem::ManuallyDrop;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::report::block_id;
+
+/// Environment variable holding the progress file descriptor
+pub const PROGRESS_FD_ENV: &str = "MX_PROGRESS_FD";
+
+/// A single progress frame
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressFrame {
+    /// A task is about to execute its code blocks
+    TaskStart {
+        task: String,
+        total_blocks: usize,
+        percent: f64,
+    },
+    /// A code block is about to execute
+    BlockStart {
+        task: String,
+        /// Stable block identifier, e.g. `Build#1`
+        id: String,
+        block: usize,
+        lang: String,
+        total_blocks: usize,
+        percent: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_ms: Option<u64>,
+    },
+    /// A task finished, successfully or not
+    TaskFinish {
+        task: String,
+        total_blocks: usize,
+        percent: f64,
+        success: bool,
+    },
+}
+
+impl ProgressFrame {
+    /// Build a block start frame, estimating the remaining time from completed blocks
+    pub fn block_start(
+        task: &str,
+        block: usize,
+        lang: &str,
+        total_blocks: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let eta_ms = (block > 0).then(|| {
+            let average = elapsed.as_millis() as f64 / block as f64;
+            (average * (total_blocks - block) as f64) as u64
+        });
+
+        Self::BlockStart {
+            task: task.to_string(),
+            id: block_id(task, block),
+            block,
+            lang: lang.to_string(),
+            total_blocks,
+            percent: percent(block, total_blocks),
+            eta_ms,
+        }
+    }
+}
+
+/// Writes progress frames to the descriptor named by `MX_PROGRESS_FD`
+#[derive(Debug)]
+pub struct ProgressReporter {
+    // The descriptor is owned by the parent process, so it must not be closed on drop.
+    file: ManuallyDrop<File>,
+}
+
+impl ProgressReporter {
+    /// Create a reporter from the `MX_PROGRESS_FD` environment variable, if set to an open,
+    /// writable descriptor
+    ///
+    /// A runner only reports progress when given a reporter with
+    /// [`Runner::with_progress_reporter`](crate::Runner::with_progress_reporter).
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(PROGRESS_FD_ENV).ok()?;
+
+        match value.trim().parse::<i32>() {
+            Ok(fd) if fd > 2 => Self::from_fd(fd),
+            _ => {
+                warn!("ignoring invalid {} value: {}", PROGRESS_FD_ENV, value);
+                None
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_fd(fd: i32) -> Option<Self> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: fcntl only reads the flags of the descriptor, whether it is open or not.
+        let (fd_flags, status_flags) = unsafe {
+            (
+                libc::fcntl(fd, libc::F_GETFD),
+                libc::fcntl(fd, libc::F_GETFL),
+            )
+        };
+        if fd_flags == -1 {
+            warn!(
+                "ignoring {}: descriptor {} is not open",
+                PROGRESS_FD_ENV, fd
+            );
+            return None;
+        }
+        if status_flags & libc::O_ACCMODE == libc::O_RDONLY {
+            warn!(
+                "ignoring {}: descriptor {} is not writable",
+                PROGRESS_FD_ENV, fd
+            );
+            return None;
+        }
+
+        // SAFETY: the descriptor was just checked to be open, the wrapper process keeps it
+        // open, and ManuallyDrop ensures we never close it.
+        let file = unsafe { File::from_raw_fd(fd) };
+        Some(Self {
+            file: ManuallyDrop::new(file),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn from_fd(_fd: i32) -> Option<Self> {
+        warn!("{} is only supported on unix platforms", PROGRESS_FD_ENV);
+        None
+    }
+
+    /// Write a frame; write errors are logged and otherwise ignored
+    pub fn emit(&self, frame: &ProgressFrame) {
+        let Ok(mut line) = serde_json::to_string(frame) else {
+            return;
+        };
+        line.push('\n');
+
+        if let Err(e) = (&*self.file).write_all(line.as_bytes()) {
+            warn!("failed to write progress frame: {}", e);
+        }
+    }
+}
+
+fn percent(done: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        (done as f64 / total as f64 * 1000.0).round() / 10.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_start_eta() {
+        let frame = ProgressFrame::block_start("Build", 2, "bash", 4, Duration::from_millis(400));
+        assert_eq!(
+            frame,
+            ProgressFrame::BlockStart {
+                task: "Build".to_string(),
+                id: "Build#2".to_string(),
+                block: 2,
+                lang: "bash".to_string(),
+                total_blocks: 4,
+                percent: 50.0,
+                eta_ms: Some(400),
+            }
+        );
+
+        let first = ProgressFrame::block_start("Build", 0, "bash", 4, Duration::ZERO);
+        assert!(matches!(
+            first,
+            ProgressFrame::BlockStart { eta_ms: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_frame_serialization() {
+        let frame = ProgressFrame::TaskFinish {
+            task: "Build".to_string(),
+            total_blocks: 3,
+            percent: 100.0,
+            success: true,
+        };
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            r#"{"event":"task_finish","task":"Build","total_blocks":3,"percent":100.0,"success":true}"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_fd_rejects_closed_and_read_only_descriptors() {
+        use std::os::fd::AsRawFd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("progress.jsonl");
+        let writable = File::create(&path).unwrap();
+        assert!(ProgressReporter::from_fd(writable.as_raw_fd()).is_some());
+
+        let read_only = File::open(&path).unwrap();
+        assert!(ProgressReporter::from_fd(read_only.as_raw_fd()).is_none());
+        assert!(ProgressReporter::from_fd(i32::MAX).is_none());
+    }
+}
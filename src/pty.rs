@@ -0,0 +1,251 @@
+//! Pseudo-terminals for interactive code blocks
+//!
+//! Output of a block is read through pipes whenever mx captures, logs, masks or prefixes it,
+//! so programs see no terminal: progress bars disappear, colors are dropped and prompts for
+//! passwords fail. A block with `pty = true` in its runtime, or a `pty` fence attribute, runs
+//! with a pseudo-terminal instead:
+//!
+//! ```toml
+//! [runtimes.bash]
+//! command = "bash"
+//! pty = true
+//! ```
+//!
+//! The terminal is the block's stdout, stderr and controlling terminal, and its stdin unless
+//! data is piped into the task. Its output reaches mx as one stream, and keys typed in mx's
+//! terminal are passed on while the block runs. Pseudo-terminals are only available on unix.
+
+use std::process::{Child, Command, ExitStatus};
+
+use crate::error::{Error, Result};
+use crate::runner::BlockOutput;
+
+/// A pseudo-terminal a code block runs in
+pub(crate) struct Pty {
+    #[cfg(unix)]
+    master: std::fs::File,
+    #[cfg(unix)]
+    slave: std::fs::File,
+}
+
+#[cfg(unix)]
+impl Pty {
+    /// Open a pseudo-terminal the size of mx's terminal, or 80x24 without one
+    pub(crate) fn open() -> Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        let mut size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO] {
+            // SAFETY: TIOCGWINSZ only writes a winsize through the pointer.
+            if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 {
+                break;
+            }
+        }
+
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: openpty writes the two descriptors; the name buffer and termios are unused.
+        let opened = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &size,
+            )
+        };
+        if opened != 0 {
+            return Err(Error::Execution(format!(
+                "Failed to open a pseudo-terminal: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        // SAFETY: openpty succeeded, so both descriptors are open and owned by nothing else.
+        Ok(unsafe {
+            Self {
+                master: std::fs::File::from_raw_fd(master),
+                slave: std::fs::File::from_raw_fd(slave),
+            }
+        })
+    }
+
+    /// Make the terminal the stdout, stderr and controlling terminal of a command, and its
+    /// stdin with `stdin`
+    ///
+    /// The command starts a session of its own, which also makes it a process group leader.
+    pub(crate) fn attach(&self, command: &mut Command, stdin: bool) -> Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        command.stdout(self.slave.try_clone()?);
+        command.stderr(self.slave.try_clone()?);
+        if stdin {
+            command.stdin(self.slave.try_clone()?);
+        }
+        // SAFETY: only async-signal-safe libc calls are made between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0
+                    || libc::ioctl(libc::STDOUT_FILENO, libc::TIOCSCTTY as _, 0) < 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Wait for a child attached to the terminal, forwarding its output and, with `keys`,
+    /// what is typed in mx's terminal
+    ///
+    /// `code` is written to the child's stdin if given. The command the child was spawned
+    /// from must be dropped first, as its copies of the terminal keep the output open.
+    pub(crate) fn wait(
+        self,
+        mut child: Child,
+        code: Option<&str>,
+        keys: bool,
+        output: BlockOutput<'_>,
+    ) -> Result<ExitStatus> {
+        use std::io::Write;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::runner::{OutputStream, forward_output};
+
+        let reader = MasterReader(self.master.try_clone()?);
+        let writer = self.master;
+        drop(self.slave);
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || forward_output(reader, OutputStream::Stdout, output));
+            if keys {
+                let (writer, done) = (&writer, &done);
+                scope.spawn(move || forward_keys(writer, done));
+            }
+
+            let written = match (code, child.stdin.take()) {
+                (Some(code), Some(mut input)) => input
+                    .write_all(code.as_bytes())
+                    .map_err(|e| Error::Execution(format!("Failed to write to stdin: {}", e))),
+                _ => Ok(()),
+            };
+            let status = child
+                .wait()
+                .map_err(|e| Error::Execution(format!("Failed to wait for process: {}", e)));
+            // Stops forwarding keys even if the code could not be written
+            done.store(true, Ordering::Relaxed);
+            written.and(status)
+        })
+    }
+}
+
+#[cfg(not(unix))]
+impl Pty {
+    pub(crate) fn open() -> Result<Self> {
+        Err(Error::Execution(
+            "pseudo-terminals are only supported on unix".to_string(),
+        ))
+    }
+
+    pub(crate) fn attach(&self, _command: &mut Command, _stdin: bool) -> Result<()> {
+        unreachable!("a pseudo-terminal is never opened")
+    }
+
+    pub(crate) fn wait(
+        self,
+        _child: Child,
+        _code: Option<&str>,
+        _keys: bool,
+        _output: BlockOutput<'_>,
+    ) -> Result<ExitStatus> {
+        unreachable!("a pseudo-terminal is never opened")
+    }
+}
+
+/// Reads the output of a pseudo-terminal, which fails with `EIO` rather than reporting the
+/// end once every process has closed it
+#[cfg(unix)]
+struct MasterReader(std::fs::File);
+
+#[cfg(unix)]
+impl std::io::Read for MasterReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Pass what is typed in mx's terminal on to the pseudo-terminal until `done` is set
+///
+/// mx's terminal is in raw mode meanwhile, so keys such as Ctrl-C reach the block's
+/// terminal, which turns them into signals for the block.
+#[cfg(unix)]
+fn forward_keys(mut master: &std::fs::File, done: &std::sync::atomic::AtomicBool) {
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+
+    let _raw = RawMode::enable(libc::STDIN_FILENO);
+    let mut buffer = [0u8; 1024];
+    while !done.load(Ordering::Relaxed) {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: poll reads and writes the single pollfd passed to it.
+        if unsafe { libc::poll(&mut poll, 1, 100) } <= 0 {
+            continue;
+        }
+        // SAFETY: read writes at most `buffer.len()` bytes into the buffer.
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if n <= 0 || master.write_all(&buffer[..n as usize]).is_err() {
+            break;
+        }
+    }
+}
+
+/// A terminal in raw mode, restored to its previous settings on drop
+#[cfg(unix)]
+struct RawMode {
+    fd: i32,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable(fd: i32) -> Option<Self> {
+        // SAFETY: termios is plain data, filled in by tcgetattr before it is used.
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: tcgetattr and tcsetattr only access the termios passed to them.
+        unsafe {
+            if libc::tcgetattr(fd, &mut saved) != 0 {
+                return None;
+            }
+            let mut raw = saved;
+            libc::cfmakeraw(&mut raw);
+            // mx's own output still needs newlines turned into line breaks
+            raw.c_oflag = saved.c_oflag;
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+        }
+        Some(Self { fd, saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in `enable`.
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Python bindings
+//!
+//! Enabled with the `mx-py` feature and built with maturin (see `pyproject.toml`):
+//!
+//! ```python
+//! import mx
+//!
+//! for task in mx.list_tasks("README.md"):
+//!     print(task.title, task.tags)
+//!
+//! report = mx.run_task("Build", file="README.md", args=["--release"])
+//! print(report.duration, report.slowest_block().lang)
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+use crate::config::Config;
+use crate::report::{BlockReport, ExecutionReport};
+use crate::runner::{Runner, Section};
+
+create_exception!(mx, MxError, PyException, "Error raised by mx");
+
+/// A task (section) in a Markdown file
+#[pyclass(name = "Task", module = "mx", frozen, get_all)]
+#[derive(Clone)]
+pub struct PyTask {
+    /// Section title
+    title: String,
+    /// Description from the section content
+    description: Option<String>,
+    /// Tags of the task
+    tags: Vec<String>,
+    /// 1-based line number of the heading
+    line: Option<usize>,
+    /// Task file the task was read from
+    file: Option<String>,
+    /// Languages of the code blocks, in order
+    languages: Vec<String>,
+}
+
+#[pymethods]
+impl PyTask {
+    fn __repr__(&self) -> String {
+        format!("Task(title={:?})", self.title)
+    }
+}
+
+impl From<Section> for PyTask {
+    fn from(section: Section) -> Self {
+        Self {
+            languages: section.codes.into_iter().map(|code| code.lang).collect(),
+            title: section.title,
+            description: section.description,
+            tags: section.tags,
+            line: section.line,
+            file: section.file.map(|file| file.display().to_string()),
+        }
+    }
+}
+
+/// Timing of a single executed code block
+#[pyclass(name = "BlockReport", module = "mx", frozen, get_all)]
+#[derive(Clone)]
+pub struct PyBlockReport {
+    /// Stable block identifier, e.g. `Build#1`
+    id: String,
+    /// Index of the code block within the section
+    index: usize,
+    /// Language of the code block
+    lang: String,
+    /// Wall-clock execution time in seconds
+    duration: f64,
+    /// Why the block was skipped, or None if it ran
+    skipped: Option<String>,
+}
+
+#[pymethods]
+impl PyBlockReport {
+    fn __repr__(&self) -> String {
+        format!(
+            "BlockReport(id={:?}, lang={:?}, duration={:.3})",
+            self.id, self.lang, self.duration
+        )
+    }
+}
+
+impl From<&BlockReport> for PyBlockReport {
+    fn from(block: &BlockReport) -> Self {
+        Self {
+            id: block.id.clone(),
+            index: block.index,
+            lang: block.lang.clone(),
+            duration: block.duration.as_secs_f64(),
+            skipped: block.skipped.clone(),
+        }
+    }
+}
+
+/// Timing report of a task execution
+#[pyclass(name = "ExecutionReport", module = "mx", frozen)]
+pub struct PyExecutionReport {
+    report: ExecutionReport,
+}
+
+#[pymethods]
+impl PyExecutionReport {
+    /// Task (section title) that was executed
+    #[getter]
+    fn task(&self) -> &str {
+        &self.report.task
+    }
+
+    /// Per-block timings, in execution order
+    #[getter]
+    fn blocks(&self) -> Vec<PyBlockReport> {
+        self.report.blocks.iter().map(PyBlockReport::from).collect()
+    }
+
+    /// Total wall-clock time of the task in seconds
+    #[getter]
+    fn duration(&self) -> f64 {
+        self.report.duration.as_secs_f64()
+    }
+
+    /// Why the whole task was skipped, or None if it ran
+    #[getter]
+    fn skipped(&self) -> Option<&str> {
+        self.report.skipped.as_deref()
+    }
+
+    /// Warnings about the task, as messages
+    #[getter]
+    fn warnings(&self) -> Vec<String> {
+        self.report
+            .warnings
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// The block that took the longest, if any block ran
+    fn slowest_block(&self) -> Option<PyBlockReport> {
+        self.report.slowest_block().map(PyBlockReport::from)
+    }
+
+    /// Serialize the report as JSON
+    fn to_json(&self) -> PyResult<String> {
+        self.report
+            .to_json()
+            .map_err(|e| MxError::new_err(e.to_string()))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ExecutionReport(task={:?}, duration={:.3})",
+            self.report.task,
+            self.report.duration.as_secs_f64()
+        )
+    }
+}
+
+/// List the tasks defined in a Markdown file
+#[pyfunction]
+#[pyo3(signature = (file = "README.md", config = None))]
+fn list_tasks(file: &str, config: Option<&str>) -> PyResult<Vec<PyTask>> {
+    let runner = Runner::new(load_config(config)?);
+    let sections = runner.list_task_sections(file).map_err(to_py_err)?;
+
+    Ok(sections.into_iter().map(PyTask::from).collect())
+}
+
+/// Run a task, returning its execution report
+///
+/// Code block output goes to the process' stdout and stderr. The GIL is released while the
+/// task runs.
+#[pyfunction]
+#[pyo3(signature = (task, file = "README.md", config = None, args = Vec::new()))]
+fn run_task(
+    py: Python<'_>,
+    task: &str,
+    file: &str,
+    config: Option<&str>,
+    args: Vec<String>,
+) -> PyResult<PyExecutionReport> {
+    let config = load_config(config)?;
+
+    let report = py
+        .allow_threads(|| Runner::new(config).run_task_with_args(file, task, &args))
+        .map_err(to_py_err)?;
+
+    Ok(PyExecutionReport { report })
+}
+
+fn load_config(path: Option<&str>) -> PyResult<Config> {
+    match path {
+        Some(path) => Config::from_file(path).map_err(to_py_err),
+        None => Ok(Config::default()),
+    }
+}
+
+fn to_py_err(error: crate::Error) -> PyErr {
+    MxError::new_err(error.to_string())
+}
+
+/// The `mx` Python module
+#[pymodule]
+#[pyo3(name = "mx")]
+fn mx_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("MxError", m.py().get_type::<MxError>())?;
+    m.add_class::<PyTask>()?;
+    m.add_class::<PyBlockReport>()?;
+    m.add_class::<PyExecutionReport>()?;
+    m.add_function(wrap_pyfunction!(list_tasks, m)?)?;
+    m.add_function(wrap_pyfunction!(run_task, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,297 @@
+//! Remote execution of code blocks over SSH or in Kubernetes pods
+//!
+//! A code block with a remote target runs there instead of locally, with its output
+//! streamed back. The target comes from, in order of precedence, `mx run --on TARGET`, the
+//! task's `{remote=TARGET}` heading attribute or `remote` metadata entry, and the runtime's
+//! `remote` setting (`target` is accepted as a synonym everywhere):
+//!
+//! ```toml
+//! [runtimes.bash]
+//! command = "bash"
+//! remote = "admin@db1.example.com"
+//!
+//! [runtimes.psql]
+//! command = "psql"
+//! target = "k8s:deployment/postgres"
+//!
+//! [kubernetes]
+//! namespace = "prod"
+//! context = "prod-cluster"
+//! ```
+//!
+//! Targets starting with `k8s:` name a pod or workload and run through `kubectl exec -i`;
+//! anything else is an SSH destination (an optional `ssh:` prefix is stripped).
+//!
+//! The runtime command runs through `sh -c`, in the remote user's home directory or the
+//! container's working directory. Code files are shipped over stdin and removed afterwards,
+//! and `MX_*` variables are exported in the remote script, as neither SSH nor kubectl
+//! forward the environment.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command::shell_quote;
+use crate::config::Config;
+use crate::runner::Section;
+
+/// Program used to reach SSH hosts
+pub const SSH: &str = "ssh";
+
+/// Program used to reach Kubernetes pods
+pub const KUBECTL: &str = "kubectl";
+
+/// Prefix of Kubernetes targets, e.g. `k8s:deployment/web`
+pub const KUBERNETES_PREFIX: &str = "k8s:";
+
+/// Keys naming the target of a task or runtime
+const TARGET_KEYS: [&str; 2] = ["remote", "target"];
+
+/// Cluster settings for `k8s:` targets, the `[kubernetes]` configuration table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KubernetesConfig {
+    /// Namespace of the pods, instead of the context's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// kubeconfig context, instead of the current one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+impl KubernetesConfig {
+    /// Whether nothing is configured
+    pub fn is_empty(&self) -> bool {
+        self.namespace.is_none() && self.context.is_none()
+    }
+}
+
+/// Where a code block runs instead of locally
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Remote {
+    /// An SSH destination, e.g. `user@host` or a `Host` alias from `~/.ssh/config`
+    Ssh { host: String },
+    /// A pod, or a workload such as `deployment/web` whose first pod is used
+    Kubernetes {
+        resource: String,
+        namespace: Option<String>,
+        context: Option<String>,
+    },
+}
+
+impl Remote {
+    /// Parse a target, taking the namespace and context of `k8s:` targets from `kubernetes`
+    pub fn parse(target: &str, kubernetes: &KubernetesConfig) -> Self {
+        match target.strip_prefix(KUBERNETES_PREFIX) {
+            Some(resource) => Remote::Kubernetes {
+                resource: resource.to_string(),
+                namespace: kubernetes.namespace.clone(),
+                context: kubernetes.context.clone(),
+            },
+            None => Remote::Ssh {
+                host: target.strip_prefix("ssh:").unwrap_or(target).to_string(),
+            },
+        }
+    }
+
+    /// The target for a code block of a language, or `None` if it runs locally
+    ///
+    /// `target` is a target given for the whole run, overriding the task and the runtime.
+    pub fn resolve(
+        config: &Config,
+        lang: &str,
+        section: Option<&Section>,
+        target: Option<&str>,
+    ) -> Option<Self> {
+        let task_target = section.and_then(|section| {
+            TARGET_KEYS.iter().find_map(|key| {
+                section
+                    .attribute(key)
+                    .or_else(|| section.metadata.get(*key).cloned())
+            })
+        });
+
+        target
+            .map(str::to_string)
+            .or(task_target)
+            .or_else(|| config.get_remote(lang).map(str::to_string))
+            .filter(|target| !target.is_empty())
+            .map(|target| Self::parse(&target, &config.kubernetes))
+    }
+
+    /// Program reaching the target
+    pub fn program(&self) -> &'static str {
+        match self {
+            Remote::Ssh { .. } => SSH,
+            Remote::Kubernetes { .. } => KUBECTL,
+        }
+    }
+
+    /// Script running a command on the target
+    ///
+    /// With a `file`, the script first writes its stdin to that path and removes the file
+    /// once the command exits, keeping its status.
+    pub fn script(&self, argv: &[String], env: &[(String, String)], file: Option<&str>) -> String {
+        let mut script = String::new();
+        if !env.is_empty() {
+            let exports: Vec<String> = env
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, shell_quote(value)))
+                .collect();
+            script.push_str(&format!("export {}; ", exports.join(" ")));
+        }
+
+        let command: Vec<String> = argv.iter().map(|arg| shell_quote(arg)).collect();
+        let command = command.join(" ");
+        match file {
+            Some(file) => {
+                let file = shell_quote(file);
+                script.push_str(&format!(
+                    "umask 077 && cat > {file} || exit; {command}; status=$?; rm -f {file}; exit $status"
+                ));
+            }
+            None => script.push_str(&command),
+        }
+        script
+    }
+
+    /// Arguments to [`Remote::program`] running a script with `sh -c` on the target
+    ///
+    /// The remote login shell of an SSH host may not be POSIX, so it is only given a quoted
+    /// `sh -c` call.
+    pub fn args(&self, script: &str) -> Vec<String> {
+        match self {
+            Remote::Ssh { host } => vec![
+                "-T".to_string(),
+                "--".to_string(),
+                host.clone(),
+                format!("sh -c {}", shell_quote(script)),
+            ],
+            Remote::Kubernetes {
+                resource,
+                namespace,
+                context,
+            } => {
+                let mut args = Vec::new();
+                if let Some(context) = context {
+                    args.extend(["--context".to_string(), context.clone()]);
+                }
+                if let Some(namespace) = namespace {
+                    args.extend(["--namespace".to_string(), namespace.clone()]);
+                }
+                args.extend(["exec", "-i", resource, "--", "sh", "-c", script].map(String::from));
+                args
+            }
+        }
+    }
+}
+
+impl fmt::Display for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Remote::Ssh { host } => write!(f, "{}", host),
+            Remote::Kubernetes {
+                resource,
+                namespace,
+                ..
+            } => {
+                write!(f, "{}{}", KUBERNETES_PREFIX, resource)?;
+                match namespace {
+                    Some(namespace) => write!(f, " in {}", namespace),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::RuntimeConfig;
+
+    fn ssh(host: &str) -> Remote {
+        Remote::Ssh {
+            host: host.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "bash".to_string(),
+            RuntimeConfig::Detailed {
+                command: "bash".to_string(),
+                execution_mode: Default::default(),
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                shell: false,
+                sandbox: None,
+                remote: Some("runtime-host".to_string()),
+                pty: false,
+            },
+        );
+        let section = Section {
+            metadata: BTreeMap::from([("remote".to_string(), "task-host".to_string())]),
+            ..Default::default()
+        };
+
+        let resolve = |lang, section, target| Remote::resolve(&config, lang, section, target);
+        assert_eq!(resolve("bash", None, None), Some(ssh("runtime-host")));
+        assert_eq!(resolve("python", None, None), None);
+        assert_eq!(
+            resolve("bash", Some(&section), None),
+            Some(ssh("task-host"))
+        );
+        assert_eq!(
+            resolve("python", Some(&section), Some("ssh:cli-host")),
+            Some(ssh("cli-host"))
+        );
+    }
+
+    #[test]
+    fn test_kubernetes_target() {
+        let kubernetes = KubernetesConfig {
+            namespace: Some("prod".to_string()),
+            context: None,
+        };
+        let remote = Remote::parse("k8s:deployment/web", &kubernetes);
+
+        assert_eq!(remote.program(), KUBECTL);
+        assert_eq!(remote.to_string(), "k8s:deployment/web in prod");
+        assert_eq!(
+            remote.args("echo hi"),
+            [
+                "--namespace",
+                "prod",
+                "exec",
+                "-i",
+                "deployment/web",
+                "--",
+                "sh",
+                "-c",
+                "echo hi"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_script() {
+        let remote = ssh("admin@db1");
+        let argv = vec!["python3".to_string(), "/tmp/mx_1-main.py".to_string()];
+        let env = vec![("MX_ARG_0".to_string(), "it's".to_string())];
+
+        assert_eq!(
+            remote.script(&argv, &env, Some("/tmp/mx_1-main.py")),
+            "export MX_ARG_0='it'\\''s'; umask 077 && cat > '/tmp/mx_1-main.py' || exit; \
+             'python3' '/tmp/mx_1-main.py'; status=$?; rm -f '/tmp/mx_1-main.py'; exit $status"
+        );
+        assert_eq!(
+            remote.args(&remote.script(&argv[..1], &[], None)),
+            ["-T", "--", "admin@db1", "sh -c ''\\''python3'\\'''"]
+        );
+    }
+}
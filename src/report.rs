@@ -0,0 +1,126 @@
+//! Execution reports for mx task runs
+
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::error::{Error, Result};
+use crate::warning::Warning;
+
+/// Stable identifier of a code block within a run, e.g. `Build#2`
+///
+/// The index counts every code block of the section in document order, including blocks
+/// that are skipped, so the same block always gets the same ID in events, errors and reports.
+pub fn block_id(task: &str, index: usize) -> String {
+    format!("{}#{}", task, index)
+}
+
+/// Timing information for a single executed code block
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockReport {
+    /// Stable block identifier (see [`block_id`])
+    pub id: String,
+    /// Index of the code block within its section
+    pub index: usize,
+    /// Language of the code block
+    pub lang: String,
+    /// Wall-clock time spent executing the block
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    /// Why the block was skipped instead of executed, if it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+/// Timing summary of a task execution
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExecutionReport {
+    /// Title of the executed section
+    pub task: String,
+    /// Executed code blocks in document order
+    pub blocks: Vec<BlockReport>,
+    /// Total wall-clock time of the task
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    /// Why the whole task was skipped, if it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    /// Warnings about the task, e.g. code blocks without a language
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+impl ExecutionReport {
+    /// Create an empty report for the given task
+    pub fn new(task: impl Into<String>) -> Self {
+        Self {
+            task: task.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Return the slowest executed block, if any
+    pub fn slowest_block(&self) -> Option<&BlockReport> {
+        self.blocks.iter().max_by_key(|block| block.duration)
+    }
+
+    /// Serialize the report as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Execution(format!("Failed to serialize report: {}", e)))
+    }
+}
+
+fn serialize_millis<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slowest_block() {
+        let mut report = ExecutionReport::new("Build");
+        report.blocks.push(BlockReport {
+            id: block_id("Build", 0),
+            index: 0,
+            lang: "bash".to_string(),
+            duration: Duration::from_millis(10),
+            skipped: None,
+        });
+        report.blocks.push(BlockReport {
+            id: block_id("Build", 1),
+            index: 1,
+            lang: "python".to_string(),
+            duration: Duration::from_millis(30),
+            skipped: None,
+        });
+
+        assert_eq!(report.slowest_block().map(|b| b.index), Some(1));
+        assert!(ExecutionReport::new("Empty").slowest_block().is_none());
+    }
+
+    #[test]
+    fn test_report_json() {
+        let mut report = ExecutionReport::new("Build");
+        report.duration = Duration::from_millis(1500);
+        report.blocks.push(BlockReport {
+            id: block_id("Build", 0),
+            index: 0,
+            lang: "bash".to_string(),
+            duration: Duration::from_millis(1500),
+            skipped: None,
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["task"], "Build");
+        assert_eq!(json["duration_ms"], 1500.0);
+        assert_eq!(json["blocks"][0]["id"], "Build#0");
+        assert_eq!(json["blocks"][0]["lang"], "bash");
+        assert!(json["blocks"][0].get("skipped").is_none());
+    }
+}
@@ -0,0 +1,513 @@
+//! Reports of task runs for audits and incident reviews
+//!
+//! `mx run --report run.md` (or `run.html`) records every task of the run as it happens:
+//! each code block as it ran, its output, exit status, duration and timestamps, and whether
+//! the task succeeded. The report is written after the run, including when it fails, as an
+//! auditable record of a runbook execution. Output is recorded as the terminal shows it,
+//! with secrets masked and escape sequences removed.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::events::{BlockOutcome, RunnerObserver};
+use crate::logs::rfc3339_timestamp;
+use crate::report::{ExecutionReport, block_id};
+use crate::runner::{CodeBlock, OutputStream, Section};
+use crate::source::code_fence;
+
+/// Format of a run report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    /// A standalone HTML page
+    Html,
+}
+
+impl ReportFormat {
+    /// The format of a report file, from its extension; Markdown unless it is `.html`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm") => {
+                ReportFormat::Html
+            }
+            _ => ReportFormat::Markdown,
+        }
+    }
+}
+
+/// A code block of a recorded task
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRecord {
+    /// Block ID, e.g. `Deploy#1`
+    pub id: String,
+    pub lang: String,
+    /// Code as it ran, after any edit made while stepping through the task
+    pub code: String,
+    /// When the block started, unless it was skipped
+    pub started: Option<SystemTime>,
+    pub finished: Option<SystemTime>,
+    pub duration: Duration,
+    pub exit_code: Option<i32>,
+    /// Whether the block succeeded, or `None` if it never finished
+    pub success: Option<bool>,
+    /// Why the block was skipped, if it was
+    pub skipped: Option<String>,
+    /// Stdout and stderr, interleaved as they were printed
+    pub output: Vec<u8>,
+}
+
+/// A task of a recorded run
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskRecord {
+    pub task: String,
+    pub started: SystemTime,
+    pub finished: Option<SystemTime>,
+    pub duration: Duration,
+    /// Whether the task succeeded, or `None` if it never finished
+    pub success: Option<bool>,
+    /// Why the whole task was skipped, if it was
+    pub skipped: Option<String>,
+    pub blocks: Vec<BlockRecord>,
+}
+
+/// Observer recording the tasks of a run for a report
+#[derive(Debug)]
+pub struct RunRecorder {
+    started: SystemTime,
+    tasks: Mutex<Vec<TaskRecord>>,
+}
+
+impl Default for RunRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunRecorder {
+    pub fn new() -> Self {
+        Self {
+            started: SystemTime::now(),
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The tasks recorded so far, in the order they ran
+    pub fn tasks(&self) -> Vec<TaskRecord> {
+        self.tasks.lock().unwrap().clone()
+    }
+
+    /// Apply a change to a block of the running task, adding it if it is not recorded yet
+    fn update_block(&self, section: &Section, index: usize, update: impl FnOnce(&mut BlockRecord)) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(task) = tasks.last_mut() else {
+            return;
+        };
+        let id = block_id(&section.title, index);
+        let position = match task.blocks.iter().position(|block| block.id == id) {
+            Some(position) => position,
+            None => {
+                let code = section.codes.get(index);
+                task.blocks.push(BlockRecord {
+                    id,
+                    lang: code.map(|code| code.lang.clone()).unwrap_or_default(),
+                    code: code.map(|code| code.code.clone()).unwrap_or_default(),
+                    started: None,
+                    finished: None,
+                    duration: Duration::ZERO,
+                    exit_code: None,
+                    success: None,
+                    skipped: None,
+                    output: Vec::new(),
+                });
+                task.blocks.len() - 1
+            }
+        };
+        update(&mut task.blocks[position]);
+    }
+
+    /// The report of the run, started by `command`
+    pub fn render(&self, format: ReportFormat, command: &str) -> String {
+        let run = RunSummary::new(self, command);
+        match format {
+            ReportFormat::Markdown => markdown(&run),
+            ReportFormat::Html => html(&run),
+        }
+    }
+}
+
+impl RunnerObserver for RunRecorder {
+    fn on_section_start(&self, section: &Section) {
+        self.tasks.lock().unwrap().push(TaskRecord {
+            task: section.title.clone(),
+            started: SystemTime::now(),
+            finished: None,
+            duration: Duration::ZERO,
+            success: None,
+            skipped: None,
+            blocks: Vec::new(),
+        });
+    }
+
+    fn on_block_start(&self, section: &Section, index: usize, block: &CodeBlock) {
+        self.update_block(section, index, |record| {
+            record.code = block.code.clone();
+            record.started = Some(SystemTime::now());
+        });
+    }
+
+    fn on_block_output(&self, id: &str, _stream: OutputStream, data: &[u8]) {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(block) = tasks
+            .last_mut()
+            .and_then(|task| task.blocks.iter_mut().rfind(|block| block.id == id))
+        {
+            block.output.extend_from_slice(data);
+        }
+    }
+
+    fn on_block_complete(&self, section: &Section, outcome: &BlockOutcome) {
+        self.update_block(section, outcome.report.index, |record| {
+            if record.started.is_some() {
+                record.finished = Some(SystemTime::now());
+            }
+            record.duration = outcome.report.duration;
+            record.exit_code = outcome.exit_code;
+            record.success = Some(outcome.success);
+            record.skipped = outcome.report.skipped.clone();
+        });
+    }
+
+    fn on_section_complete(&self, report: &ExecutionReport, success: bool) {
+        if let Some(task) = self.tasks.lock().unwrap().last_mut() {
+            task.finished = Some(SystemTime::now());
+            task.duration = report.duration;
+            task.success = Some(success);
+            task.skipped = report.skipped.clone();
+        }
+    }
+}
+
+/// What a report shows, with its text ready to be written in either format
+struct RunSummary {
+    command: String,
+    started: String,
+    finished: String,
+    result: &'static str,
+    tasks: Vec<TaskRecord>,
+}
+
+impl RunSummary {
+    fn new(recorder: &RunRecorder, command: &str) -> Self {
+        let tasks = recorder.tasks();
+        let failed = tasks.iter().any(|task| task.success != Some(true));
+        Self {
+            command: command.to_string(),
+            started: rfc3339_timestamp(recorder.started),
+            finished: rfc3339_timestamp(SystemTime::now()),
+            result: if failed { "failed" } else { "succeeded" },
+            tasks,
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
+
+fn task_status(task: &TaskRecord) -> String {
+    match (&task.skipped, task.success) {
+        (Some(reason), _) => format!("skipped: {}", reason),
+        (None, Some(true)) => format!("succeeded in {}", format_duration(task.duration)),
+        (None, Some(false)) => format!("failed after {}", format_duration(task.duration)),
+        (None, None) => "did not finish".to_string(),
+    }
+}
+
+fn block_status(block: &BlockRecord) -> String {
+    let status = match (&block.skipped, block.success, block.exit_code) {
+        (Some(reason), _, _) => return format!("skipped: {}", reason),
+        (None, None, _) => return "did not finish".to_string(),
+        (None, Some(true), _) => "succeeded".to_string(),
+        (None, Some(false), Some(code)) => format!("failed with exit code {}", code),
+        (None, Some(false), None) => "failed".to_string(),
+    };
+    format!("{} in {}", status, format_duration(block.duration))
+}
+
+/// Start and finish times of a task or block
+fn timestamps(started: Option<SystemTime>, finished: Option<SystemTime>) -> String {
+    match (started, finished) {
+        (Some(started), Some(finished)) => format!(
+            "started {}, finished {}",
+            rfc3339_timestamp(started),
+            rfc3339_timestamp(finished)
+        ),
+        (Some(started), None) => format!("started {}", rfc3339_timestamp(started)),
+        _ => String::new(),
+    }
+}
+
+/// Output as text, without the escape sequences and control characters of a terminal
+fn output_text(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let mut cleaned = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // CSI sequences such as colors end at a letter
+            '\x1b' if chars.next_if_eq(&'[').is_some() => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            '\n' | '\t' => cleaned.push(c),
+            c if c.is_control() => {}
+            c => cleaned.push(c),
+        }
+    }
+    cleaned
+}
+
+fn markdown(run: &RunSummary) -> String {
+    let mut output = String::from("# Run report\n\n");
+    let _ = writeln!(output, "| | |\n|-|-|");
+    let _ = writeln!(
+        output,
+        "| Command | `{}` |",
+        run.command.replace('|', "\\|")
+    );
+    let _ = writeln!(output, "| Started | {} |", run.started);
+    let _ = writeln!(output, "| Finished | {} |", run.finished);
+    let _ = writeln!(output, "| Result | {} |", run.result);
+
+    for task in &run.tasks {
+        let _ = writeln!(output, "\n## {}\n", task.task);
+        let _ = writeln!(
+            output,
+            "**{}** ({})",
+            task_status(task),
+            timestamps(Some(task.started), task.finished)
+        );
+
+        for block in &task.blocks {
+            let _ = writeln!(output, "\n### {} ({})\n", block.id, block.lang);
+            let times = timestamps(block.started, block.finished);
+            if times.is_empty() {
+                let _ = writeln!(output, "**{}**", block_status(block));
+            } else {
+                let _ = writeln!(output, "**{}** ({})", block_status(block), times);
+            }
+            let code = block.code.trim_end();
+            let fence = code_fence(code);
+            let _ = writeln!(output, "\n{}{}\n{}\n{}", fence, block.lang, code, fence);
+
+            let text = output_text(&block.output);
+            let text = text.trim_end();
+            if !text.is_empty() {
+                let fence = code_fence(text);
+                let _ = writeln!(output, "\nOutput:\n\n{}text\n{}\n{}", fence, text, fence);
+            }
+        }
+    }
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:60rem;margin:2rem auto;\
+padding:0 1rem;color:#222}table{border-collapse:collapse}td,th{text-align:left;\
+padding:.2rem .8rem .2rem 0}pre{background:#f5f5f5;padding:.8rem;overflow-x:auto}\
+.output{background:#1e1e1e;color:#ddd}.succeeded{color:#2a7d2a}.failed{color:#b22}\
+.skipped,.unfinished{color:#888}";
+
+/// CSS class of a status, from its first word
+fn status_class(status: &str) -> &'static str {
+    match status.split_whitespace().next() {
+        Some("succeeded") => "succeeded",
+        Some("failed") => "failed",
+        Some("skipped:") => "skipped",
+        _ => "unfinished",
+    }
+}
+
+fn html(run: &RunSummary) -> String {
+    let mut output = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(
+        output,
+        "<title>Run report: {}</title>",
+        escape_html(&run.command)
+    );
+    let _ = writeln!(output, "<style>{}</style>\n</head>\n<body>", HTML_STYLE);
+    let _ = writeln!(output, "<h1>Run report</h1>\n<table>");
+    let rows = [
+        (
+            "Command",
+            format!("<code>{}</code>", escape_html(&run.command)),
+        ),
+        ("Started", run.started.clone()),
+        ("Finished", run.finished.clone()),
+        (
+            "Result",
+            format!("<span class=\"{}\">{}</span>", run.result, run.result),
+        ),
+    ];
+    for (name, value) in rows {
+        let _ = writeln!(output, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+    }
+    output.push_str("</table>\n");
+
+    for task in &run.tasks {
+        let status = task_status(task);
+        let _ = writeln!(output, "<section>\n<h2>{}</h2>", escape_html(&task.task));
+        let _ = writeln!(
+            output,
+            "<p><strong class=\"{}\">{}</strong> ({})</p>",
+            status_class(&status),
+            escape_html(&status),
+            timestamps(Some(task.started), task.finished)
+        );
+
+        for block in &task.blocks {
+            let status = block_status(block);
+            let _ = writeln!(
+                output,
+                "<h3>{} ({})</h3>",
+                escape_html(&block.id),
+                escape_html(&block.lang)
+            );
+            let _ = write!(
+                output,
+                "<p><strong class=\"{}\">{}</strong>",
+                status_class(&status),
+                escape_html(&status)
+            );
+            let times = timestamps(block.started, block.finished);
+            if !times.is_empty() {
+                let _ = write!(output, " ({})", times);
+            }
+            output.push_str("</p>\n");
+            let _ = writeln!(
+                output,
+                "<pre><code>{}</code></pre>",
+                escape_html(block.code.trim_end())
+            );
+
+            let text = output_text(&block.output);
+            let text = text.trim_end();
+            if !text.is_empty() {
+                let _ = writeln!(output, "<pre class=\"output\">{}</pre>", escape_html(text));
+            }
+        }
+        output.push_str("</section>\n");
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::BlockReport;
+
+    fn record_run() -> RunRecorder {
+        let section = Section {
+            title: "Deploy".to_string(),
+            codes: vec![
+                CodeBlock {
+                    lang: "bash".to_string(),
+                    code: "echo '<ok>'\n".to_string(),
+                    ..Default::default()
+                },
+                CodeBlock {
+                    lang: "bash".to_string(),
+                    code: "exit 3\n".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let outcome = |index: usize, exit_code, success, skipped: Option<&str>| BlockOutcome {
+            report: BlockReport {
+                id: block_id("Deploy", index),
+                index,
+                lang: "bash".to_string(),
+                duration: Duration::from_millis(5),
+                skipped: skipped.map(str::to_string),
+            },
+            exit_code,
+            success,
+        };
+
+        let recorder = RunRecorder::new();
+        recorder.on_section_start(&section);
+        recorder.on_block_start(&section, 0, &section.codes[0]);
+        recorder.on_block_output("Deploy#0", OutputStream::Stdout, b"\x1b[32m<ok>\x1b[0m\n");
+        recorder.on_block_complete(&section, &outcome(0, Some(0), true, None));
+        recorder.on_block_start(&section, 1, &section.codes[1]);
+        recorder.on_block_complete(&section, &outcome(1, Some(3), false, None));
+        recorder.on_section_complete(&ExecutionReport::new("Deploy"), false);
+        recorder
+    }
+
+    #[test]
+    fn test_record_run() {
+        let tasks = record_run().tasks();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].success, Some(false));
+        assert_eq!(tasks[0].blocks.len(), 2);
+        assert_eq!(output_text(&tasks[0].blocks[0].output), "<ok>\n");
+        assert_eq!(tasks[0].blocks[1].exit_code, Some(3));
+        assert!(tasks[0].blocks[1].finished.is_some());
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let report = record_run().render(ReportFormat::Markdown, "mx run Deploy");
+        assert!(report.starts_with("# Run report\n\n| | |\n|-|-|\n| Command | `mx run Deploy` |"));
+        assert!(report.contains("| Result | failed |"));
+        assert!(report.contains("\n## Deploy\n\n**failed after 0ms** (started "));
+        assert!(report.contains("\n### Deploy#0 (bash)\n\n**succeeded in 5ms** (started "));
+        assert!(report.contains("```bash\necho '<ok>'\n```\n\nOutput:\n\n```text\n<ok>\n```\n"));
+        assert!(report.contains("**failed with exit code 3 in 5ms**"));
+    }
+
+    #[test]
+    fn test_render_html() {
+        let report = record_run().render(ReportFormat::Html, "mx run Deploy");
+        assert!(report.starts_with("<!DOCTYPE html>"));
+        assert!(report.contains("<pre><code>echo '&lt;ok&gt;'</code></pre>"));
+        assert!(report.contains("<pre class=\"output\">&lt;ok&gt;</pre>"));
+        assert!(
+            report.contains("<strong class=\"failed\">failed with exit code 3 in 5ms</strong>")
+        );
+    }
+
+    #[test]
+    fn test_report_format_from_path() {
+        assert_eq!(
+            ReportFormat::from_path(Path::new("run.HTML")),
+            ReportFormat::Html
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("run.md")),
+            ReportFormat::Markdown
+        );
+        assert_eq!(
+            ReportFormat::from_path(Path::new("report")),
+            ReportFormat::Markdown
+        );
+    }
+}
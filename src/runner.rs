@@ -1,24 +1,119 @@
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::hash::{Hash, Hasher};
+use std::io::{IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 
-use mq_lang::{Engine, Ident, RuntimeValue, parse_markdown_input};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
 
+use crate::command::{self, Substitutions};
+use crate::condition;
 use crate::config::{Config, ExecutionMode};
+use crate::devshell::DevShell;
 use crate::error::{Error, Result};
-
-const SECTIONS_QUERY: &str = include_str!("../sections.mq");
+use crate::events::{BlockOutcome, EventHandler, EventObserver, RunnerObserver};
+use crate::extract::{self, SectionExtractor};
+use crate::files::{TaskRef, canonical};
+use crate::formats::{self, TaskSource};
+use crate::limits;
+use crate::lock::TaskLock;
+use crate::logs::{BlockLog, LogDir};
+use crate::namespace;
+use crate::process::{self, ChildGroup, KillHandle, ProcessGroups};
+use crate::progress::{ProgressFrame, ProgressReporter};
+use crate::pty::Pty;
+use crate::remote::Remote;
+use crate::report::{BlockReport, ExecutionReport, block_id};
+use crate::sandbox::Policy;
+use crate::secrets::{MaskedStream, Secrets};
+use crate::shell;
+use crate::source::{
+    is_mdx, locate_sections, normalize_mdx, normalize_title, parse_attributes, parse_heading,
+    slugify, split_at_heading_levels, split_list,
+};
+use crate::toolchain::ToolEnv;
+use crate::validate::{self, DependencyTarget, Issue, edit_distance};
+use crate::warning;
+
+/// Maximum number of documents whose extracted sections are kept in memory
+const SECTION_CACHE_CAPACITY: usize = 64;
+
+/// Reason code blocks are reported as skipped in a dry run
+pub(crate) const DRY_RUN: &str = "dry run";
+
+/// A file whose sections were extracted, and how to tell it has not changed since
+struct CachedFile {
+    modified: SystemTime,
+    len: u64,
+    /// Key of its sections in the section cache
+    hash: u64,
+}
 
 /// Represents a code block in a section
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct CodeBlock {
     /// Language of the code block
     pub lang: String,
     /// Code content
     pub code: String,
+    /// 1-based line number of the opening code fence, if it could be located
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// Byte range of the whole fenced block in the Markdown source
+    #[serde(default)]
+    pub span: Option<Range<usize>>,
+    /// Task file the block was read from; `None` for Markdown that was not read from a file
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Fence info string after the language (e.g. `tags=ci name=build`)
+    #[serde(default)]
+    pub meta: Option<String>,
+    /// Stdout the block is expected to print, from an `output` block right after it
+    #[serde(default)]
+    pub expected_output: Option<String>,
+}
+
+impl CodeBlock {
+    /// Get the value of a fence attribute; bare attributes like `skip` have an empty value
+    pub fn attribute(&self, key: &str) -> Option<String> {
+        parse_attributes(self.meta.as_deref()?)
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Name given with a `name=` fence attribute, to select the block with `Task#name`
+    pub fn name(&self) -> Option<String> {
+        self.attribute("name").filter(|name| !name.is_empty())
+    }
+
+    /// Title from a `title="..."` fence attribute, as used by Docusaurus
+    pub fn title(&self) -> Option<String> {
+        self.attribute("title").filter(|title| !title.is_empty())
+    }
+
+    /// Tags declared with a `tags=a,b` fence attribute
+    pub fn tags(&self) -> Vec<String> {
+        self.attribute("tags")
+            .map(|tags| split_list(&tags))
+            .unwrap_or_default()
+    }
+
+    /// Whether the block reads from the terminal, set with an `interactive` fence attribute or
+    /// for the whole task, see [`Section::is_interactive`]
+    pub fn is_interactive(&self, section: Option<&Section>) -> bool {
+        self.attribute("interactive").map_or_else(
+            || section.is_some_and(Section::is_interactive),
+            |value| value != "false",
+        )
+    }
 }
 
 /// Represents a section with its code blocks
@@ -28,312 +123,811 @@ pub struct Section {
     pub title: String,
     /// Heading level
     pub level: u8,
+    /// Title of the enclosing section when a range of heading levels is extracted
+    #[serde(default)]
+    pub parent: Option<String>,
     /// Code blocks in this section
     pub codes: Vec<CodeBlock>,
     /// Optional description extracted from the section content
     pub description: Option<String>,
+    /// 1-based line number of the section heading, if it could be located
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// Byte range of the heading line in the Markdown source
+    #[serde(default)]
+    pub span: Option<Range<usize>>,
+    /// Task file the section was read from; `None` for Markdown that was not read from a file
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Tags from a heading suffix (`## Deploy [ci,danger]`) or `tags=` fence attributes
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Attributes from a heading suffix in braces (e.g. `{os=linux}`)
+    #[serde(default)]
+    pub meta: Option<String>,
+    /// Entries of a metadata table or definition list right under the heading, by lowercased
+    /// name (e.g. `| depends | build |`)
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Section {
+    /// Get the value of a heading attribute; bare attributes like `deprecated` have an empty value
+    pub fn attribute(&self, key: &str) -> Option<String> {
+        parse_attributes(self.meta.as_deref()?)
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    /// GitHub-style anchor of the title, e.g. `build-and-test` for `## Build and test`
+    ///
+    /// A custom heading ID such as `## Build {#build-step}` takes precedence.
+    pub fn anchor(&self) -> String {
+        self.meta
+            .as_deref()
+            .and_then(|meta| {
+                parse_attributes(meta)
+                    .into_iter()
+                    .find_map(|(key, _)| key.strip_prefix('#').map(str::to_string))
+            })
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| slugify(&self.title))
+    }
+
+    /// Check whether the section has the given tag
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Index of a code block, given by its index (`2`) or its `name=` attribute
+    pub fn find_block(&self, block: &str) -> Option<usize> {
+        match block.parse::<usize>() {
+            Ok(index) => (index < self.codes.len()).then_some(index),
+            Err(_) => self
+                .codes
+                .iter()
+                .position(|code| code.name().as_deref() == Some(block)),
+        }
+    }
+
+    /// Tasks this task depends on, from a `depends` metadata entry
+    pub fn dependencies(&self) -> Vec<String> {
+        self.metadata
+            .get("depends")
+            .map(|depends| split_list(depends))
+            .unwrap_or_default()
+    }
+
+    /// Parameters the task requires, from a `params` metadata entry, e.g. `VERSION, REGION`
+    pub fn params(&self) -> Vec<String> {
+        self.metadata
+            .get("params")
+            .map(|params| split_list(params))
+            .unwrap_or_default()
+    }
+
+    /// Glob patterns of the files the task works on, from the `sources` metadata entry,
+    /// relative to the file declaring it
+    pub fn sources(&self) -> Vec<String> {
+        self.metadata
+            .get("sources")
+            .map(|sources| split_list(sources))
+            .unwrap_or_default()
+    }
+
+    /// Whether the task must not run twice at once, set with a `{lock}` heading attribute or a
+    /// `lock` metadata entry
+    pub fn requires_lock(&self) -> bool {
+        self.attribute("lock")
+            .or_else(|| self.metadata.get("lock").cloned())
+            .is_some_and(|value| value != "false")
+    }
+
+    /// Whether the task's blocks read from the terminal, set with an `{interactive}` heading
+    /// attribute or an `interactive` metadata entry
+    pub fn is_interactive(&self) -> bool {
+        self.attribute("interactive")
+            .or_else(|| self.metadata.get("interactive").cloned())
+            .is_some_and(|value| value != "false")
+    }
+
+    /// Languages of the section's code blocks in order of first appearance, without blocks
+    /// that have no language
+    pub fn languages(&self) -> Vec<&str> {
+        let mut languages = Vec::new();
+        for block in &self.codes {
+            if !block.lang.is_empty() && !languages.contains(&block.lang.as_str()) {
+                languages.push(block.lang.as_str());
+            }
+        }
+        languages
+    }
+
+    /// Shift source positions by a byte offset and line count
+    fn shift_positions(&mut self, offset: usize, lines: usize) {
+        fn shift(line: &mut Option<usize>, span: &mut Option<Range<usize>>, o: usize, l: usize) {
+            if let Some(line) = line {
+                *line += l;
+            }
+            if let Some(span) = span {
+                *span = span.start + o..span.end + o;
+            }
+        }
+
+        shift(&mut self.line, &mut self.span, offset, lines);
+        for code in &mut self.codes {
+            shift(&mut code.line, &mut code.span, offset, lines);
+        }
+    }
 }
 
+/// A part of a Markdown document that could not be extracted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractionDiagnostic {
+    /// 1-based line number of the heading that starts the skipped chunk
+    pub line: usize,
+    /// Heading text of the skipped chunk
+    pub heading: String,
+    /// Error reported while extracting the chunk
+    pub message: String,
+}
+
+/// Result of a resilient extraction
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Extraction {
+    /// Sections that were extracted successfully, in document order
+    pub sections: Vec<Section>,
+    /// Chunks that were skipped because they could not be extracted
+    pub diagnostics: Vec<ExtractionDiagnostic>,
+}
+
+/// Output stream of a code block process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives output of code block processes as it is produced
+///
+/// The handler is called from reader threads, one per stream, with raw chunks of bytes.
+pub type OutputHandler = Arc<dyn Fn(OutputStream, &[u8]) + Send + Sync>;
+
+/// What to do with a code block, as decided by a [`StepHandler`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepAction {
+    /// Run the block as written
+    Run,
+    /// Run this code instead of the block's
+    RunEdited(String),
+    /// Skip the block, reporting it as skipped
+    Skip,
+    /// Stop the task, failing with [`Error::Cancelled`]
+    Abort,
+}
+
+/// Decides whether and how each code block runs, just before it would, e.g. by asking the user
+///
+/// Called with the section, the index of the block and the block itself.
+pub type StepHandler = Arc<dyn Fn(&Section, usize, &CodeBlock) -> StepAction + Send + Sync>;
+
 /// Task runner that executes code blocks in Markdown sections
+///
+/// A runner is meant to be long-lived: its section extractor, such as the mq engine, is set
+/// up once, when a document is first extracted, and sections extracted from a document are
+/// cached by content, so repeated runs (e.g. from an editor integration) only pay for
+/// documents that changed. Files whose modification time and size are unchanged are not even
+/// read again.
 pub struct Runner {
-    config: Config,
-    engine: Engine,
+    pub(crate) config: Config,
+    extractor: Mutex<Box<dyn SectionExtractor>>,
+    /// Readers of task files in other formats than Markdown, by file extension
+    task_sources: Vec<Arc<dyn TaskSource>>,
+    section_cache: Mutex<HashMap<u64, Vec<Section>>>,
+    file_cache: Mutex<HashMap<PathBuf, CachedFile>>,
+    pub(crate) progress: Option<Arc<ProgressReporter>>,
+    pub(crate) output: Option<OutputHandler>,
+    pub(crate) observers: Vec<Arc<dyn RunnerObserver>>,
+    pub(crate) forward_stdin: bool,
+    pub(crate) log_dir: Option<LogDir>,
+    pub(crate) running: Arc<ProcessGroups>,
+    pub(crate) root: Option<PathBuf>,
+    pub(crate) lock_wait: bool,
+    pub(crate) remote: Option<String>,
+    pub(crate) step: Option<StepHandler>,
+    detached: bool,
+    pub(crate) secrets: Arc<Secrets>,
+    /// Environment variables set for every code block, before those of its runtime
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) dry_run: bool,
 }
 
 impl Runner {
     /// Create a new Runner with the given configuration
     pub fn new(config: Config) -> Self {
-        let mut engine: Engine = Engine::default();
-        engine.load_builtin_module();
+        let extractor = extract::from_config(&config);
+        let secrets = Arc::new(Secrets::from_env(&config));
+
+        Self {
+            log_dir: config.log_dir.as_ref().map(LogDir::new),
+            config,
+            extractor: Mutex::new(extractor),
+            task_sources: formats::builtin(),
+            section_cache: Mutex::default(),
+            file_cache: Mutex::default(),
+            progress: None,
+            output: None,
+            observers: Vec::new(),
+            forward_stdin: false,
+            running: Arc::default(),
+            root: None,
+            lock_wait: false,
+            remote: None,
+            step: None,
+            detached: false,
+            secrets,
+            env: Vec::new(),
+            dry_run: false,
+        }
+    }
 
-        Self { config, engine }
+    /// Forward this process' stdin to code blocks, e.g. when data is piped into mx
+    ///
+    /// Runtimes in stdin mode receive their code as a file instead, so that their stdin is
+    /// free for the data.
+    pub fn with_stdin_forwarding(mut self, forward: bool) -> Self {
+        self.forward_stdin = forward;
+        self
     }
 
-    /// Create a new Runner with default configuration
-    pub fn with_default_config() -> Self {
-        Self::new(Config::default())
+    /// Send code block output to a handler instead of inheriting the terminal
+    pub fn with_output_handler(mut self, handler: OutputHandler) -> Self {
+        self.output = Some(handler);
+        self
     }
 
-    /// Load and parse a Markdown file
-    pub fn load_markdown<P: AsRef<Path>>(&self, path: P) -> Result<String> {
-        fs::read_to_string(path).map_err(Error::Io)
+    /// Extract sections with another extractor than the configured one
+    pub fn with_extractor(mut self, extractor: impl SectionExtractor + 'static) -> Self {
+        self.extractor = Mutex::new(Box::new(extractor));
+        self.section_cache = Mutex::default();
+        self
     }
 
-    /// Extract sections from Markdown content
-    pub fn extract_sections(&mut self, markdown: &str) -> Result<Vec<Section>> {
-        let input = parse_markdown_input(markdown)
-            .map_err(|e| Error::Markdown(format!("Failed to parse markdown: {}", e)))?;
+    /// Read task files with the extensions of a source with it, before the built-in formats
+    pub fn with_task_source(mut self, source: impl TaskSource + 'static) -> Self {
+        self.task_sources.insert(0, Arc::new(source));
+        self
+    }
 
-        let query = format!(
-            "{}\n | nodes | sections_with_code({})",
-            SECTIONS_QUERY, self.config.heading_level
-        );
+    /// Run code blocks in a project root directory, which is also passed in `MX_ROOT`
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
 
-        let result = self
-            .engine
-            .eval(&query, input.into_iter())
-            .map_err(|e| Error::Query(format!("Failed to execute query: {}", e)))?;
+    /// Keep code blocks away from the terminal: they do not take it over and read an empty
+    /// stdin, for when mx draws on the terminal itself
+    pub fn with_detached_terminal(mut self, detached: bool) -> Self {
+        self.detached = detached;
+        self
+    }
 
-        let sections = self.parse_sections(result)?;
+    /// Wait for tasks marked `{lock}` that are running elsewhere instead of failing with
+    /// [`Error::Locked`]
+    pub fn with_lock_wait(mut self, wait: bool) -> Self {
+        self.lock_wait = wait;
+        self
+    }
 
-        Ok(sections)
+    /// Run every code block on an SSH host or a `k8s:` target, overriding the `remote` of
+    /// tasks and runtimes
+    ///
+    /// See [`crate::remote`].
+    pub fn with_remote(mut self, target: impl Into<String>) -> Self {
+        self.remote = Some(target.into());
+        self
     }
 
-    fn parse_sections(&self, result: mq_lang::RuntimeValues) -> Result<Vec<Section>> {
-        let mut sections = Vec::new();
+    /// Set an environment variable for every code block; runtime `env` entries take precedence
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
 
-        for value in result.into_iter() {
-            if let RuntimeValue::Dict(dict) = value {
-                let section = self.parse_section(&dict)?;
-                sections.push(section);
-            }
-        }
+    /// Go through tasks without running their code blocks, which are reported as skipped
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
 
-        Ok(sections)
+    /// Ask a handler before each code block of a section whether and how to run it
+    ///
+    /// Blocks skipped by their guards are not asked about.
+    pub fn with_step_handler(mut self, handler: StepHandler) -> Self {
+        self.step = Some(handler);
+        self
     }
 
-    fn parse_section(&self, dict: &BTreeMap<Ident, RuntimeValue>) -> Result<Section> {
-        let title = dict
-            .get(&Ident::from("title"))
-            .and_then(|v| match v {
-                RuntimeValue::String(s) => Some(s.to_string()),
-                _ => None,
-            })
-            .unwrap_or_default();
+    /// Project root code blocks run in, if set with [`Runner::with_root`]
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
 
-        let level = dict
-            .get(&Ident::from("level"))
-            .and_then(|v| match v {
-                RuntimeValue::Number(n) => Some(n.value() as u8),
-                _ => None,
-            })
-            .unwrap_or(self.config.heading_level);
+    /// Report task and block events to a handler, e.g. a [`JsonlWriter`](crate::events::JsonlWriter)
+    pub fn with_event_handler(self, handler: EventHandler) -> Self {
+        self.with_observer(Arc::new(EventObserver(handler)))
+    }
 
-        let codes = dict
-            .get(&Ident::from("codes"))
-            .and_then(|v| match v {
-                RuntimeValue::Array(arr) => Some(self.parse_code_blocks(arr)),
-                _ => None,
-            })
-            .unwrap_or_else(|| Ok(Vec::new()))?;
+    /// Add an observer notified as sections and code blocks run
+    ///
+    /// Observers are called in the order they were added, on the thread running the task,
+    /// except for [`RunnerObserver::on_block_output`] which is called from the threads
+    /// draining the block's stdout and stderr.
+    pub fn with_observer(mut self, observer: Arc<dyn RunnerObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
 
-        let description = dict.get(&Ident::from("description")).and_then(|v| match v {
-            RuntimeValue::String(s) => Some(s.to_string()),
-            _ => None,
-        });
+    /// Write machine-parsable progress frames while tasks run, see [`crate::progress`]
+    pub fn with_progress_reporter(mut self, reporter: ProgressReporter) -> Self {
+        self.progress = Some(Arc::new(reporter));
+        self
+    }
 
-        Ok(Section {
-            title,
-            level,
-            codes,
-            description,
-        })
+    /// Create a new Runner with default configuration
+    pub fn with_default_config() -> Self {
+        Self::new(Config::default())
     }
 
-    fn parse_code_blocks(&self, arr: &[RuntimeValue]) -> Result<Vec<CodeBlock>> {
-        let mut blocks = Vec::new();
+    /// Get the configuration used by this runner
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-        for item in arr {
-            if let RuntimeValue::Dict(dict) = item {
-                let lang = dict
-                    .get(&Ident::from("lang"))
-                    .and_then(|v| match v {
-                        RuntimeValue::String(s) => Some(s.to_string()),
-                        _ => None,
-                    })
-                    .unwrap_or_default();
+    /// Kill the code blocks this runner is running, along with every process they started
+    ///
+    /// The killed blocks fail, which stops their tasks.
+    pub fn kill_running(&self) {
+        self.running.kill_all();
+    }
 
-                let code = dict
-                    .get(&Ident::from("code"))
-                    .and_then(|v| match v {
-                        RuntimeValue::String(s) => Some(s.to_string()),
-                        _ => None,
-                    })
-                    .unwrap_or_default();
+    /// Handle for killing this runner's code blocks from another thread, e.g. on a timeout
+    pub fn kill_handle(&self) -> KillHandle {
+        KillHandle(Arc::clone(&self.running))
+    }
 
-                blocks.push(CodeBlock { lang, code });
-            }
+    /// Load and parse a Markdown file
+    pub fn load_markdown<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        fs::read_to_string(path).map_err(Error::Io)
+    }
+
+    /// Load a Markdown file the way its sections are extracted from it
+    ///
+    /// MDX syntax of `.mdx` files is blanked out, so JSX components and admonitions around
+    /// code blocks do not hide them. Byte offsets and line numbers stay the same.
+    pub fn load_task_markdown<P: AsRef<Path>>(&self, path: P) -> Result<String> {
+        let markdown = self.load_markdown(&path)?;
+        if is_mdx(path.as_ref()) {
+            return Ok(normalize_mdx(&markdown));
+        }
+        Ok(markdown)
+    }
+
+    /// Extract sections from Markdown content
+    ///
+    /// When `resilient` is enabled in the configuration, sections that fail to extract are
+    /// skipped and logged as warnings instead of failing the whole document.
+    pub fn extract_sections(&self, markdown: &str) -> Result<Vec<Section>> {
+        if !self.config.resilient {
+            return self.eval_sections(markdown);
+        }
+
+        let extraction = self.extract_sections_resilient(markdown);
+        for diagnostic in &extraction.diagnostics {
+            warn!(
+                line = diagnostic.line,
+                heading = %diagnostic.heading,
+                "skipped section: {}",
+                diagnostic.message
+            );
         }
 
-        Ok(blocks)
+        Ok(extraction.sections)
     }
 
-    pub fn find_section<'a>(&self, sections: &'a [Section], title: &str) -> Option<&'a Section> {
-        sections.iter().find(|s| s.title == title)
+    /// Extract sections, skipping chunks of the document that fail to extract
+    ///
+    /// The whole document is evaluated first; only if that fails is it split at headings of
+    /// the configured levels and each chunk evaluated on its own, so one malformed snippet
+    /// doesn't hide every other task.
+    pub fn extract_sections_resilient(&self, markdown: &str) -> Extraction {
+        if let Ok(sections) = self.eval_sections(markdown) {
+            return Extraction {
+                sections,
+                diagnostics: Vec::new(),
+            };
+        }
+
+        self.eval_chunks(markdown)
     }
 
-    pub fn execute_section(&self, section: &Section) -> Result<()> {
-        self.execute_section_with_args(section, &[])
+    /// Validate Markdown content against the configured runtimes, and the dependencies of
+    /// its tasks on each other
+    pub fn validate(&self, markdown: &str) -> Vec<Issue> {
+        let extraction = self.extract_sections_resilient(markdown);
+        let mut issues = validate::validate(&self.config, markdown, &extraction);
+        issues.extend(validate::validate_dependencies(&extraction, |dependency| {
+            self.dependency_target(&extraction.sections, dependency)
+        }));
+        issues
     }
 
-    pub fn execute_section_with_args(&self, section: &Section, args: &[String]) -> Result<()> {
-        for code_block in &section.codes {
-            if code_block.lang.is_empty() {
-                continue;
+    /// Where a dependency of a task of a file with these sections points, as
+    /// [`Runner::resolve_dependencies`] would find it, without reading other files
+    fn dependency_target(&self, sections: &[Section], dependency: &str) -> DependencyTarget {
+        let reference = TaskRef::parse(dependency);
+        if reference.file.is_some() {
+            return DependencyTarget::OtherFile;
+        }
+        match self.find_section(sections, reference.task) {
+            Some(found) => sections
+                .iter()
+                .position(|section| std::ptr::eq(section, found))
+                .map_or(DependencyTarget::Unknown, DependencyTarget::Task),
+            None if self.split_namespace(reference.task).is_some() => DependencyTarget::OtherFile,
+            None => DependencyTarget::Unknown,
+        }
+    }
+
+    fn eval_sections(&self, markdown: &str) -> Result<Vec<Section>> {
+        let key = content_hash(markdown);
+        if let Some(sections) = locked(&self.section_cache).get(&key) {
+            debug!(count = sections.len(), "using cached sections");
+            return Ok(sections.clone());
+        }
+
+        let sections = match self.config.heading_level.single() {
+            Some(level) => self.eval_level(markdown, level)?,
+            None => {
+                let extraction = self.eval_chunks(markdown);
+                if let Some(diagnostic) = extraction.diagnostics.into_iter().next() {
+                    return Err(Error::Query(format!(
+                        "{} (section '{}' at line {})",
+                        diagnostic.message, diagnostic.heading, diagnostic.line
+                    )));
+                }
+                extraction.sections
             }
+        };
+        debug!(
+            count = sections.len(),
+            level = %self.config.heading_level,
+            "extracted sections"
+        );
 
-            self.execute_code_with_args(&code_block.lang, &code_block.code, args)?;
+        let mut cache = locked(&self.section_cache);
+        if cache.len() >= SECTION_CACHE_CAPACITY {
+            cache.clear();
         }
+        cache.insert(key, sections.clone());
 
-        Ok(())
+        Ok(sections)
     }
 
-    pub fn execute_code(&self, lang: &str, code: &str) -> Result<()> {
-        self.execute_code_with_args(lang, code, &[])
+    /// Extract the sections of a single heading level
+    fn eval_level(&self, markdown: &str, level: u8) -> Result<Vec<Section>> {
+        let mut sections = locked(&self.extractor).extract(markdown, level)?;
+        locate_sections(markdown, level, &mut sections);
+        Ok(sections)
     }
 
-    pub fn execute_code_with_args(&self, lang: &str, code: &str, args: &[String]) -> Result<()> {
-        let runtime = self
-            .config
-            .get_runtime(lang)
-            .ok_or_else(|| Error::RuntimeNotFound(lang.to_string()))?;
-
-        let parts: Vec<&str> = runtime.split_whitespace().collect();
-        if parts.is_empty() {
-            return Err(Error::RuntimeNotFound(lang.to_string()));
+    /// Evaluate each chunk between headings of the configured levels on its own
+    ///
+    /// Chunks that fail are reported as diagnostics. Sections get the title of the nearest
+    /// enclosing section as their parent.
+    fn eval_chunks(&self, markdown: &str) -> Extraction {
+        let mut extraction = Extraction::default();
+        let mut ancestors: Vec<(u8, String)> = Vec::new();
+
+        for chunk in split_at_heading_levels(markdown, self.config.heading_level.levels()) {
+            while ancestors
+                .last()
+                .is_some_and(|(level, _)| *level >= chunk.level)
+            {
+                ancestors.pop();
+            }
+            let parent = ancestors.last().map(|(_, title)| title.clone());
+            ancestors.push((chunk.level, parse_heading(chunk.heading).title.to_string()));
+
+            match self.eval_level(chunk.text, chunk.level) {
+                Ok(sections) => {
+                    extraction
+                        .sections
+                        .extend(sections.into_iter().map(|mut section| {
+                            section.shift_positions(chunk.offset, chunk.line - 1);
+                            section.parent = parent.clone();
+                            section
+                        }));
+                }
+                Err(e) => extraction.diagnostics.push(ExtractionDiagnostic {
+                    line: chunk.line,
+                    heading: chunk.heading.to_string(),
+                    message: e.to_string(),
+                }),
+            }
         }
 
-        // Get execution mode from config
-        let execution_mode = self.config.get_execution_mode(lang);
-
-        match execution_mode {
-            ExecutionMode::File => self.execute_code_with_file_and_args(lang, code, &parts, args),
-            ExecutionMode::Arg => self.execute_code_with_arg_mode(code, &parts, args),
-            ExecutionMode::Stdin => self.execute_code_with_stdin_and_args(code, &parts, args),
-        }
+        extraction
     }
 
-    fn execute_code_with_stdin_and_args(
-        &self,
-        code: &str,
-        parts: &[&str],
-        task_args: &[String],
-    ) -> Result<()> {
-        let cmd = parts[0];
-        let args = &parts[1..];
-
-        // Use inherit() for stdout/stderr to preserve TTY and colors
-        let mut child = Command::new(cmd)
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .envs(Self::prepare_env_vars(task_args))
-            .spawn()
-            .map_err(|e| Error::Execution(format!("Failed to spawn process: {}", e)))?;
+    /// Find a section by title, or by anchor when the name starts with `#` (`#build-and-test`)
+    ///
+    /// Titles are then compared ignoring case, formatting, emoji and trailing punctuation, so
+    /// `deploy` finds `## 🚀 Deploy!`. Otherwise the section matching the name the way the
+    /// `match` setting says is returned; a name matching several titles or an invalid `regex`
+    /// pattern matches nothing, see [`Runner::task_not_found`] for why. Of several sections
+    /// with the same title, the first one is returned.
+    pub fn find_section<'a>(&self, sections: &'a [Section], title: &str) -> Option<&'a Section> {
+        self.find_sections(sections, title).into_iter().next()
+    }
 
-        // Write code to stdin
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(code.as_bytes())
-                .map_err(|e| Error::Execution(format!("Failed to write to stdin: {}", e)))?;
-            drop(stdin);
+    /// Every section a task name refers to: the one [`Runner::find_section`] finds and the
+    /// others with the same title, in document order
+    ///
+    /// A name of the form `Parent/Title`, or `parent:title` with the namespace separator, only
+    /// looks at sections nested in `Parent`.
+    pub fn find_sections<'a>(&self, sections: &'a [Section], title: &str) -> Vec<&'a Section> {
+        if let Some(found) = self.match_section(sections, title) {
+            return sections.iter().filter(|s| s.title == found.title).collect();
         }
 
-        // Wait for completion
-        let status = child
-            .wait()
-            .map_err(|e| Error::Execution(format!("Failed to wait for process: {}", e)))?;
+        let separator = self.config.namespaces.separator.as_str();
+        let Some((parent, title)) = title
+            .rsplit_once('/')
+            .or_else(|| title.rsplit_once(separator))
+        else {
+            return Vec::new();
+        };
+        let parent = normalize_title(parent);
+        let nested: Vec<Section> = sections
+            .iter()
+            .filter(|s| {
+                s.parent
+                    .as_deref()
+                    .is_some_and(|p| normalize_title(p) == parent)
+            })
+            .cloned()
+            .collect();
+        let Some(found) = self.match_section(&nested, title) else {
+            return Vec::new();
+        };
+        sections
+            .iter()
+            .filter(|s| s.title == found.title && s.parent == found.parent)
+            .collect()
+    }
+
+    /// The section matching a task name, see [`Runner::find_section`]
+    fn match_section<'a>(&self, sections: &'a [Section], title: &str) -> Option<&'a Section> {
+        sections
+            .iter()
+            .find(|s| s.title == title)
+            .or_else(|| {
+                let anchor = slugify(title.strip_prefix('#')?);
+                sections.iter().find(|s| s.anchor() == anchor)
+            })
+            .or_else(|| {
+                let key = normalize_title(title);
+                if key.is_empty() {
+                    return None;
+                }
+                sections.iter().find(|s| normalize_title(&s.title) == key)
+            })
+            .or_else(|| self.config.title_match.find(sections, title).ok().flatten())
+    }
 
-        if !status.success() {
-            return Err(Error::Execution("Execution failed".to_string()));
+    /// Error for a task name no section matched, which explains an invalid `regex` pattern or
+    /// a name matching several titles, or suggests the sections with the closest titles
+    pub(crate) fn task_not_found(&self, sections: &[Section], title: &str) -> Error {
+        if let Err(e) = self.config.title_match.find(sections, title) {
+            return e;
+        }
+        Error::SectionNotFound {
+            task: title.to_string(),
+            suggestions: closest_titles(sections, title),
         }
+    }
 
-        Ok(())
+    pub fn execute_section(&self, section: &Section) -> Result<ExecutionReport> {
+        self.execute_section_with_args(section, &[])
     }
 
-    fn execute_code_with_arg_mode(
+    pub fn execute_section_with_args(
         &self,
-        code: &str,
-        parts: &[&str],
-        task_args: &[String],
-    ) -> Result<()> {
-        let cmd = parts[0];
-        // Append code as an argument to the command
-        let mut args: Vec<&str> = parts[1..].to_vec();
-        args.push(code);
-
-        // Use inherit() for stdout/stderr to preserve TTY and colors
-        let mut child = Command::new(cmd)
-            .args(args)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .envs(Self::prepare_env_vars(task_args))
-            .spawn()
-            .map_err(|e| Error::Execution(format!("Failed to spawn process: {}", e)))?;
+        section: &Section,
+        args: &[String],
+    ) -> Result<ExecutionReport> {
+        self.execute_blocks(section, args, 0..section.codes.len())
+    }
 
-        // Write code to stdin
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(code.as_bytes())
-                .map_err(|e| Error::Execution(format!("Failed to write to stdin: {}", e)))?;
-            drop(stdin);
+    /// Execute only the code blocks of a section whose indexes are in a range, e.g. a single
+    /// block, or the rest of a task from a block on
+    ///
+    /// The other blocks are left out of the report.
+    pub fn execute_blocks(
+        &self,
+        section: &Section,
+        args: &[String],
+        blocks: Range<usize>,
+    ) -> Result<ExecutionReport> {
+        let mut run = SectionRun::new(
+            section,
+            &self.config,
+            &self.observers,
+            self.step.as_ref(),
+            self.progress.as_deref(),
+            self.dry_run,
+        );
+        if run.skipped() {
+            return Ok(run.finish());
         }
 
-        // Wait for completion
-        let status = child
-            .wait()
-            .map_err(|e| Error::Execution(format!("Failed to wait for process: {}", e)))?;
+        let _lock = self.lock_task(section).map_err(|e| run.fail(e))?;
+        run.begin();
 
-        if !status.success() {
-            return Err(Error::Execution("Execution failed".to_string()));
+        for index in blocks.filter(|&index| index < section.codes.len()) {
+            let Some(code_block) = run.next_block(index)? else {
+                continue;
+            };
+
+            let id = block_id(&section.title, index);
+            let log = match &self.log_dir {
+                Some(log_dir) => Some(log_dir.open_block(&section.title, index)?),
+                None => None,
+            };
+            let output = self.block_output(Some(&id), log.as_ref());
+            let started = Instant::now();
+            let exit = self.execute_block(&code_block, args, Some(section), output);
+            if let Err(e) = run.finish_block(index, &code_block, exit, started.elapsed()) {
+                if matches!(e, Error::Cancelled(_)) {
+                    self.run_cancel_hooks(&section.title, &id);
+                }
+                return Err(e);
+            }
         }
 
-        Ok(())
+        Ok(run.finish())
     }
 
-    fn execute_code_with_file_and_args(
-        &self,
-        lang: &str,
-        code: &str,
-        parts: &[&str],
-        task_args: &[String],
-    ) -> Result<()> {
-        use std::env;
-
-        // Create temporary directory
-        let temp_dir = env::temp_dir();
+    /// Take the lock of a task marked `{lock}`, in the project root or working directory
+    fn lock_task(&self, section: &Section) -> Result<Option<TaskLock>> {
+        if !section.requires_lock() || self.dry_run {
+            return Ok(None);
+        }
 
-        // Use language name as file extension, or map known languages
-        let file_ext = match lang {
-            "go" | "golang" => "go",
-            "python" => "py",
-            "ruby" => "rb",
-            "javascript" | "js" => "js",
-            "typescript" | "ts" => "ts",
-            _ => lang, // Use language name as extension for custom languages
+        let root = match &self.root {
+            Some(root) => root.clone(),
+            None => std::env::current_dir()?,
+        };
+        let lock = if self.lock_wait {
+            TaskLock::acquire(&root, &section.title)?
+        } else {
+            TaskLock::try_acquire(&root, &section.title)?
         };
+        Ok(Some(lock))
+    }
 
-        // Generate unique file name
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let file_name = format!("mx_temp_{}.{}", timestamp, file_ext);
-        let temp_file = temp_dir.join(&file_name);
+    /// Run the configured `on_cancel` hooks after a task was interrupted
+    fn run_cancel_hooks(&self, task: &str, block: &str) {
+        for hook in &self.config.on_cancel {
+            info!(task, block, hook, "running cancel hook");
+            match process::cancel_hook(hook, task, block).status() {
+                Ok(status) if !status.success() => warn!(hook, %status, "cancel hook failed"),
+                Ok(_) => {}
+                Err(e) => warn!(hook, "failed to run cancel hook: {}", e),
+            }
+        }
+    }
 
-        // Write code to temporary file
-        fs::write(&temp_file, code)
-            .map_err(|e| Error::Execution(format!("Failed to write temp file: {}", e)))?;
+    /// Where the output of a block goes: the output handler, its log and the observers
+    fn block_output<'a>(
+        &'a self,
+        id: Option<&'a str>,
+        log: Option<&'a BlockLog>,
+    ) -> BlockOutput<'a> {
+        BlockOutput {
+            handler: self.output.as_ref(),
+            log,
+            observers: id.map(|id| (id, self.observers.as_slice())),
+            secrets: &self.secrets,
+        }
+    }
 
-        // Execute go run <file>
-        let status = Command::new(parts[0])
-            .args(&parts[1..])
-            .arg(&temp_file)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .envs(Self::prepare_env_vars(task_args))
-            .status()
-            .map_err(|e| Error::Execution(format!("Failed to execute {}: {}", lang, e)))?;
+    pub fn execute_code(&self, lang: &str, code: &str) -> Result<()> {
+        self.execute_code_with_args(lang, code, &[])
+    }
 
-        // Clean up temporary file
-        fs::remove_file(&temp_file).ok();
+    pub fn execute_code_with_args(&self, lang: &str, code: &str, args: &[String]) -> Result<()> {
+        let block = CodeBlock {
+            lang: lang.to_string(),
+            code: code.to_string(),
+            ..Default::default()
+        };
+        self.execute_block(&block, args, None, self.block_output(None, None))
+            .and_then(|(status, program)| check_status(lang, &program, status))
+    }
 
-        if !status.success() {
-            Err(Error::Execution(format!("{} execution failed", lang)))
-        } else {
-            Ok(())
+    /// Execute a code block of a task, if any, sending its output where `output` says
+    ///
+    /// Returns the exit status along with the program that ran the block.
+    fn execute_block(
+        &self,
+        block: &CodeBlock,
+        args: &[String],
+        section: Option<&Section>,
+        output: BlockOutput<'_>,
+    ) -> Result<(ExitStatus, String)> {
+        let lang = block.lang.as_str();
+        let mut invocation = Invocation::prepare(
+            &self.config,
+            self.forward_stdin,
+            block,
+            args,
+            section,
+            self.remote.as_deref(),
+            &self.secrets,
+        )?;
+        invocation.root = self.root.clone();
+        invocation.env.splice(0..0, self.env.iter().cloned());
+        invocation.resolve_tools()?;
+
+        let mut command = invocation.command();
+        log_command(&command, &self.secrets);
+        if invocation.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        } else if self.detached && !self.forward_stdin {
+            command.stdin(Stdio::null());
+        }
+        // The terminal is also the block's stdin when nothing else is
+        let terminal_stdin = invocation.stdin.is_none() && !self.forward_stdin && !self.detached;
+        let pty = invocation.pty.then(Pty::open).transpose()?;
+        let foreground = match &pty {
+            Some(pty) => {
+                pty.attach(&mut command, terminal_stdin)?;
+                false
+            }
+            // Interactive blocks write to the terminal themselves, prompts included, unless
+            // there are values to mask in their output; they still read from it
+            None if invocation.interactive && !self.detached => {
+                if !self.secrets.is_empty() {
+                    command.stdout(output.stdio()).stderr(output.stdio());
+                }
+                process::configure(&mut command, true)
+            }
+            None => {
+                command.stdout(output.stdio()).stderr(output.stdio());
+                process::configure(&mut command, !self.detached)
+            }
+        };
+        let child = command
+            .spawn()
+            .map_err(|e| Error::Execution(format!("Failed to execute {}: {}", lang, e)))?;
+        drop(command);
+
+        // Kills whatever the block left running unless it succeeded
+        let group = ChildGroup::new(child.id(), foreground, &self.running);
+        let status = match pty {
+            Some(pty) => {
+                let keys = terminal_stdin && std::io::stdin().is_terminal();
+                pty.wait(child, invocation.stdin.as_deref(), keys, output)?
+            }
+            None => wait_child(child, invocation.stdin.as_deref(), output)?,
+        };
+        if status.success() && !process::interrupted() {
+            group.finish();
         }
+        Ok((status, invocation.program))
     }
 
     /// Prepare environment variables from task arguments
@@ -354,102 +948,2723 @@ impl Runner {
     }
 
     /// Run a specific task by section title
-    pub fn run_task<P: AsRef<Path>>(&mut self, markdown_path: P, task_name: &str) -> Result<()> {
+    pub fn run_task<P: AsRef<Path>>(
+        &self,
+        markdown_path: P,
+        task_name: &str,
+    ) -> Result<ExecutionReport> {
         self.run_task_with_args(markdown_path, task_name, &[])
     }
 
     /// Run a specific task with arguments
     pub fn run_task_with_args<P: AsRef<Path>>(
-        &mut self,
+        &self,
         markdown_path: P,
         task_name: &str,
         args: &[String],
-    ) -> Result<()> {
-        let markdown = self.load_markdown(markdown_path)?;
-        let sections = self.extract_sections(&markdown)?;
-
-        let section = self
-            .find_section(&sections, task_name)
-            .ok_or_else(|| Error::SectionNotFound(task_name.to_string()))?;
+    ) -> Result<ExecutionReport> {
+        let (_, section) = self.find_task_in_files(&[markdown_path], task_name)?;
+        self.execute_section_with_args(&section, args)
+    }
 
-        self.execute_section_with_args(section, args)
+    /// Find a task across several Markdown files
+    ///
+    /// Returns the file defining the task together with its section. It is an error if the
+    /// task is defined in more than one of the files, or more than once in a file.
+    pub fn find_task_in_files<P: AsRef<Path>>(
+        &self,
+        markdown_paths: &[P],
+        task_name: &str,
+    ) -> Result<(PathBuf, Section)> {
+        self.find_task_at(markdown_paths, task_name, None)
     }
 
-    /// List all available tasks (sections) in a Markdown file
-    pub fn list_tasks<P: AsRef<Path>>(&mut self, markdown_path: P) -> Result<Vec<String>> {
-        let markdown = self.load_markdown(markdown_path)?;
-        let sections = self.extract_sections(&markdown)?;
+    /// Find a task across several Markdown files, picking one of several tasks with the same
+    /// title by its 0-based index in file and document order
+    pub fn find_task_at<P: AsRef<Path>>(
+        &self,
+        markdown_paths: &[P],
+        task_name: &str,
+        index: Option<usize>,
+    ) -> Result<(PathBuf, Section)> {
+        let mut found: Vec<(PathBuf, Section)> = Vec::new();
+        let mut all = Vec::new();
+
+        for path in markdown_paths {
+            let sections = self.list_task_sections(path)?;
+            for section in self.find_sections(&sections, task_name) {
+                found.push((path.as_ref().to_path_buf(), section.clone()));
+            }
+            all.extend(sections);
+        }
 
-        Ok(sections
-            .into_iter()
-            .map(|s| format!("{}: {}", s.title, s.description.unwrap_or_default()))
-            .collect())
-    }
+        // A name such as `db:migrate` is the `migrate` task of the file namespaced `db`
+        if found.is_empty()
+            && let Some((namespace, task)) = self.split_namespace(task_name)
+        {
+            for path in markdown_paths {
+                if namespace::file_namespace(path.as_ref()).as_deref() != Some(&*namespace) {
+                    continue;
+                }
+                let sections = self.list_task_sections(path)?;
+                for section in self.find_sections(&sections, task) {
+                    found.push((path.as_ref().to_path_buf(), section.clone()));
+                }
+            }
+        }
 
-    /// List all available task sections in a Markdown file with their details
-    pub fn list_task_sections<P: AsRef<Path>>(&mut self, markdown_path: P) -> Result<Vec<Section>> {
-        let markdown = self.load_markdown(markdown_path)?;
-        self.extract_sections(&markdown)
+        // Tasks of the configuration are only found if no Markdown task is, as part of the
+        // first file
+        let config_tasks = self.config.task_sections();
+        if found.is_empty()
+            && let Some(path) = markdown_paths.first()
+        {
+            for section in self.find_sections(&config_tasks, task_name) {
+                found.push((path.as_ref().to_path_buf(), section.clone()));
+            }
+        }
+        all.extend(config_tasks);
+
+        let (path, section) = match (found.len(), index) {
+            (0, _) => return Err(self.task_not_found(&all, task_name)),
+            (count, Some(index)) if index >= count => {
+                return Err(Error::TaskIndexOutOfRange {
+                    task: task_name.to_string(),
+                    index,
+                    count,
+                });
+            }
+            (_, Some(index)) => found.swap_remove(index),
+            (1, None) => found.remove(0),
+            (_, None)
+                if found.iter().all(|(path, _)| {
+                    found.iter().filter(|(other, _)| other == path).count() == 1
+                }) =>
+            {
+                return Err(Error::AmbiguousTask {
+                    task: task_name.to_string(),
+                    files: found.into_iter().map(|(path, _)| path).collect(),
+                    titles: Vec::new(),
+                });
+            }
+            (_, None) => {
+                return Err(Error::DuplicateTitle {
+                    task: found[0].1.title.clone(),
+                    locations: found
+                        .iter()
+                        .map(|(path, section)| task_location(path, section))
+                        .collect(),
+                });
+            }
+        };
+        info!(task = %section.title, file = %path.display(), "matched section");
+        Ok((path, section))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_runner_creation() {
-        let runner = Runner::with_default_config();
-        assert_eq!(runner.config.heading_level, 2);
+    /// Split a task name into the namespace of a file and the task within it, e.g. `db` and
+    /// `migrate` for `db:migrate`
+    fn split_namespace<'a>(&self, task_name: &'a str) -> Option<(String, &'a str)> {
+        let separator = self.config.namespaces.separator.as_str();
+        let (namespace, task) = task_name.split_once(separator)?;
+        Some((slugify(namespace), task.trim_start()))
     }
 
-    #[test]
-    fn test_extract_sections() {
-        let markdown = r#"# Title
+    /// Canonical name of a task, such as `db:deploy:staging`, namespaced by its file when
+    /// given one
+    pub fn qualified_name(&self, section: &Section, file: Option<&Path>) -> String {
+        let namespace = file.and_then(namespace::file_namespace);
+        let separator = &self.config.namespaces.separator;
+        namespace::qualified_name(section, namespace.as_deref(), separator)
+    }
 
-## Task 1
+    /// A task preceded by everything it depends on, in the order they must run
+    ///
+    /// Dependencies are listed in the `depends` metadata entry of a section. They are looked
+    /// up in the same file unless they name another one, as in `docs/db.md#migrate`, which
+    /// is relative to the file declaring the dependency, or a namespace, as in `db:migrate`
+    /// for a `db.md` or `db/README.md` next to it. Each file is read once, and a task
+    /// that several others depend on appears once.
+    pub fn resolve_dependencies(
+        &self,
+        markdown_path: &Path,
+        section: &Section,
+    ) -> Result<Vec<(PathBuf, Section)>> {
+        let mut files = HashMap::new();
+        let mut visiting = Vec::new();
+        let mut order = Vec::new();
+        self.visit_dependencies(
+            markdown_path,
+            section,
+            &mut files,
+            &mut visiting,
+            &mut order,
+        )?;
+        Ok(order)
+    }
 
-```bash
+    /// Depth-first walk of the dependency graph, appending tasks after their dependencies
+    fn visit_dependencies(
+        &self,
+        markdown_path: &Path,
+        section: &Section,
+        files: &mut HashMap<PathBuf, Vec<Section>>,
+        visiting: &mut Vec<(PathBuf, String)>,
+        order: &mut Vec<(PathBuf, Section)>,
+    ) -> Result<()> {
+        let key = (
+            canonical(markdown_path.to_path_buf()),
+            section.title.clone(),
+        );
+        if order
+            .iter()
+            .any(|(path, done)| done.title == key.1 && canonical(path.clone()) == key.0)
+        {
+            return Ok(());
+        }
+        if let Some(start) = visiting.iter().position(|visited| *visited == key) {
+            let mut cycle: Vec<String> = visiting[start..]
+                .iter()
+                .map(|(_, title)| title.clone())
+                .collect();
+            cycle.push(section.title.clone());
+            return Err(Error::DependencyCycle(cycle));
+        }
+        visiting.push(key);
+
+        for dependency in section.dependencies() {
+            let missing = || Error::MissingDependency {
+                task: section.title.clone(),
+                dependency: dependency.clone(),
+            };
+            let (path, found) = self
+                .find_dependency(markdown_path, &dependency, files, missing)?
+                .ok_or_else(missing)?;
+            debug!(task = %section.title, dependency = %found.title, file = %path.display(), "resolved dependency");
+
+            self.visit_dependencies(&path, &found, files, visiting, order)?;
+        }
+
+        visiting.pop();
+        order.push((markdown_path.to_path_buf(), section.clone()));
+        Ok(())
+    }
+
+    /// The task a `depends` entry of a task in `markdown_path` refers to, and its file
+    ///
+    /// Files read are kept in `files`; a file that does not exist fails with `missing`.
+    pub(crate) fn find_dependency(
+        &self,
+        markdown_path: &Path,
+        dependency: &str,
+        files: &mut HashMap<PathBuf, Vec<Section>>,
+        missing: impl Fn() -> Error,
+    ) -> Result<Option<(PathBuf, Section)>> {
+        let reference = TaskRef::parse(dependency);
+        let dir = markdown_path.parent().unwrap_or(Path::new(""));
+        let path = match reference.file {
+            Some(file) => dir.join(file),
+            None => markdown_path.to_path_buf(),
+        };
+
+        let sections = self.cached_sections(&path, files, &missing)?;
+        if let Some(found) = self.find_section(sections, reference.task) {
+            return Ok(Some((path, found.clone())));
+        }
+        if let Err(e @ Error::AmbiguousTask { .. }) =
+            self.config.title_match.find(sections, reference.task)
+        {
+            return Err(e);
+        }
+        if reference.file.is_none()
+            && let Some((namespace, task)) = self.split_namespace(reference.task)
+            && let Some(path) = namespace::find_file(dir, &namespace)
+        {
+            let sections = self.cached_sections(&path, files, missing)?;
+            return match self.find_section(sections, task) {
+                Some(found) => Ok(Some((path, found.clone()))),
+                None => match self.config.title_match.find(sections, task) {
+                    Err(e @ Error::AmbiguousTask { .. }) => Err(e),
+                    _ => Ok(None),
+                },
+            };
+        }
+        Ok(None)
+    }
+
+    /// Tasks of a file the dependency walk reads, from the cache after the first time
+    fn cached_sections<'f>(
+        &self,
+        path: &Path,
+        files: &'f mut HashMap<PathBuf, Vec<Section>>,
+        missing: impl FnOnce() -> Error,
+    ) -> Result<&'f [Section]> {
+        let file_key = canonical(path.to_path_buf());
+        if !files.contains_key(&file_key) {
+            let sections = self.list_task_sections(path).map_err(|e| match e {
+                Error::Io(io) if io.kind() == std::io::ErrorKind::NotFound => missing(),
+                e => e,
+            })?;
+            files.insert(file_key.clone(), sections);
+        }
+        Ok(&files[&file_key])
+    }
+
+    /// Find all tasks with the given tag across several Markdown files, in document order
+    pub fn find_tagged_in_files<P: AsRef<Path>>(
+        &self,
+        markdown_paths: &[P],
+        tag: &str,
+    ) -> Result<Vec<(PathBuf, Section)>> {
+        let mut found = Vec::new();
+
+        for path in markdown_paths {
+            let sections = self.list_task_sections(path)?;
+            found.extend(
+                sections
+                    .into_iter()
+                    .filter(|section| section.has_tag(tag))
+                    .map(|section| (path.as_ref().to_path_buf(), section)),
+            );
+        }
+
+        Ok(found)
+    }
+
+    /// Run a task looked up across several Markdown files
+    pub fn run_task_in_files<P: AsRef<Path>>(
+        &self,
+        markdown_paths: &[P],
+        task_name: &str,
+        args: &[String],
+    ) -> Result<ExecutionReport> {
+        let (_, section) = self.find_task_in_files(markdown_paths, task_name)?;
+        self.execute_section_with_args(&section, args)
+    }
+
+    /// List all available tasks (sections) in a Markdown file
+    pub fn list_tasks<P: AsRef<Path>>(&self, markdown_path: P) -> Result<Vec<String>> {
+        let sections = self.list_task_sections(markdown_path)?;
+
+        Ok(sections
+            .into_iter()
+            .map(|s| format!("{}: {}", s.title, s.description.unwrap_or_default()))
+            .collect())
+    }
+
+    /// List all available task sections in a Markdown file with their details
+    ///
+    /// The sections of a file whose modification time and size are the same as when it was
+    /// last extracted are reused without reading it. Files in other formats, such as AsciiDoc
+    /// or Org-mode, are read by the task source for their extension.
+    pub fn list_task_sections<P: AsRef<Path>>(&self, markdown_path: P) -> Result<Vec<Section>> {
+        let path = markdown_path.as_ref();
+        if let Some(source) = self.task_sources.iter().find(|source| source.reads(path)) {
+            let text = fs::read_to_string(path).map_err(Error::Io)?;
+            let sections = source.sections(&text, self.config.heading_level.levels())?;
+            return Ok(with_file(sections, path));
+        }
+        let metadata = fs::metadata(path).map_err(Error::Io)?;
+        let modified = metadata.modified().map_err(Error::Io)?;
+        let unchanged = locked(&self.file_cache)
+            .get(path)
+            .filter(|file| file.modified == modified && file.len == metadata.len())
+            .map(|file| file.hash);
+        if let Some(hash) = unchanged
+            && let Some(sections) = locked(&self.section_cache).get(&hash)
+        {
+            debug!(file = %path.display(), "using cached sections of unchanged file");
+            return Ok(with_file(sections.clone(), path));
+        }
+
+        let markdown = self.load_task_markdown(path)?;
+        let sections = self.extract_sections(&markdown)?;
+        let mut cache = locked(&self.file_cache);
+        if cache.len() >= SECTION_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(
+            path.to_path_buf(),
+            CachedFile {
+                modified,
+                len: metadata.len(),
+                hash: content_hash(&markdown),
+            },
+        );
+        Ok(with_file(sections, path))
+    }
+
+    /// Task sections of a file; the same as [`Runner::list_task_sections`]
+    pub fn sections<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Section>> {
+        self.list_task_sections(path)
+    }
+
+    /// Evaluate an mq query over a Markdown document, returning the values it yields as text
+    ///
+    /// The functions of the sections module, such as `sections_with_code`, are available to
+    /// the query, so it sees the document as mx does. `None` values are left out. Queries
+    /// need the mq extractor.
+    pub fn query(&self, markdown: &str, query: &str) -> Result<Vec<String>> {
+        locked(&self.extractor).query(markdown, query)
+    }
+}
+
+/// The bookkeeping of running a section's code blocks: skipping, the step handler, progress
+/// frames, observers and the report
+///
+/// Shared by the blocking and the async runner, which only differ in how a block is spawned
+/// and waited for.
+pub(crate) struct SectionRun<'a> {
+    section: &'a Section,
+    config: &'a Config,
+    observers: &'a [Arc<dyn RunnerObserver>],
+    step: Option<&'a StepHandler>,
+    progress: Option<&'a ProgressReporter>,
+    dry_run: bool,
+    report: ExecutionReport,
+    started: Instant,
+    begun: bool,
+}
+
+impl<'a> SectionRun<'a> {
+    pub(crate) fn new(
+        section: &'a Section,
+        config: &'a Config,
+        observers: &'a [Arc<dyn RunnerObserver>],
+        step: Option<&'a StepHandler>,
+        progress: Option<&'a ProgressReporter>,
+        dry_run: bool,
+    ) -> Self {
+        let mut report = ExecutionReport::new(&section.title);
+        report.warnings = warning::task_warnings(section);
+        let run = Self {
+            section,
+            config,
+            observers,
+            step,
+            progress,
+            dry_run,
+            report,
+            started: Instant::now(),
+            begun: false,
+        };
+        run.notify(|observer| observer.on_section_start(section));
+        run
+    }
+
+    /// Whether the whole task is skipped by its condition, recording the reason if it is
+    pub(crate) fn skipped(&mut self) -> bool {
+        let reason = condition::skip_reason(self.section.meta.as_deref());
+        if let Some(reason) = &reason {
+            info!(task = %self.section.title, %reason, "skipping task");
+        }
+        self.report.skipped = reason;
+        self.report.skipped.is_some()
+    }
+
+    /// Start running blocks, once the task's lock is taken
+    pub(crate) fn begin(&mut self) {
+        self.begun = true;
+        self.emit_progress(|| ProgressFrame::TaskStart {
+            task: self.section.title.clone(),
+            total_blocks: self.section.codes.len(),
+            percent: 0.0,
+        });
+    }
+
+    /// The code block to run at an index, as the step handler may have edited it
+    ///
+    /// Returns `None` for blocks that do not run, which are reported as skipped unless their
+    /// language is not runnable.
+    pub(crate) fn next_block(&mut self, index: usize) -> Result<Option<Cow<'a, CodeBlock>>> {
+        let section = self.section;
+        if process::interrupted() {
+            return Err(self.fail(Error::Cancelled(section.title.clone())));
+        }
+
+        let code_block = &section.codes[index];
+        let id = block_id(&section.title, index);
+        if self.config.ignores_language(&code_block.lang) {
+            debug!(
+                block = %id,
+                lang = %code_block.lang,
+                "skipping block without a runnable language"
+            );
+            return Ok(None);
+        }
+
+        if let Some(reason) = condition::skip_reason(code_block.meta.as_deref()) {
+            info!(block = %id, %reason, "skipping code block");
+            self.skip_block(index, &code_block.lang, reason);
+            return Ok(None);
+        }
+
+        if self.dry_run {
+            info!(block = %id, "not running code block in a dry run");
+            self.skip_block(index, &code_block.lang, DRY_RUN.to_string());
+            return Ok(None);
+        }
+
+        let code_block = match self.step.map(|step| step(section, index, code_block)) {
+            None | Some(StepAction::Run) => Cow::Borrowed(code_block),
+            Some(StepAction::RunEdited(code)) => Cow::Owned(CodeBlock {
+                code,
+                ..code_block.clone()
+            }),
+            Some(StepAction::Skip) => {
+                info!(block = %id, "skipping code block as asked");
+                let reason = "skipped by the user".to_string();
+                self.skip_block(index, &code_block.lang, reason);
+                return Ok(None);
+            }
+            Some(StepAction::Abort) => {
+                return Err(self.fail(Error::Cancelled(section.title.clone())));
+            }
+        };
+
+        self.emit_progress(|| {
+            ProgressFrame::block_start(
+                &section.title,
+                index,
+                &code_block.lang,
+                section.codes.len(),
+                self.started.elapsed(),
+            )
+        });
+        self.notify(|observer| observer.on_block_start(section, index, &code_block));
+        Ok(Some(code_block))
+    }
+
+    /// Record how a code block exited, failing the task if it did not succeed
+    pub(crate) fn finish_block(
+        &mut self,
+        index: usize,
+        code_block: &CodeBlock,
+        exit: Result<(ExitStatus, String)>,
+        duration: Duration,
+    ) -> Result<()> {
+        let id = block_id(&self.section.title, index);
+        let exit_code = exit.as_ref().ok().and_then(|(status, _)| status.code());
+        let result = if process::interrupted() {
+            Err(Error::Cancelled(self.section.title.clone()))
+        } else {
+            exit.and_then(|(status, program)| check_status(&code_block.lang, &program, status))
+        };
+        info!(
+            block = %id,
+            lang = %code_block.lang,
+            elapsed_ms = duration.as_millis() as u64,
+            success = result.is_ok(),
+            "finished code block"
+        );
+
+        let block = BlockReport {
+            id,
+            index,
+            lang: code_block.lang.clone(),
+            duration,
+            skipped: None,
+        };
+        self.notify_block_complete(&block, exit_code, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                self.report.blocks.push(block);
+                Ok(())
+            }
+            Err(e @ Error::Cancelled(_)) => Err(self.fail(e)),
+            Err(e) => Err(self.fail(Error::BlockFailed {
+                task: self.section.title.clone(),
+                file: code_block.file.clone(),
+                block: index,
+                line: code_block.line,
+                span: code_block.span.clone(),
+                exit_code,
+                source: Box::new(e),
+            })),
+        }
+    }
+
+    /// Report the task as failed with an error, which is returned
+    pub(crate) fn fail(&mut self, error: Error) -> Error {
+        self.report.duration = self.started.elapsed();
+        if self.begun {
+            self.emit_progress(|| ProgressFrame::TaskFinish {
+                task: self.section.title.clone(),
+                total_blocks: self.section.codes.len(),
+                percent: 100.0,
+                success: false,
+            });
+        }
+        self.notify(|observer| observer.on_section_complete(&self.report, false));
+        error
+    }
+
+    /// Report the task as finished, returning its report
+    pub(crate) fn finish(mut self) -> ExecutionReport {
+        if self.report.skipped.is_none() {
+            self.report.duration = self.started.elapsed();
+            self.emit_progress(|| ProgressFrame::TaskFinish {
+                task: self.section.title.clone(),
+                total_blocks: self.section.codes.len(),
+                percent: 100.0,
+                success: true,
+            });
+        }
+        self.notify(|observer| observer.on_section_complete(&self.report, true));
+        self.report
+    }
+
+    /// Report a code block as skipped for a reason
+    fn skip_block(&mut self, index: usize, lang: &str, reason: String) {
+        let block = BlockReport {
+            id: block_id(&self.section.title, index),
+            index,
+            lang: lang.to_string(),
+            duration: Duration::ZERO,
+            skipped: Some(reason),
+        };
+        self.notify_block_complete(&block, None, true);
+        self.report.blocks.push(block);
+    }
+
+    /// Emit a progress frame if a progress descriptor is configured
+    fn emit_progress(&self, frame: impl FnOnce() -> ProgressFrame) {
+        if let Some(progress) = self.progress {
+            progress.emit(&frame());
+        }
+    }
+
+    /// Call every observer in registration order
+    fn notify(&self, call: impl Fn(&dyn RunnerObserver)) {
+        for observer in self.observers {
+            call(observer.as_ref());
+        }
+    }
+
+    fn notify_block_complete(&self, block: &BlockReport, exit_code: Option<i32>, success: bool) {
+        if self.observers.is_empty() {
+            return;
+        }
+
+        let outcome = BlockOutcome {
+            report: block.clone(),
+            exit_code,
+            success,
+        };
+        self.notify(|observer| observer.on_block_complete(self.section, &outcome));
+    }
+}
+
+/// Turn an unsuccessful exit status of the program running a code block into an error
+pub(crate) fn check_status(lang: &str, program: &str, status: ExitStatus) -> Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::CommandFailed {
+            lang: lang.to_string(),
+            command: program.to_string(),
+            status,
+        })
+    }
+}
+
+/// Up to three section titles closest to a task name that matched none, closest first
+fn closest_titles(sections: &[Section], name: &str) -> Vec<String> {
+    let name = normalize_title(name);
+    let mut candidates: Vec<(usize, &str)> = sections
+        .iter()
+        .filter_map(|section| {
+            let title = normalize_title(&section.title);
+            let distance = if !name.is_empty() && title.contains(&name) {
+                0
+            } else {
+                edit_distance(&name, &title)
+            };
+            (distance <= (title.chars().count() / 3).max(2)).then_some((distance, &*section.title))
+        })
+        .collect();
+    candidates.sort_by_key(|(distance, _)| *distance);
+
+    let mut titles: Vec<String> = Vec::new();
+    for (_, title) in candidates {
+        if !titles.iter().any(|t| t == title) && titles.len() < 3 {
+            titles.push(title.to_string());
+        }
+    }
+    titles
+}
+
+/// Lock a cache of the runner; a panic while it was held cannot leave it inconsistent
+fn locked<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Record the file that sections and their code blocks were read from
+fn with_file(mut sections: Vec<Section>, path: &Path) -> Vec<Section> {
+    for section in &mut sections {
+        section.file = Some(path.to_path_buf());
+        for code in &mut section.codes {
+            code.file = Some(path.to_path_buf());
+        }
+    }
+    sections
+}
+
+/// Where a task is defined, e.g. `README.md:12 (under Setup)`
+fn task_location(path: &Path, section: &Section) -> String {
+    let mut location = path.display().to_string();
+    if let Some(line) = section.line {
+        location.push_str(&format!(":{}", line));
+    }
+    if let Some(parent) = &section.parent {
+        location.push_str(&format!(" (under {})", parent));
+    }
+    location
+}
+
+/// Log the command line a code block is spawned with, after every wrapper was applied
+///
+/// Secrets are masked, since remote scripts export the environment on their command line.
+pub(crate) fn log_command(command: &Command, secrets: &Secrets) {
+    if !tracing::enabled!(tracing::Level::DEBUG) {
+        return;
+    }
+    let argv = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy());
+    debug!(command = %secrets.mask_str(&shell_words::join(argv)), "spawning process");
+}
+
+/// A code block resolved into the process running it
+pub(crate) struct Invocation {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+    pub(crate) env: Vec<(String, String)>,
+    /// Code to write to the process' stdin, in stdin mode
+    pub(crate) stdin: Option<String>,
+    /// Working directory, also passed in `MX_ROOT`
+    pub(crate) root: Option<PathBuf>,
+    /// Sandbox wrapping the command, see [`crate::sandbox`]
+    pub(crate) sandbox: Option<Policy>,
+    /// Version manager resolving the program once the working directory is known, see
+    /// [`crate::toolchain`]
+    tools: Option<ToolEnv>,
+    /// Run in a pseudo-terminal, see [`crate::pty`]
+    pub(crate) pty: bool,
+    /// Leave stdin, stdout and stderr to the terminal, see [`CodeBlock::is_interactive`]
+    pub(crate) interactive: bool,
+    /// Directory holding the code file and the arguments file, if either is needed
+    ///
+    /// Removed when the invocation is dropped, unless `keep_temp` is set.
+    temp_dir: Option<TempDir>,
+    keep_temp: bool,
+}
+
+impl Invocation {
+    /// Resolve the runtime of a code block of a task, if any, into the process to spawn
+    pub(crate) fn prepare(
+        config: &Config,
+        forward_stdin: bool,
+        block: &CodeBlock,
+        task_args: &[String],
+        section: Option<&Section>,
+        host: Option<&str>,
+        secrets: &Secrets,
+    ) -> Result<Self> {
+        let (lang, code) = (block.lang.as_str(), block.code.as_str());
+        let runtime_config = config
+            .runtime_for(block, section)?
+            .ok_or_else(|| Error::RuntimeNotFound(lang.to_string()))?;
+        let runtime = runtime_config.command();
+        let runtime_args = runtime_config.args();
+        let shell = runtime_config.shell();
+
+        // A shell command line is left for `sh` to parse
+        let parts = if shell {
+            vec![runtime.to_string()]
+        } else {
+            runtime_config.command_argv()?
+        };
+        if parts.is_empty() || runtime.trim().is_empty() {
+            return Err(Error::RuntimeNotFound(lang.to_string()));
+        }
+
+        let remote = Remote::resolve(config, lang, section, host);
+
+        // Get execution mode from config
+        let mut execution_mode = runtime_config.execution_mode();
+        if remote.is_some() && forward_stdin {
+            debug!(
+                lang,
+                "stdin carries the code to the remote host and is not forwarded"
+            );
+        } else if forward_stdin && execution_mode == ExecutionMode::Stdin {
+            debug!(lang, "stdin is forwarded; passing code as a file instead");
+            execution_mode = ExecutionMode::File;
+        }
+        let pty = block
+            .attribute("pty")
+            .map_or(runtime_config.pty(), |value| value != "false");
+        let interactive = block.is_interactive(section);
+        if (pty || interactive) && remote.is_none() && execution_mode == ExecutionMode::Stdin {
+            debug!(
+                lang,
+                "stdin is the terminal; passing code as a file instead"
+            );
+            execution_mode = ExecutionMode::File;
+        }
+        // A shell reading its script from stdin would hand the rest of it to the block's
+        // commands as their input
+        let reads_stdin = block.attribute("reads-stdin").map_or_else(
+            || shell::is_shell(&runtime) && shell::reads_stdin(code),
+            |value| value != "false",
+        );
+        let mut code_flag = false;
+        if reads_stdin && execution_mode == ExecutionMode::Stdin {
+            execution_mode = match &config.shell_stdin {
+                ExecutionMode::Arg if shell::is_shell(&runtime) => {
+                    code_flag = true;
+                    ExecutionMode::Arg
+                }
+                ExecutionMode::Stdin => ExecutionMode::Stdin,
+                _ => ExecutionMode::File,
+            };
+            debug!(lang, mode = %execution_mode, "the block reads stdin; moving its code off it");
+        }
+        debug!(lang, %runtime, mode = ?execution_mode, "selected runtime");
+
+        let mut env = Vec::new();
+        for (key, value) in runtime_config.env() {
+            let value = secrets.resolve(config, value)?;
+            if config.is_sensitive(key) {
+                secrets.mark_sensitive(&value);
+            }
+            env.push((key.clone(), value));
+        }
+        env.extend(Runner::prepare_env_vars(task_args));
+        let mut invocation = Self {
+            program: parts[0].clone(),
+            args: parts[1..].iter().chain(runtime_args).cloned().collect(),
+            env,
+            stdin: None,
+            root: None,
+            sandbox: Policy::resolve(config, lang, section),
+            tools: config.tool_env,
+            pty,
+            interactive,
+            temp_dir: None,
+            keep_temp: config.keep_temp,
+        };
+
+        let mut file = None;
+        let mut code_placed = false;
+        let steps = runtime_config.steps();
+        if !steps.is_empty() {
+            // Every step runs in turn through one `sh -c` script, in the temporary directory
+            // that holds the code file and the compiled program
+            let path = invocation.temp_dir()?.write_code(lang, code)?;
+            let dir = invocation.temp_dir()?.0.path().to_path_buf();
+            let out = dir.join(format!("main{}", std::env::consts::EXE_SUFFIX));
+            let subs = Substitutions {
+                file: Some(&path),
+                code,
+                args: task_args,
+                dir: Some(&dir),
+                out: Some(&out),
+            };
+            let script = command::build_steps_script(steps, &subs)?;
+            debug!(script = %script, "running pipeline steps");
+            invocation.program = "sh".to_string();
+            invocation.args = vec!["-c".to_string(), script];
+            file = Some(path);
+            code_placed = true;
+        } else if shell || command::has_placeholders(&runtime) {
+            // Substitution happens on the argument vector; with `shell` the command runs
+            // through `sh -c` and every substituted value is quoted.
+            if command::uses_file(&runtime) {
+                file = Some(invocation.temp_dir()?.write_code(lang, code)?);
+            }
+            let dir = if command::uses_dir(&runtime) {
+                Some(invocation.temp_dir()?.0.path().to_path_buf())
+            } else {
+                None
+            };
+            let subs = Substitutions {
+                file: file.as_deref(),
+                code,
+                args: task_args,
+                dir: dir.as_deref(),
+                out: None,
+            };
+
+            // Runtime arguments go right after the program
+            let argv = if shell {
+                let script = command::build_shell_script(&runtime, &subs);
+                let script = command::insert_shell_args(&script, runtime_args);
+                vec!["sh".to_string(), "-c".to_string(), script]
+            } else {
+                let mut argv = command::build_argv(&parts, &subs);
+                argv.splice(1..1, runtime_args.iter().cloned());
+                argv
+            };
+            invocation.program = argv[0].clone();
+            invocation.args = argv[1..].to_vec();
+            debug!(argv = ?argv, "built command from template");
+            code_placed = command::places_code(&runtime);
+        }
+
+        // Without `{file}` or `{code}`, the execution mode says where the code goes
+        if !code_placed {
+            match execution_mode {
+                ExecutionMode::File => {
+                    let path = invocation.temp_dir()?.write_code(lang, code)?;
+                    invocation.push_arg(shell, &path.to_string_lossy());
+                    debug!(file = %path.display(), "passing code as a file");
+                    file = Some(path);
+                }
+                ExecutionMode::Arg => {
+                    // Append code as an argument to the command
+                    if code_flag {
+                        invocation.push_arg(shell, "-c");
+                    }
+                    invocation.push_arg(shell, code);
+                    debug!("passing code as an argument");
+                }
+                ExecutionMode::Stdin => {
+                    invocation.stdin = Some(code.to_string());
+                    debug!("passing code on stdin");
+                }
+            }
+        }
+
+        if let Some(remote) = &remote {
+            invocation.run_on(remote, file.as_deref(), code, secrets);
+            invocation.tools = None;
+            invocation.pty = false;
+            invocation.interactive = false;
+        } else if let Some(shell) = DevShell::resolve(config, section)? {
+            let (program, args) = shell.wrap(&invocation.program, &invocation.args);
+            debug!(shell = ?shell.argv, "running code block in a dev shell");
+            invocation.program = program;
+            invocation.args = args;
+            invocation.tools = None;
+        }
+        invocation.fit_to_limits(lang, task_args)?;
+        Ok(invocation)
+    }
+
+    /// Let the version manager of `tool_env` resolve the program, in the working directory
+    pub(crate) fn resolve_tools(&mut self) -> Result<()> {
+        if let Some(tools) = self.tools.take() {
+            let (program, args) = tools.resolve(&self.program, &self.args, self.root.as_deref())?;
+            self.program = program;
+            self.args = args;
+        }
+        Ok(())
+    }
+
+    /// Append an argument to the command, or to the `sh -c` script when `shell` is set
+    fn push_arg(&mut self, shell: bool, arg: &str) {
+        match self.args.last_mut() {
+            Some(script) if shell => {
+                script.push(' ');
+                script.push_str(&command::shell_quote(arg));
+            }
+            _ => self.args.push(arg.to_string()),
+        }
+    }
+
+    /// Run the command on a remote host or in a pod instead
+    ///
+    /// A local code file is shipped over stdin to a remote path named after it, and the
+    /// environment is exported by the remote script. Sandboxes only apply to local processes.
+    fn run_on(&mut self, remote: &Remote, file: Option<&Path>, code: &str, secrets: &Secrets) {
+        let mut argv: Vec<String> = self.argv().cloned().collect();
+        let remote_file = file.map(|file| {
+            // The local temporary directory has a unique name
+            let dir = file.parent().and_then(Path::file_name).unwrap_or_default();
+            let name = file.file_name().unwrap_or_default();
+            let remote_file = format!("/tmp/{}-{}", dir.to_string_lossy(), name.to_string_lossy());
+            let local = file.to_string_lossy();
+            for arg in &mut argv {
+                *arg = arg.replace(local.as_ref(), &remote_file);
+            }
+            self.stdin = Some(code.to_string());
+            remote_file
+        });
+
+        let script = remote.script(&argv, &self.env, remote_file.as_deref());
+        let masked = secrets.mask_str(&script);
+        debug!(target = %remote, script = %masked, "running code block remotely");
+        self.program = remote.program().to_string();
+        self.args = remote.args(&script);
+        self.env.clear();
+        self.sandbox = None;
+    }
+
+    /// Keep the command line and environment within the platform limits
+    ///
+    /// Task arguments that do not fit in the environment are written to a file instead, each
+    /// followed by a NUL byte, and its path is passed in `MX_ARGS_FILE`.
+    fn fit_to_limits(&mut self, lang: &str, task_args: &[String]) -> Result<()> {
+        let fixed =
+            limits::inherited_env_size() + self.argv().map(limits::string_size).sum::<usize>();
+        let env_size = |env: &[(String, String)]| {
+            env.iter()
+                .map(|(key, value)| limits::env_entry_size(key, value))
+                .sum::<usize>()
+        };
+
+        let env_fits = self
+            .env
+            .iter()
+            .all(|(key, value)| limits::fits_string(key.len() + 1 + value.len()))
+            && fixed + env_size(&self.env) <= limits::budget();
+        if !env_fits {
+            let contents: String = task_args.iter().map(|arg| format!("{}\0", arg)).collect();
+            let file = self.temp_dir()?.write("args", contents.as_bytes())?;
+            debug!(
+                file = %file.display(),
+                "task arguments exceed the environment size limit; passing them in a file"
+            );
+            self.env
+                .retain(|(key, _)| key != "MX_ARGS" && !key.starts_with("MX_ARG_"));
+            self.env.push((
+                "MX_ARGS_FILE".to_string(),
+                file.to_string_lossy().into_owned(),
+            ));
+        }
+
+        let longest = self.argv().map(String::len).max().unwrap_or_default();
+        if !limits::fits_string(longest) {
+            return Err(Error::CommandTooLarge {
+                lang: lang.to_string(),
+                size: longest,
+                limit: limits::MAX_STRING_LEN,
+            });
+        }
+
+        let size = fixed + env_size(&self.env);
+        if size > limits::budget() {
+            return Err(Error::CommandTooLarge {
+                lang: lang.to_string(),
+                size,
+                limit: limits::budget(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn argv(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.program).chain(&self.args)
+    }
+
+    /// The temporary directory of this invocation, created on first use
+    fn temp_dir(&mut self) -> Result<&TempDir> {
+        let dir = match self.temp_dir.take() {
+            Some(dir) => dir,
+            None => TempDir::new(self.keep_temp)?,
+        };
+        Ok(self.temp_dir.insert(dir))
+    }
+
+    /// The command to spawn, without stdio configured
+    pub(crate) fn command(&self) -> Command {
+        let mut command = match &self.sandbox {
+            Some(policy) => {
+                let writable: Vec<&Path> = (self.root.iter().map(PathBuf::as_path))
+                    .chain(self.temp_dir.iter().map(|dir| dir.0.path()))
+                    .collect();
+                policy.command(&self.program, &self.args, &writable)
+            }
+            None => {
+                let mut command = Command::new(&self.program);
+                command.args(&self.args);
+                command
+            }
+        };
+        command.envs(self.env.iter().cloned());
+        if let Some(root) = &self.root {
+            command.current_dir(root).env("MX_ROOT", root);
+        }
+        command
+    }
+}
+
+/// A private, uniquely named temporary directory for one execution, removed on drop
+///
+/// Removal happens on unwinding too; with `keep`, the directory is left in place for
+/// inspecting the generated files.
+struct TempDir(tempfile::TempDir);
+
+impl TempDir {
+    fn new(keep: bool) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("mx_")
+            .disable_cleanup(keep)
+            .tempdir()
+            .map_err(|e| Error::Execution(format!("Failed to create temp directory: {}", e)))?;
+        if keep {
+            info!(dir = %dir.path().display(), "keeping temporary files");
+        }
+        Ok(Self(dir))
+    }
+
+    /// Write code to `main.<ext>`, with an extension matching its language
+    fn write_code(&self, lang: &str, code: &str) -> Result<PathBuf> {
+        // Use language name as file extension, or map known languages
+        let file_ext = match lang {
+            "go" | "golang" => "go",
+            "python" => "py",
+            "ruby" => "rb",
+            "javascript" | "js" => "js",
+            "typescript" | "ts" | "deno" => "ts",
+            "rust" => "rs",
+            "c++" => "cpp",
+            "r" => "R",
+            "julia" => "jl",
+            "powershell" | "pwsh" => "ps1",
+            _ => lang, // Use language name as extension for custom languages
+        };
+
+        self.write(&format!("main.{}", file_ext), code.as_bytes())
+    }
+
+    /// Write contents to a file in the directory
+    fn write(&self, name: &str, contents: &[u8]) -> Result<PathBuf> {
+        let path = self.0.path().join(name);
+        fs::write(&path, contents)
+            .map_err(|e| Error::Execution(format!("Failed to write temp file: {}", e)))?;
+        Ok(path)
+    }
+}
+
+/// Where the output of a code block goes
+#[derive(Clone, Copy)]
+pub(crate) struct BlockOutput<'a> {
+    pub(crate) handler: Option<&'a OutputHandler>,
+    pub(crate) log: Option<&'a BlockLog>,
+    /// Block ID and the observers to notify, when running as part of a section
+    pub(crate) observers: Option<(&'a str, &'a [Arc<dyn RunnerObserver>])>,
+    /// Secrets to mask in the output
+    pub(crate) secrets: &'a Secrets,
+}
+
+impl BlockOutput<'_> {
+    /// Stdio for the process: inherited to preserve TTY and colors unless output is captured
+    ///
+    /// Output is captured while there are secrets or sensitive values to mask.
+    pub(crate) fn stdio(&self) -> Stdio {
+        let observed = self
+            .observers
+            .is_some_and(|(_, observers)| !observers.is_empty());
+        if self.handler.is_some() || self.log.is_some() || observed || !self.secrets.is_empty() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        }
+    }
+
+    /// Pass a chunk of captured output on to the log, the observers and the handler (or the
+    /// terminal)
+    ///
+    /// The chunk is expected to be masked already, see [`MaskedStream`].
+    pub(crate) fn write(&self, stream: OutputStream, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if let Some(log) = self.log {
+            log.write(stream, data);
+        }
+        if let Some((id, observers)) = self.observers {
+            for observer in observers {
+                observer.on_block_output(id, stream, data);
+            }
+        }
+
+        if let Some(handler) = self.handler {
+            handler(stream, data);
+        } else {
+            let _ = match stream {
+                OutputStream::Stdout => std::io::stdout().lock().write_all(data),
+                OutputStream::Stderr => std::io::stderr().lock().write_all(data),
+            };
+        }
+    }
+}
+
+/// Write code to the child's stdin if given, forward piped output, and wait for it to exit
+fn wait_child(
+    mut child: Child,
+    stdin: Option<&str>,
+    output: BlockOutput<'_>,
+) -> Result<ExitStatus> {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    std::thread::scope(|scope| {
+        // Output is drained on separate threads so a chatty process cannot block on a full
+        // pipe while we are still writing its stdin.
+        if let Some(stdout) = stdout {
+            scope.spawn(move || forward_output(stdout, OutputStream::Stdout, output));
+        }
+        if let Some(stderr) = stderr {
+            scope.spawn(move || forward_output(stderr, OutputStream::Stderr, output));
+        }
+
+        if let (Some(code), Some(mut input)) = (stdin, child.stdin.take()) {
+            input
+                .write_all(code.as_bytes())
+                .map_err(|e| Error::Execution(format!("Failed to write to stdin: {}", e)))?;
+        }
+
+        child
+            .wait()
+            .map_err(|e| Error::Execution(format!("Failed to wait for process: {}", e)))
+    })
+}
+
+/// Read a process output stream to the end, passing each chunk on
+pub(crate) fn forward_output(mut reader: impl Read, stream: OutputStream, output: BlockOutput<'_>) {
+    let mut buffer = [0; 8192];
+    let mut masked = MaskedStream::new(output.secrets);
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => output.write(stream, &masked.push(&buffer[..n])),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                warn!("failed to read process output: {}", e);
+                break;
+            }
+        }
+    }
+    output.write(stream, &masked.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{RuntimeConfig, TitleMatch};
+    use crate::events::{BlockOutcome, RunEvent};
+    use crate::secrets::SecretSource;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_runner_creation() {
+        let runner = Runner::with_default_config();
+        assert_eq!(runner.config.heading_level, 2);
+    }
+
+    /// Extractor failing every extraction
+    struct Broken;
+
+    impl SectionExtractor for Broken {
+        fn extract(&mut self, _markdown: &str, _level: u8) -> Result<Vec<Section>> {
+            Err(Error::Query("broken".to_string()))
+        }
+    }
+
+    #[cfg(feature = "mq")]
+    #[test]
+    fn test_query_error() {
+        let runner = Runner::with_default_config();
+        let err = runner
+            .query("## Build\n", "this is | not ( a query")
+            .unwrap_err();
+        assert!(matches!(err, Error::Query(_)));
+    }
+
+    #[cfg(feature = "cmark")]
+    #[test]
+    fn test_mdx_sections() {
+        use crate::extract::CmarkExtractor;
+
+        let markdown = "import Tabs from '@theme/Tabs';\n\n## Install {#setup}\n\n:::info\n\
+                        Needs Node.\n:::\n\n<Tabs>\n<TabItem value=\"npm\">\n\
+                        ```bash title=\"Install with npm\"\nnpm install\n```\n</TabItem>\n\
+                        </Tabs>\n";
+        let temp = tempfile::tempdir().unwrap();
+        let mdx = temp.path().join("install.mdx");
+        let plain = temp.path().join("install.md");
+        fs::write(&mdx, markdown).unwrap();
+        fs::write(&plain, markdown).unwrap();
+        let runner = Runner::with_default_config().with_extractor(CmarkExtractor);
+
+        // Plain Markdown is read as written
+        let sections = runner.list_task_sections(&plain).unwrap();
+        let description = sections[0].description.as_deref().unwrap_or_default();
+        assert!(description.contains("<Tabs>"), "{:?}", description);
+
+        let sections = runner.list_task_sections(&mdx).unwrap();
+        assert_eq!(sections.len(), 1);
+        let install = &sections[0];
+        assert_eq!(install.title, "Install");
+        assert_eq!(install.anchor(), "setup");
+        assert_eq!(install.description.as_deref(), Some("Needs Node."));
+        assert_eq!(install.codes.len(), 1);
+        assert_eq!(install.codes[0].code, "npm install");
+        assert_eq!(install.codes[0].line, Some(11));
+        assert_eq!(
+            install.codes[0].title().as_deref(),
+            Some("Install with npm")
+        );
+    }
+
+    #[test]
+    fn test_section_languages() {
+        let block = |lang: &str| CodeBlock {
+            lang: lang.to_string(),
+            ..Default::default()
+        };
+        let section = Section {
+            codes: vec![block("bash"), block(""), block("python"), block("bash")],
+            ..Default::default()
+        };
+
+        assert_eq!(section.languages(), ["bash", "python"]);
+    }
+
+    #[test]
+    fn test_extract_sections() {
+        let markdown = r#"# Title
+
+## Task 1
+
+```bash
 echo "hello"
 ```
 
-## Task 2
+## Task 2
+
+```python
+print("world")
+```
+"#;
+
+        let runner = Runner::with_default_config();
+        let sections = runner.extract_sections(markdown).unwrap();
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].title, "Task 1");
+        assert_eq!(sections[0].codes.len(), 1);
+        assert_eq!(sections[0].codes[0].lang, "bash");
+    }
+
+    #[test]
+    fn test_repeated_extraction_uses_cache() {
+        let markdown = "## Build\n\n```bash\nmake\n```\n";
+        let mut runner = Runner::with_default_config();
+
+        let first = runner.extract_sections(markdown).unwrap();
+        assert_eq!(runner.section_cache.lock().unwrap().len(), 1);
+
+        // A cached document is never evaluated again, even by a broken extractor
+        runner.extractor = Mutex::new(Box::new(Broken));
+        for _ in 0..100 {
+            let again = runner.extract_sections(markdown).unwrap();
+            assert_eq!(again.len(), first.len());
+        }
+        assert_eq!(runner.section_cache.lock().unwrap().len(), 1);
+
+        assert!(runner.extract_sections("## Other\n").is_err());
+    }
+
+    /// Reads a task per line, as `Title` or `Title: dependency`, counting the documents it
+    /// extracts
+    struct CountingTasks(Arc<AtomicUsize>);
+
+    impl SectionExtractor for CountingTasks {
+        fn extract(&mut self, markdown: &str, _level: u8) -> Result<Vec<Section>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let task = |line: &str| {
+                let (title, depends) = line.split_once(": ").unwrap_or((line, ""));
+                Section {
+                    title: title.to_string(),
+                    level: 2,
+                    metadata: BTreeMap::from([("depends".to_string(), depends.to_string())]),
+                    ..Default::default()
+                }
+            };
+            Ok(markdown.lines().map(task).collect())
+        }
+    }
+
+    #[test]
+    fn test_cached_document_is_not_extracted_again() {
+        let extractions = Arc::new(AtomicUsize::new(0));
+        let runner =
+            Runner::with_default_config().with_extractor(CountingTasks(Arc::clone(&extractions)));
+
+        for _ in 0..100 {
+            let sections = runner.extract_sections("Build\nTest").unwrap();
+            assert_eq!(sections.len(), 2);
+        }
+        assert_eq!(extractions.load(Ordering::SeqCst), 1);
+
+        runner.extract_sections("Build").unwrap();
+        assert_eq!(extractions.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_repeated_runs_reuse_extracted_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let readme = temp.path().join("README.md");
+        let db = temp.path().join("db.md");
+        fs::write(&readme, "Deploy: db.md#Migrate\nBuild").unwrap();
+        fs::write(&db, "Migrate").unwrap();
+        let extractions = Arc::new(AtomicUsize::new(0));
+        let runner =
+            Runner::with_default_config().with_extractor(CountingTasks(Arc::clone(&extractions)));
+
+        // A long-lived runner, such as the dashboard's, resolves a task again for every run
+        let deploy = runner.find_task_in_files(&[&readme], "Deploy").unwrap().1;
+        for _ in 0..100 {
+            let queue = runner.resolve_dependencies(&readme, &deploy).unwrap();
+            let titles: Vec<_> = queue.iter().map(|(_, s)| s.title.as_str()).collect();
+            assert_eq!(titles, ["Migrate", "Deploy"]);
+        }
+        assert_eq!(extractions.load(Ordering::SeqCst), 2);
+
+        // An edited file is extracted again, the other one is not
+        fs::write(&db, "Migrate\nSeed").unwrap();
+        runner.resolve_dependencies(&readme, &deploy).unwrap();
+        assert_eq!(extractions.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_unchanged_file_is_not_read_again() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("tasks.md");
+        let task = |title: &str| Section {
+            title: title.to_string(),
+            level: 2,
+            ..Default::default()
+        };
+        let mut runner = Runner::with_default_config();
+        let write = |runner: &mut Runner, markdown: &str, title: &str| {
+            fs::write(&path, markdown).unwrap();
+            runner
+                .section_cache
+                .lock()
+                .unwrap()
+                .insert(content_hash(markdown), vec![task(title)]);
+        };
+
+        write(&mut runner, "## Build\n", "Build");
+        let sections = runner.list_task_sections(&path).unwrap();
+        assert_eq!(sections[0].title, "Build");
+        assert_eq!(runner.file_cache.lock().unwrap().len(), 1);
+
+        // Served from the cache while the file is unchanged, without extracting it again
+        runner
+            .section_cache
+            .lock()
+            .unwrap()
+            .values_mut()
+            .for_each(|s| s[0].title = "Cached".to_string());
+        runner.extractor = Mutex::new(Box::new(Broken));
+        assert_eq!(runner.list_task_sections(&path).unwrap()[0].title, "Cached");
+
+        write(&mut runner, "## Deploy\n", "Deploy");
+        assert_eq!(runner.list_task_sections(&path).unwrap()[0].title, "Deploy");
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            runner.list_task_sections(&path),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_sections_record_their_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("tasks.md");
+        let markdown = "## Build\n\n```bash\nmake\n```\n";
+        fs::write(&path, markdown).unwrap();
+        let runner = Runner::with_default_config();
+        let section = Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "bash".to_string(),
+                code: "make".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        runner
+            .section_cache
+            .lock()
+            .unwrap()
+            .insert(content_hash(markdown), vec![section]);
+
+        for _ in 0..2 {
+            let sections = runner.list_task_sections(&path).unwrap();
+            assert_eq!(sections[0].file.as_deref(), Some(path.as_path()));
+            assert_eq!(sections[0].codes[0].file.as_deref(), Some(path.as_path()));
+        }
+        assert_eq!(runner.extract_sections(markdown).unwrap()[0].file, None);
+    }
+
+    #[test]
+    fn test_section_anchor_custom_id() {
+        let section = Section {
+            title: "Task 4".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(section.anchor(), "task-4");
+
+        let custom_id = Section {
+            meta: Some("#fourth".to_string()),
+            ..section
+        };
+        assert_eq!(custom_id.anchor(), "fourth");
+    }
+
+    #[test]
+    fn test_find_section() {
+        let sections = vec![
+            Section {
+                title: "Task 1".to_string(),
+                level: 2,
+                ..Default::default()
+            },
+            Section {
+                title: "Task 2".to_string(),
+                level: 2,
+                ..Default::default()
+            },
+        ];
+
+        let runner = Runner::with_default_config();
+        let found = runner.find_section(&sections, "Task 1");
+        assert!(found.is_some());
+        assert_eq!(found.unwrap().title, "Task 1");
+
+        let not_found = runner.find_section(&sections, "Task 3");
+        assert!(not_found.is_none());
+
+        let by_anchor = runner.find_section(&sections, "#task-2");
+        assert_eq!(by_anchor.unwrap().title, "Task 2");
+        assert!(runner.find_section(&sections, "#task-3").is_none());
+        assert!(runner.find_section(&sections, "task-2").is_none());
+
+        let mut runner = Runner::with_default_config();
+        runner.config.title_match = TitleMatch::Regex;
+        let by_pattern = runner.find_section(&sections, "task [2-3]");
+        assert_eq!(by_pattern.unwrap().title, "Task 2");
+        assert!(runner.find_section(&sections, "task (").is_none());
+        assert!(matches!(
+            runner.task_not_found(&sections, "task ("),
+            Error::Config(message) if message.contains("task (")
+        ));
+        assert!(matches!(
+            runner.task_not_found(&sections, "task 5"),
+            Error::SectionNotFound { suggestions, .. } if suggestions == ["Task 1", "Task 2"]
+        ));
+    }
+
+    #[test]
+    fn test_task_matching_several_titles_is_ambiguous() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("README.md");
+        let markdown = "## Deploy web\n\n## Deploy db\n";
+        fs::write(&path, markdown).unwrap();
+        let sections: Vec<Section> = ["Deploy web", "Deploy db"]
+            .into_iter()
+            .map(|title| Section {
+                title: title.to_string(),
+                level: 2,
+                ..Default::default()
+            })
+            .collect();
+
+        let mut runner = Runner::with_default_config();
+        runner.config.title_match = TitleMatch::Contains;
+        runner
+            .section_cache
+            .lock()
+            .unwrap()
+            .insert(content_hash(markdown), sections);
+
+        let err = runner.find_task_in_files(&[&path], "deploy").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Task 'deploy' matches several tasks: 'Deploy web', 'Deploy db'"
+        );
+        let (_, section) = runner.find_task_in_files(&[&path], "deploy db").unwrap();
+        assert_eq!(section.title, "Deploy db");
+    }
+
+    #[test]
+    fn test_task_not_found_suggests_closest_titles() {
+        let sections: Vec<Section> = ["Build", "Deploy", "Build docs", "Test"]
+            .into_iter()
+            .map(|title| Section {
+                title: title.to_string(),
+                level: 2,
+                ..Default::default()
+            })
+            .collect();
+        let runner = Runner::with_default_config();
+
+        let err = runner.task_not_found(&sections, "biuld");
+        assert_eq!(
+            err.to_string(),
+            "Section not found: biuld; did you mean 'Build'?"
+        );
+        let Error::SectionNotFound { suggestions, .. } = runner.task_not_found(&sections, "docs")
+        else {
+            panic!("expected a section not found error");
+        };
+        assert_eq!(suggestions, ["Build docs"]);
+        assert_eq!(
+            runner.task_not_found(&sections, "buil").to_string(),
+            "Section not found: buil; did you mean one of 'Build', 'Build docs'?"
+        );
+        assert_eq!(
+            runner.task_not_found(&sections, "release").to_string(),
+            "Section not found: release"
+        );
+    }
+
+    #[test]
+    fn test_find_section_ignores_formatting() {
+        let sections: Vec<Section> = ["🚀 Deploy", "**Build** `mx`!", "Deploy preview"]
+            .into_iter()
+            .map(|title| Section {
+                title: title.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let runner = Runner::with_default_config();
+        let find = |name: &str| {
+            runner
+                .find_section(&sections, name)
+                .map(|section| section.title.as_str())
+        };
+
+        assert_eq!(find("deploy"), Some("🚀 Deploy"));
+        assert_eq!(find("DEPLOY  PREVIEW"), Some("Deploy preview"));
+        assert_eq!(find("build mx"), Some("**Build** `mx`!"));
+        assert_eq!(find("build"), None);
+        assert_eq!(find("🎉"), None);
+    }
 
-```python
-print("world")
-```
-"#;
+    #[test]
+    fn test_duplicate_titles() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let task = |title: &str, parent: &str, line: usize| Section {
+            title: title.to_string(),
+            level: 3,
+            parent: Some(parent.to_string()),
+            line: Some(line),
+            ..Default::default()
+        };
 
-        let mut runner = Runner::with_default_config();
-        let sections = runner.extract_sections(markdown).unwrap();
+        let runner = Runner::with_default_config();
+        let add_file = |name: &str, sections: Vec<Section>| {
+            let path = dir.join(name);
+            let markdown = format!("{:?}", path);
+            fs::write(&path, &markdown).unwrap();
+            runner
+                .section_cache
+                .lock()
+                .unwrap()
+                .insert(content_hash(&markdown), sections);
+            path
+        };
+        let ops = add_file(
+            "ops.md",
+            vec![
+                task("Deploy", "Staging", 3),
+                task("Build", "Staging", 6),
+                task("Deploy", "Production", 9),
+            ],
+        );
+        let web = add_file("web.md", vec![task("Build", "Web", 2)]);
 
-        assert_eq!(sections.len(), 2);
-        assert_eq!(sections[0].title, "Task 1");
-        assert_eq!(sections[0].codes.len(), 1);
-        assert_eq!(sections[0].codes[0].lang, "bash");
+        let err = runner.find_task_in_files(&[&ops], "Deploy").unwrap_err();
+        let Error::DuplicateTitle { task, locations } = err else {
+            panic!("expected a duplicate title error, got {:?}", err);
+        };
+        assert_eq!(task, "Deploy");
+        assert_eq!(
+            locations,
+            [
+                format!("{}:3 (under Staging)", ops.display()),
+                format!("{}:9 (under Production)", ops.display()),
+            ]
+        );
+
+        let (_, second) = runner.find_task_at(&[&ops], "Deploy", Some(1)).unwrap();
+        assert_eq!(second.line, Some(9));
+        assert!(matches!(
+            runner.find_task_at(&[&ops], "Deploy", Some(2)),
+            Err(Error::TaskIndexOutOfRange {
+                index: 2,
+                count: 2,
+                ..
+            })
+        ));
+        let (_, nested) = runner
+            .find_task_in_files(&[&ops], "production/deploy")
+            .unwrap();
+        assert_eq!(nested.line, Some(9));
+
+        assert!(matches!(
+            runner.find_task_in_files(&[&ops, &web], "Build"),
+            Err(Error::AmbiguousTask { .. })
+        ));
+        let (path, _) = runner
+            .find_task_at(&[&ops, &web], "Build", Some(1))
+            .unwrap();
+        assert_eq!(path, web);
     }
 
     #[test]
-    fn test_find_section() {
-        let sections = vec![
-            Section {
-                title: "Task 1".to_string(),
-                level: 2,
+    fn test_config_tasks() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("tasks.md");
+        let markdown = "## Build\n";
+        fs::write(&path, markdown).unwrap();
+        let config: Config = toml::from_str(
+            "[tasks.Build]\nrun = \"make\"\n\n[tasks.Clean]\nrun = \"rm -rf target\"\n",
+        )
+        .unwrap();
+        let runner = Runner::new(config);
+        let build = Section {
+            title: "Build".to_string(),
+            level: 2,
+            ..Default::default()
+        };
+        runner
+            .section_cache
+            .lock()
+            .unwrap()
+            .insert(content_hash(markdown), vec![build]);
+
+        let (found_in, clean) = runner.find_task_in_files(&[&path], "Clean").unwrap();
+        assert_eq!(found_in, path);
+        assert_eq!(clean.codes[0].lang, "sh");
+        assert_eq!(clean.codes[0].code, "rm -rf target");
+
+        // The Markdown task wins over the configuration's task with its title
+        let (_, build) = runner.find_task_in_files(&[&path], "Build").unwrap();
+        assert!(build.codes.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_dependencies() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        let task = |title: &str, depends: &str| Section {
+            title: title.to_string(),
+            level: 2,
+            metadata: BTreeMap::from([("depends".to_string(), depends.to_string())]),
+            ..Default::default()
+        };
+
+        // Files are read, but their sections come from the cache
+        let runner = Runner::with_default_config();
+        let add_file = |path: &Path, sections: Vec<Section>| {
+            let markdown = format!("{:?}", path);
+            fs::write(path, &markdown).unwrap();
+            runner
+                .section_cache
+                .lock()
+                .unwrap()
+                .insert(content_hash(&markdown), sections);
+        };
+        let main = dir.join("README.md");
+        let db = dir.join("docs").join("db.md");
+        add_file(
+            &main,
+            vec![
+                task("Deploy", "Build, docs/db.md#migrate"),
+                task("Build", ""),
+                task("Loop", "Loop"),
+                task("Broken", "docs/db.md#seed"),
+            ],
+        );
+        add_file(&db, vec![task("Migrate", "../README.md#build")]);
+
+        let sections = runner.list_task_sections(&main).unwrap();
+        let order = runner.resolve_dependencies(&main, &sections[0]).unwrap();
+        let titles: Vec<_> = order
+            .iter()
+            .map(|(path, section)| (section.title.as_str(), canonical(path.clone())))
+            .collect();
+        let main = canonical(main);
+        assert_eq!(
+            titles,
+            [
+                ("Build", main.clone()),
+                ("Migrate", canonical(db)),
+                ("Deploy", main.clone()),
+            ]
+        );
+
+        assert!(matches!(
+            runner.resolve_dependencies(&main, &sections[2]),
+            Err(Error::DependencyCycle(cycle)) if cycle == ["Loop", "Loop"]
+        ));
+        assert!(matches!(
+            runner.resolve_dependencies(&main, &sections[3]),
+            Err(Error::MissingDependency { dependency, .. }) if dependency == "docs/db.md#seed"
+        ));
+    }
+
+    #[test]
+    fn test_namespaced_tasks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("api")).unwrap();
+        let task = |title: &str, parent: Option<&str>, depends: &str| Section {
+            title: title.to_string(),
+            level: 2,
+            parent: parent.map(str::to_string),
+            metadata: BTreeMap::from([("depends".to_string(), depends.to_string())]),
+            ..Default::default()
+        };
+
+        let runner = Runner::with_default_config();
+        let add_file = |path: &Path, sections: Vec<Section>| {
+            let markdown = format!("{:?}", path);
+            fs::write(path, &markdown).unwrap();
+            runner
+                .section_cache
+                .lock()
+                .unwrap()
+                .insert(content_hash(&markdown), sections);
+        };
+        let db = dir.join("db.md");
+        let api = dir.join("api").join("README.md");
+        add_file(
+            &db,
+            vec![
+                task("Deploy", None, ""),
+                task("Staging", Some("Deploy"), "api:build"),
+                task("Migrate", None, ""),
+            ],
+        );
+        add_file(
+            &api,
+            vec![task("Build", None, ""), task("Migrate", None, "")],
+        );
+
+        let (path, section) = runner
+            .find_task_in_files(&[&db, &api], "db:migrate")
+            .unwrap();
+        assert_eq!((path, section.title.as_str()), (db.clone(), "Migrate"));
+        let (path, section) = runner
+            .find_task_in_files(&[&db, &api], "DB:Deploy:Staging")
+            .unwrap();
+        assert_eq!((path, section.title.as_str()), (db.clone(), "Staging"));
+        assert_eq!(
+            runner.qualified_name(&section, Some(&db)),
+            "db:deploy:staging"
+        );
+        assert_eq!(runner.qualified_name(&section, None), "deploy:staging");
+
+        let order = runner.resolve_dependencies(&db, &section).unwrap();
+        let titles: Vec<_> = order
+            .iter()
+            .map(|(path, section)| (section.title.as_str(), canonical(path.clone())))
+            .collect();
+        assert_eq!(
+            titles,
+            [("Build", canonical(api)), ("Staging", canonical(db))]
+        );
+    }
+
+    #[test]
+    fn test_execute_section_skips_guarded_blocks() {
+        let block = |meta: Option<&str>| CodeBlock {
+            lang: "sh".to_string(),
+            code: "true".to_string(),
+            meta: meta.map(str::to_string),
+            ..Default::default()
+        };
+        let mut section = Section {
+            title: "Install".to_string(),
+            level: 2,
+            codes: vec![block(Some("os=plan9")), block(None)],
+            ..Default::default()
+        };
+        let runner = Runner::with_default_config();
+
+        let report = runner.execute_section(&section).unwrap();
+        assert_eq!(report.skipped, None);
+        assert_eq!(
+            report.blocks[0].skipped.as_deref(),
+            Some(format!("os=plan9 (running on {})", std::env::consts::OS).as_str())
+        );
+        assert_eq!(report.blocks[1].skipped, None);
+
+        section.meta = Some("if-env=MX_TEST_SURELY_UNSET_VARIABLE".to_string());
+        let report = runner.execute_section(&section).unwrap();
+        assert!(report.skipped.is_some());
+        assert!(report.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_execute_section_skips_illustrative_blocks() {
+        let block = |lang: &str, meta: Option<&str>| CodeBlock {
+            lang: lang.to_string(),
+            code: "exit 1".to_string(),
+            meta: meta.map(str::to_string),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Deploy".to_string(),
+            level: 2,
+            codes: vec![block("sh", Some("no_run")), block("console", None)],
+            ..Default::default()
+        };
+
+        let report = Runner::with_default_config()
+            .execute_section(&section)
+            .unwrap();
+        assert_eq!(report.blocks.len(), 1);
+        assert_eq!(report.blocks[0].skipped.as_deref(), Some("marked no_run"));
+    }
+
+    #[test]
+    fn test_execute_selected_blocks() {
+        let block = |code: &str, meta: Option<&str>| CodeBlock {
+            lang: "sh".to_string(),
+            code: code.to_string(),
+            meta: meta.map(str::to_string),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Migrate".to_string(),
+            level: 2,
+            codes: vec![
+                block("exit 1", None),
+                block("true", Some("name=apply")),
+                block("true", None),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(section.find_block("apply"), Some(1));
+        assert_eq!(section.find_block("2"), Some(2));
+        assert_eq!(section.find_block("3"), None);
+
+        let runner = Runner::with_default_config();
+        let report = runner.execute_blocks(&section, &[], 1..2).unwrap();
+        let ids: Vec<&str> = report
+            .blocks
+            .iter()
+            .map(|block| block.id.as_str())
+            .collect();
+        assert_eq!(ids, ["Migrate#1"]);
+        assert_eq!(
+            runner
+                .execute_blocks(&section, &[], 1..3)
+                .unwrap()
+                .blocks
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_step_handler() {
+        let block = |code: &str| CodeBlock {
+            lang: "sh".to_string(),
+            code: code.to_string(),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Runbook".to_string(),
+            level: 2,
+            codes: vec![
+                block("exit 1"),
+                block("exit 2"),
+                block("true"),
+                block("exit 3"),
+            ],
+            ..Default::default()
+        };
+        let runner = Runner::with_default_config().with_step_handler(Arc::new(
+            |_: &Section, index: usize, _: &CodeBlock| match index {
+                0 => StepAction::Skip,
+                1 => StepAction::RunEdited("true".to_string()),
+                2 => StepAction::Run,
+                _ => StepAction::Abort,
+            },
+        ));
+
+        assert!(matches!(
+            runner.execute_section(&section),
+            Err(Error::Cancelled(task)) if task == "Runbook"
+        ));
+        let report = runner.execute_blocks(&section, &[], 0..3).unwrap();
+        assert_eq!(
+            report.blocks[0].skipped.as_deref(),
+            Some("skipped by the user")
+        );
+        assert_eq!(report.blocks[1].skipped, None);
+    }
+
+    #[test]
+    fn test_block_failure_uses_block_id() {
+        let block = |code: &str| CodeBlock {
+            lang: "sh".to_string(),
+            code: code.to_string(),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![block("true"), block("exit 1")],
+            ..Default::default()
+        };
+
+        let err = Runner::with_default_config()
+            .execute_section(&section)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Task 'Build' failed at block Build#1:")
+        );
+    }
+
+    #[test]
+    fn test_block_killed_by_a_signal_fails_without_cancelling_later_runs() {
+        let section = |code: &str| Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "sh".to_string(),
+                code: code.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let runner = Runner::with_default_config();
+
+        let err = runner
+            .execute_section(&section("kill -TERM $$"))
+            .unwrap_err();
+        assert!(matches!(err, Error::BlockFailed { .. }));
+        assert!(runner.execute_section(&section("echo ok")).is_ok());
+    }
+
+    #[test]
+    fn test_events_report_exit_codes() {
+        let block = |code: &str| CodeBlock {
+            lang: "sh".to_string(),
+            code: code.to_string(),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![block("true"), block("exit 3")],
+            ..Default::default()
+        };
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let runner = Runner::with_default_config().with_event_handler(Arc::new(move |event| {
+            sink.lock().unwrap().push(event.clone())
+        }));
+        assert!(runner.execute_section(&section).is_err());
+
+        let events = events.lock().unwrap();
+        let names: Vec<_> = events
+            .iter()
+            .map(|event| serde_json::to_value(event).unwrap()["event"].clone())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "task_started",
+                "block_started",
+                "block_finished",
+                "block_started",
+                "block_finished",
+                "task_finished"
+            ]
+        );
+        assert!(matches!(
+            &events[4],
+            RunEvent::BlockFinished { id, exit_code: Some(3), success: false, .. } if id == "Build#1"
+        ));
+        assert!(matches!(
+            events[5],
+            RunEvent::TaskFinished { success: false, .. }
+        ));
+    }
+
+    #[test]
+    fn test_observer() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl RunnerObserver for Recorder {
+            fn on_section_start(&self, section: &Section) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {}", section.title));
+            }
+
+            fn on_block_output(&self, id: &str, stream: OutputStream, data: &[u8]) {
+                let text = String::from_utf8_lossy(data);
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {:?} {}", id, stream, text.trim_end()));
+            }
+
+            fn on_block_complete(&self, _section: &Section, outcome: &BlockOutcome) {
+                self.0.lock().unwrap().push(format!(
+                    "{} exited {:?}",
+                    outcome.report.id, outcome.exit_code
+                ));
+            }
+
+            fn on_section_complete(&self, report: &ExecutionReport, success: bool) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", report.task, success));
+            }
+        }
+
+        let section = Section {
+            title: "Greet".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "sh".to_string(),
+                code: "echo hi".to_string(),
                 ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let recorder = Arc::new(Recorder::default());
+        let runner = Runner::with_default_config().with_observer(recorder.clone());
+        runner.execute_section(&section).unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "start Greet",
+                "Greet#0 Stdout hi",
+                "Greet#0 exited Some(0)",
+                "Greet true"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_running_stops_grandchildren() {
+        let runner = Runner::with_default_config();
+        let handle = runner.kill_handle();
+        let killer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            handle.kill();
+        });
+
+        let started = Instant::now();
+        let err = runner
+            .execute_code("sh", "sleep 30 & sleep 30 & wait")
+            .unwrap_err();
+        killer.join().unwrap();
+
+        assert!(err.to_string().contains("execution failed"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_large_arguments_are_passed_in_a_file() {
+        let large = "x".repeat(limits::MAX_STRING_LEN);
+        let code = format!(
+            r#"test -z "$MX_ARG_0" && test "$(tr -d '\0' < "$MX_ARGS_FILE" | wc -c)" -eq {}"#,
+            large.len() + 1
+        );
+
+        Runner::with_default_config()
+            .execute_code_with_args("sh", &code, &[large, "y".to_string()])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_oversized_command_line_is_reported() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "inline".to_string(),
+            RuntimeConfig::Detailed {
+                command: "sh -c".to_string(),
+                execution_mode: ExecutionMode::Arg,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: false,
             },
-            Section {
-                title: "Task 2".to_string(),
-                level: 2,
+        );
+
+        let err = Runner::new(config)
+            .execute_code("inline", &"x".repeat(limits::MAX_STRING_LEN + 1))
+            .unwrap_err();
+        assert!(matches!(err, Error::CommandTooLarge { ref lang, .. } if lang == "inline"));
+    }
+
+    #[test]
+    fn test_quoted_runtime_commands() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "quoted".to_string(),
+            RuntimeConfig::Simple(r#"sh -c 'test "$0" = "a b"' "a b""#.to_string()),
+        );
+        config.runtimes.insert(
+            "argv".to_string(),
+            RuntimeConfig::Argv(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                r#"test "$0" = "it's""#.to_string(),
+                "it's".to_string(),
+            ]),
+        );
+        config.runtimes.insert(
+            "broken".to_string(),
+            RuntimeConfig::Simple("sh -c 'exit 0".to_string()),
+        );
+
+        let runner = Runner::new(config);
+        runner.execute_code("quoted", "").unwrap();
+        runner.execute_code("argv", "").unwrap();
+        assert!(matches!(
+            runner.execute_code("broken", ""),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_args_and_env() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "sh".to_string(),
+            RuntimeConfig::Detailed {
+                command: "sh".to_string(),
+                execution_mode: ExecutionMode::Stdin,
+                args: vec!["-s".to_string(), "--".to_string(), "two words".to_string()],
+                env: BTreeMap::from([("GREETING".to_string(), "hello".to_string())]),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: false,
+            },
+        );
+
+        let code = r#"test "$1" = "two words" && test "$GREETING" = hello && test "$MX_ARG_0" = x"#;
+        Runner::new(config)
+            .execute_code_with_args("sh", code, &["x".to_string()])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_template_without_code_placeholder_uses_execution_mode() {
+        let runtime = |command: &str, execution_mode, shell| RuntimeConfig::Detailed {
+            command: command.to_string(),
+            execution_mode,
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            shell,
+            sandbox: None,
+            remote: None,
+            pty: false,
+        };
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "deno".to_string(),
+            runtime(
+                "deno run --allow-read={dir} {args}",
+                ExecutionMode::File,
+                false,
+            ),
+        );
+        config.runtimes.insert(
+            "sql".to_string(),
+            runtime("sqlite3 app.db | column -t", ExecutionMode::Stdin, true),
+        );
+        let args = vec!["x".to_string()];
+        let prepare = |lang: &str| {
+            let block = CodeBlock {
+                lang: lang.to_string(),
+                code: "code".to_string(),
                 ..Default::default()
+            };
+            Invocation::prepare(&config, false, &block, &args, None, None, &Secrets::new())
+        };
+
+        let deno = prepare("deno").unwrap();
+        let dir = deno.temp_dir.as_ref().unwrap().0.path();
+        assert_eq!(deno.program, "deno");
+        assert_eq!(
+            deno.args,
+            [
+                "run".to_string(),
+                format!("--allow-read={}", dir.display()),
+                "x".to_string(),
+                dir.join("main.ts").to_string_lossy().into_owned(),
+            ]
+        );
+        assert_eq!(deno.stdin, None);
+
+        let sql = prepare("sql").unwrap();
+        assert_eq!(sql.args, ["-c", "sqlite3 app.db | column -t"]);
+        assert_eq!(sql.stdin.as_deref(), Some("code"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runtime_steps() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "shc".to_string(),
+            RuntimeConfig::Steps {
+                steps: vec!["cp {file} {out}".to_string(), "sh {out} {args}".to_string()],
+                env: BTreeMap::new(),
+                sandbox: None,
+                remote: None,
             },
-        ];
+        );
+        config.runtimes.insert(
+            "broken".to_string(),
+            RuntimeConfig::Steps {
+                steps: vec!["false".to_string(), "touch {dir}/ran".to_string()],
+                env: BTreeMap::new(),
+                sandbox: None,
+                remote: None,
+            },
+        );
+
+        let runner = Runner::new(config);
+        runner
+            .execute_code_with_args("shc", r#"test "$1" = "a b""#, &["a b".to_string()])
+            .unwrap();
+        assert!(runner.execute_code("broken", "").is_err());
+    }
+
+    #[test]
+    fn test_remote_invocation_ships_code_file() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "python".to_string(),
+            RuntimeConfig::Detailed {
+                command: "python3 -u".to_string(),
+                execution_mode: ExecutionMode::File,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: false,
+            },
+        );
+        let code = "print('hi')";
+        let args = vec!["x".to_string()];
+        let block = CodeBlock {
+            lang: "python".to_string(),
+            code: code.to_string(),
+            ..Default::default()
+        };
+
+        let invocation = Invocation::prepare(
+            &config,
+            false,
+            &block,
+            &args,
+            None,
+            Some("admin@db1"),
+            &Secrets::new(),
+        )
+        .unwrap();
+        assert_eq!(invocation.program, crate::remote::SSH);
+        assert_eq!(invocation.args[..3], ["-T", "--", "admin@db1"]);
+        assert_eq!(invocation.stdin.as_deref(), Some(code));
+        assert!(invocation.env.is_empty());
+
+        let local = invocation
+            .temp_dir
+            .as_ref()
+            .unwrap()
+            .0
+            .path()
+            .join("main.py");
+        let script = &invocation.args[3];
+        assert!(script.contains("MX_ARG_0"));
+        assert!(script.contains("python3"));
+        assert!(!script.contains(&*local.to_string_lossy()));
+    }
+
+    #[test]
+    fn test_dev_shell_wraps_local_blocks() {
+        let config = Config {
+            nix_shell: Some("nix develop".to_string()),
+            ..Default::default()
+        };
+        let block = CodeBlock {
+            lang: "python".to_string(),
+            code: "print('hi')".to_string(),
+            ..Default::default()
+        };
+        let prepare = |section: Option<&Section>, host: Option<&str>| {
+            Invocation::prepare(&config, false, &block, &[], section, host, &Secrets::new())
+                .unwrap()
+        };
+
+        let invocation = prepare(None, None);
+        assert_eq!(invocation.program, "nix");
+        assert_eq!(invocation.args, ["develop", "--command", "python3"]);
+        assert_eq!(invocation.stdin.as_deref(), Some("print('hi')"));
+
+        let outside = Section {
+            meta: Some("devshell=off".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(prepare(Some(&outside), None).program, "python3");
+        assert_eq!(prepare(None, Some("admin@db1")).program, crate::remote::SSH);
+    }
+
+    #[test]
+    fn test_stdin_forwarding_passes_code_as_file() {
+        // $0 is the script path when sh runs a file, and "sh" when it reads code from stdin
+        let code = r#"case "$0" in */mx_*/main.sh) ;; *) exit 1 ;; esac"#;
 
         let runner = Runner::with_default_config();
-        let found = runner.find_section(&sections, "Task 1");
-        assert!(found.is_some());
-        assert_eq!(found.unwrap().title, "Task 1");
+        assert!(runner.execute_code("sh", code).is_err());
 
-        let not_found = runner.find_section(&sections, "Task 3");
-        assert!(not_found.is_none());
+        let runner = Runner::with_default_config().with_stdin_forwarding(true);
+        assert!(runner.execute_code("sh", code).is_ok());
+    }
+
+    #[test]
+    fn test_interactive_blocks_pass_code_as_file() {
+        let config = Config::default();
+        let prepare = |meta: Option<&str>, section: &Section| {
+            let block = CodeBlock {
+                lang: "sh".to_string(),
+                code: "./ask-name".to_string(),
+                meta: meta.map(str::to_string),
+                ..Default::default()
+            };
+            let secrets = Secrets::new();
+            Invocation::prepare(&config, false, &block, &[], Some(section), None, &secrets).unwrap()
+        };
+        let plain = Section::default();
+        let interactive = Section {
+            meta: Some("interactive".to_string()),
+            ..Default::default()
+        };
+        let prompt = Section {
+            metadata: BTreeMap::from([("interactive".to_string(), "true".to_string())]),
+            ..Default::default()
+        };
+
+        assert!(!prepare(None, &plain).interactive);
+        assert!(prepare(None, &plain).stdin.is_some());
+        for invocation in [
+            prepare(Some("interactive"), &plain),
+            prepare(None, &interactive),
+            prepare(None, &prompt),
+        ] {
+            assert!(invocation.interactive);
+            assert!(invocation.stdin.is_none());
+            assert!(invocation.temp_dir.is_some());
+        }
+        assert!(!prepare(Some("interactive=false"), &interactive).interactive);
+    }
+
+    #[test]
+    fn test_shell_blocks_reading_stdin_get_their_code_elsewhere() {
+        let captured = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&captured);
+        // An empty stdin: the `read` fails instead of consuming the next line of the script
+        let runner = Runner::with_default_config()
+            .with_detached_terminal(true)
+            .with_output_handler(Arc::new(move |_, data: &[u8]| {
+                sink.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(data));
+            }));
+        runner
+            .execute_code("sh", "read line || echo none\necho second")
+            .unwrap();
+        assert_eq!(*captured.lock().unwrap(), "none\nsecond\n");
+
+        let mut config = Config {
+            shell_stdin: ExecutionMode::Arg,
+            ..Default::default()
+        };
+        let prepare = |config: &Config, meta: Option<&str>, code: &str| {
+            let block = CodeBlock {
+                lang: "bash".to_string(),
+                code: code.to_string(),
+                meta: meta.map(str::to_string),
+                ..Default::default()
+            };
+            Invocation::prepare(config, false, &block, &[], None, None, &Secrets::new()).unwrap()
+        };
+        let invocation = prepare(&config, None, "read -r name");
+        assert_eq!(invocation.args, ["-c", "read -r name"]);
+        assert!(invocation.stdin.is_none());
+        assert!(prepare(&config, None, "echo hi").stdin.is_some());
+        assert!(
+            prepare(&config, Some("reads-stdin=false"), "read x")
+                .stdin
+                .is_some()
+        );
+        assert_eq!(
+            prepare(&config, Some("reads-stdin"), "./prompt").args,
+            ["-c", "./prompt"]
+        );
+
+        config.shell_stdin = ExecutionMode::Stdin;
+        assert!(prepare(&config, None, "read -r name").stdin.is_some());
+    }
+
+    #[test]
+    fn test_blocks_run_in_root() {
+        let root = std::env::temp_dir().canonicalize().unwrap();
+        Runner::with_default_config()
+            .with_root(&root)
+            .execute_code(
+                "sh",
+                &format!(
+                    r#"test "$MX_ROOT" = {0} && test "$(pwd -P)" = {0}"#,
+                    command::shell_quote(&root.to_string_lossy())
+                ),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_keep_temp() {
+        let temp = tempfile::tempdir().unwrap();
+        let record = temp.path().join("script");
+        let code = format!(
+            r#"printf '%s' "$0" > {}"#,
+            command::shell_quote(&record.to_string_lossy())
+        );
+
+        let config = Config {
+            keep_temp: true,
+            ..Default::default()
+        };
+        Runner::new(config)
+            .with_stdin_forwarding(true)
+            .execute_code("sh", &code)
+            .unwrap();
+
+        let script = PathBuf::from(fs::read_to_string(&record).unwrap());
+        assert_eq!(fs::read_to_string(&script).unwrap(), code);
+        fs::remove_dir_all(script.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_output_handler() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&captured);
+        let runner = Runner::with_default_config().with_output_handler(Arc::new(
+            move |stream, data: &[u8]| {
+                sink.lock()
+                    .unwrap()
+                    .push((stream, String::from_utf8_lossy(data).into_owned()));
+            },
+        ));
+
+        runner.execute_code("sh", "echo out; echo err >&2").unwrap();
+
+        let captured = captured.lock().unwrap();
+        let output = |stream| {
+            captured
+                .iter()
+                .filter(|(s, _)| *s == stream)
+                .map(|(_, text)| text.as_str())
+                .collect::<String>()
+        };
+        assert_eq!(output(OutputStream::Stdout), "out\n");
+        assert_eq!(output(OutputStream::Stderr), "err\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pty_runtime_runs_in_a_terminal() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "sh".to_string(),
+            RuntimeConfig::Detailed {
+                command: "sh".to_string(),
+                execution_mode: ExecutionMode::Stdin,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: true,
+            },
+        );
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&captured);
+        // Forwarding stdin keeps the test's own stdin away from the terminal
+        let runner = Runner::new(config)
+            .with_stdin_forwarding(true)
+            .with_output_handler(Arc::new(move |stream, data: &[u8]| {
+                sink.lock().unwrap().push((stream, data.to_vec()));
+            }));
+
+        runner
+            .execute_code("sh", "test -t 1 && test -t 2 && echo terminal >&2")
+            .unwrap();
+        assert!(runner.execute_code("sh", "exit 3").is_err());
+
+        let captured = captured.lock().unwrap();
+        assert!(captured.iter().all(|(s, _)| *s == OutputStream::Stdout));
+        let output = captured
+            .iter()
+            .flat_map(|(_, data)| data)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(String::from_utf8_lossy(&output), "terminal\r\n");
+    }
+
+    #[test]
+    fn test_secrets_and_sensitive_values_are_masked() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "sh".to_string(),
+            RuntimeConfig::Detailed {
+                command: "sh".to_string(),
+                execution_mode: ExecutionMode::Stdin,
+                args: Vec::new(),
+                env: BTreeMap::from([
+                    ("TOKEN".to_string(), "secret:token".to_string()),
+                    ("API_KEY".to_string(), "abc123".to_string()),
+                ]),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: false,
+            },
+        );
+        config.secrets.insert(
+            "token".to_string(),
+            SecretSource::Command {
+                command: "echo hunter2".to_string(),
+            },
+        );
+        config.sensitive = vec!["*_KEY".to_string()];
+        let captured = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&captured);
+        let runner = Runner::new(config).with_output_handler(Arc::new(move |_, data: &[u8]| {
+            sink.lock()
+                .unwrap()
+                .push_str(&String::from_utf8_lossy(data));
+        }));
+
+        runner
+            .execute_code("sh", r#"test "$TOKEN" = hunter2 && echo "$TOKEN $API_KEY""#)
+            .unwrap();
+        assert_eq!(*captured.lock().unwrap(), "******** ********\n");
+    }
+
+    #[test]
+    fn test_interactive_blocks_mask_secrets() {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "sh".to_string(),
+            RuntimeConfig::Detailed {
+                command: "sh".to_string(),
+                execution_mode: ExecutionMode::Stdin,
+                args: Vec::new(),
+                env: BTreeMap::from([("TOKEN".to_string(), "secret:token".to_string())]),
+                shell: false,
+                sandbox: None,
+                remote: None,
+                pty: false,
+            },
+        );
+        config.secrets.insert(
+            "token".to_string(),
+            SecretSource::Command {
+                command: "echo hunter2".to_string(),
+            },
+        );
+        let captured = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&captured);
+        let runner = Runner::new(config).with_output_handler(Arc::new(move |_, data: &[u8]| {
+            sink.lock()
+                .unwrap()
+                .push_str(&String::from_utf8_lossy(data));
+        }));
+        let section = Section {
+            title: "Login".to_string(),
+            meta: Some("interactive".to_string()),
+            codes: vec![CodeBlock {
+                lang: "sh".to_string(),
+                code: "echo \"token: $TOKEN\"".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        runner.execute_section(&section).unwrap();
+        assert_eq!(*captured.lock().unwrap(), "token: ********\n");
+    }
+
+    #[test]
+    fn test_command_template_with_hostile_code() {
+        let code = "'; touch pwned; echo '$(id)";
+        let run = |shell: bool| {
+            let mut config = Config::default();
+            config.runtimes.insert(
+                "hostile".to_string(),
+                RuntimeConfig::Detailed {
+                    command: "printf %s {code}".to_string(),
+                    execution_mode: ExecutionMode::Arg,
+                    args: Vec::new(),
+                    env: BTreeMap::new(),
+                    shell,
+                    sandbox: None,
+                    remote: None,
+                    pty: false,
+                },
+            );
+            let captured = Arc::new(Mutex::new(String::new()));
+            let sink = Arc::clone(&captured);
+            let runner =
+                Runner::new(config).with_output_handler(Arc::new(move |_, data: &[u8]| {
+                    sink.lock()
+                        .unwrap()
+                        .push_str(&String::from_utf8_lossy(data))
+                }));
+
+            runner.execute_code("hostile", code).unwrap();
+            captured.lock().unwrap().clone()
+        };
+
+        assert_eq!(run(false), code);
+        assert_eq!(run(true), code);
     }
 }
@@ -0,0 +1,291 @@
+//! Sandboxed execution of code blocks
+//!
+//! A runtime with `sandbox` set has its command wrapped so code blocks cannot write outside
+//! the working directory or reach the network:
+//!
+//! ```toml
+//! [runtimes.bash]
+//! command = "bash"
+//! sandbox = "bwrap"
+//! ```
+//!
+//! Tasks can ask to loosen the sandbox with heading attributes: `{allow-net}` keeps network
+//! access, `{sandbox=off}` runs the task's blocks unsandboxed. A runbook cannot let itself
+//! out though: the attributes only count for tasks listed in the `[sandbox]` table of the
+//! configuration, or for every task with `--allow-net` and `--no-sandbox`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::runner::Section;
+
+/// Environment variables kept by the `env` sandbox, besides mx's own `MX_*` variables
+pub const KEPT_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "LANG",
+    "LC_ALL",
+    "TERM",
+    "TMPDIR",
+    "TZ",
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+];
+
+/// How code blocks of a runtime are confined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sandbox {
+    /// Bubblewrap: read-only root, writable working and temporary directories, no network
+    Bwrap,
+    /// Firejail with the same restrictions as `bwrap`
+    Firejail,
+    /// Only a cleared environment, keeping [`KEPT_ENV`]; no filesystem or network limits
+    Env,
+}
+
+/// Tasks whose escapes from the sandbox are honoured, the `[sandbox]` configuration table
+///
+/// Lists hold task titles, or `*` for every task.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// Tasks whose `{allow-net}` attribute keeps network access
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_net: Vec<String>,
+    /// Tasks whose `{sandbox=off}` attribute runs them unsandboxed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_off: Vec<String>,
+}
+
+impl SandboxConfig {
+    /// Whether nothing is configured
+    pub fn is_empty(&self) -> bool {
+        self.allow_net.is_empty() && self.allow_off.is_empty()
+    }
+}
+
+/// The sandbox a code block runs in, after per-task escapes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub sandbox: Sandbox,
+    /// Keep network access, from the task's `{allow-net}` attribute
+    pub allow_net: bool,
+}
+
+impl Policy {
+    /// The sandbox for a code block of a language, or `None` if it runs unconfined
+    ///
+    /// Escapes of tasks missing from the allowlists are ignored with a warning.
+    pub fn resolve(config: &Config, lang: &str, section: Option<&Section>) -> Option<Self> {
+        let sandbox = config.get_sandbox(lang)?;
+        let attribute = |key: &str| section.and_then(|section| section.attribute(key));
+        let allowed = |escape: &str, tasks: &[String], key: &str| {
+            let Some(section) = section else {
+                return false;
+            };
+            if tasks
+                .iter()
+                .any(|task| task == "*" || *task == section.title)
+            {
+                return true;
+            }
+            warn!(
+                task = %section.title,
+                "ignoring {{{}}}: the task is not listed in sandbox.{}", escape, key
+            );
+            false
+        };
+
+        if attribute("sandbox").is_some_and(|value| value == "off")
+            && allowed("sandbox=off", &config.sandbox.allow_off, "allow_off")
+        {
+            return None;
+        }
+
+        Some(Self {
+            sandbox,
+            allow_net: attribute("allow-net").is_some()
+                && allowed("allow-net", &config.sandbox.allow_net, "allow_net"),
+        })
+    }
+
+    /// Command running `program` with `args` inside the sandbox
+    ///
+    /// `writable` lists directories besides the working directory the block may write to,
+    /// such as the directory holding its code file.
+    pub fn command(&self, program: &str, args: &[String], writable: &[&Path]) -> Command {
+        let mut writable: Vec<PathBuf> = writable.iter().map(|dir| dir.to_path_buf()).collect();
+        if let Ok(cwd) = std::env::current_dir() {
+            writable.insert(0, cwd);
+        }
+
+        let mut command = match self.sandbox {
+            Sandbox::Bwrap => {
+                let mut command = Command::new("bwrap");
+                command.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+                command.args(["--tmpfs", "/tmp"]);
+                for dir in &writable {
+                    command.arg("--bind").arg(dir).arg(dir);
+                }
+                command.args(["--unshare-all", "--die-with-parent"]);
+                if self.allow_net {
+                    command.arg("--share-net");
+                }
+                command.arg("--");
+                command
+            }
+            Sandbox::Firejail => {
+                let mut command = Command::new("firejail");
+                command.args(["--quiet", "--noprofile", "--read-only=/"]);
+                for dir in &writable {
+                    command.arg(flag("--read-write=", dir));
+                }
+                if !self.allow_net {
+                    command.arg("--net=none");
+                }
+                command.arg("--");
+                command
+            }
+            Sandbox::Env => {
+                let mut command = Command::new(program);
+                command
+                    .env_clear()
+                    .envs(std::env::vars_os().filter(|(key, _)| {
+                        key.to_str().is_some_and(|key| KEPT_ENV.contains(&key))
+                    }));
+                command.args(args);
+                return command;
+            }
+        };
+
+        command.arg(program).args(args);
+        command
+    }
+}
+
+fn flag(name: &str, path: &Path) -> OsString {
+    let mut flag = OsString::from(name);
+    flag.push(path);
+    flag
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::{ExecutionMode, RuntimeConfig};
+
+    fn config(sandbox: Sandbox) -> Config {
+        let mut config = Config::default();
+        config.runtimes.insert(
+            "bash".to_string(),
+            RuntimeConfig::Detailed {
+                command: "bash".to_string(),
+                execution_mode: ExecutionMode::Stdin,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                shell: false,
+                sandbox: Some(sandbox),
+                remote: None,
+                pty: false,
+            },
+        );
+        config
+    }
+
+    fn task(meta: &str) -> Section {
+        Section {
+            title: "Deploy".to_string(),
+            meta: Some(meta.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_task_escapes_are_ignored_unless_allowed() {
+        let confined = Some(Policy {
+            sandbox: Sandbox::Bwrap,
+            allow_net: false,
+        });
+        let config = config(Sandbox::Bwrap);
+
+        assert_eq!(Policy::resolve(&config, "python", None), None);
+        assert_eq!(
+            Policy::resolve(&config, "bash", Some(&task("allow-net"))),
+            confined
+        );
+        assert_eq!(
+            Policy::resolve(&config, "bash", Some(&task("sandbox=off"))),
+            confined
+        );
+    }
+
+    #[test]
+    fn test_allowed_task_escapes() {
+        let mut config = config(Sandbox::Bwrap);
+        config.sandbox.allow_net = vec!["Deploy".to_string()];
+        config.sandbox.allow_off = vec!["*".to_string()];
+
+        assert_eq!(
+            Policy::resolve(&config, "bash", Some(&task("allow-net"))),
+            Some(Policy {
+                sandbox: Sandbox::Bwrap,
+                allow_net: true
+            })
+        );
+        assert_eq!(
+            Policy::resolve(&config, "bash", Some(&task("sandbox=off"))),
+            None
+        );
+
+        config.sandbox.allow_net = vec!["Build".to_string()];
+        let policy = Policy::resolve(&config, "bash", Some(&task("allow-net"))).unwrap();
+        assert!(!policy.allow_net);
+    }
+
+    #[test]
+    fn test_bwrap_command() {
+        let policy = Policy::resolve(&config(Sandbox::Bwrap), "bash", None).unwrap();
+        let command = policy.command("bash", &["-e".to_string()], &[Path::new("/tmp/mx_1")]);
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy())
+            .collect();
+
+        assert_eq!(command.get_program(), "bwrap");
+        assert!(
+            args.windows(3)
+                .any(|w| w == ["--bind", "/tmp/mx_1", "/tmp/mx_1"])
+        );
+        assert!(args.contains(&"--unshare-all".into()));
+        assert!(!args.contains(&"--share-net".into()));
+        assert_eq!(args[args.len() - 3..], ["--", "bash", "-e"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_sandbox_clears_environment() {
+        let policy = Policy::resolve(&config(Sandbox::Env), "bash", None).unwrap();
+        let output = policy
+            .command("sh", &["-c".to_string(), "env".to_string()], &[])
+            .env("MX_ARG_0", "kept")
+            .output()
+            .unwrap();
+        let env = String::from_utf8_lossy(&output.stdout);
+
+        assert!(env.lines().any(|line| line == "MX_ARG_0=kept"));
+        // Set by cargo for the test process
+        assert!(!env.contains("CARGO_"));
+    }
+}
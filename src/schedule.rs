@@ -0,0 +1,219 @@
+//! Concurrency groups of tasks run in parallel
+//!
+//! A `group` heading attribute or metadata entry (`## Migrate {group=db}`) puts a task in a
+//! group whose tasks never run at the same time, while tasks of other groups still run
+//! alongside them. A `serial` task runs alone. [`Schedule::start_next`] hands out tasks in
+//! document order as their dependencies finish and the tasks running allow it.
+
+use crate::runner::Section;
+
+/// What a task may run alongside
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Concurrency {
+    /// Any other task
+    #[default]
+    Any,
+    /// Any task outside the named group
+    Group(String),
+    /// No other task
+    Serial,
+}
+
+impl Concurrency {
+    /// Concurrency of a task, from its `serial` or `group` attribute or metadata entry
+    pub fn of(section: &Section) -> Self {
+        let value = |key: &str| {
+            section
+                .attribute(key)
+                .or_else(|| section.metadata.get(key).cloned())
+        };
+        if value("serial").is_some_and(|serial| serial != "false") {
+            return Self::Serial;
+        }
+        match value("group") {
+            Some(group) if !group.trim().is_empty() => Self::Group(group.trim().to_string()),
+            _ => Self::Any,
+        }
+    }
+
+    /// Whether a task may start while another one runs
+    pub fn allows(&self, running: &Concurrency) -> bool {
+        match (self, running) {
+            (Self::Serial, _) | (_, Self::Serial) => false,
+            (Self::Group(group), Self::Group(other)) => group != other,
+            _ => true,
+        }
+    }
+}
+
+/// Order in which tasks start when several run at once
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    tasks: Vec<Scheduled>,
+}
+
+#[derive(Debug, Clone)]
+struct Scheduled {
+    concurrency: Concurrency,
+    /// Indices of the tasks that must finish first
+    depends: Vec<usize>,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    Running,
+    Finished,
+}
+
+impl Schedule {
+    /// Schedule tasks, given in document order with the indices of the tasks they depend on
+    pub fn new(tasks: impl IntoIterator<Item = (Concurrency, Vec<usize>)>) -> Self {
+        let tasks = tasks
+            .into_iter()
+            .map(|(concurrency, depends)| Scheduled {
+                concurrency,
+                depends,
+                state: State::Pending,
+            })
+            .collect();
+        Self { tasks }
+    }
+
+    /// Start the first pending task that can run now, returning its index
+    ///
+    /// A ready `serial` task that has to wait for the running tasks holds back the tasks after
+    /// it, so that it is not kept waiting forever.
+    pub fn start_next(&mut self) -> Option<usize> {
+        let running: Vec<&Concurrency> = self
+            .tasks
+            .iter()
+            .filter(|task| task.state == State::Running)
+            .map(|task| &task.concurrency)
+            .collect();
+
+        let mut started = None;
+        for (index, task) in self.tasks.iter().enumerate() {
+            let ready = task.state == State::Pending
+                && task.depends.iter().all(|&dep| {
+                    self.tasks
+                        .get(dep)
+                        .is_none_or(|d| d.state == State::Finished)
+                });
+            if !ready {
+                continue;
+            }
+            if running.iter().all(|other| task.concurrency.allows(other)) {
+                started = Some(index);
+                break;
+            }
+            if task.concurrency == Concurrency::Serial {
+                break;
+            }
+        }
+
+        let index = started?;
+        self.tasks[index].state = State::Running;
+        Some(index)
+    }
+
+    /// Mark a started task as finished
+    pub fn finish(&mut self, index: usize) {
+        if let Some(task) = self.tasks.get_mut(index) {
+            task.state = State::Finished;
+        }
+    }
+
+    /// Whether every task has finished
+    pub fn is_done(&self) -> bool {
+        self.tasks.iter().all(|task| task.state == State::Finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrency_of_section() {
+        let section = |meta: Option<&str>, metadata: &[(&str, &str)]| Section {
+            meta: meta.map(str::to_string),
+            metadata: metadata
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(Concurrency::of(&section(None, &[])), Concurrency::Any);
+        assert_eq!(
+            Concurrency::of(&section(Some("group=db"), &[])),
+            Concurrency::Group("db".to_string())
+        );
+        assert_eq!(
+            Concurrency::of(&section(None, &[("group", "db")])),
+            Concurrency::Group("db".to_string())
+        );
+        assert_eq!(
+            Concurrency::of(&section(Some("serial"), &[])),
+            Concurrency::Serial
+        );
+        assert_eq!(
+            Concurrency::of(&section(None, &[("serial", "true"), ("group", "db")])),
+            Concurrency::Serial
+        );
+        assert_eq!(
+            Concurrency::of(&section(None, &[("serial", "false")])),
+            Concurrency::Any
+        );
+    }
+
+    #[test]
+    fn test_schedule_groups() {
+        let db = || Concurrency::Group("db".to_string());
+        let mut schedule = Schedule::new([
+            (db(), vec![]),
+            (db(), vec![]),
+            (Concurrency::Group("web".to_string()), vec![]),
+            (Concurrency::Any, vec![0]),
+        ]);
+
+        // The second db task waits for the first, the web task does not
+        assert_eq!(schedule.start_next(), Some(0));
+        assert_eq!(schedule.start_next(), Some(2));
+        assert_eq!(schedule.start_next(), None);
+
+        schedule.finish(0);
+        assert_eq!(schedule.start_next(), Some(1));
+        assert_eq!(schedule.start_next(), Some(3));
+        assert_eq!(schedule.start_next(), None);
+
+        for index in [1, 2, 3] {
+            schedule.finish(index);
+        }
+        assert!(schedule.is_done());
+    }
+
+    #[test]
+    fn test_schedule_serial() {
+        let mut schedule = Schedule::new([
+            (Concurrency::Any, vec![]),
+            (Concurrency::Serial, vec![]),
+            (Concurrency::Any, vec![]),
+        ]);
+
+        // The serial task waits for the first and holds back the last
+        assert_eq!(schedule.start_next(), Some(0));
+        assert_eq!(schedule.start_next(), None);
+
+        schedule.finish(0);
+        assert_eq!(schedule.start_next(), Some(1));
+        assert_eq!(schedule.start_next(), None);
+
+        schedule.finish(1);
+        assert_eq!(schedule.start_next(), Some(2));
+        schedule.finish(2);
+        assert!(schedule.is_done());
+    }
+}
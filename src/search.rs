@@ -0,0 +1,251 @@
+//! Full-text search over task titles, descriptions and code
+//!
+//! A query is split into words; a task matches if every word appears, case-insensitively,
+//! in its title, description or code blocks. Each matching line is reported as a snippet
+//! with the positions of the words, for highlighting.
+
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::runner::Section;
+
+/// Longest snippet, in characters, before it is shortened around the first match
+const MAX_SNIPPET_CHARS: usize = 100;
+
+/// Where in a task a match was found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum MatchField {
+    /// The section title
+    Title,
+    /// The description
+    Description,
+    /// A code block, by index and language
+    Code { block: usize, lang: String },
+}
+
+/// A line of a task containing query words
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snippet {
+    pub field: MatchField,
+    /// 1-based line number in the Markdown file, if known
+    pub line: Option<usize>,
+    /// The matching line, trimmed and shortened around the first match
+    pub text: String,
+    /// Byte ranges of the query words in `text`
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// A task matching a query
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchMatch<'a> {
+    pub section: &'a Section,
+    pub snippets: Vec<Snippet>,
+}
+
+/// Find the sections matching every word of `query`
+///
+/// An empty query matches nothing.
+pub fn search<'a>(sections: &'a [Section], query: &str) -> Vec<SearchMatch<'a>> {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    sections
+        .iter()
+        .filter_map(|section| {
+            let snippets = section_snippets(section, &words);
+            let all_found = words.iter().all(|word| {
+                snippets.iter().any(|snippet| {
+                    snippet.highlights.iter().any(|range| {
+                        snippet.text[range.clone()].to_lowercase() == word.to_lowercase()
+                    })
+                })
+            });
+            all_found.then_some(SearchMatch { section, snippets })
+        })
+        .collect()
+}
+
+fn section_snippets(section: &Section, words: &[&str]) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+
+    if let Some(snippet) = snippet(MatchField::Title, section.line, &section.title, words) {
+        snippets.push(snippet);
+    }
+
+    if let Some(description) = &section.description {
+        snippets.extend(
+            description
+                .lines()
+                .filter_map(|line| snippet(MatchField::Description, None, line, words)),
+        );
+    }
+
+    for (index, block) in section.codes.iter().enumerate() {
+        snippets.extend(block.code.lines().enumerate().filter_map(|(offset, line)| {
+            let field = MatchField::Code {
+                block: index,
+                lang: block.lang.clone(),
+            };
+            // The code starts on the line after the opening fence
+            let number = block.line.map(|fence| fence + 1 + offset);
+            snippet(field, number, line, words)
+        }));
+    }
+
+    snippets
+}
+
+fn snippet(field: MatchField, line: Option<usize>, text: &str, words: &[&str]) -> Option<Snippet> {
+    let text = shorten(text.trim(), words);
+    let mut highlights: Vec<Range<usize>> = words
+        .iter()
+        .flat_map(|word| find_ignore_case(&text, word))
+        .collect();
+    if highlights.is_empty() {
+        return None;
+    }
+    highlights.sort_by_key(|range| range.start);
+
+    Some(Snippet {
+        field,
+        line,
+        text,
+        highlights,
+    })
+}
+
+/// Cut a long line down to a window around the first query word
+fn shorten(text: &str, words: &[&str]) -> String {
+    if text.chars().count() <= MAX_SNIPPET_CHARS {
+        return text.to_string();
+    }
+
+    let first = words
+        .iter()
+        .filter_map(|word| {
+            find_ignore_case(text, word)
+                .first()
+                .map(|range| range.start)
+        })
+        .min()
+        .unwrap_or(0);
+    let before = text[..first].chars().count();
+    let skip = before.saturating_sub(MAX_SNIPPET_CHARS / 4);
+
+    let mut shortened: String = text.chars().skip(skip).take(MAX_SNIPPET_CHARS).collect();
+    if skip > 0 {
+        shortened.insert_str(0, "...");
+    }
+    if skip + MAX_SNIPPET_CHARS < text.chars().count() {
+        shortened.push_str("...");
+    }
+    shortened
+}
+
+/// Byte ranges of the non-overlapping occurrences of `needle`, ignoring case
+fn find_ignore_case(haystack: &str, needle: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+
+    while start < haystack.len() {
+        let mut end = start;
+        let mut rest = haystack[start..].chars();
+        let found = needle.chars().all(|expected| match rest.next() {
+            Some(c) if c.to_lowercase().eq(expected.to_lowercase()) => {
+                end += c.len_utf8();
+                true
+            }
+            _ => false,
+        });
+
+        if found {
+            ranges.push(start..end);
+            start = end;
+        } else {
+            start += haystack[start..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CodeBlock;
+
+    fn sections() -> Vec<Section> {
+        vec![
+            Section {
+                title: "Deploy".to_string(),
+                description: Some("Push the image to production".to_string()),
+                line: Some(3),
+                codes: vec![CodeBlock {
+                    lang: "bash".to_string(),
+                    code: "docker push app\nkubectl rollout restart deploy/app\n".to_string(),
+                    line: Some(7),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            Section {
+                title: "Build image".to_string(),
+                codes: vec![CodeBlock {
+                    lang: "bash".to_string(),
+                    code: "docker build -t app .".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_every_word_must_match() {
+        let sections = sections();
+
+        let matches = search(&sections, "DOCKER production");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].section.title, "Deploy");
+
+        let fields: Vec<_> = matches[0]
+            .snippets
+            .iter()
+            .map(|snippet| (&snippet.field, snippet.line, snippet.text.as_str()))
+            .collect();
+        let code = MatchField::Code {
+            block: 0,
+            lang: "bash".to_string(),
+        };
+        assert_eq!(
+            fields,
+            [
+                (
+                    &MatchField::Description,
+                    None,
+                    "Push the image to production"
+                ),
+                (&code, Some(8), "docker push app"),
+            ]
+        );
+        let code_snippet = &matches[0].snippets[1];
+        assert_eq!(code_snippet.highlights.len(), 1);
+        assert_eq!(code_snippet.highlights[0], 0..6);
+
+        assert_eq!(search(&sections, "docker").len(), 2);
+        assert!(search(&sections, "  ").is_empty());
+    }
+
+    #[test]
+    fn test_long_lines_are_shortened() {
+        let line = format!("{}needle{}", "a".repeat(200), "b".repeat(200));
+        let snippet = snippet(MatchField::Title, None, &line, &["NEEDLE"]).unwrap();
+
+        assert!(snippet.text.starts_with("...") && snippet.text.ends_with("..."));
+        assert_eq!(&snippet.text[snippet.highlights[0].clone()], "needle");
+    }
+}
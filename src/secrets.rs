@@ -0,0 +1,354 @@
+//! Secrets injected into code blocks without writing them in task or configuration files
+//!
+//! ```toml
+//! [secrets]
+//! github_token = { env = "GITHUB_TOKEN" }
+//! db_password = { command = "op read op://ops/db/password" }
+//! deploy_key = { keychain = "deploy", account = "ci" }
+//!
+//! [runtimes.psql]
+//! command = "psql"
+//! env = { PGPASSWORD = "secret:db_password" }
+//! ```
+//!
+//! A runtime `env` value of `secret:NAME` is replaced with the secret when a code block of
+//! that runtime runs, so blocks that never run never fetch it. Each secret is fetched at
+//! most once per run: from an environment variable, from the stdout of a command such as
+//! `op read` or `pass show`, or from the system keychain (`security` on macOS,
+//! `secret-tool` elsewhere).
+//!
+//! Once fetched, a secret is replaced with [`MASK`] in everything mx passes on: block
+//! output on the terminal, log files, events and debug logs. So are the values of the
+//! environment variables marked sensitive, whether mx inherited them or a runtime sets them:
+//!
+//! ```toml
+//! sensitive = ["AWS_SECRET_ACCESS_KEY", "*_TOKEN"]
+//! ```
+//!
+//! Block output is always captured rather than inherited while there is something to mask,
+//! as the terminal would otherwise show it unmasked.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Prefix of values referring to a secret, e.g. `secret:db_password`
+pub const SECRET_PREFIX: &str = "secret:";
+
+/// What a secret is replaced with in output
+pub const MASK: &str = "********";
+
+/// Where a secret comes from, an entry of the `[secrets]` configuration table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SecretSource {
+    /// An environment variable of mx
+    Env { env: String },
+    /// The stdout of a shell command, without its trailing newline
+    Command { command: String },
+    /// A password of the system keychain, by service and optionally account
+    Keychain {
+        keychain: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account: Option<String>,
+    },
+}
+
+impl SecretSource {
+    /// Fetch the secret
+    pub fn fetch(&self) -> std::result::Result<String, String> {
+        match self {
+            SecretSource::Env { env } => {
+                std::env::var(env).map_err(|_| format!("environment variable {} is not set", env))
+            }
+            SecretSource::Command { command } => {
+                let mut sh = Command::new("sh");
+                sh.args(["-c", command]);
+                run(sh, command)
+            }
+            SecretSource::Keychain { keychain, account } => {
+                let mut command = if cfg!(target_os = "macos") {
+                    let mut command = Command::new("security");
+                    command.args(["find-generic-password", "-w", "-s", keychain]);
+                    if let Some(account) = account {
+                        command.args(["-a", account]);
+                    }
+                    command
+                } else {
+                    let mut command = Command::new("secret-tool");
+                    command.args(["lookup", "service", keychain]);
+                    if let Some(account) = account {
+                        command.args(["account", account]);
+                    }
+                    command
+                };
+                let program = command.get_program().to_string_lossy().into_owned();
+                command.stdin(Stdio::null());
+                run(command, &program)
+            }
+        }
+    }
+}
+
+/// Run a command printing a secret, keeping its stderr on the terminal for prompts
+fn run(mut command: Command, name: &str) -> std::result::Result<String, String> {
+    let output = command
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("failed to run '{}': {}", name, e))?;
+    if !output.status.success() {
+        return Err(format!("'{}' exited with {}", name, output.status));
+    }
+
+    let secret = String::from_utf8(output.stdout)
+        .map_err(|_| format!("'{}' printed a secret that is not UTF-8", name))?;
+    Ok(secret.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Name of the secret a value refers to, if it is a `secret:NAME` reference
+pub fn secret_name(value: &str) -> Option<&str> {
+    value.strip_prefix(SECRET_PREFIX)
+}
+
+/// Secrets fetched during a run, kept to reuse them, and the values to mask in output
+#[derive(Debug, Default)]
+pub struct Secrets {
+    values: Mutex<BTreeMap<String, String>>,
+    /// Values of sensitive environment variables
+    sensitive: Mutex<BTreeSet<String>>,
+}
+
+impl Secrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Secrets masking the values of mx's sensitive environment variables, which code
+    /// blocks inherit
+    pub fn from_env(config: &Config) -> Self {
+        let secrets = Self::new();
+        if !config.sensitive.is_empty() {
+            for (var, value) in std::env::vars_os() {
+                if let (Some(var), Some(value)) = (var.to_str(), value.to_str())
+                    && config.is_sensitive(var)
+                {
+                    secrets.mark_sensitive(value);
+                }
+            }
+        }
+        secrets
+    }
+
+    /// Mask a value in output from now on
+    pub fn mark_sensitive(&self, value: &str) {
+        if !value.is_empty() {
+            self.sensitive.lock().unwrap().insert(value.to_string());
+        }
+    }
+
+    /// A value with a `secret:NAME` reference replaced by the secret, fetching it if needed
+    pub fn resolve(&self, config: &Config, value: &str) -> Result<String> {
+        let Some(name) = secret_name(value) else {
+            return Ok(value.to_string());
+        };
+
+        let mut values = self.values.lock().unwrap();
+        if let Some(secret) = values.get(name) {
+            return Ok(secret.clone());
+        }
+        let source = config.secrets.get(name).ok_or_else(|| Error::Secret {
+            name: name.to_string(),
+            message: "not defined under [secrets] in mx.toml".to_string(),
+        })?;
+        let secret = source.fetch().map_err(|message| Error::Secret {
+            name: name.to_string(),
+            message,
+        })?;
+        values.insert(name.to_string(), secret.clone());
+        Ok(secret)
+    }
+
+    /// Whether there is nothing to mask: no secret was fetched and no value is sensitive
+    pub fn is_empty(&self) -> bool {
+        self.values.lock().unwrap().is_empty() && self.sensitive.lock().unwrap().is_empty()
+    }
+
+    /// Every value to mask, longest first so one containing another is masked whole
+    fn masked_values(&self) -> Vec<String> {
+        let mut masked: Vec<String> = self
+            .values
+            .lock()
+            .unwrap()
+            .values()
+            .chain(self.sensitive.lock().unwrap().iter())
+            .filter(|value| !value.is_empty())
+            .cloned()
+            .collect();
+        masked.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        masked
+    }
+
+    /// Output with every secret and sensitive value replaced by [`MASK`]
+    pub fn mask<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut data = Cow::Borrowed(data);
+        for value in self.masked_values() {
+            if let Some(masked) = replace(&data, value.as_bytes(), MASK.as_bytes()) {
+                data = Cow::Owned(masked);
+            }
+        }
+        data
+    }
+
+    /// Text with every fetched secret replaced by [`MASK`]
+    pub fn mask_str<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.mask(text.as_bytes()) {
+            Cow::Borrowed(_) => Cow::Borrowed(text),
+            Cow::Owned(masked) => Cow::Owned(String::from_utf8_lossy(&masked).into_owned()),
+        }
+    }
+}
+
+/// Masks a process output stream read in chunks, catching values split across chunks
+///
+/// The end of a chunk that could be the start of a masked value is held back until the
+/// next chunk shows whether it is.
+pub struct MaskedStream<'a> {
+    secrets: &'a Secrets,
+    pending: Vec<u8>,
+}
+
+impl<'a> MaskedStream<'a> {
+    pub fn new(secrets: &'a Secrets) -> Self {
+        Self {
+            secrets,
+            pending: Vec::new(),
+        }
+    }
+
+    /// The masked output that can be passed on after reading a chunk
+    pub fn push<'d>(&mut self, data: &'d [u8]) -> Cow<'d, [u8]> {
+        if self.pending.is_empty() && self.secrets.is_empty() {
+            return Cow::Borrowed(data);
+        }
+
+        self.pending.extend_from_slice(data);
+        let mut masked = self.secrets.mask(&self.pending).into_owned();
+        let held = self
+            .secrets
+            .masked_values()
+            .iter()
+            .map(|value| partial_suffix(&masked, value.as_bytes()))
+            .max()
+            .unwrap_or(0);
+        self.pending = masked.split_off(masked.len() - held);
+        Cow::Owned(masked)
+    }
+
+    /// The masked output held back, once the stream ended
+    pub fn finish(self) -> Vec<u8> {
+        self.secrets.mask(&self.pending).into_owned()
+    }
+}
+
+/// Length of the longest end of `data` that starts `value` without being all of it
+fn partial_suffix(data: &[u8], value: &[u8]) -> usize {
+    (1..value.len().min(data.len() + 1))
+        .rev()
+        .find(|&len| data.ends_with(&value[..len]))
+        .unwrap_or(0)
+}
+
+/// Replace every occurrence of `from`, or return `None` if there is none
+fn replace(data: &[u8], from: &[u8], to: &[u8]) -> Option<Vec<u8>> {
+    let mut replaced = Vec::new();
+    let mut rest = data;
+    let mut found = false;
+    while let Some(at) = rest.windows(from.len()).position(|window| window == from) {
+        replaced.extend_from_slice(&rest[..at]);
+        replaced.extend_from_slice(to);
+        rest = &rest[at + from.len()..];
+        found = true;
+    }
+    replaced.extend_from_slice(rest);
+    found.then_some(replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_and_mask() {
+        let mut config = Config::default();
+        config.secrets.insert(
+            "token".to_string(),
+            SecretSource::Command {
+                command: "printf 's3cr3t\\n'".to_string(),
+            },
+        );
+        config.secrets.insert(
+            "missing".to_string(),
+            SecretSource::Env {
+                env: "MX_TEST_UNSET_SECRET".to_string(),
+            },
+        );
+        let secrets = Secrets::new();
+
+        assert_eq!(secrets.resolve(&config, "plain").unwrap(), "plain");
+        assert_eq!(secrets.mask(b"s3cr3t").as_ref(), b"s3cr3t");
+        assert_eq!(secrets.resolve(&config, "secret:token").unwrap(), "s3cr3t");
+        assert!(matches!(
+            secrets.resolve(&config, "secret:missing"),
+            Err(Error::Secret { name, .. }) if name == "missing"
+        ));
+        assert!(secrets.resolve(&config, "secret:unknown").is_err());
+
+        assert_eq!(
+            secrets.mask(b"token=s3cr3t, again s3cr3t\n").as_ref(),
+            b"token=********, again ********\n"
+        );
+        assert!(matches!(secrets.mask_str("nothing here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_masked_stream() {
+        let secrets = Secrets::new();
+        secrets.mark_sensitive("hunter2");
+        let mut stream = MaskedStream::new(&secrets);
+
+        let mut output = Vec::new();
+        for chunk in ["pass: hun", "ter2, hu", "mble hu"] {
+            output.extend_from_slice(&stream.push(chunk.as_bytes()));
+        }
+        assert_eq!(output, b"pass: ********, humble ");
+        output.extend(stream.finish());
+        assert_eq!(output, b"pass: ********, humble hu");
+    }
+
+    #[test]
+    fn test_deserialize_sources() {
+        let secrets: BTreeMap<String, SecretSource> = toml::from_str(
+            r#"
+a = { env = "A" }
+b = { command = "pass show b" }
+c = { keychain = "deploy", account = "ci" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            secrets["c"],
+            SecretSource::Keychain {
+                keychain: "deploy".to_string(),
+                account: Some("ci".to_string()),
+            }
+        );
+        assert!(matches!(secrets["b"], SecretSource::Command { .. }));
+    }
+}
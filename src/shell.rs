@@ -0,0 +1,177 @@
+//! Shell code that reads its own stdin
+//!
+//! A shell reading its script from stdin hands that same stream to the commands it runs, so
+//! a `read` or a bare `cat` in the script consumes the rest of the script as input. Shell
+//! blocks that look like they read stdin therefore get their code as a file instead (or as
+//! an argument, see the `shell_stdin` configuration key). The check errs on the side of
+//! reading: a block it flags runs just the same from a file.
+
+/// Shells that read their script from stdin when given no file
+const SHELLS: &[&str] = &["ash", "bash", "dash", "fish", "ksh", "mksh", "sh", "zsh"];
+
+/// Builtins that always read stdin unless it is redirected
+const READERS: &[&str] = &["mapfile", "read", "readarray", "select"];
+
+/// Commands that read stdin when given no file operands
+const FILTERS: &[&str] = &["cat", "head", "tail", "tee", "xargs", "wc", "sort"];
+
+/// Commands that read stdin when told to stay interactive with `-i`
+const INTERACTIVE: &[&str] = &["docker", "kubectl", "podman"];
+
+/// Words that may come before a command without being one
+const KEYWORDS: &[&str] = &[
+    "!", "do", "elif", "else", "exec", "if", "then", "time", "until", "while",
+];
+
+/// Whether a command line starts a shell that would read its script from stdin
+pub(crate) fn is_shell(command: &str) -> bool {
+    command
+        .split_whitespace()
+        .next()
+        .map(|program| program.rsplit(['/', '\\']).next().unwrap_or(program))
+        .is_some_and(|program| SHELLS.contains(&program))
+}
+
+/// Whether shell code looks like it reads from its stdin
+pub(crate) fn reads_stdin(code: &str) -> bool {
+    if code.contains("/dev/stdin") || code.contains("<&0") {
+        return true;
+    }
+    code.lines()
+        .map(strip_comment)
+        .flat_map(commands)
+        .any(|(command, piped)| !piped && command_reads_stdin(command))
+}
+
+/// A line without a comment starting at a word
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Simple commands of a line, each with whether its stdin comes from a pipe
+fn commands(line: &str) -> Vec<(&str, bool)> {
+    let mut commands = Vec::new();
+    let (mut start, mut piped) = (0, false);
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let separator = match bytes[i] {
+            b'|' if bytes.get(i + 1) == Some(&b'|') => Some((2, false)),
+            b'&' if bytes.get(i + 1) == Some(&b'&') => Some((2, false)),
+            // `<&0` was handled already; `>&2` and `&>` are redirections
+            b'&' if i > 0 && matches!(bytes[i - 1], b'<' | b'>') => None,
+            b'&' if bytes.get(i + 1) == Some(&b'>') => None,
+            b'|' => Some((1, true)),
+            b';' | b'&' | b'(' | b')' | b'{' | b'}' | b'`' => Some((1, false)),
+            _ => None,
+        };
+        match separator {
+            Some((len, pipe)) => {
+                commands.push((&line[start..i], piped));
+                piped = pipe;
+                i += len;
+                start = i;
+            }
+            None => i += 1,
+        }
+    }
+    commands.push((&line[start..], piped));
+    commands
+}
+
+/// Whether a simple command reads stdin, i.e. has no input redirection and is a reader
+fn command_reads_stdin(command: &str) -> bool {
+    if command.contains('<') {
+        return false;
+    }
+    let mut words = command
+        .split_whitespace()
+        .skip_while(|word| KEYWORDS.contains(word) || is_assignment(word));
+    let Some(program) = words.next() else {
+        return false;
+    };
+    let args: Vec<&str> = words.collect();
+
+    if READERS.contains(&program) {
+        return true;
+    }
+    if FILTERS.contains(&program) {
+        return args.iter().all(|arg| arg.starts_with('-') && *arg != "--") || args.contains(&"-");
+    }
+    if program == "ssh" {
+        return !args
+            .iter()
+            .any(|arg| arg.starts_with('-') && arg.contains('n'));
+    }
+    if INTERACTIVE.contains(&program) {
+        return args.iter().any(|arg| {
+            *arg == "--interactive"
+                || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains('i'))
+        });
+    }
+    false
+}
+
+/// Whether a word is a variable assignment like `FOO=bar`
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_shell() {
+        assert!(is_shell("bash"));
+        assert!(is_shell("/usr/bin/zsh -e"));
+        assert!(!is_shell("python3"));
+        assert!(!is_shell("bashful"));
+    }
+
+    #[test]
+    fn test_reads_stdin() {
+        for code in [
+            "read -r name",
+            "echo start; read answer",
+            "if read -p 'Continue? ' reply; then echo ok; fi",
+            "while IFS= read -r line; do echo \"$line\"; done",
+            "mapfile -t lines",
+            "cat",
+            "cat - > out.txt",
+            "payload=$(cat)",
+            "sort -u | head -n 3",
+            "ssh admin@db1 uptime",
+            "docker exec -it web sh",
+            "kubectl exec --interactive pod -- sh",
+            "diff /dev/stdin expected.txt",
+        ] {
+            assert!(reads_stdin(code), "{}", code);
+        }
+
+        for code in [
+            "echo hello",
+            "cat README.md",
+            "echo data | cat",
+            "ls | sort | head -n 3",
+            "read -r name < answers.txt",
+            "cat <<EOF\nhello\nEOF",
+            "ssh -n admin@db1 uptime",
+            "docker exec web ls",
+            "echo 'read this' # not a read",
+            "echo done >&2",
+            "thread=1",
+        ] {
+            assert!(!reads_stdin(code), "{}", code);
+        }
+    }
+}
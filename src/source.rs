@@ -0,0 +1,823 @@
+//! Scanning of Markdown source for headings and code fences
+//!
+//! The mq query extracts section contents, but not where they are in the file. These helpers
+//! work on the raw text to recover source positions and fence info strings.
+
+use std::collections::BTreeMap;
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+
+use crate::runner::Section;
+
+/// A chunk of a Markdown document starting at a heading
+pub(crate) struct Chunk<'a> {
+    /// Level of the heading
+    pub level: u8,
+    /// 1-based line number of the heading
+    pub line: usize,
+    /// Byte offset of the heading
+    pub offset: usize,
+    /// Heading text without the `#` marker
+    pub heading: &'a str,
+    /// Chunk text including the heading
+    pub text: &'a str,
+}
+
+/// Split Markdown at headings of the given level, ignoring lines inside code fences
+///
+/// Content before the first heading is dropped since it cannot contain a section.
+pub(crate) fn split_at_headings(markdown: &str, level: u8) -> Vec<Chunk<'_>> {
+    split_at_heading_levels(markdown, level..=level)
+}
+
+/// Split Markdown at headings of any of the given levels, ignoring lines inside code fences
+///
+/// Each chunk runs to the next heading in the range, so a chunk never contains a deeper
+/// heading in the range.
+pub(crate) fn split_at_heading_levels(
+    markdown: &str,
+    levels: RangeInclusive<u8>,
+) -> Vec<Chunk<'_>> {
+    let mut starts = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+
+    for (index, line) in markdown.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+
+        match fence {
+            Some(open) if trimmed.starts_with(open) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None => {
+                if let Some((level, heading)) = heading_line(line)
+                    && levels.contains(&level)
+                {
+                    starts.push((level, index + 1, offset, heading));
+                }
+            }
+        }
+
+        offset += line.len();
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &(level, line, start, heading))| {
+            let end = starts.get(i + 1).map_or(markdown.len(), |next| next.2);
+            Chunk {
+                level,
+                line,
+                offset: start,
+                heading,
+                text: &markdown[start..end],
+            }
+        })
+        .collect()
+}
+
+/// Level and text of an ATX heading line such as `## Build`
+fn heading_line(line: &str) -> Option<(u8, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let heading = line[level..].strip_prefix(' ')?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level as u8, heading.trim()))
+}
+
+/// A fenced code block found in Markdown source
+pub(crate) struct Fence<'a> {
+    line: usize,
+    pub(crate) span: Range<usize>,
+    pub(crate) lang: &'a str,
+    /// Rest of the info string after the language
+    meta: &'a str,
+}
+
+impl<'a> Fence<'a> {
+    fn new(line: usize, span: Range<usize>, info: &'a str) -> Self {
+        let (lang, meta) = info.split_once(char::is_whitespace).unwrap_or((info, ""));
+        Self {
+            line,
+            span,
+            lang,
+            meta: meta.trim(),
+        }
+    }
+}
+
+/// Find fenced code blocks in Markdown source, in document order
+pub(crate) fn find_fences(markdown: &str) -> Vec<Fence<'_>> {
+    let mut fences = Vec::new();
+    // (line, offset, fence char, fence length, info string) of the currently open fence
+    let mut open: Option<(usize, usize, char, usize, &str)> = None;
+    let mut offset = 0;
+
+    for (index, line) in markdown.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let fence_char = trimmed.chars().next().unwrap_or(' ');
+        let fence_len = trimmed.chars().take_while(|&c| c == fence_char).count();
+        let is_fence = indent <= 3 && matches!(fence_char, '`' | '~') && fence_len >= 3;
+
+        match open {
+            Some((start_line, start, open_char, open_len, info))
+                if is_fence
+                    && fence_char == open_char
+                    && fence_len >= open_len
+                    && trimmed[fence_len..].trim().is_empty() =>
+            {
+                fences.push(Fence::new(
+                    start_line,
+                    start..offset + line.trim_end().len(),
+                    info,
+                ));
+                open = None;
+            }
+            Some(_) => {}
+            None if is_fence => {
+                let info = trimmed[fence_len..].trim();
+                open = Some((index + 1, offset, fence_char, fence_len, info));
+            }
+            None => {}
+        }
+
+        offset += line.len();
+    }
+
+    if let Some((line, start, _, _, info)) = open {
+        // An unclosed fence runs to the end of the document
+        fences.push(Fence::new(line, start..markdown.trim_end().len(), info));
+    }
+
+    fences
+}
+
+/// What MDX syntax a blanked line belongs to, when it continues on the next lines
+#[derive(Clone, Copy, PartialEq)]
+enum MdxBlock {
+    /// An `import`/`export` statement, running to the next blank line
+    Esm,
+    /// A JSX tag whose props span several lines, running to the line closing it with `>`
+    Tag,
+    /// A `{/* comment */}`, running to the line closing it
+    Comment,
+}
+
+/// Whether a file is an MDX document, whose syntax [`normalize_mdx`] blanks out
+pub(crate) fn is_mdx(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mdx"))
+}
+
+/// Blank out MDX and Docusaurus syntax that is not Markdown
+///
+/// ESM statements, lines of JSX component tags (`<Tabs>`, `</TabItem>`, `<>`), `{/* */}`
+/// comments and `:::info` admonition markers outside code fences are replaced with spaces, so
+/// that they do not swallow the code blocks next to them or end up as descriptions. Byte
+/// offsets and line numbers stay the same.
+pub(crate) fn normalize_mdx(markdown: &str) -> String {
+    let mut normalized = String::with_capacity(markdown.len());
+    let mut fence: Option<(char, usize)> = None;
+    let mut block: Option<MdxBlock> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let trimmed = content.trim_start();
+        let fence_char = trimmed.chars().next().unwrap_or(' ');
+        let fence_len = trimmed.chars().take_while(|&c| c == fence_char).count();
+        let is_fence = matches!(fence_char, '`' | '~') && fence_len >= 3;
+
+        let blank = match (fence, block) {
+            (Some((open_char, open_len)), _) => {
+                if is_fence && fence_char == open_char && fence_len >= open_len {
+                    fence = None;
+                }
+                false
+            }
+            (None, Some(MdxBlock::Esm)) => {
+                if trimmed.is_empty() {
+                    block = None;
+                }
+                true
+            }
+            (None, Some(MdxBlock::Tag)) => {
+                if trimmed.contains('>') {
+                    block = None;
+                }
+                true
+            }
+            (None, Some(MdxBlock::Comment)) => {
+                if trimmed.contains("*/}") {
+                    block = None;
+                }
+                true
+            }
+            (None, None) if is_fence => {
+                fence = Some((fence_char, fence_len));
+                false
+            }
+            (None, None) => {
+                if is_esm(content) {
+                    block = Some(MdxBlock::Esm);
+                    true
+                } else if is_jsx_tag(trimmed) {
+                    block = (!trimmed.contains('>')).then_some(MdxBlock::Tag);
+                    true
+                } else if trimmed.starts_with("{/*") {
+                    block = (!trimmed.contains("*/}")).then_some(MdxBlock::Comment);
+                    true
+                } else {
+                    trimmed.starts_with(":::")
+                }
+            }
+        };
+
+        if blank {
+            normalized.push_str(&" ".repeat(content.len()));
+            normalized.push_str(&line[content.len()..]);
+        } else {
+            normalized.push_str(line);
+        }
+    }
+
+    normalized
+}
+
+/// Whether a line starts an MDX `import` or `export` statement
+fn is_esm(line: &str) -> bool {
+    if let Some(rest) = line.strip_prefix("import ") {
+        return rest.starts_with(['{', '*', '\'', '"']) || rest.contains(" from ");
+    }
+    line.strip_prefix("export ").is_some_and(|rest| {
+        ["const ", "let ", "var ", "function ", "default ", "{"]
+            .iter()
+            .any(|start| rest.starts_with(start))
+    })
+}
+
+/// Whether a line starts with a JSX component or fragment tag, such as `<Tabs>` or `</>`
+///
+/// Lowercase tags are HTML, which Markdown handles itself.
+fn is_jsx_tag(line: &str) -> bool {
+    let Some(tag) = line.strip_prefix('<') else {
+        return false;
+    };
+    let tag = tag.strip_prefix('/').unwrap_or(tag);
+    tag.starts_with('>') || tag.starts_with(|c: char| c.is_ascii_uppercase())
+}
+
+/// Populate source positions of extracted sections and their code blocks
+///
+/// Sections are matched to headings of the configured level in document order (by title when
+/// the counts differ), and code blocks to fences within the section by language.
+pub(crate) fn locate_sections(markdown: &str, level: u8, sections: &mut [Section]) {
+    let chunks = split_at_headings(markdown, level);
+    let positional = chunks.len() == sections.len();
+    let mut next_chunk = 0;
+
+    for (index, section) in sections.iter_mut().enumerate() {
+        let found = if positional {
+            Some(index)
+        } else {
+            chunks[next_chunk..]
+                .iter()
+                .position(|chunk| parse_heading(chunk.heading).title == section.title)
+                .map(|position| next_chunk + position)
+        };
+        let Some(found) = found else {
+            continue;
+        };
+        next_chunk = found + 1;
+
+        let chunk = &chunks[found];
+        let heading_len = chunk.text.find('\n').unwrap_or(chunk.text.len());
+        section.line = Some(chunk.line);
+        section.span = Some(chunk.offset..chunk.offset + heading_len);
+
+        let fences = find_fences(chunk.text);
+        let mut next_fence = 0;
+        for code in section.codes.iter_mut() {
+            let Some(position) = fences[next_fence..]
+                .iter()
+                .position(|fence| fence.lang == code.lang)
+            else {
+                continue;
+            };
+            let fence = &fences[next_fence + position];
+            next_fence += position + 1;
+
+            code.line = Some(chunk.line + fence.line - 1);
+            code.span = Some(chunk.offset + fence.span.start..chunk.offset + fence.span.end);
+            if !fence.meta.is_empty() {
+                code.meta = Some(fence.meta.to_string());
+            }
+        }
+
+        let body = &chunk.text[heading_len..];
+        section.metadata = parse_metadata(body);
+        // The query only returns the first text node, which cuts a paragraph short at inline
+        // markup such as `code`
+        if let Some(paragraph) = first_paragraph(body) {
+            section.description = Some(paragraph);
+        }
+
+        // Tags from `tags=` fence attributes apply to the whole section
+        let block_tags: Vec<String> = section.codes.iter().flat_map(|code| code.tags()).collect();
+        for tag in block_tags {
+            if !section.tags.contains(&tag) {
+                section.tags.push(tag);
+            }
+        }
+    }
+}
+
+/// Parse a metadata table or definition list right under a section heading
+///
+/// Every row of a two-column table (`| depends | build |`) and every term of a definition
+/// list (`depends` followed by `: build`) becomes an entry, keyed by the lowercased name.
+/// Repeated definitions of a term are joined with commas.
+pub(crate) fn parse_metadata(body: &str) -> BTreeMap<String, String> {
+    let mut metadata = BTreeMap::new();
+    let mut lines = body
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .peekable();
+
+    if lines
+        .peek()
+        .is_some_and(|line| line.trim_start().starts_with('|'))
+    {
+        for line in lines.take_while(|line| line.trim_start().starts_with('|')) {
+            let cells: Vec<&str> = line
+                .trim()
+                .trim_matches('|')
+                .split('|')
+                .map(str::trim)
+                .collect();
+            let is_delimiter = cells
+                .iter()
+                .all(|cell| cell.chars().all(|c| matches!(c, '-' | ':' | ' ')));
+            if let [key, value, ..] = cells[..]
+                && !is_delimiter
+                && !key.is_empty()
+            {
+                metadata.insert(key.to_lowercase(), plain_text(value));
+            }
+        }
+        return metadata;
+    }
+
+    let lines: Vec<&str> = lines.map(str::trim).collect();
+    let mut index = 0;
+    while index + 1 < lines.len()
+        && !lines[index].is_empty()
+        && !is_block_start(lines[index])
+        && lines[index + 1].starts_with(':')
+    {
+        let term = lines[index].to_lowercase();
+        index += 1;
+        while let Some(definition) = lines.get(index).and_then(|line| line.strip_prefix(':')) {
+            metadata
+                .entry(term.clone())
+                .and_modify(|value: &mut String| value.push_str(", "))
+                .or_default()
+                .push_str(&plain_text(definition.trim()));
+            index += 1;
+        }
+        while lines.get(index).is_some_and(|line| line.is_empty()) {
+            index += 1;
+        }
+    }
+
+    metadata
+}
+
+/// First paragraph of a section body as plain text, with its lines joined
+///
+/// Code blocks, tables, lists, quotes, headings and definition lists are not paragraphs.
+pub(crate) fn first_paragraph(body: &str) -> Option<String> {
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut lines = body.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        match fence {
+            Some(open) if trimmed.starts_with(open) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                if !paragraph.is_empty() {
+                    break;
+                }
+                fence = Some(&trimmed[..3]);
+            }
+            None if trimmed.is_empty() || is_block_start(trimmed) => {
+                if !paragraph.is_empty() {
+                    break;
+                }
+            }
+            // A definition list term
+            None if paragraph.is_empty()
+                && lines
+                    .peek()
+                    .is_some_and(|next| next.trim_start().starts_with(':')) =>
+            {
+                while lines
+                    .next_if(|next| next.trim_start().starts_with(':'))
+                    .is_some()
+                {}
+            }
+            None => paragraph.push(trimmed),
+        }
+    }
+
+    (!paragraph.is_empty()).then(|| plain_text(&paragraph.join(" ")))
+}
+
+/// Whether a trimmed line starts a block that is not a paragraph
+fn is_block_start(line: &str) -> bool {
+    let ordered = line.split_once(". ").is_some_and(|(number, _)| {
+        !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+    });
+    ordered
+        || ["#", "|", ">", "<!--", "- ", "* ", "+ ", ":"]
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+}
+
+/// Strip code span delimiters, strong emphasis and link targets from inline Markdown
+///
+/// The text of a code span is kept as is, so a glob such as `` `src/**` `` survives.
+fn plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '['
+            && let Some((label, target)) = rest[1..].split_once("](")
+            && !label.contains(['[', ']'])
+            && let Some(end) = target.find(')')
+        {
+            plain.push_str(&plain_text(label));
+            rest = &target[end + 1..];
+            continue;
+        }
+        if c == '`'
+            && let Some((code, after)) = rest[1..].split_once('`')
+        {
+            plain.push_str(code);
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            rest = after;
+            continue;
+        }
+        if c != '`' {
+            plain.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+
+    plain
+}
+
+/// A title reduced to what matters when matching a task name typed on the command line
+///
+/// Inline formatting, emoji and other symbols, and trailing punctuation are dropped, case is
+/// folded and whitespace collapsed, so `## 🚀 **Deploy**!` becomes `deploy`.
+pub(crate) fn normalize_title(title: &str) -> String {
+    let text: String = plain_text(title)
+        .to_lowercase()
+        .chars()
+        .filter(|c| {
+            !matches!(c, '*' | '_' | '~')
+                && (c.is_alphanumeric() || c.is_whitespace() || c.is_ascii_punctuation())
+        })
+        .collect();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words
+        .join(" ")
+        .trim_end_matches(['.', ',', ':', ';', '!', '?', ' '])
+        .to_string()
+}
+
+/// A heading split into its title and optional suffixes
+#[derive(Debug, PartialEq)]
+pub(crate) struct Heading<'a> {
+    /// Heading text without suffixes; this is the task name
+    pub title: &'a str,
+    /// Tags from a `[a, b]` suffix
+    pub tags: Vec<String>,
+    /// Attributes from a `{os=linux}` suffix, without the braces
+    pub attributes: Option<&'a str>,
+}
+
+/// Split trailing tag (`[ci, danger]`) and attribute (`{os=linux}`) suffixes off a heading
+///
+/// The suffixes may appear in either order; a heading consisting only of a suffix is kept as is.
+pub(crate) fn parse_heading(heading: &str) -> Heading<'_> {
+    let mut parsed = Heading {
+        title: heading.trim_end(),
+        tags: Vec::new(),
+        attributes: None,
+    };
+
+    while let Some((close, open)) = [(']', '['), ('}', '{')]
+        .into_iter()
+        .find(|(close, _)| parsed.title.ends_with(*close))
+    {
+        let Some((base, inner)) = parsed.title[..parsed.title.len() - 1].rsplit_once(open) else {
+            break;
+        };
+        let base = base.trim_end();
+        if base.is_empty() {
+            break;
+        }
+
+        match close {
+            ']' if parsed.tags.is_empty() => parsed.tags = split_list(inner),
+            '}' if parsed.attributes.is_none() => parsed.attributes = Some(inner.trim()),
+            _ => break,
+        }
+        parsed.title = base;
+    }
+
+    parsed
+}
+
+/// A fence of backticks longer than any run of them in the code it goes around
+pub(crate) fn code_fence(code: &str) -> String {
+    let longest = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// GitHub-style anchor of a heading: lowercased, punctuation removed, spaces as hyphens
+pub(crate) fn slugify(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// Split a comma separated list, dropping empty entries
+pub(crate) fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse fence attributes such as `name=build skip title="Build step"`
+///
+/// Bare words are returned with an empty value; double quotes around values are removed.
+pub(crate) fn parse_attributes(meta: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut chars = meta.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let key: String =
+            std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace() && *c != '=')).collect();
+        let value = if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                let value = std::iter::from_fn(|| chars.next_if(|c| *c != '"')).collect();
+                chars.next();
+                value
+            } else {
+                std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect()
+            }
+        } else {
+            String::new()
+        };
+
+        if !key.is_empty() {
+            attributes.push((key, value));
+        }
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CodeBlock;
+
+    #[test]
+    fn test_parse_heading() {
+        assert_eq!(
+            parse_heading("Deploy [ci, danger]"),
+            Heading {
+                title: "Deploy",
+                tags: vec!["ci".to_string(), "danger".to_string()],
+                attributes: None,
+            }
+        );
+        assert_eq!(
+            parse_heading("Install {os=linux} [setup]"),
+            Heading {
+                title: "Install",
+                tags: vec!["setup".to_string()],
+                attributes: Some("os=linux"),
+            }
+        );
+        assert_eq!(parse_heading("Deploy").title, "Deploy");
+        assert_eq!(parse_heading("[draft]").title, "[draft]");
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Build and test"), "build-and-test");
+        assert_eq!(slugify("Deploy (prod) v2.0!"), "deploy-prod-v20");
+        assert_eq!(slugify("snake_case  Überblick"), "snake_case--überblick");
+    }
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(normalize_title("🚀 Deploy"), "deploy");
+        assert_eq!(
+            normalize_title("**Build** `mx`  and   test!"),
+            "build mx and test"
+        );
+        assert_eq!(
+            normalize_title("Run [the app](https://example.com) ✨ ..."),
+            "run the app"
+        );
+        assert_eq!(normalize_title("_Résumé_ (v2)?"), "résumé (v2)");
+        assert_eq!(normalize_title("🎉"), "");
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        assert_eq!(
+            parse_attributes(r#"name=build  skip title="Build step" tags=ci,release"#),
+            vec![
+                ("name".to_string(), "build".to_string()),
+                ("skip".to_string(), String::new()),
+                ("title".to_string(), "Build step".to_string()),
+                ("tags".to_string(), "ci,release".to_string()),
+            ]
+        );
+        assert!(parse_attributes("  ").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_mdx() {
+        let markdown = "import Tabs from '@theme/Tabs';\nexport const meta = {\n  id: 1,\n};\n\n\
+                        ## Build\n\n:::tip[Fast]\nUse `make`.\n:::\n\n{/* hidden */}\n<Tabs\n\
+                        \x20 groupId=\"os\">\n<TabItem value=\"linux\">\n\n```jsx\n<App />\n```\n\n\
+                        </TabItem>\n</Tabs>\n<details>\n";
+
+        let normalized = normalize_mdx(markdown);
+
+        assert_eq!(normalized.len(), markdown.len());
+        let kept: Vec<_> = normalized
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                "## Build",
+                "Use `make`.",
+                "```jsx",
+                "<App />",
+                "```",
+                "<details>"
+            ]
+        );
+        assert_eq!(normalize_mdx("import the data\n"), "import the data\n");
+    }
+
+    #[test]
+    fn test_split_at_headings() {
+        let markdown = "# Title\n\n## Build\n\n```bash\n## not a heading\n```\n\n## Test\ntext\n";
+
+        let chunks = split_at_headings(markdown, 2);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].line, 3);
+        assert_eq!(chunks[0].heading, "Build");
+        assert_eq!(
+            chunks[0].text,
+            "## Build\n\n```bash\n## not a heading\n```\n\n"
+        );
+        assert_eq!(chunks[1].line, 9);
+        assert_eq!(chunks[1].heading, "Test");
+        assert_eq!(chunks[1].text, "## Test\ntext\n");
+    }
+
+    #[test]
+    fn test_split_at_heading_levels() {
+        let markdown = "# Title\n## Build\n### Debug\n#### Notes\n##Not a heading\n## Test\n";
+
+        let chunks = split_at_heading_levels(markdown, 2..=3);
+
+        let headings: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.level, chunk.line, chunk.heading))
+            .collect();
+        assert_eq!(headings, [(2, 2, "Build"), (3, 3, "Debug"), (2, 6, "Test")]);
+        assert_eq!(chunks[1].text, "### Debug\n#### Notes\n##Not a heading\n");
+    }
+
+    #[test]
+    fn test_locate_sections() {
+        let markdown = "# Title\n\n## Build\n\n```text\nexample\n```\n\n~~~bash\nmake\n~~~\n";
+        let mut sections = vec![Section {
+            title: "Build".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "bash".to_string(),
+                code: "make".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+
+        locate_sections(markdown, 2, &mut sections);
+
+        assert_eq!(sections[0].line, Some(3));
+        assert_eq!(sections[0].span, Some(9..17));
+        assert_eq!(sections[0].codes[0].line, Some(9));
+        let span = sections[0].codes[0].span.clone().unwrap();
+        assert_eq!(&markdown[span], "~~~bash\nmake\n~~~");
+    }
+
+    #[test]
+    fn test_locate_sections_tags() {
+        let markdown = "## Deploy [ci]\n\n```bash tags=danger,ci\n./deploy.sh\n```\n\n## Other\n";
+        let mut sections = vec![Section {
+            title: "Deploy".to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: "bash".to_string(),
+                code: "./deploy.sh".to_string(),
+                ..Default::default()
+            }],
+            tags: vec!["ci".to_string()],
+            ..Default::default()
+        }];
+
+        locate_sections(markdown, 2, &mut sections);
+
+        assert_eq!(sections[0].line, Some(1));
+        assert_eq!(sections[0].codes[0].meta.as_deref(), Some("tags=danger,ci"));
+        assert_eq!(
+            sections[0].codes[0].attribute("tags").as_deref(),
+            Some("danger,ci")
+        );
+        assert_eq!(
+            sections[0].tags,
+            vec!["ci".to_string(), "danger".to_string()]
+        );
+        assert!(sections[0].has_tag("danger"));
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let table = "\n| depends | `build`, test |\n|---|---|\n| Lock | true |\n\nDeploys it.\n";
+        let metadata = parse_metadata(table);
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["depends"], "build, test");
+        assert_eq!(metadata["lock"], "true");
+
+        let list = "\ndepends\n: build\n: test\n\ngroup\n: db\n\nDeploys it.\n";
+        let metadata = parse_metadata(list);
+        assert_eq!(metadata["depends"], "build, test");
+        assert_eq!(metadata["group"], "db");
+
+        assert!(parse_metadata("\nDeploys it.\n\n```bash\n: ${A:=1}\n```\n").is_empty());
+
+        let metadata = parse_metadata("\n| sources | `src/**/*.rs`, **docs** |\n|---|---|\n");
+        assert_eq!(metadata["sources"], "src/**/*.rs, docs");
+    }
+
+    #[test]
+    fn test_first_paragraph() {
+        let body = "\n| depends | build |\n\nterm\n: definition\n\nRun `cargo test` with\n\
+                    [all features](https://example.com) **first**.\n\nSecond paragraph.\n";
+        assert_eq!(
+            first_paragraph(body).as_deref(),
+            Some("Run cargo test with all features first.")
+        );
+        assert_eq!(
+            first_paragraph("\n```bash\ntext in code\n```\n\n- item\n\nAfter [ci].\n").as_deref(),
+            Some("After [ci].")
+        );
+        assert_eq!(first_paragraph("\n```bash\nmake\n```\n"), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! Starter content: projects generated by `mx init` and sections added by `mx add`
+//!
+//! Each project template is an `mx.toml` and a `TASKS.md` whose example sections use the
+//! heading level the configuration sets. The files live in `templates/` at the crate root.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::HeadingLevel;
+use crate::error::{Error, Result};
+use crate::source::{parse_heading, split_at_heading_levels};
+
+/// A starter project for `mx init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// A short configuration and a single example task
+    Minimal,
+    /// Every setting with its default, and tasks showing tags, guards and arguments
+    Full,
+    /// Build, test and lint tasks for a Cargo project
+    RustProject,
+    /// Install, test and build tasks for an npm project
+    NodeProject,
+    /// An operations runbook with procedures grouped by service
+    DocsRunbook,
+}
+
+impl Template {
+    /// Every template, in the order they are offered
+    pub const ALL: &[Template] = &[
+        Template::Minimal,
+        Template::Full,
+        Template::RustProject,
+        Template::NodeProject,
+        Template::DocsRunbook,
+    ];
+
+    /// Name used on the command line, e.g. `rust-project`
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::Minimal => "minimal",
+            Template::Full => "full",
+            Template::RustProject => "rust-project",
+            Template::NodeProject => "node-project",
+            Template::DocsRunbook => "docs-runbook",
+        }
+    }
+
+    /// One-line description for menus
+    pub fn description(self) -> &'static str {
+        match self {
+            Template::Minimal => "a short configuration and a single example task",
+            Template::Full => "every setting with its default, and tasks showing mx's features",
+            Template::RustProject => "build, test and lint tasks for a Cargo project",
+            Template::NodeProject => "install, test and build tasks for an npm project",
+            Template::DocsRunbook => "an operations runbook with procedures grouped by service",
+        }
+    }
+
+    /// Content of the generated `mx.toml`
+    pub fn config(self) -> &'static str {
+        match self {
+            Template::Minimal => include_str!("../templates/minimal/mx.toml"),
+            Template::Full => include_str!("../templates/full/mx.toml"),
+            Template::RustProject => include_str!("../templates/rust-project/mx.toml"),
+            Template::NodeProject => include_str!("../templates/node-project/mx.toml"),
+            Template::DocsRunbook => include_str!("../templates/docs-runbook/mx.toml"),
+        }
+    }
+
+    /// Content of the generated `TASKS.md`
+    pub fn tasks(self) -> &'static str {
+        match self {
+            Template::Minimal => include_str!("../templates/minimal/TASKS.md"),
+            Template::Full => include_str!("../templates/full/TASKS.md"),
+            Template::RustProject => include_str!("../templates/rust-project/TASKS.md"),
+            Template::NodeProject => include_str!("../templates/node-project/TASKS.md"),
+            Template::DocsRunbook => include_str!("../templates/docs-runbook/TASKS.md"),
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Template::ALL
+            .iter()
+            .copied()
+            .find(|template| template.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = Template::ALL.iter().map(|t| t.name()).collect();
+                Error::Config(format!(
+                    "Unknown template '{}', expected one of: {}",
+                    s,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// A task section with a description placeholder and an empty code block
+pub fn task_section(level: u8, title: &str, lang: &str) -> String {
+    format!(
+        "{} {}\n\nTODO: describe what this task does.\n\n```{}\n```\n",
+        "#".repeat(level as usize),
+        title,
+        lang
+    )
+}
+
+/// Append a [`task_section`] to a Markdown document, separated by a blank line
+///
+/// The section uses the shallowest of the heading levels. Fails with
+/// [`Error::DuplicateTask`] if a task with the same name exists at any of them.
+pub fn append_task(
+    markdown: &str,
+    level: impl Into<HeadingLevel>,
+    title: &str,
+    lang: &str,
+) -> Result<String> {
+    let level = level.into();
+    // Tag and attribute suffixes, as in `Deploy [ci]`, are not part of the task name
+    let name = parse_heading(title).title;
+    if split_at_heading_levels(markdown, level.levels())
+        .iter()
+        .any(|chunk| parse_heading(chunk.heading).title == name)
+    {
+        return Err(Error::DuplicateTask(name.to_string()));
+    }
+
+    let mut appended = markdown.trim_end().to_string();
+    if !appended.is_empty() {
+        appended.push_str("\n\n");
+    }
+    appended.push_str(&task_section(level.min(), title, lang));
+    Ok(appended)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::validate::validate_config;
+
+    #[test]
+    fn test_templates_are_valid() {
+        for template in Template::ALL {
+            assert!(
+                validate_config(template.config()).is_empty(),
+                "{}/mx.toml",
+                template
+            );
+            let config: Config = toml::from_str(template.config()).unwrap();
+
+            // The example tasks use the configured heading level
+            let marker = format!("\n{} ", "#".repeat(config.heading_level.min() as usize));
+            assert!(template.tasks().contains(&marker), "{}/TASKS.md", template);
+            assert_eq!(template.name().parse::<Template>().unwrap(), *template);
+        }
+    }
+
+    #[test]
+    fn test_append_task() {
+        let markdown = "# Tasks\n\n## Build [ci]\n\n```bash\nmake\n```\n\n\n";
+
+        assert_eq!(
+            append_task(markdown, 2, "Deploy", "sh").unwrap(),
+            "# Tasks\n\n## Build [ci]\n\n```bash\nmake\n```\n\n\
+             ## Deploy\n\nTODO: describe what this task does.\n\n```sh\n```\n"
+        );
+        assert!(matches!(
+            append_task(markdown, 2, "Build", "bash"),
+            Err(Error::DuplicateTask(title)) if title == "Build"
+        ));
+        assert!(append_task(markdown, 2, "Build {os=linux}", "bash").is_err());
+        assert!(append_task(markdown, 3, "Build", "bash").is_ok());
+        assert!(
+            append_task("", 2, "First", "bash")
+                .unwrap()
+                .starts_with("## First\n")
+        );
+    }
+}
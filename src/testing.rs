@@ -0,0 +1,246 @@
+//! Helpers for testing task definitions
+//!
+//! Enabled with the `testing` feature, typically as a dev-dependency:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! mx = { version = "*", features = ["testing"] }
+//! ```
+//!
+//! ```no_run
+//! use mx::testing::{FakeRuntime, TaskFile, assert_ran, task};
+//! use mx::{Config, ExecutionMode, Runner};
+//!
+//! let file = TaskFile::new(&task("Deploy", "bash", "./deploy.sh --prod"));
+//! let bash = FakeRuntime::new(&file, "bash", ExecutionMode::Stdin);
+//!
+//! let mut config = Config::default();
+//! config.runtimes.insert("bash".to_string(), bash.runtime_config());
+//!
+//! let report = Runner::new(config).run_task(file.path(), "Deploy").unwrap();
+//! assert_ran(&report, &["Deploy#0"]);
+//! assert_eq!(bash.calls()[0].code.trim_end(), "./deploy.sh --prod");
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ExecutionMode, RuntimeConfig};
+use crate::report::ExecutionReport;
+
+/// Markdown for a single task with one code block
+pub fn task(title: &str, lang: &str, code: &str) -> String {
+    format!("## {}\n\n```{}\n{}\n```\n\n", title, lang, code.trim_end())
+}
+
+/// A Markdown task file in its own temporary directory, removed on drop
+#[derive(Debug)]
+pub struct TaskFile {
+    dir: tempfile::TempDir,
+    path: PathBuf,
+}
+
+impl TaskFile {
+    /// Write `README.md` with the given content to a new temporary directory
+    pub fn new(markdown: &str) -> Self {
+        let dir = tempfile::Builder::new()
+            .prefix("mx_test_")
+            .tempdir()
+            .expect("failed to create test directory");
+
+        let path = dir.path().join("README.md");
+        fs::write(&path, markdown).expect("failed to write task file");
+
+        Self { dir, path }
+    }
+
+    /// Path of the Markdown file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Temporary directory holding the file, fake runtimes and anything else a test needs
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// A call recorded by a [`FakeRuntime`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    /// Arguments the runtime was called with
+    pub args: Vec<String>,
+    /// Code of the block, however it was passed (stdin, file or argument)
+    pub code: String,
+}
+
+/// A runtime script that records its invocations instead of running code
+///
+/// Only available on unix, as the runtime is a shell script.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct FakeRuntime {
+    script: PathBuf,
+    calls: PathBuf,
+    mode: ExecutionMode,
+}
+
+#[cfg(unix)]
+impl FakeRuntime {
+    /// Create a fake runtime in the task file's directory that exits successfully
+    pub fn new(file: &TaskFile, name: &str, mode: ExecutionMode) -> Self {
+        Self::with_exit_code(file, name, mode, 0)
+    }
+
+    /// Create a fake runtime that exits with the given code
+    pub fn with_exit_code(file: &TaskFile, name: &str, mode: ExecutionMode, code: i32) -> Self {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = file.dir().join(format!("fake-{}", name));
+        let calls = file.dir().join(format!("fake-{}.calls", name));
+        fs::create_dir_all(&calls).expect("failed to create call directory");
+
+        let record_code = match mode {
+            ExecutionMode::Stdin => r#"cat > "$dir/code""#,
+            ExecutionMode::File => r#"for last; do :; done; cat "$last" > "$dir/code""#,
+            ExecutionMode::Arg => r#"for last; do :; done; printf '%s' "$last" > "$dir/code""#,
+        };
+        let contents = format!(
+            "#!/bin/sh\n\
+             calls={calls}\n\
+             dir=\"$calls/$(ls \"$calls\" | wc -l | tr -d ' ')\"\n\
+             mkdir \"$dir\"\n\
+             for arg; do printf '%s\\0' \"$arg\"; done > \"$dir/args\"\n\
+             {record_code}\n\
+             exit {code}\n",
+            calls = crate::command::shell_quote(&calls.to_string_lossy()),
+        );
+        fs::write(&script, contents).expect("failed to write fake runtime");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))
+            .expect("failed to make fake runtime executable");
+
+        Self {
+            script,
+            calls,
+            mode,
+        }
+    }
+
+    /// Runtime configuration running this fake, for `Config::runtimes`
+    pub fn runtime_config(&self) -> RuntimeConfig {
+        RuntimeConfig::Detailed {
+            command: shell_words::quote(&self.script.to_string_lossy()).into_owned(),
+            execution_mode: self.mode.clone(),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            shell: false,
+            sandbox: None,
+            remote: None,
+            pty: false,
+        }
+    }
+
+    /// Calls recorded so far, in order
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        let mut calls: Vec<(usize, PathBuf)> = fs::read_dir(&self.calls)
+            .expect("failed to read recorded calls")
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let index = entry.file_name().to_str()?.parse().ok()?;
+                Some((index, entry.path()))
+            })
+            .collect();
+        calls.sort();
+
+        calls
+            .into_iter()
+            .map(|(_, dir)| {
+                let args = fs::read_to_string(dir.join("args")).unwrap_or_default();
+                RecordedCall {
+                    args: args.split_terminator('\0').map(str::to_string).collect(),
+                    code: fs::read_to_string(dir.join("code")).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Assert that exactly the given blocks ran (were not skipped), in order
+#[track_caller]
+pub fn assert_ran(report: &ExecutionReport, ids: &[&str]) {
+    let ran: Vec<&str> = report
+        .blocks
+        .iter()
+        .filter(|block| block.skipped.is_none())
+        .map(|block| block.id.as_str())
+        .collect();
+    assert_eq!(ran, ids, "blocks that ran in task '{}'", report.task);
+}
+
+/// Assert that a block was skipped, returning the reason
+#[track_caller]
+pub fn assert_skipped<'a>(report: &'a ExecutionReport, id: &str) -> &'a str {
+    match report.blocks.iter().find(|block| block.id == id) {
+        Some(block) => match &block.skipped {
+            Some(reason) => reason,
+            None => panic!("block {} ran but was expected to be skipped", id),
+        },
+        None => panic!(
+            "block {} is not in the report of task '{}'",
+            id, report.task
+        ),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::runner::{CodeBlock, Runner, Section};
+
+    #[test]
+    fn test_fake_runtime_records_calls() {
+        let file = TaskFile::new(&task("Build", "fake", "make all"));
+        let stdin = FakeRuntime::new(&file, "stdin", ExecutionMode::Stdin);
+        let arg = FakeRuntime::new(&file, "arg", ExecutionMode::Arg);
+
+        let mut config = Config::default();
+        config
+            .runtimes
+            .insert("stdin".to_string(), stdin.runtime_config());
+        config
+            .runtimes
+            .insert("arg".to_string(), arg.runtime_config());
+
+        let block = |lang: &str, code: &str, meta: Option<&str>| CodeBlock {
+            lang: lang.to_string(),
+            code: code.to_string(),
+            meta: meta.map(str::to_string),
+            ..Default::default()
+        };
+        let section = Section {
+            title: "Build".to_string(),
+            codes: vec![
+                block("stdin", "make all", None),
+                block("arg", "it's $HOME", None),
+                block("arg", "never", Some("os=plan9")),
+            ],
+            ..Default::default()
+        };
+
+        let report = Runner::new(config).execute_section(&section).unwrap();
+
+        assert_ran(&report, &["Build#0", "Build#1"]);
+        assert!(assert_skipped(&report, "Build#2").starts_with("os=plan9"));
+        assert_eq!(
+            stdin.calls(),
+            vec![RecordedCall {
+                args: Vec::new(),
+                code: "make all".to_string()
+            }]
+        );
+        assert_eq!(arg.calls()[0].args, vec!["it's $HOME".to_string()]);
+        assert_eq!(arg.calls()[0].code, "it's $HOME");
+    }
+}
@@ -0,0 +1,185 @@
+//! Colors of the command line output
+//!
+//! The `[theme]` configuration table styles the output by role. A style is a color name,
+//! optionally with modifiers:
+//!
+//! ```toml
+//! [theme]
+//! task = "bold blue"
+//! muted = "dimmed"
+//! error = "bright red"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Color names, as accepted by the terminal styling of the CLI
+pub const COLORS: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright black",
+    "bright red",
+    "bright green",
+    "bright yellow",
+    "bright blue",
+    "bright magenta",
+    "bright cyan",
+    "bright white",
+];
+
+/// Styles of the output by role, the `[theme]` configuration table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Task titles
+    pub task: String,
+    /// Paths of task files
+    pub file: String,
+    /// Tags of tasks
+    pub tag: String,
+    /// Languages of code blocks
+    pub lang: String,
+    /// Descriptions, durations and other secondary text
+    pub muted: String,
+    /// Finished tasks and passed checks
+    pub success: String,
+    /// Warnings and skipped tasks
+    pub warning: String,
+    /// Failures
+    pub error: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            task: "bold green".to_string(),
+            file: "cyan".to_string(),
+            tag: "cyan".to_string(),
+            lang: "magenta".to_string(),
+            muted: "bright black".to_string(),
+            success: "green".to_string(),
+            warning: "yellow".to_string(),
+            error: "red".to_string(),
+        }
+    }
+}
+
+impl Theme {
+    /// Roles of the theme and their styles
+    pub fn styles(&self) -> [(&'static str, &str); 8] {
+        [
+            ("task", &self.task),
+            ("file", &self.file),
+            ("tag", &self.tag),
+            ("lang", &self.lang),
+            ("muted", &self.muted),
+            ("success", &self.success),
+            ("warning", &self.warning),
+            ("error", &self.error),
+        ]
+    }
+}
+
+/// A parsed style: an optional color and modifiers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    /// One of [`COLORS`]
+    pub color: Option<&'static str>,
+    /// Modifiers, which may come before or after the color
+    pub bold: bool,
+    pub dimmed: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Parse a style such as `"bold bright_blue"`, where `_` may stand for the space in
+    /// `bright` colors
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.to_lowercase().replace('_', " ");
+        let mut style = Self::default();
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            let flag = match word {
+                "bold" => &mut style.bold,
+                "dimmed" => &mut style.dimmed,
+                "italic" => &mut style.italic,
+                "underline" => &mut style.underline,
+                "bright" => {
+                    let color = words.next().unwrap_or_default();
+                    style.set_color(&format!("bright {}", color))?;
+                    continue;
+                }
+                color => {
+                    style.set_color(color)?;
+                    continue;
+                }
+            };
+            *flag = true;
+        }
+        Ok(style)
+    }
+
+    fn set_color(&mut self, name: &str) -> Result<(), String> {
+        let Some(color) = COLORS.iter().find(|color| **color == name) else {
+            return Err(format!("unknown color or modifier '{}'", name.trim()));
+        };
+        if let Some(previous) = self.color {
+            return Err(format!("two colors, '{}' and '{}'", previous, color));
+        }
+        self.color = Some(color);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style() {
+        assert_eq!(
+            Style::parse("bold green").unwrap(),
+            Style {
+                color: Some("green"),
+                bold: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            Style::parse("Bright_Black").unwrap().color,
+            Some("bright black")
+        );
+        assert_eq!(
+            Style::parse("underline bright cyan italic").unwrap(),
+            Style {
+                color: Some("bright cyan"),
+                italic: true,
+                underline: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(Style::parse("").unwrap(), Style::default());
+
+        assert_eq!(
+            Style::parse("purple").unwrap_err(),
+            "unknown color or modifier 'purple'"
+        );
+        assert_eq!(
+            Style::parse("red blue").unwrap_err(),
+            "two colors, 'red' and 'blue'"
+        );
+    }
+
+    #[test]
+    fn test_default_theme_parses() {
+        for (_, spec) in Theme::default().styles() {
+            assert!(Style::parse(spec).is_ok(), "{}", spec);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Interpreters from version managers
+//!
+//! With `tool_env` set, the program of a code block is resolved by the project's version
+//! manager instead of taken from the first match in `PATH`:
+//!
+//! ```toml
+//! tool_env = "mise"   # or "asdf", "direnv"
+//! ```
+//!
+//! `mise` and `asdf` look the program up with `which` in the block's working directory, so
+//! `.mise.toml` and `.tool-versions` pick its version; programs they do not manage, such as
+//! `bash`, still come from `PATH`. `direnv` runs the block with the project's `.envrc`
+//! loaded.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+/// Version manager providing the interpreters of code blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolEnv {
+    /// [mise](https://mise.jdx.dev/)
+    Mise,
+    /// [asdf](https://asdf-vm.com/)
+    Asdf,
+    /// [direnv](https://direnv.net/), loading the project's `.envrc`
+    Direnv,
+}
+
+impl ToolEnv {
+    /// Name of the version manager's program
+    pub fn program(self) -> &'static str {
+        match self {
+            ToolEnv::Mise => "mise",
+            ToolEnv::Asdf => "asdf",
+            ToolEnv::Direnv => "direnv",
+        }
+    }
+
+    /// Program and arguments running `program` with the project's tool versions
+    ///
+    /// `dir` is the working directory of the block, or the current directory if `None`.
+    pub fn resolve(
+        self,
+        program: &str,
+        args: &[String],
+        dir: Option<&Path>,
+    ) -> Result<(String, Vec<String>)> {
+        if self == ToolEnv::Direnv {
+            let dir = dir.unwrap_or(Path::new("."));
+            let mut wrapped = vec!["exec".to_string(), dir.to_string_lossy().into_owned()];
+            wrapped.push(program.to_string());
+            wrapped.extend(args.iter().cloned());
+            return Ok((self.program().to_string(), wrapped));
+        }
+
+        let mut which = Command::new(self.program());
+        which.args(["which", program]).stdin(Stdio::null());
+        if let Some(dir) = dir {
+            which.current_dir(dir);
+        }
+        let output = which.output().map_err(|e| Error::Toolchain {
+            tool: self.program().to_string(),
+            program: program.to_string(),
+            message: format!("cannot run {}: {}", self.program(), e),
+        })?;
+
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !path.is_empty() {
+            debug!(tool = self.program(), program, path = %path, "resolved program");
+            return Ok((path, args.to_vec()));
+        }
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if missing_version(&message) {
+            return Err(Error::Toolchain {
+                tool: self.program().to_string(),
+                program: program.to_string(),
+                message,
+            });
+        }
+        debug!(
+            tool = self.program(),
+            program, "program is not managed; using PATH"
+        );
+        Ok((program.to_string(), args.to_vec()))
+    }
+}
+
+/// Whether a failed lookup means the project asks for a version that is not available, rather
+/// than the program not being managed at all
+fn missing_version(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("install") || message.contains("no version")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direnv_wraps_program() {
+        let (program, args) = ToolEnv::Direnv
+            .resolve("python3", &["-u".to_string()], Some(Path::new("/work")))
+            .unwrap();
+        assert_eq!(program, "direnv");
+        assert_eq!(args, ["exec", "/work", "python3", "-u"]);
+    }
+
+    #[test]
+    fn test_missing_version() {
+        assert!(missing_version("mise ERROR python@3.12.1 is not installed"));
+        assert!(missing_version(
+            "No version is set for command python3\nConsider adding one of the following versions"
+        ));
+        assert!(!missing_version("mise ERROR bash is not a mise bin"));
+        assert!(!missing_version(
+            "unknown command: bash. Perhaps you have to reshim?"
+        ));
+    }
+}
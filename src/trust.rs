@@ -0,0 +1,234 @@
+//! Trust store: which Markdown files mx may execute code from
+//!
+//! Similar to direnv, the CLI refuses to run tasks from a file until it has been reviewed
+//! and trusted with `mx trust`. The store is a plain text file with one entry per line:
+//!
+//! ```text
+//! # Everything under a directory, whatever its content
+//! /home/me/work/ops
+//! # A single file, as long as its content is unchanged (recorded by `mx trust`)
+//! 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 /home/me/src/app/README.md
+//! ```
+//!
+//! Paths are stored canonicalized. A trusted file that changes becomes untrusted again, so
+//! pulling in someone else's edits requires another review.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::user_config_dir;
+use crate::error::{Error, Result};
+
+/// Environment variable overriding the location of the trust store
+pub const TRUST_FILE_ENV: &str = "MX_TRUST_FILE";
+
+/// An entry of the trust store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustEntry {
+    /// A file or directory trusted whatever its content
+    Path(PathBuf),
+    /// A file trusted as long as its content has this SHA-256 hash
+    File { path: PathBuf, hash: String },
+}
+
+/// The set of trusted files and directories
+#[derive(Debug, Clone)]
+pub struct TrustStore {
+    path: PathBuf,
+    entries: Vec<TrustEntry>,
+}
+
+impl TrustStore {
+    /// Load the store at `path`; a missing file is an empty store
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(content) => content.lines().filter_map(parse_entry).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    /// Load the store at its default location, see [`default_path`]
+    pub fn load_default() -> Result<Self> {
+        let path = default_path().ok_or_else(|| {
+            Error::Config("Cannot locate the trust store; set MX_TRUST_FILE".to_string())
+        })?;
+        Self::load(path)
+    }
+
+    /// Location of the store
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Entries of the store, in file order
+    pub fn entries(&self) -> &[TrustEntry] {
+        &self.entries
+    }
+
+    /// Whether a Markdown file with the given content may be executed
+    pub fn is_trusted(&self, file: &Path, content: &str) -> bool {
+        let Ok(file) = file.canonicalize() else {
+            return false;
+        };
+        let hash = content_hash(content);
+
+        self.entries.iter().any(|entry| match entry {
+            TrustEntry::Path(path) => file.starts_with(path),
+            TrustEntry::File {
+                path,
+                hash: trusted,
+            } => *path == file && *trusted == hash,
+        })
+    }
+
+    /// Fail with [`Error::Untrusted`] unless the file may be executed
+    pub fn check(&self, file: &Path, content: &str) -> Result<()> {
+        if self.is_trusted(file, content) {
+            Ok(())
+        } else {
+            Err(Error::Untrusted(file.to_path_buf()))
+        }
+    }
+
+    /// Trust a file with its current content, replacing any earlier record of it
+    pub fn trust(&mut self, file: &Path, content: &str) -> Result<()> {
+        let path = file.canonicalize()?;
+        self.revoke_canonical(&path);
+        self.entries.push(TrustEntry::File {
+            path,
+            hash: content_hash(content),
+        });
+        Ok(())
+    }
+
+    /// Remove every entry for a file or directory, returning whether there was one
+    pub fn revoke(&mut self, file: &Path) -> Result<bool> {
+        let path = file.canonicalize()?;
+        Ok(self.revoke_canonical(&path))
+    }
+
+    fn revoke_canonical(&mut self, path: &Path) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|entry| match entry {
+            TrustEntry::Path(trusted) | TrustEntry::File { path: trusted, .. } => trusted != path,
+        });
+        self.entries.len() != before
+    }
+
+    /// Write the store back to its file, creating its directory if needed
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut content = String::new();
+        for entry in &self.entries {
+            match entry {
+                TrustEntry::Path(path) => writeln!(content, "{}", path.display()),
+                TrustEntry::File { path, hash } => writeln!(content, "{} {}", hash, path.display()),
+            }
+            .expect("writing to a String cannot fail");
+        }
+
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+/// Default location of the trust store
+///
+/// `MX_TRUST_FILE` if set, otherwise `trusted` in the user's configuration directory, see
+/// [`user_config_dir`].
+pub fn default_path() -> Option<PathBuf> {
+    match std::env::var_os(TRUST_FILE_ENV).filter(|value| !value.is_empty()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => user_config_dir().map(|dir| dir.join("trusted")),
+    }
+}
+
+/// Hex-encoded SHA-256 hash of a file's content
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+fn parse_entry(line: &str) -> Option<TrustEntry> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    match line.split_once(' ') {
+        Some((hash, path)) if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Some(TrustEntry::File {
+                path: PathBuf::from(path.trim_start()),
+                hash: hash.to_ascii_lowercase(),
+            })
+        }
+        _ => Some(TrustEntry::Path(PathBuf::from(line))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_file_must_be_unchanged() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let readme = dir.join("README.md");
+        fs::write(&readme, "## Build").unwrap();
+
+        let mut store = TrustStore::load(dir.join("trusted")).unwrap();
+        assert!(!store.is_trusted(&readme, "## Build"));
+
+        store.trust(&readme, "## Build").unwrap();
+        store.save().unwrap();
+
+        let store = TrustStore::load(dir.join("trusted")).unwrap();
+        assert!(store.is_trusted(&readme, "## Build"));
+        assert!(!store.is_trusted(&readme, "## Build\n\n```sh\nrm -rf ~\n```"));
+    }
+
+    #[test]
+    fn test_trusted_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let readme = dir.join("README.md");
+        fs::write(&readme, "").unwrap();
+        fs::write(
+            dir.join("trusted"),
+            format!(
+                "# ops runbooks\n{}\n",
+                dir.canonicalize().unwrap().display()
+            ),
+        )
+        .unwrap();
+
+        let mut store = TrustStore::load(dir.join("trusted")).unwrap();
+        assert!(store.is_trusted(&readme, "anything"));
+
+        assert!(store.revoke(dir).unwrap());
+        assert!(!store.is_trusted(&readme, "anything"));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash("test"),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! `mx ui`: a terminal dashboard to browse, run and watch tasks
+//!
+//! Tasks are listed on the left. The right side shows the selected task's description and
+//! code blocks, above the output of the current run or, toggled with `h`, the recent runs
+//! of the project. Tasks run on a background thread after their dependencies. As with
+//! `mx run`, their files must be trusted and every run is recorded in the history.
+//!
+//! The dashboard owns the terminal, so code blocks read an empty stdin and their output is
+//! shown without colors.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use miette::{IntoDiagnostic, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use mx::Runner;
+use mx::events::{BlockOutcome, RunnerObserver};
+use mx::history::HistoryEntry;
+use mx::process::KillHandle;
+use mx::report::block_id;
+use mx::runner::{CodeBlock, Section};
+
+use super::{SourceArgs, check_trust, format_duration, record_run, run_history};
+
+/// Number of recent runs shown in the history pane
+const HISTORY_LIMIT: usize = 100;
+
+/// Lines scrolled by Page Up and Page Down
+const PAGE: usize = 10;
+
+/// Open the dashboard for the tasks of the `--file` arguments
+pub(crate) fn run_ui(source: SourceArgs, trust: bool) -> Result<()> {
+    let mut runner = Runner::new(source.load_config()?);
+    if let Some(project) = source.project() {
+        runner = runner.with_root(project.root);
+    }
+
+    let mut tasks = Vec::new();
+    for path in source.files()? {
+        let sections = runner.list_task_sections(&path).into_diagnostic()?;
+        tasks.extend(sections.into_iter().map(|section| (path.clone(), section)));
+    }
+    if tasks.is_empty() {
+        return Err(miette::miette!("No tasks found"));
+    }
+
+    let mut app = App::new(runner, tasks, trust);
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    app.stop();
+    result
+}
+
+/// Which pane is shown under the task details
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Output,
+    History,
+}
+
+/// Output of the current or last run, shared with the thread running it
+#[derive(Default)]
+struct RunLog {
+    lines: Vec<Line<'static>>,
+    /// Output after the last newline
+    partial: String,
+    running: bool,
+}
+
+impl RunLog {
+    /// Forget the last run and start a new one
+    fn start(&mut self, title: &str) {
+        *self = Self {
+            running: true,
+            ..Self::default()
+        };
+        self.status(format!("Running {}", title), Color::Cyan);
+    }
+
+    /// Append a chunk of block output
+    fn output(&mut self, data: &[u8]) {
+        self.partial
+            .push_str(&clean(&String::from_utf8_lossy(data)));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.lines
+                .push(Line::raw(line.trim_end_matches('\n').to_string()));
+        }
+    }
+
+    /// Append a line about the run, on a line of its own
+    fn status(&mut self, text: String, color: Color) {
+        if !self.partial.is_empty() {
+            let partial = std::mem::take(&mut self.partial);
+            self.lines.push(Line::raw(partial));
+        }
+        let style = Style::new().fg(color).add_modifier(Modifier::BOLD);
+        self.lines.push(Line::styled(text, style));
+    }
+
+    fn text(&self) -> Vec<Line<'static>> {
+        let mut lines = self.lines.clone();
+        if !self.partial.is_empty() {
+            lines.push(Line::raw(self.partial.clone()));
+        }
+        lines
+    }
+}
+
+/// Drop terminal escape sequences and control characters, which the dashboard cannot show
+fn clean(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // CSI sequences such as colors end at a letter
+            '\x1b' if chars.next_if_eq(&'[').is_some() => {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            '\t' => cleaned.push_str("    "),
+            '\n' => cleaned.push('\n'),
+            c if c.is_control() => {}
+            c => cleaned.push(c),
+        }
+    }
+    cleaned
+}
+
+/// Observer adding a status line to the log when a block starts and finishes
+struct LogObserver(Arc<Mutex<RunLog>>);
+
+impl RunnerObserver for LogObserver {
+    fn on_block_start(&self, section: &Section, index: usize, block: &CodeBlock) {
+        let id = block_id(&section.title, index);
+        let text = format!("▶ {} {}", id, block.lang);
+        self.0.lock().unwrap().status(text, Color::Cyan);
+    }
+
+    fn on_block_complete(&self, _section: &Section, outcome: &BlockOutcome) {
+        let block = &outcome.report;
+        let (text, color) = match (&block.skipped, outcome.success) {
+            (Some(reason), _) => (format!("- {} skipped: {}", block.id, reason), Color::Yellow),
+            (None, true) => (
+                format!("✓ {} {}", block.id, format_duration(block.duration)),
+                Color::Green,
+            ),
+            (None, false) => {
+                let exit = match outcome.exit_code {
+                    Some(code) => format!("exit code {}, ", code),
+                    None => String::new(),
+                };
+                let duration = format_duration(block.duration);
+                (format!("✗ {} {}{}", block.id, exit, duration), Color::Red)
+            }
+        };
+        self.0.lock().unwrap().status(text, color);
+    }
+}
+
+struct App {
+    /// Runner used to look up dependencies and the history; tasks run with their own
+    runner: Runner,
+    tasks: Vec<(PathBuf, Section)>,
+    list: ListState,
+    trust: bool,
+    log: Arc<Mutex<RunLog>>,
+    worker: Option<(JoinHandle<()>, KillHandle)>,
+    pane: Pane,
+    /// Lines scrolled back from the end of the output; 0 follows new output
+    scroll_back: usize,
+    history: Vec<HistoryEntry>,
+    quit: bool,
+}
+
+impl App {
+    fn new(runner: Runner, tasks: Vec<(PathBuf, Section)>, trust: bool) -> Self {
+        let mut app = Self {
+            runner,
+            tasks,
+            list: ListState::default().with_selected(Some(0)),
+            trust,
+            log: Arc::default(),
+            worker: None,
+            pane: Pane::Output,
+            scroll_back: 0,
+            history: Vec::new(),
+            quit: false,
+        };
+        app.load_history();
+        app
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame)).into_diagnostic()?;
+
+            if event::poll(Duration::from_millis(100)).into_diagnostic()?
+                && let Event::Key(key) = event::read().into_diagnostic()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.handle_key(key);
+            }
+
+            if self
+                .worker
+                .as_ref()
+                .is_some_and(|(worker, _)| worker.is_finished())
+            {
+                if let Some((worker, _)) = self.worker.take() {
+                    let _ = worker.join();
+                }
+                self.load_history();
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let selected = self.list.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.list.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.list
+                    .select(Some((selected + 1).min(self.tasks.len() - 1)));
+            }
+            KeyCode::Enter | KeyCode::Char('r') => self.run_selected(selected),
+            KeyCode::Char('x') => {
+                if let Some((_, kill)) = &self.worker {
+                    kill.kill();
+                }
+            }
+            KeyCode::Char('h') => {
+                self.pane = match self.pane {
+                    Pane::Output => Pane::History,
+                    Pane::History => Pane::Output,
+                };
+            }
+            KeyCode::PageUp => self.scroll_back += PAGE,
+            KeyCode::PageDown => self.scroll_back = self.scroll_back.saturating_sub(PAGE),
+            KeyCode::End => self.scroll_back = 0,
+            _ => {}
+        }
+    }
+
+    /// Run a task after its dependencies on a background thread
+    fn run_selected(&mut self, selected: usize) {
+        self.pane = Pane::Output;
+        self.scroll_back = 0;
+        if self.worker.is_some() {
+            return;
+        }
+
+        let (path, section) = &self.tasks[selected];
+        let mut log = self.log.lock().unwrap();
+        log.start(&section.title);
+        let queue = match self.runner.resolve_dependencies(path, section) {
+            Ok(queue) => queue,
+            Err(e) => {
+                log.status(e.to_string(), Color::Red);
+                log.running = false;
+                return;
+            }
+        };
+        if !self.trust {
+            let mut checked: Vec<&Path> = Vec::new();
+            for (path, _) in &queue {
+                if checked.contains(&path.as_path()) {
+                    continue;
+                }
+                if let Err(e) = check_trust(&self.runner, path) {
+                    log.status(e.to_string(), Color::Red);
+                    log.status(
+                        "Trust the file with `mx trust`, or open the dashboard with --trust"
+                            .to_string(),
+                        Color::Yellow,
+                    );
+                    log.running = false;
+                    return;
+                }
+                checked.push(path);
+            }
+        }
+        drop(log);
+
+        let config = self.runner.config().clone();
+        let root = self.runner.root().map(Path::to_path_buf);
+        let log = Arc::clone(&self.log);
+        let (sender, receiver) = mpsc::channel();
+        let worker = std::thread::spawn(move || {
+            let output = Arc::clone(&log);
+            let mut runner = Runner::new(config)
+                .with_detached_terminal(true)
+                .with_observer(Arc::new(LogObserver(Arc::clone(&log))))
+                .with_output_handler(Arc::new(move |_, data: &[u8]| {
+                    output.lock().unwrap().output(data)
+                }));
+            if let Some(root) = root {
+                runner = runner.with_root(root);
+            }
+            let _ = sender.send(runner.kill_handle());
+
+            let mut success = true;
+            for (path, section) in &queue {
+                let started = SystemTime::now();
+                let result = runner.execute_section(section);
+                record_run(&runner, path, section, &[], &result, started);
+                if let Err(e) = result {
+                    log.lock().unwrap().status(e.to_string(), Color::Red);
+                    success = false;
+                    break;
+                }
+            }
+
+            let mut log = log.lock().unwrap();
+            match success {
+                true => log.status("Finished".to_string(), Color::Green),
+                false => log.status("Failed".to_string(), Color::Red),
+            }
+            log.running = false;
+        });
+        self.worker = receiver.recv().ok().map(|kill| (worker, kill));
+    }
+
+    /// Kill the running task, if any, and wait for its thread
+    fn stop(&mut self) {
+        if let Some((worker, kill)) = self.worker.take() {
+            kill.kill();
+            let _ = worker.join();
+        }
+    }
+
+    /// Reload the recent runs, newest first
+    fn load_history(&mut self) {
+        let mut entries = run_history(&self.runner).entries().unwrap_or_default();
+        entries.reverse();
+        entries.truncate(HISTORY_LIMIT);
+        self.history = entries;
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [tasks, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let [details, bottom] =
+            Layout::vertical([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(right);
+
+        self.draw_tasks(frame, tasks);
+        self.draw_details(frame, details);
+        match self.pane {
+            Pane::Output => self.draw_output(frame, bottom),
+            Pane::History => self.draw_history(frame, bottom),
+        }
+
+        let keys = "↑↓ select  enter run  x stop  h history  PgUp/PgDn scroll  q quit";
+        frame.render_widget(
+            Paragraph::new(keys).style(Style::new().fg(Color::DarkGray)),
+            footer,
+        );
+    }
+
+    fn draw_tasks(&mut self, frame: &mut Frame, area: Rect) {
+        let several_files = self.tasks.iter().any(|(path, _)| *path != self.tasks[0].0);
+        let items: Vec<ListItem> = self
+            .tasks
+            .iter()
+            .map(|(path, section)| {
+                let mut line = vec![Span::raw(section.title.clone())];
+                if several_files {
+                    let file = path.file_name().unwrap_or_default().to_string_lossy();
+                    line.push(Span::styled(
+                        format!("  {}", file),
+                        Style::new().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(line))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(Block::bordered().title(" Tasks "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("› ");
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let (path, section) = &self.tasks[self.list.selected().unwrap_or(0)];
+        let dim = Style::new().fg(Color::DarkGray);
+        let mut lines = vec![Line::styled(
+            section.title.clone(),
+            Style::new().add_modifier(Modifier::BOLD),
+        )];
+        lines.push(Line::styled(path.display().to_string(), dim));
+        if let Some(description) = &section.description {
+            lines.push(Line::default());
+            lines.extend(description.lines().map(|line| Line::raw(line.to_string())));
+        }
+        let dependencies = section.dependencies();
+        if !section.tags.is_empty() || !dependencies.is_empty() {
+            lines.push(Line::default());
+        }
+        if !section.tags.is_empty() {
+            lines.push(Line::raw(format!("Tags: {}", section.tags.join(", "))));
+        }
+        if !dependencies.is_empty() {
+            lines.push(Line::raw(format!(
+                "Depends on: {}",
+                dependencies.join(", ")
+            )));
+        }
+
+        for (index, block) in section.codes.iter().enumerate() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                format!("── {} {}", block_id(&section.title, index), block.lang),
+                Style::new().fg(Color::Magenta),
+            ));
+            lines.extend(block.code.lines().map(|line| Line::raw(clean(line))));
+        }
+
+        let details = Paragraph::new(Text::from(lines))
+            .block(Block::bordered().title(" Task "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(details, area);
+    }
+
+    fn draw_output(&mut self, frame: &mut Frame, area: Rect) {
+        let log = self.log.lock().unwrap();
+        let lines = log.text();
+        let title = if log.running {
+            " Output (running) "
+        } else {
+            " Output "
+        };
+        drop(log);
+
+        // Output is not wrapped, so a line of text is a row
+        let height = area.height.saturating_sub(2) as usize;
+        let bottom = lines.len().saturating_sub(height);
+        self.scroll_back = self.scroll_back.min(bottom);
+        let top = (bottom - self.scroll_back).min(u16::MAX as usize) as u16;
+
+        let output = Paragraph::new(Text::from(lines))
+            .block(Block::bordered().title(title))
+            .scroll((top, 0));
+        frame.render_widget(output, area);
+    }
+
+    fn draw_history(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .history
+            .iter()
+            .map(|entry| {
+                let (mark, color) = match entry.success() {
+                    true => ("✓", Color::Green),
+                    false => ("✗", Color::Red),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{} ", mark), Style::new().fg(color)),
+                    Span::styled(
+                        format!("{}  ", entry.timestamp),
+                        Style::new().fg(Color::DarkGray),
+                    ),
+                    Span::raw(entry.task.clone()),
+                    Span::styled(
+                        format!("  {}", format_duration(entry.duration())),
+                        Style::new().fg(Color::DarkGray),
+                    ),
+                ]))
+            })
+            .collect();
+
+        let history = List::new(items).block(Block::bordered().title(" Recent runs "));
+        frame.render_widget(history, area);
+    }
+}
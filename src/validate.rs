@@ -0,0 +1,1108 @@
+//! Static validation of Markdown task files and configuration files
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::Serialize;
+use toml_edit::{ImDocument, Item, TableLike};
+
+use crate::command;
+use crate::condition;
+use crate::config::{Config, HeadingLevel};
+use crate::runner::Extraction;
+use crate::secrets::secret_name;
+use crate::sql::Connection;
+use crate::theme::{Style, Theme};
+
+/// Top-level keys of a configuration file
+pub const CONFIG_KEYS: &[&str] = &[
+    "extends",
+    "extractor",
+    "heading_level",
+    "ignore_languages",
+    "keep_temp",
+    "kubernetes",
+    "log_dir",
+    "match",
+    "max_extends_depth",
+    "namespaces",
+    "nix_shell",
+    "on_cancel",
+    "resilient",
+    "runtimes",
+    "secrets",
+    "sections_query",
+    "sensitive",
+    "shell_stdin",
+    "sql",
+    "tasks",
+    "theme",
+    "tool_env",
+    "workspace",
+];
+
+/// Keys of a detailed runtime table
+pub const RUNTIME_KEYS: &[&str] = &[
+    "args",
+    "command",
+    "env",
+    "execution_mode",
+    "pty",
+    "remote",
+    "sandbox",
+    "shell",
+    "steps",
+    "target",
+];
+
+/// Keys of a detailed runtime table that do not apply to a pipeline of `steps`
+const COMMAND_ONLY_KEYS: &[&str] = &["args", "command", "execution_mode", "pty", "shell"];
+
+/// Keys of the `[kubernetes]` table
+pub const KUBERNETES_KEYS: &[&str] = &["context", "namespace"];
+
+/// Keys of the `[workspace]` table
+pub const WORKSPACE_KEYS: &[&str] = &["file", "members"];
+
+/// Keys of the `[namespaces]` table
+pub const NAMESPACE_KEYS: &[&str] = &["separator"];
+
+/// Keys of a `[tasks.NAME]` table
+pub const TASK_KEYS: &[&str] = &["description", "lang", "run", "tags"];
+
+/// Keys of the `[sql]` table
+pub const SQL_KEYS: &[&str] = &["default", "profiles"];
+
+/// Keys of a `[secrets]` entry
+pub const SECRET_KEYS: &[&str] = &["account", "command", "env", "keychain"];
+
+/// Keys of a `[secrets]` entry naming where the secret comes from
+const SECRET_SOURCES: &[&str] = &["env", "command", "keychain"];
+
+/// Severity of a validation issue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The task file cannot be executed as written
+    Error,
+    /// The task file is executable but likely not what was intended
+    Warning,
+}
+
+/// A problem found while validating a task file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Issue {
+    /// Severity of the issue
+    pub severity: Severity,
+    /// Human readable description
+    pub message: String,
+    /// Label for the highlighted source location
+    pub label: String,
+    /// Optional hint on how to fix the issue
+    pub help: Option<String>,
+    /// Byte range in the Markdown source the issue refers to
+    pub span: Option<Range<usize>>,
+}
+
+impl Issue {
+    fn error(message: String, label: &str, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+            label: label.to_string(),
+            help: None,
+            span,
+        }
+    }
+
+    fn warning(message: String, label: &str, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message, label, span)
+        }
+    }
+
+    fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+/// Validate extracted sections against the configuration and the Markdown source
+///
+/// Checks that every code block has a configured runtime whose binary exists, that task
+/// titles are unique, and reports sections that failed to extract.
+pub fn validate(config: &Config, markdown: &str, extraction: &Extraction) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for diagnostic in &extraction.diagnostics {
+        issues.push(
+            Issue::error(
+                format!("Failed to extract section '{}'", diagnostic.heading),
+                "this section could not be parsed",
+                line_span(markdown, diagnostic.line),
+            )
+            .with_help(diagnostic.message.clone()),
+        );
+    }
+
+    let mut checked_langs: HashMap<&str, bool> = HashMap::new();
+    // Index of the first task with a title, and how many have it so far
+    let mut seen_titles: HashMap<&str, (usize, usize)> = HashMap::new();
+
+    for (index, section) in extraction.sections.iter().enumerate() {
+        let span = section.span.clone();
+
+        let (first, count) = seen_titles.entry(&section.title).or_insert((index, 0));
+        if *count > 0 {
+            issues.push(
+                Issue::warning(
+                    format!("Duplicate task title '{}'", section.title),
+                    "duplicate defined here",
+                    span.clone(),
+                )
+                .with_help(format!(
+                    "task #{} has the same title; run this one with --index {}",
+                    first, count
+                )),
+            );
+        }
+        *count += 1;
+
+        for (block, code) in section.codes.iter().enumerate() {
+            // Illustrative blocks need no runtime
+            if config.ignores_language(&code.lang) || condition::marked_skip(code.meta.as_deref()) {
+                continue;
+            }
+            let span = code.span.clone().or_else(|| span.clone());
+
+            match config.runtime_for(code, Some(section)) {
+                Ok(Some(Cow::Borrowed(_))) => {}
+                // The client of a SQL profile is only looked up when the block runs
+                Ok(Some(Cow::Owned(_))) => continue,
+                Ok(None) => {
+                    issues.push(
+                        Issue::error(
+                            format!(
+                                "No runtime configured for language '{}' in task '{}'",
+                                code.lang, section.title
+                            ),
+                            &format!("block #{} uses '{}'", block, code.lang),
+                            span.clone(),
+                        )
+                        .with_help(format!(
+                            "add `{} = \"<command>\"` to the [runtimes] table in mx.toml",
+                            code.lang
+                        )),
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    issues.push(
+                        Issue::error(
+                            format!("Invalid SQL profile in task '{}'", section.title),
+                            &format!("block #{} uses '{}'", block, code.lang),
+                            span.clone(),
+                        )
+                        .with_help(e.to_string()),
+                    );
+                    continue;
+                }
+            }
+
+            let available = *checked_langs
+                .entry(&code.lang)
+                .or_insert_with(|| config.validate_runtime(&code.lang).is_ok());
+            if !available {
+                issues.push(
+                    Issue::error(
+                        format!(
+                            "Runtime '{}' for language '{}' not found in PATH",
+                            config.get_runtime(&code.lang).unwrap_or_default(),
+                            code.lang
+                        ),
+                        &format!("block #{} uses '{}'", block, code.lang),
+                        span.clone(),
+                    )
+                    .with_help("install the runtime or override it with --runtime lang:command"),
+                );
+            }
+        }
+    }
+
+    issues
+}
+
+/// Where a dependency named in a task's `depends` metadata points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyTarget {
+    /// A task of the same file, by index
+    Task(usize),
+    /// A task of another file, which is not checked
+    OtherFile,
+    /// No task has that name
+    Unknown,
+}
+
+/// Check that the dependencies of tasks exist and do not depend on each other in a cycle
+///
+/// `resolve` looks up a dependency as written, the way running the task would. Dependencies
+/// on tasks of other files are not followed.
+pub fn validate_dependencies(
+    extraction: &Extraction,
+    resolve: impl Fn(&str) -> DependencyTarget,
+) -> Vec<Issue> {
+    let sections = &extraction.sections;
+    let titles: Vec<&str> = sections
+        .iter()
+        .map(|section| section.title.as_str())
+        .collect();
+    let mut issues = Vec::new();
+    let mut edges = vec![Vec::new(); sections.len()];
+
+    for (index, section) in sections.iter().enumerate() {
+        for dependency in section.dependencies() {
+            match resolve(&dependency) {
+                DependencyTarget::Task(target) => edges[index].push(target),
+                DependencyTarget::OtherFile => {}
+                DependencyTarget::Unknown => {
+                    let issue = Issue::error(
+                        format!(
+                            "Task '{}' depends on '{}', which was not found",
+                            section.title, dependency
+                        ),
+                        "declared in this task",
+                        section.span.clone(),
+                    );
+                    issues.push(match closest(&dependency, &titles) {
+                        Some(title) => issue.with_help(format!("did you mean `{}`?", title)),
+                        None => issue,
+                    });
+                }
+            }
+        }
+    }
+
+    for cycle in dependency_cycles(&edges) {
+        let mut names: Vec<&str> = cycle.iter().map(|&index| titles[index]).collect();
+        names.push(names[0]);
+        issues.push(
+            Issue::error(
+                format!("Dependency cycle: {}", names.join(" -> ")),
+                "this task depends on itself through the cycle",
+                sections[cycle[0]].span.clone(),
+            )
+            .with_help("remove one of the tasks of the cycle from a `depends` entry"),
+        );
+    }
+
+    issues
+}
+
+/// Cycles of a dependency graph given as the edges of each node, found by a depth-first walk
+/// in document order
+fn dependency_cycles(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    // 0: not visited, 1: on the current path, 2: done
+    let mut state = vec![0u8; edges.len()];
+    let mut path = Vec::new();
+    let mut cycles = Vec::new();
+
+    fn visit(
+        node: usize,
+        edges: &[Vec<usize>],
+        state: &mut [u8],
+        path: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<usize>>,
+    ) {
+        state[node] = 1;
+        path.push(node);
+        for &next in &edges[node] {
+            match state[next] {
+                0 => visit(next, edges, state, path, cycles),
+                1 => {
+                    let start = path.iter().position(|&n| n == next).unwrap_or_default();
+                    cycles.push(path[start..].to_vec());
+                }
+                _ => {}
+            }
+        }
+        path.pop();
+        state[node] = 2;
+    }
+
+    for node in 0..edges.len() {
+        if state[node] == 0 {
+            visit(node, edges, &mut state, &mut path, &mut cycles);
+        }
+    }
+    cycles
+}
+
+/// Validate the source of a configuration file
+///
+/// Reports unknown keys (with a suggestion for likely typos), heading levels outside 1-6,
+/// empty runtime commands and invalid execution modes or sandboxes, with spans pointing
+/// into the TOML source. Files it extends are not followed.
+pub fn validate_config(source: &str) -> Vec<Issue> {
+    let document = match ImDocument::parse(source) {
+        Ok(document) => document,
+        Err(e) => {
+            return vec![Issue::error(
+                "Invalid TOML".to_string(),
+                e.message(),
+                e.span(),
+            )];
+        }
+    };
+    let root = document.as_table();
+    let mut issues = Vec::new();
+
+    unknown_keys(root, CONFIG_KEYS, "configuration", &mut issues);
+
+    if let Some(item) = root.get("heading_level") {
+        let level = item
+            .as_integer()
+            .map(|level| level.to_string())
+            .or_else(|| item.as_str().map(str::to_string));
+        if level.is_none_or(|level| level.parse::<HeadingLevel>().is_err()) {
+            issues.push(
+                Issue::error(
+                    "Invalid heading_level".to_string(),
+                    "expected a number from 1 to 6, a range like \"2..4\", or \"any\"",
+                    item.span(),
+                )
+                .with_help("heading_level = 2 makes `## Title` sections tasks"),
+            );
+        }
+    }
+
+    if let Some(runtimes) = root.get("runtimes") {
+        match runtimes.as_table_like() {
+            Some(runtimes) => {
+                for (lang, runtime) in runtimes.iter() {
+                    validate_runtime(lang, runtime, &mut issues);
+                }
+            }
+            None => issues.push(Issue::error(
+                "Invalid runtimes".to_string(),
+                "expected a table of language = command",
+                runtimes.span(),
+            )),
+        }
+    }
+
+    if let Some(kubernetes) = root.get("kubernetes").and_then(Item::as_table_like) {
+        unknown_keys(kubernetes, KUBERNETES_KEYS, "[kubernetes]", &mut issues);
+    }
+
+    if let Some(workspace) = root.get("workspace").and_then(Item::as_table_like) {
+        unknown_keys(workspace, WORKSPACE_KEYS, "[workspace]", &mut issues);
+        let members = workspace.get("members").and_then(Item::as_array);
+        for pattern in members.iter().flat_map(|patterns| patterns.iter()) {
+            if let Some(Err(e)) = pattern.as_str().map(glob::Pattern::new) {
+                issues.push(
+                    Issue::error(
+                        "Invalid workspace member pattern".to_string(),
+                        e.msg,
+                        pattern.span(),
+                    )
+                    .with_help("use `*` to match any directory, e.g. \"apps/*\""),
+                );
+            }
+        }
+    }
+
+    if let Some(namespaces) = root.get("namespaces").and_then(Item::as_table_like) {
+        unknown_keys(namespaces, NAMESPACE_KEYS, "[namespaces]", &mut issues);
+        if let Some(separator) = namespaces.get("separator")
+            && let Some(value) = separator.as_str()
+            && (value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '#'))
+        {
+            issues.push(
+                Issue::error(
+                    "Invalid namespace separator".to_string(),
+                    "must not be empty or contain whitespace or `#`",
+                    separator.span(),
+                )
+                .with_help("use a separator such as \":\" or \"::\""),
+            );
+        }
+    }
+
+    if let Some(sql) = root.get("sql").and_then(Item::as_table_like) {
+        validate_sql(sql, &mut issues);
+    }
+
+    if let Some(tasks) = root.get("tasks").and_then(Item::as_table_like) {
+        for (name, task) in tasks.iter() {
+            let Some(table) = task.as_table_like() else {
+                continue;
+            };
+            unknown_keys(table, TASK_KEYS, &format!("[tasks.{}]", name), &mut issues);
+            if !table.contains_key("run") {
+                issues.push(
+                    Issue::error(
+                        format!("Task '{}' has nothing to run", name),
+                        "`run` is missing",
+                        task.span(),
+                    )
+                    .with_help("run = \"rm -rf target\""),
+                );
+            }
+        }
+    }
+
+    if let Some(theme) = root.get("theme").and_then(Item::as_table_like) {
+        validate_theme(theme, &mut issues);
+    }
+
+    validate_secrets(root, &mut issues);
+
+    let sensitive = root.get("sensitive").and_then(Item::as_array);
+    for pattern in sensitive.iter().flat_map(|patterns| patterns.iter()) {
+        if let Some(Err(e)) = pattern.as_str().map(glob::Pattern::new) {
+            issues.push(
+                Issue::error(
+                    "Invalid sensitive variable pattern".to_string(),
+                    e.msg,
+                    pattern.span(),
+                )
+                .with_help("use `*` to match any characters, e.g. \"*_TOKEN\""),
+            );
+        }
+    }
+
+    // Type errors the checks above do not cover, e.g. `resilient = "yes"`
+    if !issues.iter().any(|issue| issue.severity == Severity::Error)
+        && let Err(e) = toml::from_str::<Config>(source)
+    {
+        issues.push(Issue::error(
+            "Invalid configuration".to_string(),
+            e.message(),
+            e.span(),
+        ));
+    }
+
+    issues
+}
+
+fn validate_runtime(lang: &str, runtime: &Item, issues: &mut Vec<Issue>) {
+    let empty_command = |span| {
+        Issue::error(
+            format!("Empty command for runtime '{}'", lang),
+            "no command to run",
+            span,
+        )
+        .with_help(format!("e.g. {} = \"{}\"", lang, lang))
+    };
+
+    let check_command = |command: &Item, issues: &mut Vec<Issue>| match command.as_str() {
+        Some(line) if line.trim().is_empty() => issues.push(empty_command(command.span())),
+        Some(line) if command::split_command(line).is_err() => issues.push(
+            Issue::error(
+                format!("Invalid command for runtime '{}'", lang),
+                "missing closing quote",
+                command.span(),
+            )
+            .with_help("quote arguments containing spaces like a shell, e.g. -v \"$PWD:/work\""),
+        ),
+        _ => {}
+    };
+
+    if runtime.is_str() {
+        check_command(runtime, issues);
+        return;
+    }
+
+    if let Some(argv) = runtime.as_array() {
+        if argv.is_empty() {
+            issues.push(empty_command(runtime.span()));
+        } else if !argv.iter().all(|arg| arg.is_str()) {
+            issues.push(Issue::error(
+                format!("Invalid runtime '{}'", lang),
+                "expected an array of strings",
+                runtime.span(),
+            ));
+        }
+        return;
+    }
+
+    let Some(table) = runtime.as_table_like() else {
+        issues.push(Issue::error(
+            format!("Invalid runtime '{}'", lang),
+            "expected a command string, an array of arguments or a table",
+            runtime.span(),
+        ));
+        return;
+    };
+
+    unknown_keys(table, RUNTIME_KEYS, &format!("runtime '{}'", lang), issues);
+
+    match (table.get("command"), table.get("steps")) {
+        (Some(command), None) => check_command(command, issues),
+        (_, Some(steps)) => {
+            match steps.as_array() {
+                Some(steps) if steps.is_empty() => issues.push(empty_command(steps.span())),
+                Some(steps) if steps.iter().all(|step| step.is_str()) => {
+                    for step in steps.iter() {
+                        check_command(&Item::Value(step.clone()), issues);
+                    }
+                }
+                _ => issues.push(Issue::error(
+                    format!("Invalid steps for runtime '{}'", lang),
+                    "expected an array of commands",
+                    steps.span(),
+                )),
+            }
+            for key in COMMAND_ONLY_KEYS {
+                if let Some((key, _)) = table.get_key_value(key) {
+                    issues.push(Issue::warning(
+                        format!("'{}' is ignored for runtime '{}' with steps", key, lang),
+                        "does not apply to steps",
+                        key.span(),
+                    ));
+                }
+            }
+        }
+        (None, None) => issues.push(
+            Issue::error(
+                format!("Runtime '{}' has no command", lang),
+                "`command` is missing",
+                runtime.span(),
+            )
+            .with_help("set `command`, or `steps` to compile and then run the code"),
+        ),
+    }
+
+    for (key, allowed) in [
+        ("execution_mode", &["stdin", "file", "arg"][..]),
+        ("sandbox", &["bwrap", "firejail", "env"][..]),
+    ] {
+        if let Some(value) = table.get(key)
+            && !value.as_str().is_some_and(|value| allowed.contains(&value))
+        {
+            issues.push(
+                Issue::error(
+                    format!("Invalid {} for runtime '{}'", key, lang),
+                    &format!("expected one of {}", allowed.join(", ")),
+                    value.span(),
+                )
+                .with_help(format!("{} = \"{}\"", key, allowed[0])),
+            );
+        }
+    }
+}
+
+fn validate_theme(theme: &dyn TableLike, issues: &mut Vec<Issue>) {
+    let roles = Theme::default().styles().map(|(role, _)| role);
+    unknown_keys(theme, &roles, "[theme]", issues);
+
+    for (role, value) in theme.iter() {
+        let Some(spec) = value.as_str() else {
+            continue;
+        };
+        if let Err(e) = Style::parse(spec) {
+            issues.push(
+                Issue::error(
+                    format!("Invalid style '{}' for '{}' in [theme]", spec, role),
+                    &e,
+                    value.span(),
+                )
+                .with_help(
+                    "use a color such as `green` or `bright blue`, optionally with bold, \
+                     dimmed, italic or underline",
+                ),
+            );
+        }
+    }
+}
+
+fn validate_sql(sql: &dyn TableLike, issues: &mut Vec<Issue>) {
+    unknown_keys(sql, SQL_KEYS, "[sql]", issues);
+
+    let profiles = sql.get("profiles").and_then(Item::as_table_like);
+    for (name, url) in profiles.iter().flat_map(|profiles| profiles.iter()) {
+        if let Some(Err(e)) = url.as_str().map(Connection::parse) {
+            issues.push(
+                Issue::error(
+                    format!("Invalid connection string for SQL profile '{}'", name),
+                    "unsupported connection string",
+                    url.span(),
+                )
+                .with_help(e.to_string()),
+            );
+        }
+    }
+
+    if let Some(default) = sql.get("default")
+        && let Some(name) = default.as_str()
+        && !profiles.is_some_and(|profiles| profiles.contains_key(name))
+    {
+        issues.push(
+            Issue::error(
+                format!("Unknown default SQL profile '{}'", name),
+                "not in [sql.profiles]",
+                default.span(),
+            )
+            .with_help(format!(
+                "add it under [sql.profiles], e.g. {} = \"sqlite:app.db\"",
+                name
+            )),
+        );
+    }
+}
+
+/// Check `[secrets]` entries and the `secret:NAME` references of runtime environments
+///
+/// References to unknown secrets are only warnings, as they may be defined in an extended
+/// file.
+fn validate_secrets(root: &dyn TableLike, issues: &mut Vec<Issue>) {
+    let secrets = root.get("secrets").and_then(Item::as_table_like);
+    for (name, secret) in secrets.iter().flat_map(|secrets| secrets.iter()) {
+        let Some(table) = secret.as_table_like() else {
+            issues.push(Issue::error(
+                format!("Invalid secret '{}'", name),
+                "expected a table like { env = \"VAR\" }",
+                secret.span(),
+            ));
+            continue;
+        };
+        unknown_keys(table, SECRET_KEYS, &format!("secret '{}'", name), issues);
+
+        let sources: Vec<&str> = SECRET_SOURCES
+            .iter()
+            .copied()
+            .filter(|key| table.contains_key(key))
+            .collect();
+        if sources.len() != 1 {
+            issues.push(
+                Issue::error(
+                    format!("Secret '{}' needs exactly one source", name),
+                    if sources.is_empty() {
+                        "no env, command or keychain"
+                    } else {
+                        "more than one of env, command and keychain"
+                    },
+                    secret.span(),
+                )
+                .with_help(format!(
+                    "e.g. {} = {{ command = \"op read op://vault/item/field\" }}",
+                    name
+                )),
+            );
+        } else if sources != ["keychain"]
+            && let Some((key, _)) = table.get_key_value("account")
+        {
+            issues.push(Issue::warning(
+                format!("'account' is ignored for secret '{}'", name),
+                "only applies to keychain secrets",
+                key.span(),
+            ));
+        }
+    }
+
+    let runtimes = root.get("runtimes").and_then(Item::as_table_like);
+    for (lang, runtime) in runtimes.iter().flat_map(|runtimes| runtimes.iter()) {
+        let env = runtime
+            .as_table_like()
+            .and_then(|runtime| runtime.get("env"))
+            .and_then(Item::as_table_like);
+        for (_, value) in env.iter().flat_map(|env| env.iter()) {
+            if let Some(name) = value.as_str().and_then(secret_name)
+                && !secrets.is_some_and(|secrets| secrets.contains_key(name))
+            {
+                issues.push(
+                    Issue::warning(
+                        format!("Unknown secret '{}' in runtime '{}'", name, lang),
+                        "not in [secrets]",
+                        value.span(),
+                    )
+                    .with_help(format!(
+                        "define it under [secrets], e.g. {} = {{ env = \"{}\" }}",
+                        name,
+                        name.to_uppercase()
+                    )),
+                );
+            }
+        }
+    }
+}
+
+fn unknown_keys(table: &dyn TableLike, known: &[&str], context: &str, issues: &mut Vec<Issue>) {
+    for (key, _) in table.iter() {
+        if known.contains(&key) {
+            continue;
+        }
+
+        let span = table.get_key_value(key).and_then(|(key, _)| key.span());
+        let issue = Issue::error(
+            format!("Unknown key '{}' in {}", key, context),
+            "unknown key",
+            span,
+        );
+        issues.push(match closest(key, known) {
+            Some(suggestion) => issue.with_help(format!("did you mean `{}`?", suggestion)),
+            None => issue.with_help(format!("known keys: {}", known.join(", "))),
+        });
+    }
+}
+
+/// The candidate closest to a misspelled word, if any is close enough
+fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(previous + 1).min(row[j] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Byte range of a 1-based line number
+fn line_span(markdown: &str, line: usize) -> Option<Range<usize>> {
+    let mut offset = 0;
+
+    for (index, text) in markdown.split_inclusive('\n').enumerate() {
+        if index + 1 == line {
+            return Some(offset..offset + text.trim_end().len());
+        }
+        offset += text.len();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{CodeBlock, ExtractionDiagnostic, Section};
+    use std::collections::BTreeMap;
+
+    fn section(title: &str, lang: &str, span: Range<usize>) -> Section {
+        Section {
+            title: title.to_string(),
+            level: 2,
+            codes: vec![CodeBlock {
+                lang: lang.to_string(),
+                code: "echo hi".to_string(),
+                ..Default::default()
+            }],
+            span: Some(span),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_duplicates_and_unknown_runtimes() {
+        let markdown =
+            "## Build\n\n```bash\necho hi\n```\n\n## Build\n\n```cobol\nDISPLAY 'HI'.\n```\n";
+        let extraction = Extraction {
+            sections: vec![
+                section("Build", "bash", 0..8),
+                section("Build", "cobol", 31..39),
+            ],
+            diagnostics: Vec::new(),
+        };
+
+        let issues = validate(&Config::default(), markdown, &extraction);
+        let messages: Vec<_> = issues.iter().map(|i| i.message.as_str()).collect();
+
+        assert!(messages.contains(&"Duplicate task title 'Build'"));
+        assert!(messages.contains(&"No runtime configured for language 'cobol' in task 'Build'"));
+
+        let duplicate = issues
+            .iter()
+            .find(|i| i.severity == Severity::Warning)
+            .unwrap();
+        assert_eq!(duplicate.span, Some(31..39));
+        assert_eq!(
+            duplicate.help.as_deref(),
+            Some("task #0 has the same title; run this one with --index 1")
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_extraction_failures() {
+        let markdown = "## Broken\n\ntext\n";
+        let extraction = Extraction {
+            sections: Vec::new(),
+            diagnostics: vec![ExtractionDiagnostic {
+                line: 1,
+                heading: "Broken".to_string(),
+                message: "unexpected token".to_string(),
+            }],
+        };
+
+        let issues = validate(&Config::default(), markdown, &extraction);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].span, Some(0..9));
+        assert_eq!(issues[0].help.as_deref(), Some("unexpected token"));
+    }
+
+    fn dependent(title: &str, depends: &str, span: Range<usize>) -> Section {
+        Section {
+            metadata: BTreeMap::from([("depends".to_string(), depends.to_string())]),
+            ..section(title, "bash", span)
+        }
+    }
+
+    fn resolve_in<'a>(sections: &'a [Section]) -> impl Fn(&str) -> DependencyTarget + 'a {
+        |name| match sections.iter().position(|section| section.title == name) {
+            Some(index) => DependencyTarget::Task(index),
+            None if name.contains(".md#") => DependencyTarget::OtherFile,
+            None => DependencyTarget::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_validate_dependencies_reports_unknown_tasks() {
+        let extraction = Extraction {
+            sections: vec![
+                section("Build", "bash", 0..8),
+                dependent("Deploy", "Buld, docs/db.md#Migrate", 20..29),
+            ],
+            diagnostics: Vec::new(),
+        };
+
+        let issues = validate_dependencies(&extraction, resolve_in(&extraction.sections));
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].message,
+            "Task 'Deploy' depends on 'Buld', which was not found"
+        );
+        assert_eq!(issues[0].span, Some(20..29));
+        assert_eq!(issues[0].help.as_deref(), Some("did you mean `Build`?"));
+    }
+
+    #[test]
+    fn test_validate_dependencies_reports_cycles() {
+        let extraction = Extraction {
+            sections: vec![
+                dependent("Build", "Test", 0..8),
+                dependent("Test", "Lint", 10..17),
+                dependent("Lint", "Build", 20..27),
+                dependent("Deploy", "Build", 30..39),
+                dependent("Loop", "Loop", 40..47),
+            ],
+            diagnostics: Vec::new(),
+        };
+
+        let issues = validate_dependencies(&extraction, resolve_in(&extraction.sections));
+        let messages: Vec<_> = issues.iter().map(|i| i.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "Dependency cycle: Build -> Test -> Lint -> Build",
+                "Dependency cycle: Loop -> Loop",
+            ]
+        );
+        assert_eq!(issues[0].span, Some(0..8));
+        assert!(issues.iter().all(|i| i.severity == Severity::Error));
+    }
+
+    #[test]
+    fn test_validate_config_suggests_keys() {
+        let source = "heading_level = 7\n\n[runtimes.python]\ncommand = \"python3\"\nexecution_mod = \"file\"\n";
+
+        let issues = validate_config(source);
+
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].message, "Invalid heading_level");
+        assert_eq!(issues[0].span, Some(16..17));
+        assert_eq!(
+            issues[1].message,
+            "Unknown key 'execution_mod' in runtime 'python'"
+        );
+        assert_eq!(
+            issues[1].help.as_deref(),
+            Some("did you mean `execution_mode`?")
+        );
+        assert_eq!(&source[issues[1].span.clone().unwrap()], "execution_mod");
+    }
+
+    #[test]
+    fn test_validate_config_runtimes() {
+        let issues = validate_config(
+            "resilient = true\n[runtimes]\nbash = \"\"\nnode = { command = \"node\", execution_mode = \"pipe\" }\n",
+        );
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "Empty command for runtime 'bash'",
+                "Invalid execution_mode for runtime 'node'"
+            ]
+        );
+        assert!(validate_config("heading_level = 3\n[runtimes]\nsh = \"bash\"\n").is_empty());
+        assert!(validate_config("heading_level = \"2..4\"\n").is_empty());
+        assert!(!validate_config("heading_level = \"4..2\"\n").is_empty());
+        assert_eq!(
+            validate_config("resilient = \"yes\"\n")[0].message,
+            "Invalid configuration"
+        );
+    }
+
+    #[test]
+    fn test_validate_config_command_forms() {
+        let valid = "[runtimes]\npy = [\"docker\", \"run\", \"-i\", \"python:3.12\", \"python\"]\n\
+                     sh = 'docker run -v \"$PWD:/work\" alpine sh'\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues = validate_config("[runtimes]\npy = \"python3 'oops\"\nrb = []\n");
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid command for runtime 'py'",
+                "Empty command for runtime 'rb'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_steps() {
+        let valid = "[runtimes.c]\nsteps = [\"cc {file} -o {out}\", \"{out} {args}\"]\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues = validate_config(
+            "[runtimes.c]\ncommand = \"cc\"\nsteps = [\"cc 'oops\"]\n\n[runtimes.rs]\nsteps = []\n",
+        );
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid command for runtime 'c'",
+                "'command' is ignored for runtime 'c' with steps",
+                "Empty command for runtime 'rs'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_sql() {
+        let valid = "[sql]\ndefault = \"local\"\n\n[sql.profiles]\nlocal = \"sqlite:app.db\"\n\
+                     analytics = \"postgres://reader@warehouse/analytics\"\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues =
+            validate_config("[sql]\ndefault = \"prod\"\n\n[sql.profiles]\nlocal = \"app.db\"\n");
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid connection string for SQL profile 'local'",
+                "Unknown default SQL profile 'prod'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_secrets() {
+        let valid = "[secrets]\ntoken = { env = \"TOKEN\" }\n\
+                     key = { keychain = \"deploy\", account = \"ci\" }\n\n\
+                     [runtimes.sh]\ncommand = \"sh\"\nenv = { TOKEN = \"secret:token\" }\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues = validate_config(
+            "[secrets]\nboth = { env = \"A\", command = \"pass show a\" }\n\
+             plain = { env = \"B\", account = \"ci\" }\n\n\
+             [runtimes.sh]\ncommand = \"sh\"\nenv = { TOKEN = \"secret:token\" }\n",
+        );
+        let messages: Vec<_> = issues.iter().map(|issue| issue.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Secret 'both' needs exactly one source",
+                "'account' is ignored for secret 'plain'",
+                "Unknown secret 'token' in runtime 'sh'"
+            ]
+        );
+
+        assert!(
+            validate_config("sensitive = [\"*_TOKEN\", \"AWS_SECRET_ACCESS_KEY\"]\n").is_empty()
+        );
+        let issues = validate_config("sensitive = [\"[oops\"]\n");
+        assert_eq!(issues[0].message, "Invalid sensitive variable pattern");
+    }
+
+    #[test]
+    fn test_validate_config_kubernetes() {
+        let valid = "[runtimes.psql]\ncommand = \"psql\"\ntarget = \"k8s:deployment/db\"\n\n\
+                     [kubernetes]\nnamespace = \"prod\"\ncontext = \"prod-cluster\"\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues = validate_config("[kubernetes]\nnamspace = \"prod\"\n");
+        assert_eq!(issues[0].message, "Unknown key 'namspace' in [kubernetes]");
+        assert_eq!(issues[0].help.as_deref(), Some("did you mean `namespace`?"));
+    }
+
+    #[test]
+    fn test_validate_config_tasks() {
+        let valid =
+            "[tasks.clean]\nrun = \"rm -rf target\"\ndescription = \"Remove build output\"\n";
+        assert!(validate_config(valid).is_empty());
+
+        let issues = validate_config("[tasks.clean]\nrn = \"rm -rf target\"\n");
+        assert_eq!(issues[0].message, "Unknown key 'rn' in [tasks.clean]");
+        assert_eq!(issues[1].message, "Task 'clean' has nothing to run");
+    }
+
+    #[test]
+    fn test_validate_config_theme() {
+        assert!(validate_config("[theme]\ntask = \"bold blue\"\nmuted = \"dimmed\"\n").is_empty());
+
+        let issues = validate_config("[theme]\ntask = \"bold purple\"\ntitle = \"red\"\n");
+        assert_eq!(issues[0].message, "Unknown key 'title' in [theme]");
+        assert_eq!(
+            issues[1].message,
+            "Invalid style 'bold purple' for 'task' in [theme]"
+        );
+        assert_eq!(issues[1].label, "unknown color or modifier 'purple'");
+    }
+
+    #[test]
+    fn test_validate_config_workspace() {
+        assert!(validate_config("[workspace]\nmembers = [\"apps/*\"]\n").is_empty());
+
+        let issues = validate_config("[workspace]\nmember = [\"apps/*\"]\n");
+        assert_eq!(issues[0].message, "Unknown key 'member' in [workspace]");
+        assert_eq!(issues[0].help.as_deref(), Some("did you mean `members`?"));
+
+        let issues = validate_config("[workspace]\nmembers = [\"apps/[web\"]\n");
+        assert_eq!(issues[0].message, "Invalid workspace member pattern");
+    }
+
+    #[test]
+    fn test_validate_config_namespaces() {
+        assert!(validate_config("[namespaces]\nseparator = \"::\"\n").is_empty());
+
+        let issues = validate_config("[namespaces]\nseparator = \"\"\n");
+        assert_eq!(issues[0].message, "Invalid namespace separator");
+
+        let issues = validate_config("[namespaces]\nseparator = \"#\"\n");
+        assert_eq!(issues[0].message, "Invalid namespace separator");
+    }
+}
@@ -0,0 +1,221 @@
+//! Warnings: things that do not stop a task from running but are likely not intended
+//!
+//! Warnings are collected into execution reports rather than printed, so callers decide
+//! how to surface them, and `mx run --deny-warnings` can fail on them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::report::block_id;
+use crate::runner::Section;
+
+/// A non-fatal problem with a task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// A code block has no language, so it is never executed
+    BlockWithoutLanguage {
+        task: String,
+        /// Stable block identifier, e.g. `Build#1`
+        id: String,
+    },
+    /// Several tasks share a title, so running one needs `--index` or its parent heading
+    DuplicateTitle { task: String, count: usize },
+    /// The task is marked `{deprecated}` in its heading
+    DeprecatedTask {
+        task: String,
+        /// Value of the attribute, e.g. `{deprecated="use Build instead"}`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+    },
+    /// A `[tasks]` entry of the configuration has the title of a Markdown task, which wins
+    ShadowedConfigTask { task: String },
+}
+
+impl Warning {
+    /// Title of the task the warning is about
+    pub fn task(&self) -> &str {
+        match self {
+            Warning::BlockWithoutLanguage { task, .. }
+            | Warning::DuplicateTitle { task, .. }
+            | Warning::DeprecatedTask { task, .. }
+            | Warning::ShadowedConfigTask { task } => task,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::BlockWithoutLanguage { id, .. } => {
+                write!(f, "code block {} has no language and is not executed", id)
+            }
+            Warning::DuplicateTitle { task, count } => write!(
+                f,
+                "{} tasks are titled '{}'; pick one with --index",
+                count, task
+            ),
+            Warning::DeprecatedTask { task, note: None } => {
+                write!(f, "task '{}' is deprecated", task)
+            }
+            Warning::DeprecatedTask {
+                task,
+                note: Some(note),
+            } => write!(f, "task '{}' is deprecated: {}", task, note),
+            Warning::ShadowedConfigTask { task } => write!(
+                f,
+                "task '{}' of the configuration is hidden by the Markdown task with that title",
+                task
+            ),
+        }
+    }
+}
+
+/// Warnings about a single task that apply whenever it runs
+pub fn task_warnings(section: &Section) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    if let Some(note) = section.attribute("deprecated") {
+        warnings.push(Warning::DeprecatedTask {
+            task: section.title.clone(),
+            note: Some(note).filter(|note| !note.is_empty()),
+        });
+    }
+
+    for (index, code) in section.codes.iter().enumerate() {
+        if code.lang.is_empty() {
+            warnings.push(Warning::BlockWithoutLanguage {
+                task: section.title.clone(),
+                id: block_id(&section.title, index),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Warnings about a whole document: duplicate titles, then each task's own warnings
+pub fn document_warnings(sections: &[Section]) -> Vec<Warning> {
+    let mut warnings = duplicate_title_warnings(sections);
+    warnings.extend(sections.iter().flat_map(task_warnings));
+    warnings
+}
+
+/// One warning per task of the configuration that a Markdown task has the title of
+pub fn shadowed_config_task_warnings(config: &Config, sections: &[Section]) -> Vec<Warning> {
+    config
+        .tasks
+        .keys()
+        .filter(|title| sections.iter().any(|section| &section.title == *title))
+        .map(|title| Warning::ShadowedConfigTask {
+            task: title.clone(),
+        })
+        .collect()
+}
+
+/// One warning per title shared by several tasks, in document order
+pub fn duplicate_title_warnings(sections: &[Section]) -> Vec<Warning> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for section in sections {
+        *counts.entry(&section.title).or_default() += 1;
+    }
+
+    let mut warnings = Vec::new();
+    for section in sections {
+        if let Some(count) = counts.remove(section.title.as_str())
+            && count > 1
+        {
+            warnings.push(Warning::DuplicateTitle {
+                task: section.title.clone(),
+                count,
+            });
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CodeBlock;
+
+    fn section(title: &str, meta: Option<&str>, langs: &[&str]) -> Section {
+        Section {
+            title: title.to_string(),
+            meta: meta.map(str::to_string),
+            codes: langs
+                .iter()
+                .map(|lang| CodeBlock {
+                    lang: lang.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_document_warnings() {
+        let sections = vec![
+            section("Build", None, &["bash", ""]),
+            section("Old", Some("deprecated=\"use Build\""), &["bash"]),
+            section("Build", None, &["bash"]),
+        ];
+
+        let warnings = document_warnings(&sections);
+
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::DuplicateTitle {
+                    task: "Build".to_string(),
+                    count: 2
+                },
+                Warning::BlockWithoutLanguage {
+                    task: "Build".to_string(),
+                    id: "Build#1".to_string()
+                },
+                Warning::DeprecatedTask {
+                    task: "Old".to_string(),
+                    note: Some("use Build".to_string())
+                },
+            ]
+        );
+        assert_eq!(
+            warnings[2].to_string(),
+            "task 'Old' is deprecated: use Build"
+        );
+    }
+
+    #[test]
+    fn test_shadowed_config_tasks() {
+        let config: Config = toml::from_str(
+            "[tasks.Build]\nrun = \"make\"\n\n[tasks.Clean]\nrun = \"rm -rf target\"\n",
+        )
+        .unwrap();
+
+        let warnings = shadowed_config_task_warnings(&config, &[section("Build", None, &[])]);
+
+        assert_eq!(
+            warnings,
+            vec![Warning::ShadowedConfigTask {
+                task: "Build".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_bare_deprecated_attribute() {
+        assert_eq!(
+            task_warnings(&section("Old", Some("deprecated os=linux"), &[])),
+            vec![Warning::DeprecatedTask {
+                task: "Old".to_string(),
+                note: None
+            }]
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! WebAssembly bindings for browser previews
+//!
+//! Enabled with the `wasm` feature. Only the extraction half of mx is exposed: sections,
+//! validation and execution plans. Nothing here spawns processes. Results are returned as
+//! JSON strings and errors are thrown as JavaScript exceptions.
+//!
+//! ```sh
+//! cargo build --lib --release --target wasm32-unknown-unknown --no-default-features --features wasm
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::config::Config;
+use crate::plan::Plan;
+use crate::runner::Runner;
+
+/// Extract the sections of a Markdown document
+///
+/// Returns `{"sections": [...], "diagnostics": [...]}`; sections that fail to parse are
+/// reported as diagnostics instead of failing the whole document.
+#[wasm_bindgen(js_name = extractSections)]
+pub fn extract_sections(markdown: &str, config: Option<String>) -> Result<String, JsError> {
+    let runner = Runner::new(parse_config(config)?);
+    to_json(&runner.extract_sections_resilient(markdown))
+}
+
+/// Validate a Markdown document, returning a JSON array of issues
+///
+/// Runtime binaries cannot be looked up in the browser, so only missing runtime
+/// configuration is reported, not missing executables.
+#[wasm_bindgen]
+pub fn validate(markdown: &str, config: Option<String>) -> Result<String, JsError> {
+    let runner = Runner::new(parse_config(config)?);
+    to_json(&runner.validate(markdown))
+}
+
+/// Build the execution plan of a task as JSON
+#[wasm_bindgen]
+pub fn plan(markdown: &str, task: &str, config: Option<String>) -> Result<String, JsError> {
+    let runner = Runner::new(parse_config(config)?);
+    let sections = runner.extract_sections(markdown)?;
+    let section = runner
+        .find_section(&sections, task)
+        .ok_or_else(|| runner.task_not_found(&sections, task))?;
+
+    to_json(&Plan::build(runner.config(), section))
+}
+
+/// Parse an `mx.toml` document; `extends` is not supported as there is no file system
+fn parse_config(config: Option<String>) -> Result<Config, JsError> {
+    match config {
+        Some(config) => Ok(toml::from_str(&config)?),
+        None => Ok(Config::default()),
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, JsError> {
+    Ok(serde_json::to_string(value)?)
+}
@@ -0,0 +1,164 @@
+//! Workspaces of several packages, each with its own task file
+//!
+//! In a mono-repo, the `[workspace]` table lists the package directories by glob pattern,
+//! relative to the project root:
+//!
+//! ```toml
+//! [workspace]
+//! members = ["apps/*", "packages/*"]
+//! ```
+//!
+//! Every member with a task file (`README.md` unless `file` says otherwise) is named after
+//! its directory, so `web:build` is the `build` task of `apps/web/README.md`, run in
+//! `apps/web`.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// The `[workspace]` configuration table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Glob patterns of the member directories, relative to the project root
+    pub members: Vec<String>,
+    /// Task file of each member, relative to its directory
+    pub file: PathBuf,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            members: Vec::new(),
+            file: PathBuf::from("README.md"),
+        }
+    }
+}
+
+/// A package of a workspace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// Name of the member directory, which tasks are addressed by as in `web:build`
+    pub name: String,
+    /// Directory of the member, where its tasks run
+    pub dir: PathBuf,
+    /// Task file of the member
+    pub file: PathBuf,
+}
+
+impl WorkspaceConfig {
+    /// Whether no members are configured
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Members under a project root that have a task file, sorted by name
+    ///
+    /// Two members with the same name are an error, as tasks could not tell them apart.
+    pub fn members(&self, root: &Path) -> Result<Vec<Member>> {
+        let mut members: Vec<Member> = Vec::new();
+        for pattern in &self.members {
+            let pattern = root.join(pattern);
+            let paths = glob::glob(&pattern.to_string_lossy()).map_err(|e| {
+                Error::Config(format!(
+                    "Invalid workspace member pattern '{}': {}",
+                    pattern.display(),
+                    e
+                ))
+            })?;
+
+            for dir in paths.filter_map(|entry| entry.ok()) {
+                let file = dir.join(&self.file);
+                let Some(name) = dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                else {
+                    continue;
+                };
+                if !dir.is_dir() || !file.is_file() {
+                    continue;
+                }
+                match members.iter().find(|member| member.name == name) {
+                    Some(member) if member.dir == dir => {}
+                    Some(member) => {
+                        return Err(Error::Config(format!(
+                            "Workspace members {} and {} are both named '{}'",
+                            member.dir.display(),
+                            dir.display(),
+                            name
+                        )));
+                    }
+                    None => members.push(Member { name, dir, file }),
+                }
+            }
+        }
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(members)
+    }
+}
+
+/// Split a `member:task` reference, written with the namespace separator, into the member
+/// and the task, if it names one of the members
+///
+/// Titles such as `Deploy: staging` are left alone unless a member is called `Deploy`.
+pub fn split_task<'a, 'm>(
+    reference: &'a str,
+    members: &'m [Member],
+    separator: &str,
+) -> Option<(&'m Member, &'a str)> {
+    let (name, task) = reference.split_once(separator)?;
+    let member = members.iter().find(|member| member.name == name)?;
+    Some((member, task.trim_start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_members() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for dir in ["apps/web", "apps/api", "apps/empty", "packages/ui"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "apps/web/README.md",
+            "apps/api/README.md",
+            "packages/ui/README.md",
+        ] {
+            fs::write(root.join(file), "## Build").unwrap();
+        }
+        fs::write(root.join("apps/notes.md"), "").unwrap();
+
+        let workspace = WorkspaceConfig {
+            members: vec!["apps/*".to_string(), "packages/*".to_string()],
+            ..Default::default()
+        };
+        let members = workspace.members(root).unwrap();
+        let names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+        assert_eq!(names, ["api", "ui", "web"]);
+        assert_eq!(members[2].dir, root.join("apps/web"));
+        assert_eq!(members[2].file, root.join("apps/web/README.md"));
+
+        assert_eq!(
+            split_task("web:build", &members, ":").map(|(member, task)| (&*member.name, task)),
+            Some(("web", "build"))
+        );
+        assert_eq!(
+            split_task("web::build", &members, "::").map(|(_, task)| task),
+            Some("build")
+        );
+        assert!(split_task("Deploy: staging", &members, ":").is_none());
+        assert!(split_task("build", &members, ":").is_none());
+
+        fs::create_dir_all(root.join("packages/web")).unwrap();
+        fs::write(root.join("packages/web/README.md"), "").unwrap();
+        let err = workspace.members(root).unwrap_err();
+        assert!(err.to_string().contains("both named 'web'"));
+    }
+}
@@ -23,7 +23,7 @@ print("world")
     fs::write(&test_file, markdown).unwrap();
 
     let config = Config::default();
-    let mut runner = Runner::new(config);
+    let runner = Runner::new(config);
 
     let tasks = runner.list_tasks(&test_file).unwrap();
 
@@ -52,7 +52,7 @@ echo "testing..."
 "#;
 
     let config = Config::default();
-    let mut runner = Runner::new(config);
+    let runner = Runner::new(config);
 
     let sections = runner.extract_sections(markdown).unwrap();
 
@@ -89,10 +89,10 @@ print("world")
 "#;
 
     let config = mx::Config {
-        heading_level: 3,
+        heading_level: 3.into(),
         ..Default::default()
     };
-    let mut runner = Runner::new(config);
+    let runner = Runner::new(config);
     let sections = runner.extract_sections(markdown).unwrap();
 
     assert_eq!(sections.len(), 2);
@@ -102,7 +102,66 @@ print("world")
         sections[1].codes[0],
         CodeBlock {
             lang: "python".to_string(),
-            code: "print(\"world\")".to_string()
+            code: "print(\"world\")".to_string(),
+            line: Some(11),
+            span: Some(59..87),
+            file: None,
+            meta: None,
+            expected_output: None,
         }
     );
 }
+
+#[test]
+fn test_heading_level_range() {
+    let markdown = r#"# Title
+
+## Build
+
+```bash
+make
+```
+
+### Debug build
+
+```bash
+make debug
+```
+
+## Test
+
+```bash
+make test
+```
+"#;
+
+    let config = mx::Config {
+        heading_level: "2..3".parse().unwrap(),
+        ..Default::default()
+    };
+    let runner = Runner::new(config);
+    let sections = runner.extract_sections(markdown).unwrap();
+
+    let titles: Vec<_> = sections
+        .iter()
+        .map(|section| {
+            (
+                section.title.as_str(),
+                section.level,
+                section.parent.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        titles,
+        [
+            ("Build", 2, None),
+            ("Debug build", 3, Some("Build")),
+            ("Test", 2, None)
+        ]
+    );
+    // A section's code ends where a nested task starts
+    assert_eq!(sections[0].codes.len(), 1);
+    assert_eq!(sections[1].codes[0].code, "make debug");
+    assert_eq!(sections[1].codes[0].line, Some(11));
+}